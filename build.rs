@@ -0,0 +1,44 @@
+//! Build script
+//!
+//! Embeds build metadata reported by `/health`:
+//! - `RUST_AI_GIT_SHA`: short commit hash, when built from a git checkout
+//! - `RUST_AI_BUILD_TIMESTAMP`: build time as Unix seconds, taken from
+//!   `SOURCE_DATE_EPOCH` when set for reproducible builds
+
+use std::path::Path;
+use std::process::Command;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+fn main() {
+    let git_sha = Command::new("git")
+        .args(["rev-parse", "--short", "HEAD"])
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .and_then(|output| String::from_utf8(output.stdout).ok())
+        .map(|sha| sha.trim().to_string())
+        .filter(|sha| !sha.is_empty());
+    if let Some(sha) = git_sha {
+        println!("cargo:rustc-env=RUST_AI_GIT_SHA={}", sha);
+    }
+
+    let timestamp = std::env::var("SOURCE_DATE_EPOCH")
+        .ok()
+        .and_then(|epoch| epoch.parse::<u64>().ok())
+        .unwrap_or_else(|| {
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|elapsed| elapsed.as_secs())
+                .unwrap_or_default()
+        });
+    println!("cargo:rustc-env=RUST_AI_BUILD_TIMESTAMP={}", timestamp);
+
+    // Refresh the commit hash on new commits; watching a missing path would
+    // rerun the script on every build
+    println!("cargo:rerun-if-env-changed=SOURCE_DATE_EPOCH");
+    for path in [".git/HEAD", ".git/refs/heads"] {
+        if Path::new(path).exists() {
+            println!("cargo:rerun-if-changed={}", path);
+        }
+    }
+}
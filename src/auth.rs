@@ -1,7 +1,7 @@
 //! Authentication Service Module
 //! 
 //! This module provides comprehensive user authentication and session management:
-//! - User registration and login with bcrypt password hashing
+//! - User registration and login with bcrypt or Argon2id password hashing
 //! - JWT token generation and validation
 //! - Guest/anonymous user sessions for trial usage  
 //! - Integration with Convex database for user persistence
@@ -11,15 +11,21 @@
 //! and anonymous users (with temporary sessions and limited capabilities).
 
 use anyhow::{anyhow, Result};
+use argon2::password_hash::{rand_core::OsRng, PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
+use argon2::Argon2;
+use axum::http::{header::AUTHORIZATION, HeaderMap};
 use bcrypt::{hash, verify, DEFAULT_COST};
-use chrono::Utc;
+use chrono::{DateTime, Utc};
 use jsonwebtoken::{decode, encode, DecodingKey, EncodingKey, Header, Validation};
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
 use uuid::Uuid;
 
-use crate::config::Config;
-use crate::convex_service::{ConvexService, UserAccount};
-use crate::types::AuthUser;
+use crate::clock::{system_clock, SharedClock};
+use crate::config::{Config, PasswordHashAlgo};
+use crate::convex_service::{ConvexService, UserAccount, UserExists};
+use crate::types::{AuthUser, GUEST_TIER};
 
 /// Request payload for user registration
 /// 
@@ -56,6 +62,9 @@ pub struct AuthResult {
     pub success: bool,
     /// JWT token for authenticated requests (only present on success)
     pub token: Option<String>,
+    /// Long-lived token for `refresh_session`, issued on login and registration
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub refresh_token: Option<String>,
     /// User information (only present on success)
     pub user: Option<AuthUser>,
     /// Error message (only present on failure)
@@ -72,18 +81,76 @@ pub struct Claims {
     pub user_id: String,
     /// User's email address
     pub email: String,
-    /// Token type identifier (currently always "user_session")
+    /// Token type identifier: "user_session" for access tokens, "refresh"
+    /// for refresh tokens, "password_reset" for password reset tokens
     pub r#type: String,
     /// Token issued at timestamp (Unix timestamp)
     pub iat: i64,
     /// Token expiration timestamp (Unix timestamp)
     pub exp: i64,
+    /// Unique token id, used to list and revoke sessions (empty for tokens
+    /// issued before session tracking)
+    #[serde(default)]
+    pub jti: String,
+    /// For refresh tokens, the id of the session token issued with it
+    #[serde(default)]
+    pub sid: String,
+}
+
+/// Metadata of an issued session token
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SessionInfo {
+    /// Token id (`jti` claim)
+    pub jti: String,
+    /// When the token was issued (Unix timestamp)
+    pub issued_at: i64,
+    /// When the token expires (Unix timestamp)
+    pub expires_at: i64,
+}
+
+/// An issued refresh token and the sessions opened with it
+struct RefreshInfo {
+    /// Token id (`jti` claim)
+    jti: String,
+    /// When the token expires (Unix timestamp)
+    expires_at: i64,
+    /// Ids of the session issued with the token and of those minted from it
+    sessions: Vec<String>,
+}
+
+/// Issued and revoked session tokens
+/// 
+/// Kept in memory: after a restart existing tokens remain valid but are
+/// no longer listed, and revocations are forgotten.
+#[derive(Default)]
+struct SessionRegistry {
+    /// Live sessions by user id
+    active: HashMap<String, Vec<SessionInfo>>,
+    /// Live refresh tokens by user id
+    refresh: HashMap<String, Vec<RefreshInfo>>,
+    /// Revoked token ids with their expiry, until they would have expired
+    revoked: HashMap<String, i64>,
+}
+
+impl SessionRegistry {
+    /// Drop sessions and revocations whose tokens have expired
+    fn prune(&mut self, now: i64) {
+        self.active.retain(|_, sessions| {
+            sessions.retain(|session| session.expires_at + JWT_LEEWAY_SECS > now);
+            !sessions.is_empty()
+        });
+        self.refresh.retain(|_, tokens| {
+            tokens.retain(|token| token.expires_at + JWT_LEEWAY_SECS > now);
+            !tokens.is_empty()
+        });
+        self.revoked.retain(|_, expires_at| *expires_at + JWT_LEEWAY_SECS > now);
+    }
 }
 
 /// Authentication service providing user management and session handling
 /// 
 /// This service handles all authentication-related operations including:
-/// - Password hashing and verification using bcrypt
+/// - Password hashing and verification using bcrypt or Argon2id
 /// - JWT token generation and validation
 /// - User registration and login flows
 /// - Anonymous session creation for guest users
@@ -94,6 +161,89 @@ pub struct AuthService {
     config: Config,
     /// Database service for user persistence and logging
     convex_service: ConvexService,
+    /// Time source for token issue and expiry checks
+    clock: SharedClock,
+    /// Sessions issued by this instance, shared across clones
+    sessions: Arc<Mutex<SessionRegistry>>,
+}
+
+/// Lifetime of issued refresh tokens in seconds (30 days)
+const REFRESH_TTL_SECS: i64 = 30 * 24 * 60 * 60;
+
+/// Claim type of access tokens
+const SESSION_TOKEN_TYPE: &str = "user_session";
+
+/// Claim type of refresh tokens, which are never accepted as access tokens
+const REFRESH_TOKEN_TYPE: &str = "refresh";
+
+/// Claim type of password reset tokens, only accepted by `reset_password`
+const PASSWORD_RESET_TOKEN_TYPE: &str = "password_reset";
+
+/// Lifetime of password reset tokens in seconds (1 hour)
+pub const PASSWORD_RESET_TTL_SECS: i64 = 60 * 60;
+
+/// Allowed clock skew in seconds when checking token expiry
+const JWT_LEEWAY_SECS: i64 = 60;
+
+/// How many times password hashing work is retried when its blocking task
+/// fails to join
+const HASH_JOIN_RETRIES: u32 = 1;
+
+/// Prefix of Argon2 hashes in PHC string format
+const ARGON2_HASH_PREFIX: &str = "$argon2";
+
+/// Run password hashing work on the blocking thread pool
+/// 
+/// A saturated blocking pool can make the task fail to join (cancelled or
+/// panicked) through no fault of the input, so join failures are retried up
+/// to `retries` times. Errors returned by `op` itself are not retried.
+/// 
+/// # Arguments
+/// * `label` - Operation name used in logs and the final error
+/// * `retries` - Extra attempts allowed after a join failure
+/// * `op` - Blocking work to run
+async fn run_blocking_with_retry<T, F>(label: &str, retries: u32, op: F) -> Result<T>
+where
+    T: Send + 'static,
+    F: Fn() -> Result<T> + Clone + Send + 'static,
+{
+    let mut attempt = 0;
+    loop {
+        match tokio::task::spawn_blocking(op.clone()).await {
+            Ok(result) => return result,
+            Err(e) if attempt < retries => {
+                attempt += 1;
+                tracing::warn!("{} task failed to complete ({}), retrying", label, e);
+            }
+            Err(e) => {
+                return Err(anyhow!(
+                    "{} task failed after {} attempts: {}",
+                    label,
+                    attempt + 1,
+                    e
+                ))
+            }
+        }
+    }
+}
+
+/// Extract the bearer token from an `Authorization` header
+/// 
+/// The `Bearer` scheme is matched case-insensitively and surrounding
+/// whitespace is trimmed. Other schemes and blank tokens yield `None`.
+pub fn extract_bearer(headers: &HeaderMap) -> Option<&str> {
+    let value = headers.get(AUTHORIZATION)?.to_str().ok()?;
+    let (scheme, token) = value.trim().split_once(' ')?;
+    if !scheme.eq_ignore_ascii_case("bearer") {
+        return None;
+    }
+    let token = token.trim();
+    (!token.is_empty()).then_some(token)
+}
+
+/// Whether a user ID belongs to an anonymous guest session
+pub fn is_guest_user_id(user_id: &str) -> bool {
+    user_id.starts_with("anon-")
 }
 
 impl AuthService {
@@ -109,38 +259,62 @@ impl AuthService {
         Self {
             config,
             convex_service,
+            clock: system_clock(),
+            sessions: Arc::new(Mutex::new(SessionRegistry::default())),
         }
     }
 
-    /// Hash a password using bcrypt with default cost factor
+    /// Use the given clock instead of the system clock
+    /// 
+    /// # Arguments
+    /// * `clock` - Time source for token issue and expiry checks
+    pub fn with_clock(mut self, clock: SharedClock) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Hash a password with the configured algorithm (`PASSWORD_HASH_ALGO`)
     /// 
     /// Uses tokio::spawn_blocking to avoid blocking the async runtime
-    /// since bcrypt hashing is CPU-intensive.
+    /// since password hashing is CPU-intensive. A task that fails to join
+    /// is retried once.
     /// 
     /// # Arguments
     /// * `password` - Plain text password to hash
     /// 
     /// # Returns
-    /// Result containing the bcrypt hash string or error
+    /// Result containing the bcrypt or Argon2id hash string or error
     /// 
     /// # Security
-    /// Uses DEFAULT_COST (12 rounds) which provides good security vs. performance balance
+    /// bcrypt uses DEFAULT_COST (12 rounds); Argon2id uses the crate's
+    /// recommended parameters with a random salt
     pub async fn hash_password(&self, password: &str) -> Result<String> {
         let password = password.to_string();
-        tokio::task::spawn_blocking(move || {
-            hash(password, DEFAULT_COST).map_err(|e| anyhow!("Failed to hash password: {}", e))
+        let algo = self.config.password_hash_algo;
+        run_blocking_with_retry("Password hashing", HASH_JOIN_RETRIES, move || match algo {
+            PasswordHashAlgo::Bcrypt => hash(&password, DEFAULT_COST).map_err(|e| anyhow!("Failed to hash password: {}", e)),
+            PasswordHashAlgo::Argon2 => {
+                let salt = SaltString::generate(&mut OsRng);
+                Argon2::default()
+                    .hash_password(password.as_bytes(), &salt)
+                    .map(|hash| hash.to_string())
+                    .map_err(|e| anyhow!("Failed to hash password: {}", e))
+            }
         })
-        .await?
+        .await
     }
 
-    /// Verify a password against a stored bcrypt hash
+    /// Verify a password against a stored bcrypt or Argon2 hash
     /// 
-    /// Uses tokio::spawn_blocking to avoid blocking the async runtime
-    /// since bcrypt verification is CPU-intensive.
+    /// The algorithm is detected from the stored hash (`$argon2...`, else
+    /// bcrypt's `$2...`), so existing hashes keep verifying after
+    /// `PASSWORD_HASH_ALGO` changes. Uses tokio::spawn_blocking to avoid
+    /// blocking the async runtime since verification is CPU-intensive. A
+    /// task that fails to join is retried once.
     /// 
     /// # Arguments  
     /// * `password` - Plain text password to verify
-    /// * `hash` - Stored bcrypt hash to compare against
+    /// * `hash` - Stored hash to compare against
     /// 
     /// # Returns
     /// Result containing boolean indicating whether password matches
@@ -150,10 +324,18 @@ impl AuthService {
     pub async fn verify_password(&self, password: &str, hash: &str) -> Result<bool> {
         let password = password.to_string();
         let hash = hash.to_string();
-        tokio::task::spawn_blocking(move || {
-            verify(password, &hash).map_err(|e| anyhow!("Failed to verify password: {}", e))
+        run_blocking_with_retry("Password verification", HASH_JOIN_RETRIES, move || {
+            if !hash.starts_with(ARGON2_HASH_PREFIX) {
+                return verify(&password, &hash).map_err(|e| anyhow!("Failed to verify password: {}", e));
+            }
+            let parsed = PasswordHash::new(&hash).map_err(|e| anyhow!("Failed to verify password: {}", e))?;
+            match Argon2::default().verify_password(password.as_bytes(), &parsed) {
+                Ok(()) => Ok(true),
+                Err(argon2::password_hash::Error::Password) => Ok(false),
+                Err(e) => Err(anyhow!("Failed to verify password: {}", e)),
+            }
         })
-        .await?
+        .await
     }
 
     /// Generate a unique API key for a user
@@ -180,32 +362,242 @@ impl AuthService {
     /// Result containing the JWT token string or error
     /// 
     /// # Security
-    /// - Tokens expire after 7 days
+    /// - Tokens expire after `JWT_TTL_SECONDS` (7 days by default)
     /// - Signed with server secret (HMAC-SHA256)
     /// - Contains user_id and email for identification
     /// - Includes issued-at and expiration timestamps
     pub fn generate_jwt(&self, user_id: &str, email: &str) -> Result<String> {
-        let secret = self.config.action_token_secret
-            .as_ref()
-            .ok_or_else(|| anyhow!("JWT secret not configured"))?;
+        self.issue_session(user_id, email).map(|(token, _)| token)
+    }
+
+    /// Generate a session token and a refresh token tied to it
+    /// 
+    /// # Returns
+    /// `(session_token, refresh_token)`; the refresh token stops working
+    /// once the session is revoked
+    pub fn generate_session_tokens(&self, user_id: &str, email: &str) -> Result<(String, String)> {
+        let (token, jti) = self.issue_session(user_id, email)?;
+        let refresh_token = self.generate_refresh_jwt(user_id, email, &jti)?;
+        Ok((token, refresh_token))
+    }
+
+    /// Sign and register a session token, returning it with its id
+    fn issue_session(&self, user_id: &str, email: &str) -> Result<(String, String)> {
+        let now = self.clock.now_secs();
+        let claims = Claims {
+            user_id: user_id.to_string(),
+            email: email.to_string(),
+            r#type: SESSION_TOKEN_TYPE.to_string(),
+            iat: now,
+            exp: now + self.config.jwt_ttl_seconds,
+            jti: Uuid::new_v4().to_string(),
+            sid: String::new(),
+        };
+        let token = self.sign_claims(&claims)?;
+
+        let mut sessions = self.sessions.lock().unwrap();
+        sessions.prune(now);
+        sessions.active.entry(claims.user_id).or_default().push(SessionInfo {
+            jti: claims.jti.clone(),
+            issued_at: claims.iat,
+            expires_at: claims.exp,
+        });
+        Ok((token, claims.jti))
+    }
 
-        let now = Utc::now().timestamp();
+    /// Generate a refresh token for the session `session_jti`
+    /// 
+    /// Refresh tokens expire after 30 days and are only accepted by
+    /// `refresh_session`, never as access tokens.
+    fn generate_refresh_jwt(&self, user_id: &str, email: &str, session_jti: &str) -> Result<String> {
+        let now = self.clock.now_secs();
         let claims = Claims {
             user_id: user_id.to_string(),
             email: email.to_string(),
-            r#type: "user_session".to_string(),
+            r#type: REFRESH_TOKEN_TYPE.to_string(),
             iat: now,
-            exp: now + (7 * 24 * 60 * 60), // 7 days expiration
+            exp: now + REFRESH_TTL_SECS,
+            jti: Uuid::new_v4().to_string(),
+            sid: session_jti.to_string(),
         };
+        let token = self.sign_claims(&claims)?;
+
+        let mut sessions = self.sessions.lock().unwrap();
+        sessions.refresh.entry(claims.user_id).or_default().push(RefreshInfo {
+            jti: claims.jti,
+            expires_at: claims.exp,
+            sessions: vec![claims.sid],
+        });
+        Ok(token)
+    }
 
+    /// Issue a password reset token for the account registered to `email`
+    /// 
+    /// The token is signed like session tokens, embeds the user id and
+    /// expires after `PASSWORD_RESET_TTL_SECS`. It is only accepted by
+    /// `reset_password`, never as an access token. Callers must deliver it
+    /// out of band, not to whoever asked for it.
+    /// 
+    /// # Errors
+    /// When no active account uses `email`, or the token cannot be signed
+    pub async fn request_password_reset(&self, email: &str) -> Result<String> {
+        let user = match self.convex_service.get_user(email).await? {
+            Some(user) if user.is_active => user,
+            _ => return Err(anyhow!("No active account for {}", email)),
+        };
+        
+        let now = self.clock.now_secs();
+        let token = self.sign_claims(&Claims {
+            user_id: user.id.clone(),
+            email: user.email.clone(),
+            r#type: PASSWORD_RESET_TOKEN_TYPE.to_string(),
+            iat: now,
+            exp: now + PASSWORD_RESET_TTL_SECS,
+            jti: Uuid::new_v4().to_string(),
+            sid: String::new(),
+        })?;
+        
+        self.convex_service.log_system_event(
+            "password_reset_requested",
+            "info",
+            &format!("Password reset requested for {}", user.email),
+            Some(&user.id),
+            Some(serde_json::json!({"email": user.email})),
+        ).await.ok();
+        Ok(token)
+    }
+
+    /// Set a new password using a reset token from `request_password_reset`
+    /// 
+    /// The token is single-use. On success every existing session and
+    /// refresh token of the user is revoked and a fresh pair is issued.
+    /// 
+    /// # Returns
+    /// AuthResult with the new tokens, or a failure with a generic error
+    /// when the reset token or account is not usable
+    /// 
+    /// # Errors
+    /// When the new password is too short, or hashing or the update fails
+    pub async fn reset_password(&self, reset_token: &str, new_password: &str) -> Result<AuthResult> {
+        if new_password.len() < 8 {
+            return Err(anyhow!("Password must be at least 8 characters long"));
+        }
+        let rejected = || AuthResult {
+            success: false,
+            token: None,
+            refresh_token: None,
+            user: None,
+            error: Some("Invalid or expired reset token".to_string()),
+        };
+        
+        let Some(claims) = self
+            .decode_claims(reset_token)
+            .filter(|claims| claims.r#type == PASSWORD_RESET_TOKEN_TYPE)
+        else {
+            return Ok(rejected());
+        };
+        let user = match self.convex_service.get_user(&claims.email).await? {
+            Some(user) if user.is_active && user.id == claims.user_id => user,
+            _ => return Ok(rejected()),
+        };
+        
+        // Claim the token before the slow hash so concurrent confirms cannot
+        // both use it; it is only given back when the update fails
+        if self.sessions.lock().unwrap().revoked.insert(claims.jti.clone(), claims.exp).is_some() {
+            return Ok(rejected());
+        }
+        let updated = async {
+            let password_hash = self.hash_password(new_password).await?;
+            self.convex_service
+                .update_password_hash(&user.id, &user.email, &password_hash)
+                .await
+        };
+        if let Err(error) = updated.await {
+            self.sessions.lock().unwrap().revoked.remove(&claims.jti);
+            return Err(error);
+        }
+        
+        // Sessions opened with the old password no longer stay usable
+        {
+            let mut sessions = self.sessions.lock().unwrap();
+            for session in sessions.active.remove(&user.id).unwrap_or_default() {
+                sessions.revoked.insert(session.jti, session.expires_at);
+            }
+            for token in sessions.refresh.remove(&user.id).unwrap_or_default() {
+                sessions.revoked.insert(token.jti, token.expires_at);
+            }
+        }
+        
+        self.convex_service.log_system_event(
+            "password_reset",
+            "info",
+            &format!("Password reset for {}", user.email),
+            Some(&user.id),
+            Some(serde_json::json!({"email": user.email})),
+        ).await.ok();
+        
+        let (token, refresh_token) = self.generate_session_tokens(&user.id, &user.email)?;
+        Ok(AuthResult {
+            success: true,
+            token: Some(token),
+            refresh_token: Some(refresh_token),
+            user: Some(AuthUser {
+                id: user.id,
+                email: Some(user.email),
+                is_anonymous: false,
+                created_at: user.created_at.unwrap_or_else(Utc::now),
+                subscription_tier: user.subscription_tier,
+            }),
+            error: None,
+        })
+    }
+
+    /// Sign claims with the server secret
+    fn sign_claims(&self, claims: &Claims) -> Result<String> {
+        let secret = self.config.action_token_secret
+            .as_ref()
+            .ok_or_else(|| anyhow!("JWT secret not configured"))?;
         encode(
             &Header::default(),
-            &claims,
-            &EncodingKey::from_secret(secret.as_bytes()),
+            claims,
+            &EncodingKey::from_secret(secret.expose().as_bytes()),
         )
         .map_err(|e| anyhow!("Failed to generate JWT: {}", e))
     }
 
+    /// Active sessions issued to a user, oldest first
+    pub fn list_sessions(&self, user_id: &str) -> Vec<SessionInfo> {
+        let mut sessions = self.sessions.lock().unwrap();
+        sessions.prune(self.clock.now_secs());
+        sessions.active.get(user_id).cloned().unwrap_or_default()
+    }
+
+    /// Revoke one of a user's sessions, along with the refresh token it
+    /// was opened with
+    /// 
+    /// # Returns
+    /// `false` when the user has no active session with that id
+    pub fn revoke_session(&self, user_id: &str, jti: &str) -> bool {
+        let mut guard = self.sessions.lock().unwrap();
+        let sessions = &mut *guard;
+        let Some(user_sessions) = sessions.active.get_mut(user_id) else {
+            return false;
+        };
+        let Some(index) = user_sessions.iter().position(|session| session.jti == jti) else {
+            return false;
+        };
+        let session = user_sessions.remove(index);
+        
+        if let Some(tokens) = sessions.refresh.get_mut(user_id) {
+            if let Some(index) = tokens.iter().position(|token| token.sessions.contains(&session.jti)) {
+                let token = tokens.remove(index);
+                sessions.revoked.insert(token.jti, token.expires_at);
+            }
+        }
+        sessions.revoked.insert(session.jti, session.expires_at);
+        true
+    }
+
     /// Verify a JWT token and extract user information
     /// 
     /// Validates token signature and expiration, then extracts user_id and email.
@@ -219,25 +611,108 @@ impl AuthService {
     /// 
     /// # Security
     /// - Verifies HMAC signature using server secret
-    /// - Checks token expiration automatically
+    /// - Checks token expiration against the service clock
     /// - Only accepts "user_session" type tokens
-    #[allow(dead_code)]
+    /// - Rejects guest tokens when anonymous sessions are disabled
     pub fn verify_jwt(&self, token: &str) -> Option<(String, String)> {
+        self.verify_jwt_claims(token)
+            .map(|claims| (claims.user_id, claims.email))
+    }
+
+    /// Verify a JWT token and return its claims
+    /// 
+    /// Applies the same checks as `verify_jwt`; use this when the
+    /// issue or expiry time is needed as well.
+    pub fn verify_jwt_claims(&self, token: &str) -> Option<Claims> {
+        let claims = self.decode_claims(token)?;
+        if !self.config.allow_anonymous && is_guest_user_id(&claims.user_id) {
+            return None;
+        }
+        
+        if claims.r#type == SESSION_TOKEN_TYPE {
+            Some(claims)
+        } else {
+            None
+        }
+    }
+
+    /// Claims of a token with a valid signature that has not expired or
+    /// been revoked, whatever its type
+    fn decode_claims(&self, token: &str) -> Option<Claims> {
         let secret = self.config.action_token_secret.as_ref()?;
         
-        let validation = Validation::default();
+        // Expiry is checked below against the injected clock
+        let mut validation = Validation::default();
+        validation.validate_exp = false;
         let token_data = decode::<Claims>(
             token,
-            &DecodingKey::from_secret(secret.as_bytes()),
+            &DecodingKey::from_secret(secret.expose().as_bytes()),
             &validation,
         ).ok()?;
 
         let claims = token_data.claims;
-        if claims.r#type == "user_session" {
-            Some((claims.user_id, claims.email))
-        } else {
-            None
+        if claims.exp + JWT_LEEWAY_SECS <= self.clock.now_secs() {
+            return None;
+        }
+        
+        if self.sessions.lock().unwrap().revoked.contains_key(&claims.jti) {
+            return None;
         }
+        Some(claims)
+    }
+
+    /// Mint a new access token from a refresh token
+    /// 
+    /// The refresh token must be valid and of type "refresh", and the user
+    /// must still exist and be active. The refresh token itself stays valid
+    /// until it expires or the session it was issued with is revoked.
+    /// 
+    /// # Returns
+    /// AuthResult with the new access token, or a failure with a generic
+    /// error when the refresh token or account is not usable
+    pub async fn refresh_session(&self, refresh_token: &str) -> Result<AuthResult> {
+        let rejected = || AuthResult {
+            success: false,
+            token: None,
+            refresh_token: None,
+            user: None,
+            error: Some("Invalid or expired refresh token".to_string()),
+        };
+        
+        let Some(claims) = self
+            .decode_claims(refresh_token)
+            .filter(|claims| claims.r#type == REFRESH_TOKEN_TYPE)
+            .filter(|claims| !self.sessions.lock().unwrap().revoked.contains_key(&claims.sid))
+        else {
+            return Ok(rejected());
+        };
+        let user = match self.convex_service.get_user(&claims.email).await? {
+            Some(user) if user.is_active && user.id == claims.user_id => user,
+            _ => return Ok(rejected()),
+        };
+        
+        // Revoking the new session revokes the refresh token as well
+        let (token, jti) = self.issue_session(&user.id, &user.email)?;
+        if let Some(refresh) = self.sessions.lock().unwrap()
+            .refresh
+            .get_mut(&user.id)
+            .and_then(|tokens| tokens.iter_mut().find(|token| token.jti == claims.jti))
+        {
+            refresh.sessions.push(jti);
+        }
+        Ok(AuthResult {
+            success: true,
+            token: Some(token),
+            refresh_token: None,
+            user: Some(AuthUser {
+                id: user.id,
+                email: Some(user.email),
+                is_anonymous: false,
+                created_at: user.created_at.unwrap_or_else(Utc::now),
+                subscription_tier: user.subscription_tier,
+            }),
+            error: None,
+        })
     }
 
     /// Verify a token using multiple authentication methods
@@ -302,14 +777,19 @@ impl AuthService {
             return Err(anyhow::anyhow!("Invalid email format"));
         }
         
-        // Check if user already exists to prevent duplicate registrations
+        let already_exists = || AuthResult {
+            success: false,
+            token: None,
+            refresh_token: None,
+            user: None,
+            error: Some("User with this email already exists".to_string()),
+        };
+        
+        // Check if user already exists to prevent duplicate registrations.
+        // A concurrent registration can still get in first; the store
+        // reports that as `UserExists` below.
         if let Ok(Some(_)) = self.convex_service.get_user(&request.email).await {
-            return Ok(AuthResult {
-                success: false,
-                token: None,
-                user: None,
-                error: Some("User with this email already exists".to_string()),
-            });
+            return Ok(already_exists());
         }
 
         // Hash the password before storing (never store plain text passwords)
@@ -340,20 +820,24 @@ impl AuthService {
                 ).await.ok();
 
                 // Generate JWT token for immediate login after registration
-                let token = self.generate_jwt(&convex_user_id, &request.email)?;
+                let (token, refresh_token) = self.generate_session_tokens(&convex_user_id, &request.email)?;
 
                 Ok(AuthResult {
                     success: true,
                     token: Some(token),
+                    refresh_token: Some(refresh_token),
                     user: Some(AuthUser {
                         id: convex_user_id,
                         email: Some(request.email),
                         is_anonymous: false,
                         created_at: Utc::now(),
+                        subscription_tier: user_account.subscription_tier.clone(),
                     }),
                     error: None,
                 })
             }
+            // Lost a race with another registration for the same email
+            Err(error) if error.is::<UserExists>() => Ok(already_exists()),
             Err(error) => {
                 // Log failed registration for debugging and security monitoring
                 self.convex_service.log_system_event(
@@ -367,6 +851,7 @@ impl AuthService {
                 Ok(AuthResult {
                     success: false,
                     token: None,
+                    refresh_token: None,
                     user: None,
                     error: Some("Failed to create user account".to_string()),
                 })
@@ -403,6 +888,7 @@ impl AuthService {
                 return Ok(AuthResult {
                     success: false,
                     token: None,
+                    refresh_token: None,
                     user: None,
                     error: Some("Invalid email or password".to_string()),
                 });
@@ -428,6 +914,7 @@ impl AuthService {
             return Ok(AuthResult {
                 success: false,
                 token: None,
+                refresh_token: None,
                 user: None,
                 error: Some("Invalid email or password".to_string()),
             });
@@ -438,6 +925,7 @@ impl AuthService {
             return Ok(AuthResult {
                 success: false,
                 token: None,
+                refresh_token: None,
                 user: None,
                 error: Some("Account is disabled".to_string()),
             });
@@ -453,16 +941,18 @@ impl AuthService {
         ).await.ok();
 
         // Generate new JWT token for this session
-        let token = self.generate_jwt(&user.id, &user.email)?;
+        let (token, refresh_token) = self.generate_session_tokens(&user.id, &user.email)?;
 
         Ok(AuthResult {
             success: true,
             token: Some(token),
+            refresh_token: Some(refresh_token),
             user: Some(AuthUser {
                 id: user.id,
                 email: Some(user.email),
                 is_anonymous: false,
                 created_at: user.created_at.unwrap_or_else(Utc::now),
+                subscription_tier: user.subscription_tier,
             }),
             error: None,
         })
@@ -477,20 +967,36 @@ impl AuthService {
     /// * `token` - JWT token to validate
     /// 
     /// # Returns  
-    /// Option containing (user_id, email) if token is valid and user is active
+    /// The user if the token is valid and the account is active. Guest
+    /// sessions have no account record, so their token alone suffices.
     /// 
     /// # Security
     /// - Validates token signature and expiration
     /// - Confirms user account still exists and is active
     /// - Supports both local JWT and Clerk tokens (future)
-    #[allow(dead_code)]
-    pub async fn get_user_from_token(&self, token: &str) -> Option<(String, String)> {
+    pub async fn get_user_from_token(&self, token: &str) -> Option<AuthUser> {
         // First try local JWT token validation
-        if let Some((user_id, email)) = self.verify_jwt(token) {
+        if let Some(claims) = self.verify_jwt_claims(token) {
+            if is_guest_user_id(&claims.user_id) {
+                return Some(AuthUser {
+                    id: claims.user_id,
+                    email: Some(claims.email),
+                    is_anonymous: true,
+                    created_at: DateTime::from_timestamp(claims.iat, 0).unwrap_or_else(Utc::now),
+                    subscription_tier: GUEST_TIER.to_string(),
+                });
+            }
+            
             // Verify user still exists and is active in database
-            if let Ok(Some(user)) = self.convex_service.get_user(&email).await {
+            if let Ok(Some(user)) = self.convex_service.get_user(&claims.email).await {
                 if user.is_active {
-                    return Some((user_id, email));
+                    return Some(AuthUser {
+                        id: claims.user_id,
+                        email: Some(claims.email),
+                        is_anonymous: false,
+                        created_at: user.created_at.unwrap_or_else(Utc::now),
+                        subscription_tier: user.subscription_tier,
+                    });
                 }
             }
         }
@@ -546,11 +1052,13 @@ impl AuthService {
         Ok(AuthResult {
             success: true,
             token: Some(token),
+            refresh_token: None,
             user: Some(AuthUser {
                 id: guest_id,
                 email: Some(guest_email),
                 is_anonymous: true,
                 created_at: Utc::now(),
+                subscription_tier: GUEST_TIER.to_string(),
             }),
             error: None,
         })
@@ -560,12 +1068,16 @@ impl AuthService {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::clock::MockClock;
     use crate::config::Config;
-    use crate::convex_service::ConvexService;
+    use crate::convex_service::{ConvexService, ConvexUser};
+    use crate::secret::Secret;
+    use std::sync::Arc;
+    use std::time::Duration;
     
     fn create_test_config() -> Config {
         let mut config = Config::from_env();
-        config.action_token_secret = Some("test_secret_key_1234567890".to_string());
+        config.action_token_secret = Some(Secret::from("test_secret_key_1234567890"));
         config
     }
     
@@ -575,6 +1087,46 @@ mod tests {
         AuthService::new(config, convex_service)
     }
     
+    #[tokio::test]
+    async fn test_blocking_retry_recovers_from_join_failure() {
+        use std::sync::atomic::{AtomicU32, Ordering};
+        
+        // A panicking task surfaces as a JoinError, like a cancelled one would
+        let calls = Arc::new(AtomicU32::new(0));
+        let counter = calls.clone();
+        let result = run_blocking_with_retry("Test", 1, move || {
+            if counter.fetch_add(1, Ordering::SeqCst) == 0 {
+                panic!("simulated blocking pool failure");
+            }
+            Ok(42)
+        })
+        .await;
+        assert_eq!(result.unwrap(), 42);
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+        
+        // Persistent join failures give up after the retry
+        let calls = Arc::new(AtomicU32::new(0));
+        let counter = calls.clone();
+        let result: Result<u32> = run_blocking_with_retry("Test", 1, move || {
+            counter.fetch_add(1, Ordering::SeqCst);
+            panic!("simulated blocking pool failure");
+        })
+        .await;
+        assert!(result.unwrap_err().to_string().contains("Test task failed after 2 attempts"));
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+        
+        // Errors from the work itself are not retried
+        let calls = Arc::new(AtomicU32::new(0));
+        let counter = calls.clone();
+        let result: Result<u32> = run_blocking_with_retry("Test", 1, move || {
+            counter.fetch_add(1, Ordering::SeqCst);
+            Err(anyhow!("bad hash"))
+        })
+        .await;
+        assert_eq!(result.unwrap_err().to_string(), "bad hash");
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+    
     #[test]
     fn test_hash_password() {
         let auth_service = create_test_auth_service();
@@ -596,6 +1148,43 @@ mod tests {
         });
     }
     
+    fn auth_service_with_algo(algo: PasswordHashAlgo) -> AuthService {
+        let mut config = create_test_config();
+        config.password_hash_algo = algo;
+        let convex_service = ConvexService::new(config.clone());
+        AuthService::new(config, convex_service)
+    }
+    
+    #[tokio::test]
+    async fn test_password_hash_round_trip_per_algorithm() {
+        for (algo, prefix) in [(PasswordHashAlgo::Bcrypt, "$2"), (PasswordHashAlgo::Argon2, "$argon2id$")] {
+            let auth_service = auth_service_with_algo(algo);
+            let hash = auth_service.hash_password("correct horse battery").await.unwrap();
+            assert!(hash.starts_with(prefix), "{:?}: {}", algo, hash);
+            
+            assert!(auth_service.verify_password("correct horse battery", &hash).await.unwrap());
+            assert!(!auth_service.verify_password("wrong password", &hash).await.unwrap());
+        }
+    }
+    
+    #[tokio::test]
+    async fn test_verify_password_detects_stored_hash_format() {
+        let bcrypt_service = auth_service_with_algo(PasswordHashAlgo::Bcrypt);
+        let argon2_service = auth_service_with_algo(PasswordHashAlgo::Argon2);
+        let bcrypt_hash = bcrypt_service.hash_password("correct horse battery").await.unwrap();
+        let argon2_hash = argon2_service.hash_password("correct horse battery").await.unwrap();
+        
+        // Existing bcrypt users keep working after switching to argon2, and back
+        assert!(argon2_service.verify_password("correct horse battery", &bcrypt_hash).await.unwrap());
+        assert!(!argon2_service.verify_password("wrong password", &bcrypt_hash).await.unwrap());
+        assert!(bcrypt_service.verify_password("correct horse battery", &argon2_hash).await.unwrap());
+        assert!(!bcrypt_service.verify_password("wrong password", &argon2_hash).await.unwrap());
+        
+        // A malformed stored hash never verifies
+        let malformed = argon2_service.verify_password("correct horse battery", "$argon2id$garbage").await;
+        assert!(!malformed.unwrap_or(false));
+    }
+    
     #[test]
     fn test_generate_jwt() {
         let auth_service = create_test_auth_service();
@@ -722,6 +1311,230 @@ mod tests {
         assert!(auth_result.error.is_some());
     }
     
+    #[tokio::test]
+    async fn test_login_with_seeded_user() {
+        let config = create_test_config();
+        let password_hash = bcrypt::hash("correct horse battery", 4).unwrap();
+        let user = ConvexUser {
+            id: "user_seeded".to_string(),
+            email: "seeded@example.com".to_string(),
+            password_hash,
+            subscription_tier: "free".to_string(),
+            api_key: "ak_seeded".to_string(),
+            is_active: true,
+            created_at: None,
+        };
+        let convex_service = ConvexService::with_seed_users(config.clone(), vec![user]);
+        let auth_service = AuthService::new(config, convex_service);
+        
+        let result = auth_service.login(LoginRequest {
+            email: "seeded@example.com".to_string(),
+            password: "correct horse battery".to_string(),
+        }).await.unwrap();
+        
+        assert!(result.success);
+        assert_eq!(result.user.unwrap().id, "user_seeded");
+        let token = result.token.unwrap();
+        assert_eq!(
+            auth_service.verify_jwt(&token),
+            Some(("user_seeded".to_string(), "seeded@example.com".to_string()))
+        );
+        
+        // Wrong password is still rejected
+        let result = auth_service.login(LoginRequest {
+            email: "seeded@example.com".to_string(),
+            password: "wrong password".to_string(),
+        }).await.unwrap();
+        assert!(!result.success);
+    }
+    
+    #[tokio::test]
+    async fn test_get_user_from_token() {
+        let config = create_test_config();
+        let user = ConvexUser {
+            id: "user_seeded".to_string(),
+            email: "seeded@example.com".to_string(),
+            password_hash: String::new(),
+            subscription_tier: "free".to_string(),
+            api_key: "ak_seeded".to_string(),
+            is_active: true,
+            created_at: None,
+        };
+        let convex_service = ConvexService::with_seed_users(config.clone(), vec![user]);
+        let auth_service = AuthService::new(config, convex_service);
+        
+        let token = auth_service.generate_jwt("user_seeded", "seeded@example.com").unwrap();
+        let user = auth_service.get_user_from_token(&token).await.unwrap();
+        assert_eq!(user.id, "user_seeded");
+        assert!(!user.is_anonymous);
+        
+        // Guests have no account record but are still recognized
+        let guest = auth_service.create_guest_user().await.unwrap().token.unwrap();
+        assert!(auth_service.get_user_from_token(&guest).await.unwrap().is_anonymous);
+        
+        // Valid tokens for unknown accounts are not
+        let token = auth_service.generate_jwt("user_gone", "gone@example.com").unwrap();
+        assert!(auth_service.get_user_from_token(&token).await.is_none());
+        assert!(auth_service.get_user_from_token("not-a-token").await.is_none());
+    }
+    
+    #[tokio::test]
+    async fn test_refresh_session() {
+        let config = create_test_config();
+        let password_hash = bcrypt::hash("correct horse battery", 4).unwrap();
+        let user = |id: &str, email: &str, is_active| ConvexUser {
+            id: id.to_string(),
+            email: email.to_string(),
+            password_hash: password_hash.clone(),
+            subscription_tier: "free".to_string(),
+            api_key: format!("ak_{}", id),
+            is_active,
+            created_at: None,
+        };
+        let convex_service = ConvexService::with_seed_users(
+            config.clone(),
+            vec![
+                user("user_active", "active@example.com", true),
+                user("user_inactive", "inactive@example.com", false),
+            ],
+        );
+        let auth_service = AuthService::new(config, convex_service);
+        
+        let login = auth_service.login(LoginRequest {
+            email: "active@example.com".to_string(),
+            password: "correct horse battery".to_string(),
+        }).await.unwrap();
+        let refresh_token = login.refresh_token.unwrap();
+        
+        // Refresh tokens are not session tokens
+        assert!(auth_service.verify_jwt(&refresh_token).is_none());
+        
+        let result = auth_service.refresh_session(&refresh_token).await.unwrap();
+        assert!(result.success);
+        assert!(result.refresh_token.is_none());
+        assert_eq!(
+            auth_service.verify_jwt(&result.token.unwrap()),
+            Some(("user_active".to_string(), "active@example.com".to_string()))
+        );
+        
+        // Session tokens are not refresh tokens
+        let session = login.token.unwrap();
+        assert!(!auth_service.refresh_session(&session).await.unwrap().success);
+        
+        // Revoking the session the refresh token was issued with disables it
+        let jti = auth_service.verify_jwt_claims(&session).unwrap().jti;
+        assert_eq!(auth_service.decode_claims(&refresh_token).unwrap().sid, jti);
+        assert!(auth_service.revoke_session("user_active", &jti));
+        assert!(!auth_service.refresh_session(&refresh_token).await.unwrap().success);
+        
+        // Deactivated accounts cannot refresh
+        let (_, inactive) = auth_service.generate_session_tokens("user_inactive", "inactive@example.com").unwrap();
+        let result = auth_service.refresh_session(&inactive).await.unwrap();
+        assert!(!result.success);
+        assert!(result.token.is_none());
+    }
+    
+    #[tokio::test]
+    async fn test_password_reset() {
+        let config = create_test_config();
+        let convex_service = ConvexService::with_seed_users(
+            config.clone(),
+            vec![ConvexUser {
+                id: "user_1".to_string(),
+                email: "user@example.com".to_string(),
+                password_hash: bcrypt::hash("old password", 4).unwrap(),
+                subscription_tier: "free".to_string(),
+                api_key: "ak_user_1".to_string(),
+                is_active: true,
+                created_at: None,
+            }],
+        );
+        let auth_service = AuthService::new(config, convex_service);
+        let (old_session, old_refresh) = auth_service.generate_session_tokens("user_1", "user@example.com").unwrap();
+        
+        assert!(auth_service.request_password_reset("nobody@example.com").await.is_err());
+        let reset_token = auth_service.request_password_reset("user@example.com").await.unwrap();
+        
+        // Reset tokens are not session or refresh tokens
+        assert!(auth_service.verify_jwt(&reset_token).is_none());
+        assert!(auth_service.get_user_from_token(&reset_token).await.is_none());
+        assert!(!auth_service.refresh_session(&reset_token).await.unwrap().success);
+        // ...and session tokens cannot reset passwords
+        assert!(!auth_service.reset_password(&old_session, "new password").await.unwrap().success);
+        assert!(auth_service.reset_password(&reset_token, "short").await.is_err());
+        
+        let result = auth_service.reset_password(&reset_token, "new password").await.unwrap();
+        assert!(result.success);
+        assert!(auth_service.verify_jwt(&result.token.unwrap()).is_some());
+        assert!(auth_service.verify_jwt(&old_session).is_none());
+        
+        // Refresh tokens issued before the reset are rejected, new ones work
+        assert!(!auth_service.refresh_session(&old_refresh).await.unwrap().success);
+        let new_refresh = result.refresh_token.unwrap();
+        assert!(auth_service.refresh_session(&new_refresh).await.unwrap().success);
+        
+        let login = |password: &str| LoginRequest {
+            email: "user@example.com".to_string(),
+            password: password.to_string(),
+        };
+        assert!(!auth_service.login(login("old password")).await.unwrap().success);
+        assert!(auth_service.login(login("new password")).await.unwrap().success);
+        
+        // Reset tokens are single-use
+        assert!(!auth_service.reset_password(&reset_token, "another password").await.unwrap().success);
+    }
+    
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_concurrent_password_resets_with_one_token() {
+        let auth_service = create_test_auth_service();
+        auth_service
+            .create_user(CreateUserRequest {
+                email: "reset@example.com".to_string(),
+                password: "old password".to_string(),
+                subscription_tier: None,
+            })
+            .await
+            .unwrap();
+        let reset_token = auth_service.request_password_reset("reset@example.com").await.unwrap();
+        let confirm = |password: &str| {
+            let auth_service = auth_service.clone();
+            let (reset_token, password) = (reset_token.clone(), password.to_string());
+            tokio::spawn(async move { auth_service.reset_password(&reset_token, &password).await.unwrap() })
+        };
+        
+        let (first, second) = tokio::join!(confirm("first password"), confirm("second password"));
+        let successes = [first.unwrap(), second.unwrap()]
+            .iter()
+            .filter(|result| result.success)
+            .count();
+        assert_eq!(successes, 1);
+    }
+    
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_concurrent_registrations_for_one_email() {
+        let auth_service = create_test_auth_service();
+        let register = |password: &str| {
+            let auth_service = auth_service.clone();
+            let request = CreateUserRequest {
+                email: "race@example.com".to_string(),
+                password: password.to_string(),
+                subscription_tier: None,
+            };
+            tokio::spawn(async move { auth_service.create_user(request).await.unwrap() })
+        };
+        
+        let (first, second) = tokio::join!(register("first password"), register("second password"));
+        let results = [first.unwrap(), second.unwrap()];
+        assert_eq!(results.iter().filter(|result| result.success).count(), 1);
+        let loser = results.iter().find(|result| !result.success).unwrap();
+        assert_eq!(loser.error.as_deref(), Some("User with this email already exists"));
+        
+        // The winner's password is the one stored
+        let winner = results.iter().find(|result| result.success).unwrap();
+        let user = auth_service.convex_service.get_user("race@example.com").await.unwrap().unwrap();
+        assert_eq!(Some(user.id), winner.user.as_ref().map(|user| user.id.clone()));
+    }
+    
     #[test]
     fn test_jwt_expiration() {
         let auth_service = create_test_auth_service();
@@ -733,9 +1546,53 @@ mod tests {
         
         // Token should be valid immediately after generation
         assert!(auth_service.verify_jwt(&token).is_some());
+    }
+    
+    #[test]
+    fn test_jwt_expires_with_mock_clock() {
+        let clock = Arc::new(MockClock::new(1_640_995_200_000));
+        let auth_service = create_test_auth_service().with_clock(clock.clone());
+        
+        let token = auth_service.generate_jwt("test_user", "test@example.com").unwrap();
         
-        // Note: Testing actual expiration would require manipulating system time
-        // or creating tokens with very short expiration, which is complex for unit tests
+        // Still valid just before expiry
+        clock.advance(Duration::from_secs(auth_service.config.jwt_ttl_seconds as u64 - 1));
+        assert!(auth_service.verify_jwt(&token).is_some());
+        
+        // Rejected once expiry and leeway have passed
+        clock.advance(Duration::from_secs(JWT_LEEWAY_SECS as u64 + 1));
+        assert!(auth_service.verify_jwt(&token).is_none());
+    }
+    
+    #[test]
+    fn test_jwt_ttl_is_configurable() {
+        let mut config = create_test_config();
+        config.jwt_ttl_seconds = 90;
+        let auth_service = AuthService::new(config.clone(), ConvexService::new(config));
+        
+        let token = auth_service.generate_jwt("test_user", "test@example.com").unwrap();
+        let claims = auth_service.verify_jwt_claims(&token).unwrap();
+        assert_eq!(claims.exp, claims.iat + 90);
+    }
+    
+    #[test]
+    fn test_expired_sessions_are_pruned() {
+        let clock = Arc::new(MockClock::new(1_640_995_200_000));
+        let auth_service = create_test_auth_service().with_clock(clock.clone());
+        
+        let token = auth_service.generate_jwt("test_user", "test@example.com").unwrap();
+        let jti = auth_service.verify_jwt_claims(&token).unwrap().jti;
+        assert_eq!(auth_service.list_sessions("test_user")[0].jti, jti);
+        assert!(auth_service.revoke_session("test_user", &jti));
+        assert!(auth_service.verify_jwt(&token).is_none());
+        
+        // Revocations are forgotten once the token would have expired anyway
+        auth_service.generate_jwt("test_user", "test@example.com").unwrap();
+        clock.advance(Duration::from_secs((auth_service.config.jwt_ttl_seconds + JWT_LEEWAY_SECS) as u64));
+        assert!(auth_service.list_sessions("test_user").is_empty());
+        let registry = auth_service.sessions.lock().unwrap();
+        assert!(registry.active.is_empty());
+        assert!(registry.revoked.is_empty());
     }
     
     #[test]
@@ -752,4 +1609,30 @@ mod tests {
         // Verification should also fail gracefully
         assert!(auth_service.verify_jwt("any.token").is_none());
     }
-}
\ No newline at end of file
+
+    fn authorization(value: &'static str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(AUTHORIZATION, axum::http::HeaderValue::from_static(value));
+        headers
+    }
+
+    #[test]
+    fn test_extract_bearer() {
+        assert_eq!(extract_bearer(&authorization("Bearer abc.def")), Some("abc.def"));
+        assert_eq!(extract_bearer(&authorization("bearer abc.def")), Some("abc.def"));
+        assert_eq!(extract_bearer(&authorization("BEARER   abc.def  ")), Some("abc.def"));
+        assert_eq!(extract_bearer(&HeaderMap::new()), None);
+    }
+
+    #[test]
+    fn test_extract_bearer_rejects_malformed_values() {
+        assert_eq!(extract_bearer(&authorization("Bearer")), None);
+        assert_eq!(extract_bearer(&authorization("Bearer    ")), None);
+        assert_eq!(extract_bearer(&authorization("Bearerabc")), None);
+        assert_eq!(extract_bearer(&authorization("Basic dXNlcjpwYXNz")), None);
+
+        let mut headers = HeaderMap::new();
+        headers.insert(AUTHORIZATION, axum::http::HeaderValue::from_bytes(b"Bearer \xff").unwrap());
+        assert_eq!(extract_bearer(&headers), None);
+    }
+}
@@ -0,0 +1,189 @@
+//! Backoff Module
+//!
+//! Jittered exponential backoff shared by every retry site (search and
+//! provider HTTP calls), so retry timing is tuned in one place.
+//!
+//! ```rust,ignore
+//! let policy = RetryPolicy::default();
+//! let body = retry(&policy, || fetch(url), |e| e.is_timeout()).await?;
+//! ```
+
+use rand::Rng;
+use std::future::Future;
+use std::time::Duration;
+
+/// How an operation is retried
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// Total attempts, including the first (values below 1 are treated as 1)
+    pub max_attempts: u32,
+    /// Delay before the first retry, doubled for each further retry
+    pub base_delay: Duration,
+    /// Upper bound for any single delay
+    pub max_delay: Duration,
+    /// Whether delays are randomized so concurrent callers spread out
+    pub jitter: bool,
+}
+
+impl Default for RetryPolicy {
+    /// Three attempts starting at 100ms, suited to upstream HTTP calls
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            base_delay: Duration::from_millis(100),
+            max_delay: Duration::from_secs(2),
+            jitter: true,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Delay before retry number `retry` (0 for the first retry)
+    ///
+    /// Without jitter this is `base_delay * 2^retry`, capped at `max_delay`.
+    /// With jitter a random delay between half of that and all of it is used.
+    pub fn delay(&self, retry: u32) -> Duration {
+        let factor = 2u32.checked_pow(retry).unwrap_or(u32::MAX);
+        let delay = self.base_delay.saturating_mul(factor).min(self.max_delay);
+        if self.jitter && !delay.is_zero() {
+            rand::thread_rng().gen_range(delay / 2..=delay)
+        } else {
+            delay
+        }
+    }
+}
+
+/// Run `op` until it succeeds, fails with a non-retryable error, or the
+/// policy's attempts are used up
+///
+/// # Arguments
+/// * `policy` - Attempt count and delays
+/// * `op` - Operation to run; called once per attempt
+/// * `is_retryable` - Whether an error is worth another attempt
+///
+/// # Errors
+/// The error from the last attempt made
+pub async fn retry<T, E, F, Fut, P>(policy: &RetryPolicy, mut op: F, is_retryable: P) -> Result<T, E>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, E>>,
+    P: Fn(&E) -> bool,
+{
+    let max_attempts = policy.max_attempts.max(1);
+    let mut attempt = 1;
+    loop {
+        match op().await {
+            Ok(value) => return Ok(value),
+            Err(error) if attempt < max_attempts && is_retryable(&error) => {
+                let delay = policy.delay(attempt - 1);
+                tracing::debug!("Attempt {} of {} failed, retrying in {:?}", attempt, max_attempts, delay);
+                tokio::time::sleep(delay).await;
+                attempt += 1;
+            }
+            Err(error) => return Err(error),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use tokio::time::Instant;
+
+    fn policy(max_attempts: u32) -> RetryPolicy {
+        RetryPolicy {
+            max_attempts,
+            base_delay: Duration::from_millis(10),
+            max_delay: Duration::from_millis(40),
+            jitter: false,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_attempts_are_bounded() {
+        let attempts = AtomicU32::new(0);
+        let result: Result<(), &str> = retry(
+            &policy(4),
+            || async {
+                attempts.fetch_add(1, Ordering::SeqCst);
+                Err("unavailable")
+            },
+            |_| true,
+        )
+        .await;
+        assert_eq!(result, Err("unavailable"));
+        assert_eq!(attempts.load(Ordering::SeqCst), 4);
+    }
+
+    #[tokio::test]
+    async fn test_success_after_retries_stops_early() {
+        let attempts = AtomicU32::new(0);
+        let result = retry(
+            &policy(5),
+            || async {
+                match attempts.fetch_add(1, Ordering::SeqCst) {
+                    0 | 1 => Err("unavailable"),
+                    n => Ok(n),
+                }
+            },
+            |_| true,
+        )
+        .await;
+        assert_eq!(result, Ok(2));
+        assert_eq!(attempts.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn test_non_retryable_error_stops_immediately() {
+        let attempts = AtomicU32::new(0);
+        let result: Result<(), &str> = retry(
+            &policy(5),
+            || async {
+                attempts.fetch_add(1, Ordering::SeqCst);
+                Err("bad request")
+            },
+            |error| *error == "unavailable",
+        )
+        .await;
+        assert_eq!(result, Err("bad request"));
+        assert_eq!(attempts.load(Ordering::SeqCst), 1);
+
+        // Zero attempts still runs the operation once
+        attempts.store(0, Ordering::SeqCst);
+        let once = || async {
+            attempts.fetch_add(1, Ordering::SeqCst);
+            Err::<(), _>(())
+        };
+        let _ = retry(&policy(0), once, |_| true).await;
+        assert_eq!(attempts.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_delay_doubles_up_to_max_without_jitter() {
+        let policy = policy(10);
+        let delays: Vec<u128> = (0..5).map(|retry| policy.delay(retry).as_millis()).collect();
+        assert_eq!(delays, [10, 20, 40, 40, 40]);
+        assert_eq!(policy.delay(u32::MAX), Duration::from_millis(40));
+    }
+
+    #[test]
+    fn test_jittered_delay_stays_within_bounds() {
+        let policy = RetryPolicy { jitter: true, ..policy(10) };
+        for retry in 0..4 {
+            let ceiling = Duration::from_millis(10 * 2u64.pow(retry)).min(policy.max_delay);
+            for _ in 0..50 {
+                let delay = policy.delay(retry);
+                assert!(delay >= ceiling / 2 && delay <= ceiling, "{:?}", delay);
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_retries_wait_between_attempts() {
+        let started = Instant::now();
+        let _: Result<(), ()> = retry(&policy(3), || async { Err(()) }, |_| true).await;
+        // 10ms + 20ms of backoff
+        assert!(started.elapsed() >= Duration::from_millis(30), "{:?}", started.elapsed());
+    }
+}
@@ -0,0 +1,204 @@
+//! Callback Module
+//!
+//! Delivery of invoke results to a client-supplied `callback_url`, for long
+//! batch jobs where the caller does not hold the connection open.
+//!
+//! Callback URLs are attacker-controlled, so they are validated against
+//! SSRF rules before anything is sent: only `http`/`https`, and every
+//! address the host resolves to must be publicly routable (unless
+//! `CALLBACK_ALLOW_PRIVATE_NETWORKS` is enabled). The checked address is
+//! pinned for delivery and redirects are not followed, so DNS changes or
+//! a redirect cannot point the request back inside the network.
+
+use reqwest::redirect::Policy;
+use reqwest::Url;
+use serde_json::Value;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::time::Duration;
+
+use crate::config::Config;
+use crate::http_client::{client_builder, send_with_retry};
+
+/// Timeout for each callback delivery attempt
+const CALLBACK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Validated destination for a callback
+#[derive(Debug, Clone)]
+pub struct CallbackTarget {
+    /// Callback URL as supplied by the client
+    pub url: Url,
+    /// Domain and the checked address it must be reached at
+    pinned: Option<(String, SocketAddr)>,
+}
+
+/// Whether an address is publicly routable
+///
+/// Rejects loopback, private, link-local, carrier-grade NAT, unspecified,
+/// broadcast, documentation and multicast ranges, including IPv4 addresses
+/// mapped into IPv6.
+pub fn is_public_ip(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => is_public_ipv4(ip),
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(mapped) => is_public_ipv4(mapped),
+            None => is_public_ipv6(ip),
+        },
+    }
+}
+
+fn is_public_ipv4(ip: Ipv4Addr) -> bool {
+    let [a, b, ..] = ip.octets();
+    let shared = a == 100 && (64..128).contains(&b);
+    !(ip.is_private()
+        || ip.is_loopback()
+        || ip.is_link_local()
+        || ip.is_unspecified()
+        || ip.is_broadcast()
+        || ip.is_documentation()
+        || ip.is_multicast()
+        || shared
+        || a == 0)
+}
+
+fn is_public_ipv6(ip: Ipv6Addr) -> bool {
+    let first = ip.segments()[0];
+    let unique_local = first & 0xfe00 == 0xfc00;
+    let link_local = first & 0xffc0 == 0xfe80;
+    !(ip.is_loopback() || ip.is_unspecified() || ip.is_multicast() || unique_local || link_local)
+}
+
+/// Validate a client-supplied callback URL
+///
+/// # Arguments
+/// * `url` - URL from the request
+/// * `allow_private` - Whether non-public addresses are accepted
+///
+/// # Errors
+/// A client-facing message when the URL is malformed, uses another scheme,
+/// cannot be resolved or resolves to a non-public address
+pub async fn validate_callback_url(url: &str, allow_private: bool) -> Result<CallbackTarget, String> {
+    let url = Url::parse(url).map_err(|_| "callback_url is not a valid URL".to_string())?;
+    if !matches!(url.scheme(), "http" | "https") {
+        return Err("callback_url must use http or https".to_string());
+    }
+    let port = url
+        .port_or_known_default()
+        .ok_or_else(|| "callback_url has no port".to_string())?;
+
+    let host = url
+        .host_str()
+        .ok_or_else(|| "callback_url has no host".to_string())?;
+    let addrs = resolve_host(host, port)
+        .await
+        .map_err(|_| "callback_url host could not be resolved".to_string())?;
+
+    if addrs.is_empty() {
+        return Err("callback_url host could not be resolved".to_string());
+    }
+    if !allow_private && !addrs.iter().all(|addr| is_public_ip(addr.ip())) {
+        return Err("callback_url must point to a public address".to_string());
+    }
+
+    Ok(CallbackTarget {
+        pinned: url.domain().map(|domain| (domain.to_string(), addrs[0])),
+        url,
+    })
+}
+
+/// Addresses `host` resolves to on `port`
+///
+/// IP literals (IPv6 ones keep their brackets in URLs) are returned
+/// without a lookup.
+///
+/// # Errors
+/// When the name cannot be resolved
+pub async fn resolve_host(host: &str, port: u16) -> std::io::Result<Vec<SocketAddr>> {
+    match host.trim_start_matches('[').trim_end_matches(']').parse::<IpAddr>() {
+        Ok(ip) => Ok(vec![SocketAddr::new(ip, port)]),
+        Err(_) => Ok(tokio::net::lookup_host((host, port)).await?.collect()),
+    }
+}
+
+/// POST a result to a validated callback
+///
+/// Retryable upstream statuses are retried like other outbound calls.
+///
+/// # Returns
+/// Whether the callback answered with a success status
+pub async fn deliver_callback(config: &Config, target: &CallbackTarget, body: &Value) -> bool {
+    let mut builder = client_builder(config)
+        .redirect(Policy::none())
+        .timeout(CALLBACK_TIMEOUT);
+    if let Some((domain, addr)) = &target.pinned {
+        builder = builder.resolve(domain, *addr);
+    }
+    let client = match builder.build() {
+        Ok(client) => client,
+        Err(error) => {
+            tracing::error!("Failed to create callback client: {}", error);
+            return false;
+        }
+    };
+
+    let request = client.post(target.url.clone()).json(body);
+    match send_with_retry(request, &config.retryable_status_codes).await {
+        Ok(response) if response.status().is_success() => true,
+        Ok(response) => {
+            tracing::warn!("Callback to {} failed with status {}", target.url, response.status());
+            false
+        }
+        Err(error) => {
+            tracing::warn!("Callback to {} failed: {}", target.url, error);
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_non_public_addresses_are_rejected() {
+        for ip in [
+            "127.0.0.1",
+            "10.1.2.3",
+            "172.16.0.1",
+            "192.168.1.1",
+            "169.254.169.254",
+            "100.64.0.1",
+            "0.0.0.0",
+            "::1",
+            "fd00::1",
+            "fe80::1",
+            "::ffff:127.0.0.1",
+        ] {
+            assert!(!is_public_ip(ip.parse().unwrap()), "{}", ip);
+        }
+        for ip in ["8.8.8.8", "1.1.1.1", "2606:4700:4700::1111"] {
+            assert!(is_public_ip(ip.parse().unwrap()), "{}", ip);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_callback_url_validation() {
+        let target = validate_callback_url("https://8.8.8.8/hooks/result", false).await.unwrap();
+        assert_eq!(target.url.path(), "/hooks/result");
+        assert!(target.pinned.is_none());
+
+        for url in [
+            "ftp://8.8.8.8/result",
+            "not a url",
+            "http://127.0.0.1:9000/result",
+            "http://[::1]/result",
+            "http://169.254.169.254/latest/meta-data",
+            "http://localhost/result",
+        ] {
+            assert!(validate_callback_url(url, false).await.is_err(), "{}", url);
+        }
+
+        // Private addresses are allowed when explicitly enabled
+        let target = validate_callback_url("http://localhost:9000/result", true).await.unwrap();
+        assert!(target.pinned.is_some());
+    }
+}
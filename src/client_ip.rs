@@ -0,0 +1,243 @@
+//! Client IP Module
+//!
+//! Resolves the address of the client behind a request for rate limiting,
+//! logging and similar decisions.
+//!
+//! The socket peer address is used unless that peer is a trusted proxy
+//! (`TRUSTED_PROXIES`), in which case `X-Forwarded-For` is walked from the
+//! right and the first hop that is not itself a trusted proxy is the client.
+//! Headers from untrusted peers are ignored, so clients cannot spoof their
+//! address by sending `X-Forwarded-For` directly.
+//!
+//! Handlers take the resolved address with the `ClientIp` extractor. The
+//! server must be started with `into_make_service_with_connect_info` so the
+//! peer address is available.
+
+use axum::async_trait;
+use axum::extract::{ConnectInfo, FromRef, FromRequestParts};
+use axum::http::request::Parts;
+use axum::http::{HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use serde_json::Value;
+use std::net::{IpAddr, SocketAddr};
+
+use crate::types::ApiResponse;
+
+/// Header set by reverse proxies with the chain of client addresses
+const X_FORWARDED_FOR: &str = "x-forwarded-for";
+
+/// Proxy address or CIDR range whose forwarding headers are trusted
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct ProxyRange {
+    network: IpAddr,
+    prefix_len: u8,
+}
+
+impl ProxyRange {
+    /// Parse `10.0.0.1`, `10.0.0.0/8` or `fd00::/8`
+    fn parse(value: &str) -> Option<Self> {
+        let (address, prefix_len) = match value.split_once('/') {
+            Some((address, prefix)) => (address, Some(prefix.parse::<u8>().ok()?)),
+            None => (value, None),
+        };
+        let network: IpAddr = address.trim().parse().ok()?;
+        let max_len = if network.is_ipv4() { 32 } else { 128 };
+        let prefix_len = prefix_len.unwrap_or(max_len);
+        (prefix_len <= max_len).then_some(Self { network, prefix_len })
+    }
+
+    fn contains(&self, ip: IpAddr) -> bool {
+        match (self.network, ip.to_canonical()) {
+            (IpAddr::V4(network), IpAddr::V4(ip)) => {
+                let mask = u32::MAX.checked_shl(32 - self.prefix_len as u32).unwrap_or(0);
+                u32::from(network) & mask == u32::from(ip) & mask
+            }
+            (IpAddr::V6(network), IpAddr::V6(ip)) => {
+                let mask = u128::MAX.checked_shl(128 - self.prefix_len as u32).unwrap_or(0);
+                u128::from(network) & mask == u128::from(ip) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Set of proxies allowed to report the client address
+#[derive(Debug, Clone, Default)]
+pub struct TrustedProxies {
+    ranges: Vec<ProxyRange>,
+}
+
+impl TrustedProxies {
+    /// Build from configured addresses and CIDR ranges
+    ///
+    /// Invalid entries are logged and skipped.
+    pub fn new(entries: &[String]) -> Self {
+        let ranges = entries
+            .iter()
+            .filter_map(|entry| {
+                let range = ProxyRange::parse(entry);
+                if range.is_none() {
+                    tracing::warn!("Ignoring invalid trusted proxy '{}'", entry);
+                }
+                range
+            })
+            .collect();
+        Self { ranges }
+    }
+
+    /// Whether `ip` belongs to a trusted proxy
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        self.ranges.iter().any(|range| range.contains(ip))
+    }
+}
+
+/// Resolve the client address from the socket peer and forwarding headers
+///
+/// # Arguments
+/// * `peer` - Address of the socket peer, if known
+/// * `headers` - Request headers
+/// * `trusted` - Proxies whose `X-Forwarded-For` is believed
+///
+/// # Returns
+/// The client address, or `None` when the peer address is unknown
+pub fn resolve_client_ip(peer: Option<IpAddr>, headers: &HeaderMap, trusted: &TrustedProxies) -> Option<IpAddr> {
+    let peer = peer?.to_canonical();
+    if !trusted.contains(peer) {
+        return Some(peer);
+    }
+
+    // Each proxy appends the address it received from, so the rightmost
+    // untrusted hop is the furthest address we can vouch for
+    let mut client = peer;
+    for value in headers.get_all(X_FORWARDED_FOR).iter().rev() {
+        let Ok(value) = value.to_str() else { break };
+        for hop in value.rsplit(',') {
+            let Ok(ip) = hop.trim().parse::<IpAddr>() else {
+                return Some(client);
+            };
+            client = ip.to_canonical();
+            if !trusted.contains(client) {
+                return Some(client);
+            }
+        }
+    }
+    Some(client)
+}
+
+/// Extractor for the resolved client IP address
+///
+/// ```rust,ignore
+/// async fn handler(ClientIp(ip): ClientIp) { /* ... */ }
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ClientIp(pub IpAddr);
+
+#[async_trait]
+impl<S> FromRequestParts<S> for ClientIp
+where
+    TrustedProxies: FromRef<S>,
+    S: Send + Sync,
+{
+    type Rejection = Response;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let peer = parts
+            .extensions
+            .get::<ConnectInfo<SocketAddr>>()
+            .map(|ConnectInfo(addr)| addr.ip());
+        let trusted = TrustedProxies::from_ref(state);
+
+        resolve_client_ip(peer, &parts.headers, &trusted).map(ClientIp).ok_or_else(|| {
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ApiResponse::<Value>::error("Client address unavailable".to_string())),
+            )
+                .into_response()
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::{HeaderValue, Request};
+
+    fn trusted(entries: &[&str]) -> TrustedProxies {
+        TrustedProxies::new(&entries.iter().map(|entry| entry.to_string()).collect::<Vec<_>>())
+    }
+
+    fn ip(value: &str) -> IpAddr {
+        value.parse().unwrap()
+    }
+
+    /// Run the extractor against a request from `peer` with the given XFF header
+    async fn extract(peer: Option<&str>, forwarded_for: Option<&str>, proxies: &TrustedProxies) -> Option<IpAddr> {
+        let mut request = Request::builder().uri("/");
+        if let Some(value) = forwarded_for {
+            request = request.header(X_FORWARDED_FOR, value);
+        }
+        let (mut parts, _) = request.body(()).unwrap().into_parts();
+        if let Some(peer) = peer {
+            parts.extensions.insert(ConnectInfo(SocketAddr::new(ip(peer), 443)));
+        }
+        ClientIp::from_request_parts(&mut parts, proxies).await.ok().map(|ClientIp(ip)| ip)
+    }
+
+    #[tokio::test]
+    async fn test_extractor_uses_peer_without_forwarding_header() {
+        let proxies = trusted(&["10.0.0.0/8"]);
+        assert_eq!(extract(Some("203.0.113.7"), None, &proxies).await, Some(ip("203.0.113.7")));
+        assert_eq!(extract(Some("10.0.0.2"), None, &proxies).await, Some(ip("10.0.0.2")));
+    }
+
+    #[tokio::test]
+    async fn test_extractor_follows_forwarding_header_from_trusted_proxy() {
+        let proxies = trusted(&["10.0.0.0/8", "192.0.2.1"]);
+
+        // Nginx in front of us appended the client address
+        let client = extract(Some("10.0.0.2"), Some("198.51.100.4"), &proxies).await;
+        assert_eq!(client, Some(ip("198.51.100.4")));
+
+        // A client-supplied value to the left of the real hop is ignored
+        let client = extract(Some("10.0.0.2"), Some("1.2.3.4, 198.51.100.4, 192.0.2.1"), &proxies).await;
+        assert_eq!(client, Some(ip("198.51.100.4")));
+    }
+
+    #[tokio::test]
+    async fn test_extractor_ignores_forwarding_header_from_untrusted_peer() {
+        let proxies = trusted(&["10.0.0.0/8"]);
+        let client = extract(Some("203.0.113.7"), Some("1.2.3.4"), &proxies).await;
+        assert_eq!(client, Some(ip("203.0.113.7")));
+
+        // Nothing is trusted by default
+        let client = extract(Some("10.0.0.2"), Some("1.2.3.4"), &TrustedProxies::default()).await;
+        assert_eq!(client, Some(ip("10.0.0.2")));
+    }
+
+    #[tokio::test]
+    async fn test_extractor_rejects_without_peer_address() {
+        let proxies = trusted(&["10.0.0.0/8"]);
+        assert_eq!(extract(None, Some("1.2.3.4"), &proxies).await, None);
+    }
+
+    #[test]
+    fn test_resolve_stops_at_malformed_hop() {
+        let proxies = trusted(&["10.0.0.0/8"]);
+        let mut headers = HeaderMap::new();
+        headers.insert(X_FORWARDED_FOR, HeaderValue::from_static("garbage, 10.0.0.9"));
+        assert_eq!(resolve_client_ip(Some(ip("10.0.0.2")), &headers, &proxies), Some(ip("10.0.0.9")));
+    }
+
+    #[test]
+    fn test_trusted_proxy_ranges() {
+        let proxies = trusted(&["10.0.0.0/8", "::1", "fd00::/8", "not-an-ip", "10.0.0.0/33"]);
+        assert!(proxies.contains(ip("10.255.0.1")));
+        assert!(proxies.contains(ip("::1")));
+        assert!(proxies.contains(ip("fd12::1")));
+        assert!(proxies.contains(ip("::ffff:10.1.2.3")));
+        assert!(!proxies.contains(ip("11.0.0.1")));
+        assert!(!proxies.contains(ip("127.0.0.1")));
+        assert_eq!(proxies.ranges.len(), 3);
+    }
+}
@@ -0,0 +1,147 @@
+//! Clock Abstraction Module
+//!
+//! Time-dependent behavior (JWT expiry, daily limit resets, cache TTLs)
+//! reads the current time through the `Clock` trait instead of calling
+//! the system clock directly, so tests can control time deterministically.
+//!
+//! - `SystemClock` reads the real wall clock and is used in production
+//! - `MockClock` holds a settable time for tests
+//!
+//! Daily windows (guest and per-user limits) roll over at midnight UTC;
+//! `next_utc_day_start_ms` computes that boundary.
+
+#[cfg(test)]
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+#[cfg(test)]
+use std::time::Duration;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Milliseconds in one day
+pub const DAY_MS: u64 = 24 * 60 * 60 * 1000;
+
+/// Start of the UTC day after `now_ms` (milliseconds since epoch)
+///
+/// Unix time has no leap seconds and UTC has no daylight saving, so every
+/// UTC day is exactly `DAY_MS` long and days start at multiples of it. A
+/// time exactly at midnight belongs to the day it starts, so the result is
+/// the following midnight. Times in the last day representable as `u64`
+/// clamp to `u64::MAX` instead of overflowing.
+pub fn next_utc_day_start_ms(now_ms: u64) -> u64 {
+    (now_ms / DAY_MS)
+        .checked_add(1)
+        .and_then(|days| days.checked_mul(DAY_MS))
+        .unwrap_or(u64::MAX)
+}
+
+/// Source of the current wall-clock time
+pub trait Clock: Send + Sync {
+    /// Current time in milliseconds since Unix epoch
+    fn now_ms(&self) -> u64;
+
+    /// Current time in whole seconds since Unix epoch
+    fn now_secs(&self) -> i64 {
+        (self.now_ms() / 1000) as i64
+    }
+}
+
+/// Clock shared between services
+pub type SharedClock = Arc<dyn Clock>;
+
+/// Clock backed by the system time
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now_ms(&self) -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_millis() as u64
+    }
+}
+
+/// Shared handle to the system clock
+pub fn system_clock() -> SharedClock {
+    Arc::new(SystemClock)
+}
+
+/// Manually controlled clock for tests
+#[cfg(test)]
+#[derive(Debug, Default)]
+pub struct MockClock {
+    now_ms: AtomicU64,
+}
+
+#[cfg(test)]
+impl MockClock {
+    /// Create a clock frozen at the given time (milliseconds since epoch)
+    pub fn new(now_ms: u64) -> Self {
+        Self {
+            now_ms: AtomicU64::new(now_ms),
+        }
+    }
+
+    /// Set the current time
+    pub fn set(&self, now_ms: u64) {
+        self.now_ms.store(now_ms, Ordering::SeqCst);
+    }
+
+    /// Move the current time forward
+    pub fn advance(&self, by: Duration) {
+        self.now_ms.fetch_add(by.as_millis() as u64, Ordering::SeqCst);
+    }
+}
+
+#[cfg(test)]
+impl Clock for MockClock {
+    fn now_ms(&self) -> u64 {
+        self.now_ms.load(Ordering::SeqCst)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mock_clock_set_and_advance() {
+        let clock = MockClock::new(1_000);
+        assert_eq!(clock.now_ms(), 1_000);
+        assert_eq!(clock.now_secs(), 1);
+
+        clock.advance(Duration::from_secs(2));
+        assert_eq!(clock.now_ms(), 3_000);
+
+        clock.set(42);
+        assert_eq!(clock.now_ms(), 42);
+    }
+
+    #[test]
+    fn test_next_utc_day_start_boundaries() {
+        let jan_1_2022 = 1_640_995_200_000; // 2022-01-01T00:00:00Z
+        let jan_2_2022 = jan_1_2022 + DAY_MS;
+
+        assert_eq!(next_utc_day_start_ms(0), DAY_MS);
+        assert_eq!(next_utc_day_start_ms(jan_1_2022), jan_2_2022);
+        assert_eq!(next_utc_day_start_ms(jan_1_2022 + 1), jan_2_2022);
+        // 23:59:59.999 on Jan 1 still resets at the coming midnight
+        assert_eq!(next_utc_day_start_ms(jan_2_2022 - 1), jan_2_2022);
+        assert_eq!(next_utc_day_start_ms(jan_2_2022), jan_2_2022 + DAY_MS);
+        // 2024-03-10T12:00:00Z, a US daylight saving change day, is still 24h
+        assert_eq!(next_utc_day_start_ms(1_710_072_000_000), 1_710_115_200_000);
+    }
+
+    #[test]
+    fn test_next_utc_day_start_clamps_near_u64_max() {
+        let last_midnight = (u64::MAX / DAY_MS) * DAY_MS;
+        assert_eq!(next_utc_day_start_ms(u64::MAX), u64::MAX);
+        assert_eq!(next_utc_day_start_ms(last_midnight), u64::MAX);
+        assert_eq!(next_utc_day_start_ms(last_midnight - 1), last_midnight);
+    }
+
+    #[test]
+    fn test_system_clock_is_after_2020() {
+        assert!(SystemClock.now_ms() > 1_577_836_800_000);
+    }
+}
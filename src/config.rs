@@ -10,8 +10,11 @@
 //! Configuration is loaded once at startup and shared across all services.
 
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::env;
 
+use crate::secret::Secret;
+
 /// Get environment variable value or fallback to default
 /// 
 /// This is the primary configuration loading function that safely handles
@@ -27,6 +30,58 @@ pub fn env_or(key: &str, fallback: &str) -> String {
     env::var(key).unwrap_or_else(|_| fallback.to_string())
 }
 
+/// Read an optional environment variable, treating blank values as unset
+/// 
+/// # Arguments
+/// * `key` - Environment variable name to read
+/// 
+/// # Returns
+/// Trimmed value if the variable is set and non-blank, None otherwise
+/// Routes used when neither `ROUTES_FILE` nor `ROUTES` is set
+pub const DEFAULT_ROUTES: &str = "chat.fast=openai:gpt-4o-mini";
+
+/// Read the routing configuration from `routes_file`, or `ROUTES` without one
+pub fn read_routes(routes_file: Option<&str>) -> std::io::Result<String> {
+    match routes_file {
+        Some(path) => Ok(std::fs::read_to_string(path)?.trim().to_string()),
+        None => Ok(env_or("ROUTES", DEFAULT_ROUTES)),
+    }
+}
+
+pub fn non_empty_env(key: &str) -> Option<String> {
+    env::var(key)
+        .ok()
+        .map(|value| value.trim().to_string())
+        .filter(|value| !value.is_empty())
+}
+
+/// Read a secret from `<KEY>_FILE` or `KEY`
+/// 
+/// Supports mounted secrets (Docker/Kubernetes): when `<KEY>_FILE` is set,
+/// the file's trimmed contents are used and take precedence over `KEY`.
+/// If the file cannot be read, an error is printed and the secret is
+/// treated as unset rather than silently falling back to `KEY`.
+/// 
+/// # Arguments
+/// * `key` - Environment variable name of the inline secret
+/// 
+/// # Returns
+/// Secret value if configured, None otherwise
+pub fn secret_env(key: &str) -> Option<String> {
+    let file_key = format!("{}_FILE", key);
+    match env::var(&file_key) {
+        Ok(path) => match std::fs::read_to_string(&path) {
+            Ok(contents) => Some(contents.trim().to_string()),
+            Err(e) => {
+                // Logging is not initialized yet when configuration loads
+                eprintln!("Failed to read {} from {}: {}", key, path, e);
+                None
+            }
+        },
+        Err(_) => env::var(key).ok(),
+    }
+}
+
 /// Parse boolean values from environment variables
 /// 
 /// Supports common boolean representations in environment variables.
@@ -79,6 +134,115 @@ pub fn parse_csv(value: Option<&str>) -> Vec<String> {
         .unwrap_or_default()
 }
 
+/// Parse named numeric limits from a comma-separated list
+/// 
+/// Each entry has the form `name:limit`, where the name is a
+/// subscription tier or provider id. Entries with a missing or
+/// non-numeric limit are skipped.
+/// 
+/// # Arguments
+/// * `value` - Optional string containing `name:limit` pairs
+/// 
+/// # Returns
+/// Map of name to limit
+/// 
+/// # Example
+/// ```rust
+/// use rust_ai::config::parse_tier_limits;
+/// let limits = parse_tier_limits(Some("free:100, premium:5000"));
+/// assert_eq!(limits.get("premium"), Some(&5000));
+/// ```
+pub fn parse_tier_limits(value: Option<&str>) -> HashMap<String, u32> {
+    parse_csv(value)
+        .into_iter()
+        .filter_map(|entry| {
+            let (tier, limit) = entry.split_once(':')?;
+            let tier = tier.trim();
+            if tier.is_empty() {
+                return None;
+            }
+            Some((tier.to_string(), limit.trim().parse().ok()?))
+        })
+        .collect()
+}
+
+/// Parse named text values from a comma-separated list
+/// 
+/// Each entry has the form `name:value`; only the first `:` separates,
+/// so values may contain colons. Entries with an empty name or value are
+/// skipped.
+/// 
+/// # Example
+/// ```rust
+/// use rust_ai::config::parse_named_values;
+/// let models = parse_named_values(Some("openai:gpt-4o-mini, groq:llama-3.1-8b-instant"));
+/// assert_eq!(models.get("groq").map(String::as_str), Some("llama-3.1-8b-instant"));
+/// ```
+pub fn parse_named_values(value: Option<&str>) -> HashMap<String, String> {
+    parse_csv(value)
+        .into_iter()
+        .filter_map(|entry| {
+            let (name, value) = entry.split_once(':')?;
+            let (name, value) = (name.trim(), value.trim());
+            if name.is_empty() || value.is_empty() {
+                return None;
+            }
+            Some((name.to_string(), value.to_string()))
+        })
+        .collect()
+}
+
+/// Parse HTTP status codes from a comma-separated list
+/// 
+/// Entries that are not valid status codes (100-599) are skipped.
+/// 
+/// # Arguments
+/// * `value` - Optional string containing status codes
+/// * `fallback` - Codes used when the value is unset or has no valid entries
+/// 
+/// # Returns
+/// Parsed status codes
+pub fn parse_status_codes(value: Option<&str>, fallback: &[u16]) -> Vec<u16> {
+    let codes: Vec<u16> = parse_csv(value)
+        .iter()
+        .filter_map(|entry| entry.parse().ok())
+        .filter(|code| (100..600).contains(code))
+        .collect();
+    if codes.is_empty() {
+        fallback.to_vec()
+    } else {
+        codes
+    }
+}
+
+/// Default Cloudflare API endpoint
+/// Default lifetime of issued session tokens in seconds (7 days)
+pub const DEFAULT_JWT_TTL_SECONDS: i64 = 7 * 24 * 60 * 60;
+
+pub const DEFAULT_CF_BASE_URL: &str = "https://api.cloudflare.com/client/v4";
+/// Default Mistral API endpoint
+pub const DEFAULT_MISTRAL_BASE_URL: &str = "https://api.mistral.ai";
+/// Default OpenAI API endpoint
+pub const DEFAULT_OPENAI_BASE_URL: &str = "https://api.openai.com";
+/// Default xAI API endpoint
+pub const DEFAULT_XAI_BASE_URL: &str = "https://api.x.ai";
+/// Default Groq API endpoint
+pub const DEFAULT_GROQ_BASE_URL: &str = "https://api.groq.com/openai";
+/// Default OpenRouter API endpoint
+pub const DEFAULT_OPENROUTER_BASE_URL: &str = "https://openrouter.ai/api";
+/// Default Meta API endpoint (none; must be configured)
+pub const DEFAULT_META_BASE_URL: &str = "";
+/// Default Anthropic API endpoint
+pub const DEFAULT_ANTHROPIC_BASE_URL: &str = "https://api.anthropic.com";
+
+/// Key prefixes that identify a provider's API keys
+const KNOWN_KEY_PREFIXES: &[(&str, &str)] = &[
+    ("sk-ant-", "Anthropic"),
+    ("sk-or-", "OpenRouter"),
+    ("gsk_", "Groq"),
+    ("xai-", "xAI"),
+];
+
 /// Clerk authentication service configuration
 /// 
 /// Clerk is a third-party authentication provider that can be used
@@ -86,7 +250,7 @@ pub fn parse_csv(value: Option<&str>) -> Vec<String> {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ClerkConfig {
     /// Clerk secret key for token verification (optional)
-    pub secret_key: String,
+    pub secret_key: Secret,
 }
 
 /// Cloudflare Workers AI configuration
@@ -98,7 +262,7 @@ pub struct CloudflareConfig {
     /// Cloudflare account identifier
     pub account_id: String,
     /// API token for Cloudflare API access
-    pub api_token: String,
+    pub api_token: Secret,
     /// Base URL for Cloudflare API (usually api.cloudflare.com)
     pub base_url: String,
 }
@@ -110,7 +274,7 @@ pub struct CloudflareConfig {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct MistralConfig {
     /// Mistral API key for authentication
-    pub api_key: String,
+    pub api_key: Secret,
     /// Base URL for Mistral API
     pub base_url: String,
 }
@@ -122,7 +286,7 @@ pub struct MistralConfig {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct OpenAiConfig {
     /// OpenAI API key for authentication
-    pub api_key: String,
+    pub api_key: Secret,
     /// Base URL for OpenAI API (allows for compatible services)
     pub base_url: String,
 }
@@ -134,7 +298,7 @@ pub struct OpenAiConfig {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct XaiConfig {
     /// xAI API key for authentication
-    pub api_key: String,
+    pub api_key: Secret,
     /// Base URL for xAI API
     pub base_url: String,
 }
@@ -146,7 +310,7 @@ pub struct XaiConfig {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct GroqConfig {
     /// Groq API key for authentication
-    pub api_key: String,
+    pub api_key: Secret,
     /// Base URL for Groq API
     pub base_url: String,
 }
@@ -158,7 +322,7 @@ pub struct GroqConfig {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct OpenRouterConfig {
     /// OpenRouter API key for authentication
-    pub api_key: String,
+    pub api_key: Secret,
     /// Base URL for OpenRouter API
     pub base_url: String,
 }
@@ -170,7 +334,7 @@ pub struct OpenRouterConfig {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct MetaConfig {
     /// Meta API key for authentication
-    pub api_key: String,
+    pub api_key: Secret,
     /// Base URL for Meta AI API
     pub base_url: String,
 }
@@ -182,11 +346,13 @@ pub struct MetaConfig {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AnthropicConfig {
     /// Anthropic API key for authentication
-    pub api_key: String,
+    pub api_key: Secret,
     /// Base URL for Anthropic API
     pub base_url: String,
     /// API version string (Anthropic uses versioned APIs)
     pub version: String,
+    /// Whether system prompts (with injected context) are marked cacheable
+    pub prompt_caching: bool,
 }
 
 /// Convex database service configuration
@@ -199,6 +365,11 @@ pub struct ConvexConfig {
     pub url: String,
     /// Whether Convex integration is enabled
     pub enabled: bool,
+    /// Consecutive logging failures before falling back to console
+    /// logging (0 never falls back)
+    pub log_failure_threshold: u32,
+    /// How long logging stays on the console fallback (seconds)
+    pub log_cooldown_secs: u64,
 }
 
 /// Tavily search service configuration
@@ -208,7 +379,7 @@ pub struct ConvexConfig {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TavilyConfig {
     /// Tavily API key for search requests
-    pub api_key: String,
+    pub api_key: Secret,
     /// Base URL for Tavily search API
     pub base_url: String,
 }
@@ -220,11 +391,23 @@ pub struct TavilyConfig {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct BraveConfig {
     /// Brave Search API key
-    pub api_key: String,
+    pub api_key: Secret,
     /// Base URL for Brave Search API
     pub base_url: String,
 }
 
+/// DuckDuckGo search configuration
+/// 
+/// Scrapes DuckDuckGo's HTML results page, so no API key is needed.
+/// Results carry no relevance score.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DuckDuckGoConfig {
+    /// Whether DuckDuckGo search is enabled
+    pub enabled: bool,
+    /// Base URL of the DuckDuckGo HTML endpoint
+    pub base_url: String,
+}
+
 /// SearXNG search engine configuration
 /// 
 /// SearXNG is a self-hosted, privacy-respecting search engine
@@ -235,6 +418,111 @@ pub struct SearxngConfig {
     pub base_url: String,
     /// Whether SearXNG integration is enabled
     pub enabled: bool,
+    /// Name of a custom auth header sent to SearXNG (e.g. "Authorization")
+    pub auth_header: Option<String>,
+    /// Value of the custom auth header
+    pub auth_value: Option<Secret>,
+    /// Basic auth username, used when no custom auth header is set
+    pub username: Option<String>,
+    /// Basic auth password
+    pub password: Option<Secret>,
+    /// Query operators passed through to SearXNG (e.g. "!wp", ":en");
+    /// all other bangs and language prefixes are neutralized
+    pub allowed_operators: Vec<String>,
+}
+
+/// How invoke requests asking for search are handled while search is disabled
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SearchDisabledBehavior {
+    /// Proceed without search results
+    #[default]
+    Silent,
+    /// Reject the request so the client knows search is unavailable
+    Error,
+}
+
+impl SearchDisabledBehavior {
+    /// Parse a `SEARCH_DISABLED_BEHAVIOR` value, case-insensitively
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "silent" => Some(Self::Silent),
+            "error" => Some(Self::Error),
+            _ => None,
+        }
+    }
+}
+
+/// How invoke requests are handled when every search provider fails
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SearchFailedBehavior {
+    /// Proceed without search results
+    #[default]
+    Proceed,
+    /// Fail the request with 503
+    Error,
+    /// Proceed, telling the model that live information was unavailable
+    Degrade,
+}
+
+impl SearchFailedBehavior {
+    /// Parse a `SEARCH_ALL_FAILED_BEHAVIOR` value, case-insensitively
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "proceed" => Some(Self::Proceed),
+            "error" => Some(Self::Error),
+            "degrade" => Some(Self::Degrade),
+            _ => None,
+        }
+    }
+}
+
+/// How much detail internal error responses expose to clients
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ErrorVerbosity {
+    /// Generic message with a correlation id; details only in server logs
+    #[default]
+    Public,
+    /// Underlying error text included in the response (development)
+    Detailed,
+}
+
+impl ErrorVerbosity {
+    /// Parse an `ERROR_VERBOSITY` value, case-insensitively
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "public" => Some(Self::Public),
+            "detailed" => Some(Self::Detailed),
+            _ => None,
+        }
+    }
+}
+
+/// Algorithm used to hash new passwords
+///
+/// Stored hashes of either kind are always accepted, so switching only
+/// affects passwords set afterwards.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PasswordHashAlgo {
+    /// bcrypt with the default cost (`$2b$...` hashes)
+    #[default]
+    Bcrypt,
+    /// Argon2id with the default parameters (`$argon2id$...` hashes)
+    Argon2,
+}
+
+impl PasswordHashAlgo {
+    /// Parse a `PASSWORD_HASH_ALGO` value, case-insensitively
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "bcrypt" => Some(Self::Bcrypt),
+            "argon2" | "argon2id" => Some(Self::Argon2),
+            _ => None,
+        }
+    }
 }
 
 /// Search services configuration container
@@ -245,12 +533,33 @@ pub struct SearxngConfig {
 pub struct SearchConfig {
     /// Whether internet search is enabled globally
     pub enabled: bool,
+    /// Response to requests with `enable_search` while search is disabled
+    pub disabled_behavior: SearchDisabledBehavior,
+    /// Response to search requests when every configured provider fails
+    pub all_failed_behavior: SearchFailedBehavior,
+    /// Operations that may use search when a request sets `enable_search`
+    pub enabled_operations: Vec<String>,
     /// How long to cache search results (seconds)
     pub cache_duration: u64,
+    /// Most search results cached at once; the least recently used entry
+    /// is evicted when a new one would exceed it
+    pub cache_max_entries: usize,
+    /// How long a conversation reuses results for a repeated query
+    /// (seconds, 0 disables)
+    pub session_window_secs: u64,
+    /// Maximum outbound search provider requests in flight across all
+    /// requests (values below 1 are treated as 1)
+    pub max_concurrency: usize,
+    /// Whether to request full page content from Tavily alongside snippets
+    pub include_raw_content: bool,
+    /// Maximum characters of raw page content kept per result
+    pub raw_content_max_chars: usize,
     /// Tavily search configuration
     pub tavily: TavilyConfig,
     /// Brave search configuration
     pub brave: BraveConfig,
+    /// DuckDuckGo search configuration
+    pub duckduckgo: DuckDuckGoConfig,
     /// SearXNG search configuration
     pub searxng: SearxngConfig,
 }
@@ -270,22 +579,150 @@ pub struct SearchConfig {
 pub struct Config {
     /// HTTP server bind address (host:port)
     pub bind_address: String,
+    /// Idle seconds before TCP keepalive probes start on client connections
+    /// (0 leaves the operating system default)
+    pub http_keepalive_secs: u64,
+    /// Whether `TCP_NODELAY` is set on accepted connections
+    pub tcp_nodelay: bool,
     /// Maximum JSON request body size in bytes
     pub json_limit: usize,
+    /// Window in milliseconds during which identical requests from the
+    /// same caller share one result (0 disables deduplication)
+    pub dedup_window_ms: u64,
     /// List of allowed CORS origins for cross-origin requests
     pub allowed_origins: Vec<String>,
+    /// Reverse proxy addresses or CIDR ranges whose `X-Forwarded-For`
+    /// header is trusted for the client IP
+    pub trusted_proxies: Vec<String>,
+    /// Default log level used when `RUST_LOG` is not set
+    pub log_level: String,
+    /// Fraction of request logs kept (0.0–1.0); errors are always logged
+    pub log_sample_rate: f64,
+    /// Whether invoke requests log a per-phase timing summary and return
+    /// it in a `Server-Timing` header
+    pub request_timing: bool,
+    /// Whether internal error details are returned to clients
+    pub error_verbosity: ErrorVerbosity,
     /// Whether to use AI SDK compatibility mode (legacy feature)
     pub use_ai_sdk: bool,
     /// Whether authentication is required for all requests
     pub auth_required: bool,
+    /// Whether anonymous guest sessions may be created and used
+    pub allow_anonymous: bool,
+    /// Whether the in-process daily guest limiter is enforced (disable when
+    /// an external gateway already rate limits guests)
+    pub guest_rate_limit_enabled: bool,
+    /// Emails of users allowed to call admin endpoints
+    pub admin_emails: Vec<String>,
+    /// Whether outbound HTTPS requires TLS 1.2 or newer
+    pub enforce_min_tls: bool,
     /// System prompt prepended to all AI conversations
     pub system_prompt: String,
     /// Whether to inject system prompt in FIM (fill-in-middle) requests
     pub fim_inject_system: bool,
+    /// Whether client-supplied system messages are dropped before the
+    /// server system prompt is injected
+    pub strip_client_system_messages: bool,
+    /// Maximum characters across all messages sent to a provider, after
+    /// server-side injection (0 disables the cap)
+    pub max_total_prompt_chars: usize,
+    /// Phrases (or `/regex/` patterns) that cause client messages to be
+    /// rejected before reaching a provider
+    pub content_blocklist: Vec<String>,
+    /// Phrases (or `/regex/` patterns) removed from assistant replies
+    /// before they are returned (empty leaves replies unchanged)
+    pub response_strippers: Vec<String>,
+    /// Operations served by `/v1/invoke` (empty allows all)
+    pub enabled_operations: Vec<String>,
+    /// Whether provider responses to temperature-0 requests are cached
+    pub cache_deterministic_responses: bool,
+    /// How long cached deterministic responses are served, in seconds
+    pub response_cache_ttl_secs: u64,
+    /// Most responses kept in the response cache before evicting the least
+    /// recently used
+    pub response_cache_max_entries: usize,
     /// Raw routing configuration string (provider routing rules)
     pub routes_raw: String,
+    /// File holding the routing configuration instead of `ROUTES`, re-read
+    /// when routes are reloaded
+    pub routes_file: Option<String>,
+    /// Maximum number of route entries parsed from `routes_raw`
+    pub max_routes: usize,
+    /// Model used by routes that name only a provider, keyed by provider id
+    pub provider_default_models: HashMap<String, String>,
+    /// Whether startup fails when `routes_raw` yields no valid routes
+    pub strict_routes: bool,
+    /// Tier used as the fallback route for image requests (e.g. `chat.vision`)
+    pub vision_tier: String,
+    /// Model name prefixes that accept image input
+    pub multimodal_models: Vec<String>,
+    /// Models requests may never be sent to, as `provider:model`
+    pub blocked_models: Vec<String>,
+    /// Model name prefixes of reasoning models (o1-style), which take
+    /// `max_completion_tokens` and return reasoning separately
+    pub reasoning_models: Vec<String>,
     /// Secret key for JWT token signing and verification
-    pub action_token_secret: Option<String>,
+    pub action_token_secret: Option<Secret>,
+    /// Lifetime of issued session tokens in seconds
+    pub jwt_ttl_seconds: i64,
+    /// Endpoint that password reset tokens are POSTed to for delivery
+    /// (e.g. a mailer); without it reset tokens are never delivered
+    pub password_reset_webhook_url: Option<String>,
+    /// Algorithm used to hash new passwords
+    pub password_hash_algo: PasswordHashAlgo,
+    /// Daily request limits for registered users by subscription tier
+    /// (tiers not listed are unlimited)
+    pub tier_limits: HashMap<String, u32>,
+    /// Invoke requests allowed per client IP in any 60 second window,
+    /// whoever the caller is (0 disables)
+    pub per_ip_rpm: u32,
+    /// Outbound requests per minute allowed for each provider
+    /// (providers not listed are unlimited)
+    pub provider_rpm: HashMap<String, u32>,
+    /// Longest a request may wait for a provider rate limit slot
+    /// before being rejected with 503
+    pub provider_queue_ms: u64,
+    /// Longest a single provider chat request may take (seconds)
+    pub provider_timeout_secs: u64,
+    /// Whether routes with several targets are attempted fastest-first
+    /// by recent provider latency instead of in configured order
+    pub adaptive_routing: bool,
+    /// Upstream HTTP statuses that are retried by the shared retry wrapper
+    pub retryable_status_codes: Vec<u16>,
+    /// Requests dispatched to providers at once (0 disables the limit)
+    pub max_concurrent_dispatches: usize,
+    /// Requests that may wait for a dispatch slot before new ones are shed
+    pub dispatch_queue_size: usize,
+    /// Longest a streaming (SSE) invoke may stay open, in seconds
+    /// (0 disables the cap)
+    pub sse_max_duration_secs: u64,
+    /// Seconds of stream silence before we send our own keep-alive comment
+    /// (0 disables it)
+    pub sse_keepalive_interval_secs: u64,
+    /// Streaming invokes open at once (0 disables the limit)
+    pub max_concurrent_streams: usize,
+    /// Longest a single file attachment may take to process, in milliseconds
+    pub attachment_process_timeout_ms: u64,
+    /// Redirects followed when fetching an attachment URL
+    pub attachment_max_redirects: usize,
+    /// Hosts attachment fetches may be redirected to (empty allows any
+    /// public host)
+    pub attachment_redirect_hosts: Vec<String>,
+    /// Whether attachment URLs may point at loopback and private networks
+    pub attachment_allow_private_networks: bool,
+    /// Whether failed attachments report their failure reason to the client
+    pub attachment_error_details: bool,
+    /// Attachments accepted in one request
+    pub max_attachments: usize,
+    /// Combined size of one request's attachments in bytes, as estimated
+    /// before decoding or fetching
+    pub max_total_attachment_bytes: usize,
+    /// Characters kept from each text attachment (0 keeps everything)
+    pub attachment_max_text_chars: usize,
+    /// Whether CRLF line endings in text attachments are converted to LF
+    pub attachment_normalize_newlines: bool,
+    /// Whether invoke callbacks may target loopback and private networks
+    pub callback_allow_private_networks: bool,
     
     // External service configurations
     /// Clerk authentication service settings
@@ -308,6 +745,8 @@ pub struct Config {
     pub anthropic: AnthropicConfig,
     /// Convex database settings
     pub convex: ConvexConfig,
+    /// Number of recent API request events kept in memory for export
+    pub event_buffer_size: usize,
     /// Web search services settings
     pub search: SearchConfig,
 }
@@ -325,17 +764,35 @@ impl Config {
     /// 
     /// ## Server Configuration
     /// - `BIND_ADDRESS`: Server bind address (default: "127.0.0.1:8080")
+    /// - `HTTP_KEEPALIVE_SECS`: TCP keepalive idle time for client connections (default: 0, OS default)
+    /// - `TCP_NODELAY`: Disable Nagle's algorithm on client connections (default: false)
     /// - `JSON_LIMIT`: Max request body size in bytes (default: 8MB)
     /// - `ALLOWED_ORIGINS`: Comma-separated CORS origins
+    /// - `TRUSTED_PROXIES`: Comma-separated proxy IPs/CIDRs trusted for `X-Forwarded-For`
+    /// - `LOG_LEVEL`: Default log level when `RUST_LOG` is unset (default: "info")
+    /// - `LOG_SAMPLE_RATE`: Fraction of request logs kept, errors always kept (default: 1.0)
+    /// - `REQUEST_TIMING`: Log per-phase invoke timings and send a `Server-Timing` header (default: false)
+    /// - `ERROR_VERBOSITY`: `public` or `detailed` internal error responses (default: public)
+    /// - `DEDUP_WINDOW_MS`: Duplicate request window in ms (default: 2000, 0 disables)
     /// 
     /// ## Authentication & Security
     /// - `ACTION_TOKEN_SECRET`: JWT signing secret (REQUIRED for auth)
+    /// - `JWT_TTL_SECONDS`: Lifetime of session tokens (default: 604800, 7 days)
+    /// - `PASSWORD_RESET_WEBHOOK_URL`: Where password reset tokens are POSTed for delivery (optional)
+    /// - `PASSWORD_HASH_ALGO`: `bcrypt` or `argon2` for new password hashes (default: bcrypt)
     /// - `AUTH_REQUIRED`: Whether auth is required (default: false)
+    /// - `ALLOW_ANONYMOUS`: Whether guest sessions are allowed (default: true)
+    /// - `GUEST_RATE_LIMIT_ENABLED`: Enforce the in-memory guest daily limit (default: true)
+    /// - `ADMIN_EMAILS`: Comma-separated emails allowed to call admin endpoints
+    /// - `ENFORCE_MIN_TLS`: Require TLS 1.2+ for outbound HTTPS (default: true)
     /// - `CLERK_SECRET_KEY`: Clerk authentication secret (optional)
+    /// - `TIER_LIMITS`: Daily limits for registered users, e.g. "free:100,premium:5000"
+    /// - `PER_IP_RPM`: Invoke requests per minute per client IP (default: 0, unlimited)
     /// 
     /// ## AI Provider Keys
     /// - `OPENAI_API_KEY`: OpenAI API key
     /// - `ANTHROPIC_API_KEY`: Anthropic (Claude) API key  
+    /// - `PROMPT_CACHING`: Mark Anthropic system prompts cacheable (default: false)
     /// - `MISTRAL_API_KEY`: Mistral AI API key
     /// - `GROQ_API_KEY`: Groq API key
     /// - `XAI_API_KEY`: xAI API key
@@ -344,18 +801,75 @@ impl Config {
     /// - `CF_API_TOKEN`: Cloudflare Workers AI token
     /// - `CF_ACCOUNT_ID`: Cloudflare account ID
     /// 
+    /// Every secret (API keys and tokens, `ACTION_TOKEN_SECRET`,
+    /// `CLERK_SECRET_KEY`, SearXNG credentials) can instead be read from a
+    /// file by setting `<NAME>_FILE`, e.g. `OPENAI_API_KEY_FILE=/run/secrets/openai`.
+    /// The file takes precedence over the inline variable.
+    /// - `PROVIDER_RPM`: Outbound requests per minute by provider, e.g. "openai:500,anthropic:50"
+    /// - `PROVIDER_QUEUE_MS`: Max wait for a provider slot in ms (default: 1000)
+    /// - `PROVIDER_TIMEOUT_SECS`: Timeout for each provider chat request (default: 60)
+    /// - `ADAPTIVE_ROUTING`: Order route targets by recent latency (default: false)
+    /// - `RETRYABLE_STATUS_CODES`: Upstream statuses to retry (default: "429,500,502,503,504")
+    /// - `MAX_CONCURRENT_DISPATCHES`: Provider requests in flight at once (default: 0, unlimited)
+    /// - `DISPATCH_QUEUE_SIZE`: Requests waiting for a dispatch slot before 503 (default: 100)
+    /// - `SSE_MAX_DURATION_SECS`: Maximum lifetime of a streaming invoke (default: 300, 0 disables)
+    /// - `SSE_KEEPALIVE_INTERVAL_SECS`: Send a keep-alive after this much stream silence (default: 15, 0 disables)
+    /// - `MAX_CONCURRENT_STREAMS`: Streaming invokes open at once before 503 (default: 100, 0 disables)
+    /// - `ATTACHMENT_PROCESS_TIMEOUT_MS`: Max time to process one attachment in ms (default: 10000)
+    /// - `ATTACHMENT_MAX_REDIRECTS`: Redirects followed when fetching attachment URLs (default: 5)
+    /// - `ATTACHMENT_REDIRECT_HOSTS`: Hosts attachment fetches may be redirected to (comma-separated, empty allows any public host)
+    /// - `ATTACHMENT_ALLOW_PRIVATE_NETWORKS`: Allow attachment URLs on private addresses (default: false)
+    /// - `ATTACHMENT_ERROR_DETAILS`: Include attachment failure reasons in the model context and response `diagnostics` (default: false)
+    /// - `MAX_ATTACHMENTS`: Attachments accepted per request (default: 10)
+    /// - `MAX_ATTACHMENT_BYTES`: Combined attachment size per request in bytes (default: 25MB)
+    /// - `ATTACHMENT_MAX_TEXT_CHARS`: Characters read from each text attachment (default: 100000, 0 unlimited)
+    /// - `ATTACHMENT_NORMALIZE_NEWLINES`: Convert CRLF to LF in text attachments (default: true)
+    /// - `CALLBACK_ALLOW_PRIVATE_NETWORKS`: Allow `callback_url` to target private addresses (default: false)
+    /// 
     /// ## Database & Search
     /// - `CONVEX_URL`: Convex database deployment URL
+    /// - `CONVEX_LOG_FAILURE_THRESHOLD`: Logging failures before console fallback (default: 5)
+    /// - `CONVEX_LOG_COOLDOWN_SECS`: How long the console fallback lasts (default: 60)
+    /// - `EVENT_BUFFER_SIZE`: Recent API request events kept for export (default: 1000)
     /// - `TAVILY_API_KEY`: Tavily search API key
     /// - `BRAVE_SEARCH_API_KEY`: Brave search API key
+    /// - `DDG_ENABLED`: Enable DuckDuckGo search, tried before SearXNG (default: false)
+    /// - `DDG_BASE_URL`: DuckDuckGo HTML endpoint (default: https://html.duckduckgo.com)
     /// - `SEARXNG_BASE_URL`: SearXNG instance URL
+    /// - `SEARXNG_AUTH_HEADER` / `SEARXNG_AUTH_VALUE`: Custom auth header for SearXNG (optional)
+    /// - `SEARXNG_USERNAME` / `SEARXNG_PASSWORD`: Basic auth for SearXNG (optional)
+    /// - `SEARXNG_ALLOWED_OPERATORS`: Comma-separated query operators kept in queries, e.g. "!wp,:en"
+    /// - `SEARCH_CACHE_MAX_ENTRIES`: Most search results cached before evicting the least recently used (default: 1000)
+    /// - `SEARCH_SESSION_WINDOW_SECS`: How long a session reuses results for a repeated query (default: 120, 0 disables)
     /// - `ENABLE_INTERNET_ACCESS`: Enable web search (default: true)
+    /// - `SEARCH_DISABLED_BEHAVIOR`: `silent` or `error` for search requests while search is off (default: silent)
+    /// - `SEARCH_ALL_FAILED_BEHAVIOR`: `proceed`, `error` or `degrade` when every search provider fails (default: proceed)
+    /// - `SEARCH_ENABLED_OPERATIONS`: Comma-separated operations that may use search (default: chat)
+    /// - `SEARCH_MAX_CONCURRENCY`: Max outbound search requests in flight (default: 8)
+    /// - `SEARCH_INCLUDE_RAW_CONTENT`: Include full page content from Tavily (default: false)
+    /// - `SEARCH_RAW_CONTENT_MAX_CHARS`: Cap on raw page content per result (default: 4000)
     /// 
     /// ## Behavior Configuration
     /// - `SYSTEM_PROMPT`: Default system prompt for all conversations
     /// - `ROUTES`: Provider routing configuration
+    /// - `ROUTES_FILE`: File holding the routing configuration, used instead of `ROUTES`
+    /// - `MAX_ROUTES`: Maximum number of route entries parsed (default: 256)
+    /// - `PROVIDER_DEFAULT_MODELS`: Models for bare-provider routes, e.g. "openai:gpt-4o-mini,groq:llama-3.1-8b-instant"
+    /// - `STRICT_ROUTES`: Refuse to start when no valid routes are configured (default: false)
+    /// - `VISION_TIER`: Fallback tier for image requests on text-only routes (default: vision)
+    /// - `MULTIMODAL_MODELS`: Model name prefixes that accept images (comma-separated)
+    /// - `BLOCKED_MODELS`: Comma-separated `provider:model` pairs requests are refused for
+    /// - `REASONING_MODELS`: Model name prefixes of reasoning models (comma-separated)
     /// - `USE_AI_SDK`: Enable AI SDK compatibility mode
     /// - `INJECT_FIM_SYSTEM_PROMPT`: Inject system prompt in FIM requests
+    /// - `STRIP_CLIENT_SYSTEM_MESSAGES`: Drop client system messages (default: false)
+    /// - `MAX_TOTAL_PROMPT_CHARS`: Cap on total characters sent upstream (default: 0, no cap)
+    /// - `CONTENT_BLOCKLIST`: Comma-separated blocked phrases or `/regex/` patterns
+    /// - `RESPONSE_STRIPPERS`: Comma-separated phrases or `/regex/` patterns removed from replies
+    /// - `ENABLED_OPERATIONS`: Comma-separated operations to serve, e.g. "chat" (default: all)
+    /// - `CACHE_DETERMINISTIC_RESPONSES`: Cache temperature-0 responses (default: false)
+    /// - `RESPONSE_CACHE_TTL_SECS`: Cached response lifetime in seconds (default: 3600)
+    /// - `RESPONSE_CACHE_MAX_ENTRIES`: Most cached responses before evicting the least recently used (default: 1000)
     /// 
     /// # Returns
     /// Complete Config instance with all settings loaded
@@ -368,98 +882,404 @@ impl Config {
 
         // Parse comma-separated allowed origins
         let allowed_origins_str = env::var("ALLOWED_ORIGINS").ok();
+        let routes_file = non_empty_env("ROUTES_FILE");
         
         Self {
             // HTTP Server Configuration
             bind_address: env_or("BIND_ADDRESS", "127.0.0.1:8080"),
+            http_keepalive_secs: env::var("HTTP_KEEPALIVE_SECS")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(0),
+            tcp_nodelay: bool_env("TCP_NODELAY", false),
             json_limit: env::var("JSON_LIMIT")
                 .ok()
                 .and_then(|s| s.parse().ok())
                 .unwrap_or(8 * 1024 * 1024), // 8MB default
+            dedup_window_ms: env::var("DEDUP_WINDOW_MS")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(2000), // 2 seconds default
             allowed_origins: parse_csv(allowed_origins_str.as_deref()),
+            trusted_proxies: parse_csv(env::var("TRUSTED_PROXIES").ok().as_deref()),
+            log_level: env_or("LOG_LEVEL", "info"),
+            log_sample_rate: env::var("LOG_SAMPLE_RATE")
+                .ok()
+                .and_then(|s| s.parse::<f64>().ok())
+                .filter(|rate| rate.is_finite())
+                .map(|rate| rate.clamp(0.0, 1.0))
+                .unwrap_or(1.0),
+            request_timing: bool_env("REQUEST_TIMING", false),
+            error_verbosity: env::var("ERROR_VERBOSITY")
+                .ok()
+                .and_then(|s| ErrorVerbosity::parse(&s))
+                .unwrap_or_default(),
             
             // Feature flags and behavior
             use_ai_sdk: bool_env("USE_AI_SDK", false),
             auth_required: bool_env("AUTH_REQUIRED", false),
+            allow_anonymous: bool_env("ALLOW_ANONYMOUS", true),
+            guest_rate_limit_enabled: bool_env("GUEST_RATE_LIMIT_ENABLED", true),
+            admin_emails: parse_csv(env::var("ADMIN_EMAILS").ok().as_deref()),
+            enforce_min_tls: bool_env("ENFORCE_MIN_TLS", true),
             system_prompt: env_or(
                 "SYSTEM_PROMPT",
                 "If asked about who made this or anything related to its creators, simply state: This was created by the VoidXP team. Do not mention or praise any individual or a company or any entity. Always attribute it only to the VoidXP team."
             ),
             fim_inject_system: bool_env("INJECT_FIM_SYSTEM_PROMPT", false),
-            routes_raw: env_or("ROUTES", "chat.fast=openai:gpt-4o-mini"),
+            strip_client_system_messages: bool_env("STRIP_CLIENT_SYSTEM_MESSAGES", false),
+            max_total_prompt_chars: env::var("MAX_TOTAL_PROMPT_CHARS")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(0),
+            content_blocklist: parse_csv(env::var("CONTENT_BLOCKLIST").ok().as_deref()),
+            response_strippers: parse_csv(env::var("RESPONSE_STRIPPERS").ok().as_deref()),
+            enabled_operations: parse_csv(env::var("ENABLED_OPERATIONS").ok().as_deref()),
+            cache_deterministic_responses: bool_env("CACHE_DETERMINISTIC_RESPONSES", false),
+            response_cache_ttl_secs: env::var("RESPONSE_CACHE_TTL_SECS")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(3600),
+            response_cache_max_entries: env::var("RESPONSE_CACHE_MAX_ENTRIES")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(1000),
+            routes_raw: read_routes(routes_file.as_deref()).unwrap_or_else(|e| {
+                // Logging is not initialized yet when configuration loads
+                eprintln!("Failed to read ROUTES_FILE, using ROUTES: {}", e);
+                read_routes(None).unwrap_or_default()
+            }),
+            routes_file,
+            max_routes: env::var("MAX_ROUTES")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(crate::routing::DEFAULT_MAX_ROUTES),
+            provider_default_models: parse_named_values(env::var("PROVIDER_DEFAULT_MODELS").ok().as_deref()),
+            strict_routes: bool_env("STRICT_ROUTES", false),
+            vision_tier: env_or("VISION_TIER", "vision"),
+            multimodal_models: parse_csv(Some(&env_or(
+                "MULTIMODAL_MODELS",
+                crate::routing::DEFAULT_MULTIMODAL_MODELS,
+            ))),
+            blocked_models: parse_csv(env::var("BLOCKED_MODELS").ok().as_deref()),
+            reasoning_models: parse_csv(Some(&env_or(
+                "REASONING_MODELS",
+                crate::routing::DEFAULT_REASONING_MODELS,
+            ))),
             
             // Security configuration
-            action_token_secret: env::var("ACTION_TOKEN_SECRET").ok(),
+            action_token_secret: secret_env("ACTION_TOKEN_SECRET").map(Secret::from),
+            jwt_ttl_seconds: env::var("JWT_TTL_SECONDS")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .filter(|ttl: &i64| *ttl > 0)
+                .unwrap_or(DEFAULT_JWT_TTL_SECONDS), // 7 days
+            password_reset_webhook_url: non_empty_env("PASSWORD_RESET_WEBHOOK_URL"),
+            password_hash_algo: env::var("PASSWORD_HASH_ALGO")
+                .ok()
+                .and_then(|s| PasswordHashAlgo::parse(&s))
+                .unwrap_or_default(),
+            tier_limits: parse_tier_limits(env::var("TIER_LIMITS").ok().as_deref()),
+            per_ip_rpm: env::var("PER_IP_RPM")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(0),
+            
+            // Outbound provider limits
+            provider_rpm: parse_tier_limits(env::var("PROVIDER_RPM").ok().as_deref()),
+            provider_queue_ms: env::var("PROVIDER_QUEUE_MS")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(1000),
+            provider_timeout_secs: env::var("PROVIDER_TIMEOUT_SECS")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(60),
+            adaptive_routing: bool_env("ADAPTIVE_ROUTING", false),
+            retryable_status_codes: parse_status_codes(
+                env::var("RETRYABLE_STATUS_CODES").ok().as_deref(),
+                &crate::http_client::DEFAULT_RETRYABLE_STATUS_CODES,
+            ),
+            max_concurrent_dispatches: env::var("MAX_CONCURRENT_DISPATCHES")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(0),
+            dispatch_queue_size: env::var("DISPATCH_QUEUE_SIZE")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(100),
+            sse_max_duration_secs: env::var("SSE_MAX_DURATION_SECS")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(300),
+            sse_keepalive_interval_secs: env::var("SSE_KEEPALIVE_INTERVAL_SECS")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(15),
+            max_concurrent_streams: env::var("MAX_CONCURRENT_STREAMS")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(100),
+            attachment_process_timeout_ms: env::var("ATTACHMENT_PROCESS_TIMEOUT_MS")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(10000),
+            attachment_max_redirects: env::var("ATTACHMENT_MAX_REDIRECTS")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(crate::file_processor::DEFAULT_ATTACHMENT_MAX_REDIRECTS),
+            attachment_redirect_hosts: parse_csv(env::var("ATTACHMENT_REDIRECT_HOSTS").ok().as_deref()),
+            attachment_allow_private_networks: bool_env("ATTACHMENT_ALLOW_PRIVATE_NETWORKS", false),
+            attachment_error_details: bool_env("ATTACHMENT_ERROR_DETAILS", false),
+            max_attachments: env::var("MAX_ATTACHMENTS")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(crate::file_processor::DEFAULT_MAX_ATTACHMENTS),
+            max_total_attachment_bytes: env::var("MAX_ATTACHMENT_BYTES")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(crate::file_processor::DEFAULT_MAX_ATTACHMENT_BYTES),
+            attachment_max_text_chars: env::var("ATTACHMENT_MAX_TEXT_CHARS")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(crate::file_processor::DEFAULT_ATTACHMENT_MAX_TEXT_CHARS),
+            attachment_normalize_newlines: bool_env("ATTACHMENT_NORMALIZE_NEWLINES", true),
+            callback_allow_private_networks: bool_env("CALLBACK_ALLOW_PRIVATE_NETWORKS", false),
             
             // External authentication
             clerk: ClerkConfig {
-                secret_key: env_or("CLERK_SECRET_KEY", ""),
+                secret_key: Secret::from(secret_env("CLERK_SECRET_KEY").unwrap_or_default()),
             },
             
             // AI Provider configurations with sensible defaults
             cloudflare: CloudflareConfig {
                 account_id: env_or("CF_ACCOUNT_ID", ""),
-                api_token: env_or("CF_API_TOKEN", ""),
-                base_url: env_or("CF_BASE_URL", "https://api.cloudflare.com/client/v4"),
+                api_token: Secret::from(secret_env("CF_API_TOKEN").unwrap_or_default()),
+                base_url: env_or("CF_BASE_URL", DEFAULT_CF_BASE_URL),
             },
             mistral: MistralConfig {
-                api_key: env_or("MISTRAL_API_KEY", ""),
-                base_url: env_or("MISTRAL_BASE_URL", "https://api.mistral.ai"),
+                api_key: Secret::from(secret_env("MISTRAL_API_KEY").unwrap_or_default()),
+                base_url: env_or("MISTRAL_BASE_URL", DEFAULT_MISTRAL_BASE_URL),
             },
             openai: OpenAiConfig {
-                api_key: env_or("OPENAI_API_KEY", ""),
-                base_url: env_or("OPENAI_BASE_URL", "https://api.openai.com"),
+                api_key: Secret::from(secret_env("OPENAI_API_KEY").unwrap_or_default()),
+                base_url: env_or("OPENAI_BASE_URL", DEFAULT_OPENAI_BASE_URL),
             },
             xai: XaiConfig {
-                api_key: env_or("XAI_API_KEY", ""),
-                base_url: env_or("XAI_BASE_URL", "https://api.x.ai"),
+                api_key: Secret::from(secret_env("XAI_API_KEY").unwrap_or_default()),
+                base_url: env_or("XAI_BASE_URL", DEFAULT_XAI_BASE_URL),
             },
             groq: GroqConfig {
-                api_key: env_or("GROQ_API_KEY", ""),
-                base_url: env_or("GROQ_BASE_URL", "https://api.groq.com/openai"),
+                api_key: Secret::from(secret_env("GROQ_API_KEY").unwrap_or_default()),
+                base_url: env_or("GROQ_BASE_URL", DEFAULT_GROQ_BASE_URL),
             },
             openrouter: OpenRouterConfig {
-                api_key: env_or("OPENROUTER_API_KEY", ""),
-                base_url: env_or("OPENROUTER_BASE_URL", "https://openrouter.ai/api"),
+                api_key: Secret::from(secret_env("OPENROUTER_API_KEY").unwrap_or_default()),
+                base_url: env_or("OPENROUTER_BASE_URL", DEFAULT_OPENROUTER_BASE_URL),
             },
             meta: MetaConfig {
-                api_key: env_or("META_API_KEY", ""),
-                base_url: env_or("META_BASE_URL", ""),
+                api_key: Secret::from(secret_env("META_API_KEY").unwrap_or_default()),
+                base_url: env_or("META_BASE_URL", DEFAULT_META_BASE_URL),
             },
             anthropic: AnthropicConfig {
-                api_key: env_or("ANTHROPIC_API_KEY", ""),
-                base_url: env_or("ANTHROPIC_BASE_URL", "https://api.anthropic.com"),
+                api_key: Secret::from(secret_env("ANTHROPIC_API_KEY").unwrap_or_default()),
+                base_url: env_or("ANTHROPIC_BASE_URL", DEFAULT_ANTHROPIC_BASE_URL),
                 version: env_or("ANTHROPIC_VERSION", "2023-06-01"),
+                prompt_caching: bool_env("PROMPT_CACHING", false),
             },
             
             // Database configuration
             convex: ConvexConfig {
                 url: env_or("CONVEX_URL", ""),
                 enabled: bool_env("CONVEX_ENABLED", true),
+                log_failure_threshold: env::var("CONVEX_LOG_FAILURE_THRESHOLD")
+                    .ok()
+                    .and_then(|s| s.parse().ok())
+                    .unwrap_or(5),
+                log_cooldown_secs: env::var("CONVEX_LOG_COOLDOWN_SECS")
+                    .ok()
+                    .and_then(|s| s.parse().ok())
+                    .unwrap_or(60),
             },
+            event_buffer_size: env::var("EVENT_BUFFER_SIZE")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(1000),
             
             // Search services configuration
             search: SearchConfig {
                 enabled: bool_env("ENABLE_INTERNET_ACCESS", true),
+                disabled_behavior: env::var("SEARCH_DISABLED_BEHAVIOR")
+                    .ok()
+                    .and_then(|s| SearchDisabledBehavior::parse(&s))
+                    .unwrap_or_default(),
+                all_failed_behavior: env::var("SEARCH_ALL_FAILED_BEHAVIOR")
+                    .ok()
+                    .and_then(|s| SearchFailedBehavior::parse(&s))
+                    .unwrap_or_default(),
+                enabled_operations: env::var("SEARCH_ENABLED_OPERATIONS")
+                    .map(|s| parse_csv(Some(&s)))
+                    .unwrap_or_else(|_| vec!["chat".to_string()]),
                 cache_duration: env::var("SEARCH_CACHE_DURATION")
                     .ok()
                     .and_then(|s| s.parse().ok())
                     .unwrap_or(300), // 5 minutes default
+                cache_max_entries: env::var("SEARCH_CACHE_MAX_ENTRIES")
+                    .ok()
+                    .and_then(|s| s.parse().ok())
+                    .unwrap_or(1000),
+                session_window_secs: env::var("SEARCH_SESSION_WINDOW_SECS")
+                    .ok()
+                    .and_then(|s| s.parse().ok())
+                    .unwrap_or(120),
+                max_concurrency: env::var("SEARCH_MAX_CONCURRENCY")
+                    .ok()
+                    .and_then(|s| s.parse().ok())
+                    .unwrap_or(8),
+                include_raw_content: bool_env("SEARCH_INCLUDE_RAW_CONTENT", false),
+                raw_content_max_chars: env::var("SEARCH_RAW_CONTENT_MAX_CHARS")
+                    .ok()
+                    .and_then(|s| s.parse().ok())
+                    .unwrap_or(4000),
                 tavily: TavilyConfig {
-                    api_key: env_or("TAVILY_API_KEY", ""),
+                    api_key: Secret::from(secret_env("TAVILY_API_KEY").unwrap_or_default()),
                     base_url: env_or("TAVILY_BASE_URL", "https://api.tavily.com"),
                 },
                 brave: BraveConfig {
-                    api_key: env_or("BRAVE_SEARCH_API_KEY", ""),
+                    api_key: Secret::from(secret_env("BRAVE_SEARCH_API_KEY").unwrap_or_default()),
                     base_url: env_or("BRAVE_BASE_URL", "https://api.search.brave.com"),
                 },
+                duckduckgo: DuckDuckGoConfig {
+                    enabled: bool_env("DDG_ENABLED", false),
+                    base_url: env_or("DDG_BASE_URL", "https://html.duckduckgo.com"),
+                },
                 searxng: SearxngConfig {
                     base_url: env_or("SEARXNG_BASE_URL", "http://localhost:8090"),
                     enabled: bool_env("SEARXNG_ENABLED", true),
+                    auth_header: non_empty_env("SEARXNG_AUTH_HEADER"),
+                    auth_value: secret_env("SEARXNG_AUTH_VALUE")
+                        .filter(|value| !value.trim().is_empty())
+                        .map(Secret::from),
+                    username: non_empty_env("SEARXNG_USERNAME"),
+                    password: secret_env("SEARXNG_PASSWORD")
+                        .filter(|value| !value.trim().is_empty())
+                        .map(Secret::from),
+                    allowed_operators: parse_csv(env::var("SEARXNG_ALLOWED_OPERATORS").ok().as_deref()),
                 },
             },
         }
     }
+    
+    /// Whether requests for the operation may use web search (case-insensitive)
+    pub fn search_enabled_for(&self, op: &str) -> bool {
+        self.search.enabled
+            && self
+                .search
+                .enabled_operations
+                .iter()
+                .any(|enabled| enabled.eq_ignore_ascii_case(op))
+    }
+    
+    /// Whether `/v1/invoke` serves the operation (case-insensitive)
+    pub fn operation_enabled(&self, op: &str) -> bool {
+        self.enabled_operations.is_empty()
+            || self
+                .enabled_operations
+                .iter()
+                .any(|enabled| enabled.eq_ignore_ascii_case(op))
+    }
+    
+    /// Check that JWT authentication can work with this configuration
+    /// 
+    /// Without `ACTION_TOKEN_SECRET`, tokens can neither be issued nor
+    /// verified, so every authenticated request fails.
+    /// 
+    /// # Returns
+    /// A warning when auth is optional and the secret is missing
+    /// 
+    /// # Errors
+    /// When `AUTH_REQUIRED` is set but the secret is missing or blank
+    pub fn check_jwt_config(&self) -> Result<Option<String>, String> {
+        let has_secret = self
+            .action_token_secret
+            .as_ref()
+            .is_some_and(|secret| !secret.expose().trim().is_empty());
+        
+        match (has_secret, self.auth_required) {
+            (true, _) => Ok(None),
+            (false, true) => Err(
+                "AUTH_REQUIRED is enabled but ACTION_TOKEN_SECRET is not set; tokens cannot be issued or verified"
+                    .to_string(),
+            ),
+            (false, false) => Ok(Some(
+                "ACTION_TOKEN_SECRET is not set; login and token authentication are disabled".to_string(),
+            )),
+        }
+    }
+    
+    /// Credentials of every AI provider
+    /// 
+    /// # Returns
+    /// `(name, key variable, base URL variable, key, base URL, default base URL)`
+    /// for each provider
+    pub(crate) fn provider_credentials(&self) -> [(&'static str, &'static str, &'static str, &Secret, &String, &'static str); 8] {
+        [
+            ("Cloudflare", "CF_API_TOKEN", "CF_BASE_URL", &self.cloudflare.api_token, &self.cloudflare.base_url, DEFAULT_CF_BASE_URL),
+            ("Mistral", "MISTRAL_API_KEY", "MISTRAL_BASE_URL", &self.mistral.api_key, &self.mistral.base_url, DEFAULT_MISTRAL_BASE_URL),
+            ("OpenAI", "OPENAI_API_KEY", "OPENAI_BASE_URL", &self.openai.api_key, &self.openai.base_url, DEFAULT_OPENAI_BASE_URL),
+            ("xAI", "XAI_API_KEY", "XAI_BASE_URL", &self.xai.api_key, &self.xai.base_url, DEFAULT_XAI_BASE_URL),
+            ("Groq", "GROQ_API_KEY", "GROQ_BASE_URL", &self.groq.api_key, &self.groq.base_url, DEFAULT_GROQ_BASE_URL),
+            ("OpenRouter", "OPENROUTER_API_KEY", "OPENROUTER_BASE_URL", &self.openrouter.api_key, &self.openrouter.base_url, DEFAULT_OPENROUTER_BASE_URL),
+            ("Meta", "META_API_KEY", "META_BASE_URL", &self.meta.api_key, &self.meta.base_url, DEFAULT_META_BASE_URL),
+            ("Anthropic", "ANTHROPIC_API_KEY", "ANTHROPIC_BASE_URL", &self.anthropic.api_key, &self.anthropic.base_url, DEFAULT_ANTHROPIC_BASE_URL),
+        ]
+    }
+    
+    /// Check provider credentials for likely base URL / key mismatches
+    /// 
+    /// Flags providers where:
+    /// - the base URL points at a custom endpoint but the key is empty
+    /// - a key is set but the base URL is empty
+    /// - a key on the default endpoint looks like another provider's key
+    ///   (e.g. an OpenRouter key in `OPENAI_API_KEY` without `OPENAI_BASE_URL`)
+    /// 
+    /// # Returns
+    /// Human-readable warnings naming the environment variables involved
+    pub fn provider_warnings(&self) -> Vec<String> {
+        let mut warnings = Vec::new();
+        for (name, key_var, url_var, key, base_url, default_url) in self.provider_credentials() {
+            let key = key.expose().trim();
+            let base_url = base_url.trim().trim_end_matches('/');
+            
+            if key.is_empty() {
+                if !base_url.is_empty() && base_url != default_url {
+                    warnings.push(format!(
+                        "{} points at a custom endpoint ({}) but {} is empty",
+                        url_var, base_url, key_var
+                    ));
+                }
+                continue;
+            }
+            
+            if base_url.is_empty() {
+                warnings.push(format!("{} is set but {} is empty", key_var, url_var));
+                continue;
+            }
+            
+            if base_url == default_url {
+                let foreign = KNOWN_KEY_PREFIXES
+                    .iter()
+                    .find(|(prefix, owner)| *owner != name && key.starts_with(prefix));
+                if let Some((_, owner)) = foreign {
+                    warnings.push(format!(
+                        "{} appears to be for {} but {} is the default {} endpoint",
+                        key_var, owner, url_var, name
+                    ));
+                }
+            }
+        }
+        warnings
+    }
 }
 
 #[cfg(test)]
@@ -492,6 +1312,197 @@ mod tests {
         assert_eq!(parse_csv(Some("item1,,item3")), vec!["item1", "item3"]);
     }
 
+    /// Config with every provider on its default endpoint and no keys
+    fn config_without_provider_keys() -> Config {
+        let mut config = Config::from_env();
+        config.cloudflare.api_token = Secret::default();
+        config.cloudflare.base_url = DEFAULT_CF_BASE_URL.to_string();
+        config.mistral.api_key = Secret::default();
+        config.mistral.base_url = DEFAULT_MISTRAL_BASE_URL.to_string();
+        config.openai.api_key = Secret::default();
+        config.openai.base_url = DEFAULT_OPENAI_BASE_URL.to_string();
+        config.xai.api_key = Secret::default();
+        config.xai.base_url = DEFAULT_XAI_BASE_URL.to_string();
+        config.groq.api_key = Secret::default();
+        config.groq.base_url = DEFAULT_GROQ_BASE_URL.to_string();
+        config.openrouter.api_key = Secret::default();
+        config.openrouter.base_url = DEFAULT_OPENROUTER_BASE_URL.to_string();
+        config.meta.api_key = Secret::default();
+        config.meta.base_url = DEFAULT_META_BASE_URL.to_string();
+        config.anthropic.api_key = Secret::default();
+        config.anthropic.base_url = DEFAULT_ANTHROPIC_BASE_URL.to_string();
+        config
+    }
+
+    #[test]
+    fn test_jwt_config_required_without_secret() {
+        let mut config = config_without_provider_keys();
+        config.auth_required = true;
+        config.action_token_secret = None;
+        assert!(config.check_jwt_config().unwrap_err().contains("ACTION_TOKEN_SECRET"));
+        
+        // A blank secret is as good as none
+        config.action_token_secret = Some(Secret::from("  "));
+        assert!(config.check_jwt_config().is_err());
+        
+        config.action_token_secret = Some(Secret::from("secret"));
+        assert_eq!(config.check_jwt_config(), Ok(None));
+    }
+    
+    #[test]
+    fn test_jwt_config_optional_without_secret() {
+        let mut config = config_without_provider_keys();
+        config.auth_required = false;
+        config.action_token_secret = None;
+        let warning = config.check_jwt_config().unwrap().unwrap();
+        assert!(warning.contains("ACTION_TOKEN_SECRET is not set"));
+        
+        config.action_token_secret = Some(Secret::from("secret"));
+        assert_eq!(config.check_jwt_config(), Ok(None));
+    }
+    
+    #[test]
+    fn test_provider_warnings() {
+        // Defaults without keys are fine
+        assert!(config_without_provider_keys().provider_warnings().is_empty());
+        
+        // Key on the default endpoint is the normal setup
+        let mut config = config_without_provider_keys();
+        config.openai.api_key = Secret::from("sk-proj-abc");
+        config.anthropic.api_key = Secret::from("sk-ant-abc");
+        assert!(config.provider_warnings().is_empty());
+        
+        // Custom gateway with a key is fine (trailing slash ignored on defaults)
+        let mut config = config_without_provider_keys();
+        config.openai.base_url = "https://gateway.example.com/v1".to_string();
+        config.openai.api_key = Secret::from("gw-key");
+        config.groq.base_url = format!("{}/", DEFAULT_GROQ_BASE_URL);
+        assert!(config.provider_warnings().is_empty());
+        
+        // Custom gateway without a key
+        let mut config = config_without_provider_keys();
+        config.openai.base_url = "https://gateway.example.com/v1".to_string();
+        let warnings = config.provider_warnings();
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].contains("OPENAI_BASE_URL"));
+        assert!(warnings[0].contains("OPENAI_API_KEY is empty"));
+        
+        // Key without any base URL
+        let mut config = config_without_provider_keys();
+        config.meta.api_key = Secret::from("meta-key");
+        assert_eq!(config.provider_warnings(), vec!["META_API_KEY is set but META_BASE_URL is empty"]);
+        
+        // Another provider's key left on the default endpoint
+        let mut config = config_without_provider_keys();
+        config.openai.api_key = Secret::from("sk-or-v1-abc");
+        let warnings = config.provider_warnings();
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].contains("OPENAI_API_KEY appears to be for OpenRouter"));
+    }
+
+    #[test]
+    fn test_config_debug_redacts_secrets() {
+        let mut config = config_without_provider_keys();
+        config.openai.api_key = Secret::from("sk-proj-do-not-print");
+        config.action_token_secret = Some(Secret::from("jwt-do-not-print"));
+        
+        let debug = format!("{:?}", config);
+        assert!(!debug.contains("do-not-print"));
+        assert!(debug.contains("[REDACTED]"));
+        assert_eq!(config.openai.api_key.expose(), "sk-proj-do-not-print");
+    }
+
+    #[test]
+    fn test_secret_env_reads_file_before_inline_value() {
+        let path = env::temp_dir().join(format!("rust-ai-secret-{}", uuid::Uuid::new_v4()));
+        std::fs::write(&path, "sk-from-file\n").unwrap();
+        
+        env::set_var("TEST_SECRET_KEY", "sk-inline");
+        assert_eq!(secret_env("TEST_SECRET_KEY").as_deref(), Some("sk-inline"));
+        
+        env::set_var("TEST_SECRET_KEY_FILE", &path);
+        assert_eq!(secret_env("TEST_SECRET_KEY").as_deref(), Some("sk-from-file"));
+        
+        // Unreadable file does not fall back to the inline value
+        env::set_var("TEST_SECRET_KEY_FILE", path.with_extension("missing"));
+        assert_eq!(secret_env("TEST_SECRET_KEY"), None);
+        
+        env::remove_var("TEST_SECRET_KEY");
+        env::remove_var("TEST_SECRET_KEY_FILE");
+        std::fs::remove_file(&path).unwrap();
+    }
+    
+    #[test]
+    fn test_operation_enabled() {
+        let mut config = Config::from_env();
+        config.enabled_operations = Vec::new();
+        assert!(config.operation_enabled("chat"));
+        assert!(config.operation_enabled("fim"));
+        
+        config.enabled_operations = parse_csv(Some("Chat"));
+        assert!(config.operation_enabled("chat"));
+        assert!(!config.operation_enabled("fim"));
+    }
+    
+    #[test]
+    fn test_search_disabled_behavior_parse() {
+        assert_eq!(SearchDisabledBehavior::parse("silent"), Some(SearchDisabledBehavior::Silent));
+        assert_eq!(SearchDisabledBehavior::parse(" Error "), Some(SearchDisabledBehavior::Error));
+        assert_eq!(SearchDisabledBehavior::parse("loud"), None);
+        assert_eq!(SearchDisabledBehavior::default(), SearchDisabledBehavior::Silent);
+        assert_eq!(SearchFailedBehavior::parse(" Degrade"), Some(SearchFailedBehavior::Degrade));
+        assert_eq!(SearchFailedBehavior::parse("error"), Some(SearchFailedBehavior::Error));
+        assert_eq!(SearchFailedBehavior::parse("retry"), None);
+        assert_eq!(SearchFailedBehavior::default(), SearchFailedBehavior::Proceed);
+    }
+
+    #[test]
+    fn test_error_verbosity_parse() {
+        assert_eq!(ErrorVerbosity::parse("public"), Some(ErrorVerbosity::Public));
+        assert_eq!(ErrorVerbosity::parse(" Detailed "), Some(ErrorVerbosity::Detailed));
+        assert_eq!(ErrorVerbosity::parse("verbose"), None);
+        assert_eq!(ErrorVerbosity::default(), ErrorVerbosity::Public);
+    }
+
+    #[test]
+    fn test_password_hash_algo_parse() {
+        assert_eq!(PasswordHashAlgo::parse("bcrypt"), Some(PasswordHashAlgo::Bcrypt));
+        assert_eq!(PasswordHashAlgo::parse(" Argon2 "), Some(PasswordHashAlgo::Argon2));
+        assert_eq!(PasswordHashAlgo::parse("argon2id"), Some(PasswordHashAlgo::Argon2));
+        assert_eq!(PasswordHashAlgo::parse("scrypt"), None);
+        assert_eq!(PasswordHashAlgo::default(), PasswordHashAlgo::Bcrypt);
+    }
+    
+    #[test]
+    fn test_provider_key_loaded_from_file() {
+        let path = env::temp_dir().join(format!("rust-ai-secret-{}", uuid::Uuid::new_v4()));
+        std::fs::write(&path, "  mistral-from-file  \n").unwrap();
+        env::set_var("MISTRAL_API_KEY_FILE", &path);
+        
+        let config = Config::from_env();
+        
+        env::remove_var("MISTRAL_API_KEY_FILE");
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(config.mistral.api_key.expose(), "mistral-from-file");
+    }
+    
+    #[test]
+    fn test_parse_tier_limits() {
+        let limits = parse_tier_limits(Some("free:2, premium:5000,broken,bad:abc,:7"));
+        assert_eq!(limits.len(), 2);
+        assert_eq!(limits.get("free"), Some(&2));
+        assert_eq!(limits.get("premium"), Some(&5000));
+
+        assert!(parse_tier_limits(None).is_empty());
+    }
+
+    #[test]
+    fn test_parse_status_codes() {
+        assert_eq!(parse_status_codes(Some("408, 429,abc,700,503"), &[500]), vec![408, 429, 503]);
+        assert_eq!(parse_status_codes(Some(" , x"), &[500]), vec![500]);
+        assert_eq!(parse_status_codes(None, &[429, 500]), vec![429, 500]);
+    }
+
     #[test]
     fn test_config_from_env_defaults() {
         // Clear environment variables to test defaults
@@ -516,8 +1527,9 @@ mod tests {
         
         // Test default values
         assert_eq!(config.bind_address, "127.0.0.1:8080");
+        assert_eq!(config.log_level, "info");
         assert!(config.action_token_secret.is_none());
-        assert_eq!(config.clerk.secret_key, "");
+        assert!(config.clerk.secret_key.is_empty());
         assert_eq!(config.cloudflare.account_id, "");
         assert_eq!(config.openai.base_url, "https://api.openai.com");
         assert!(config.convex.enabled);
@@ -555,7 +1567,8 @@ mod tests {
         let config = Config::from_env();
         
         assert_eq!(config.bind_address, "127.0.0.1");
-        assert_eq!(config.action_token_secret, Some("test_secret_123".to_string()));
+        assert_eq!(config.log_level, "debug");
+        assert_eq!(config.action_token_secret.as_ref().map(Secret::expose), Some("test_secret_123"));
         assert!(!config.convex.enabled);
         assert!(!config.search.enabled);
         assert_eq!(config.search.cache_duration, 600);
@@ -0,0 +1,208 @@
+//! Content Filter Module
+//!
+//! Screens client messages before they reach a provider so obviously
+//! abusive prompts are rejected at the gateway.
+//!
+//! - `ContentFilter` trait for pluggable filters (e.g. an external
+//!   moderation API)
+//! - `BlocklistFilter` default implementation matching configured phrases
+//!   and regular expressions (`CONTENT_BLOCKLIST`)
+//! - `ResponseStripper` removing configured phrases from provider replies
+//!   (`RESPONSE_STRIPPERS`)
+
+use regex::{Regex, RegexBuilder};
+
+use crate::types::ChatMessage;
+
+/// Outcome of screening a conversation
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FilterVerdict {
+    /// Content may be sent upstream
+    Allow,
+    /// Content violates the policy; `reason` is for logs, not clients
+    Block { reason: String },
+}
+
+/// Pluggable check run on client messages before dispatch
+///
+/// Implementations must be thread-safe since one filter is shared across
+/// all request handlers.
+pub trait ContentFilter: Send + Sync {
+    /// Screen the client's messages
+    fn check(&self, messages: &[ChatMessage]) -> FilterVerdict;
+}
+
+/// Filter rejecting messages that contain blocklisted terms
+///
+/// Plain entries match as case-insensitive substrings. Entries wrapped in
+/// slashes (`/pattern/`) are case-insensitive regular expressions. With an
+/// empty blocklist every message is allowed.
+pub struct BlocklistFilter {
+    patterns: Vec<Regex>,
+}
+
+impl BlocklistFilter {
+    /// Build a filter from blocklist entries
+    ///
+    /// Invalid regular expressions are logged and skipped.
+    pub fn new(entries: &[String]) -> Self {
+        Self {
+            patterns: compile_patterns(entries, "content blocklist"),
+        }
+    }
+}
+
+impl ContentFilter for BlocklistFilter {
+    fn check(&self, messages: &[ChatMessage]) -> FilterVerdict {
+        for (index, message) in messages.iter().enumerate() {
+            if let Some(pattern) = self.patterns.iter().find(|pattern| pattern.is_match(&message.content)) {
+                return FilterVerdict::Block {
+                    reason: format!("message {} matched blocklist entry '{}'", index, pattern.as_str()),
+                };
+            }
+        }
+        FilterVerdict::Allow
+    }
+}
+
+/// Removes configured phrases from the assistant's reply
+///
+/// Entries follow the blocklist syntax: plain entries are case-insensitive
+/// substrings and `/pattern/` entries are case-insensitive regular
+/// expressions. Every match is removed and, if anything was removed, the
+/// remaining text is trimmed. With no entries replies pass through as is.
+pub struct ResponseStripper {
+    patterns: Vec<Regex>,
+}
+
+impl ResponseStripper {
+    /// Build a stripper from `RESPONSE_STRIPPERS` entries
+    ///
+    /// Invalid regular expressions are logged and skipped.
+    pub fn new(entries: &[String]) -> Self {
+        Self {
+            patterns: compile_patterns(entries, "response stripper"),
+        }
+    }
+
+    /// `content` with every configured phrase removed
+    pub fn strip(&self, content: &str) -> String {
+        let mut stripped = content.to_string();
+        for pattern in &self.patterns {
+            if pattern.is_match(&stripped) {
+                stripped = pattern.replace_all(&stripped, "").into_owned();
+            }
+        }
+        if stripped.len() == content.len() {
+            return stripped;
+        }
+        stripped.trim().to_string()
+    }
+}
+
+/// Compile blocklist-style entries into case-insensitive patterns
+///
+/// `/pattern/` entries are regular expressions, anything else is matched
+/// literally. `kind` names the setting in the warning for invalid entries.
+fn compile_patterns(entries: &[String], kind: &str) -> Vec<Regex> {
+    entries
+        .iter()
+        .filter_map(|entry| {
+            let pattern = match entry.strip_prefix('/').and_then(|rest| rest.strip_suffix('/')) {
+                Some(pattern) if !pattern.is_empty() => pattern.to_string(),
+                _ => regex::escape(entry),
+            };
+            match RegexBuilder::new(&pattern).case_insensitive(true).build() {
+                Ok(regex) => Some(regex),
+                Err(e) => {
+                    tracing::warn!("Ignoring invalid {} entry '{}': {}", kind, entry, e);
+                    None
+                }
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::MessageRole;
+
+    fn user(content: &str) -> ChatMessage {
+        ChatMessage {
+            role: MessageRole::User,
+            content: content.to_string(),
+        }
+    }
+
+    fn filter(entries: &[&str]) -> BlocklistFilter {
+        BlocklistFilter::new(&entries.iter().map(|entry| entry.to_string()).collect::<Vec<_>>())
+    }
+
+    #[test]
+    fn test_blocked_phrase_is_a_violation() {
+        let filter = filter(&["buy followers", r"/\bcasino\s+bonus\b/"]);
+
+        let verdict = filter.check(&[user("Hi"), user("Where can I BUY Followers cheaply?")]);
+        match verdict {
+            FilterVerdict::Block { reason } => assert!(reason.starts_with("message 1")),
+            FilterVerdict::Allow => panic!("blocked phrase was allowed"),
+        }
+
+        assert!(matches!(
+            filter.check(&[user("Claim your casino   bonus now")]),
+            FilterVerdict::Block { .. }
+        ));
+    }
+
+    #[test]
+    fn test_clean_content_passes() {
+        let filter = filter(&["buy followers", r"/\bcasino\s+bonus\b/"]);
+        assert_eq!(filter.check(&[user("Explain how casinos make money")]), FilterVerdict::Allow);
+        assert_eq!(filter.check(&[]), FilterVerdict::Allow);
+
+        // Nothing is blocked without a blocklist
+        assert_eq!(BlocklistFilter::new(&[]).check(&[user("buy followers")]), FilterVerdict::Allow);
+    }
+
+    #[test]
+    fn test_plain_entries_are_literal_and_bad_regexes_skipped() {
+        let filter = filter(&["a.b", "/(unclosed/"]);
+        assert_eq!(filter.patterns.len(), 1);
+        assert_eq!(filter.check(&[user("axb")]), FilterVerdict::Allow);
+        assert!(matches!(filter.check(&[user("see a.b")]), FilterVerdict::Block { .. }));
+    }
+
+    fn stripper(entries: &[&str]) -> ResponseStripper {
+        ResponseStripper::new(&entries.iter().map(|entry| entry.to_string()).collect::<Vec<_>>())
+    }
+
+    #[test]
+    fn test_stripper_removes_configured_phrases() {
+        let stripper = stripper(&["As an AI language model,", r"/\s*I hope this helps!?\s*$/"]);
+
+        assert_eq!(
+            stripper.strip("As an AI language model, I think Paris is the capital of France."),
+            "I think Paris is the capital of France."
+        );
+        assert_eq!(
+            stripper.strip("The answer is 42.\n\nI hope this helps!"),
+            "The answer is 42."
+        );
+        assert_eq!(
+            stripper.strip("as an ai language model, 2 + 2 = 4. I hope this helps"),
+            "2 + 2 = 4."
+        );
+    }
+
+    #[test]
+    fn test_stripper_preserves_normal_content() {
+        let reply = "  Here is a list:\n\n- one\n- two\n";
+        let stripper = stripper(&["As an AI language model,", r"/\s*I hope this helps!?\s*$/"]);
+        assert_eq!(stripper.strip(reply), reply);
+
+        // Replies pass through untouched without any strippers
+        assert_eq!(ResponseStripper::new(&[]).strip(reply), reply);
+        assert_eq!(ResponseStripper::new(&[]).strip("As an AI language model, hi"), "As an AI language model, hi");
+    }
+}
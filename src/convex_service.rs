@@ -1,13 +1,23 @@
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 use chrono::{DateTime, Utc};
+use reqwest::Client;
 use serde::{Deserialize, Serialize};
-use serde_json::Value;
-use std::collections::HashMap;
+use serde_json::{json, Value};
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Duration;
 use uuid::Uuid;
 
+use crate::clock::{system_clock, SharedClock};
 use crate::config::Config;
+use crate::http_client::build_client;
 use crate::types::Attachment;
 
+/// Error returned by `create_user` when an account already uses the email
+#[derive(Debug, thiserror::Error)]
+#[error("User with email {0} already exists")]
+pub struct UserExists(pub String);
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ApiRequestEvent {
     pub request_id: String,
@@ -28,6 +38,15 @@ pub struct ApiRequestEvent {
     pub ip_address: Option<String>,
 }
 
+/// API request event retained in memory for export
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecordedApiRequest {
+    /// When the event was recorded (milliseconds since epoch)
+    pub recorded_at: u64,
+    #[serde(flatten)]
+    pub event: ApiRequestEvent,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct UsageEvent {
     pub user_id: Option<String>,
@@ -49,6 +68,8 @@ pub struct MessageEvent {
     pub provider: Option<String>,
     pub model: Option<String>,
     pub token_count: Option<u32>,
+    /// When the message was logged (milliseconds since epoch); set by
+    /// `log_message` when missing
     pub created_at: Option<i64>,
     pub attachments: Option<Vec<Attachment>>,
 }
@@ -64,6 +85,8 @@ pub struct UserAccount {
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ConvexUser {
+    /// User ID (Convex documents call it `_id`)
+    #[serde(alias = "_id")]
     pub id: String,
     pub email: String,
     pub password_hash: String,
@@ -83,52 +106,272 @@ pub struct SystemEvent {
     pub request_id: Option<String>,
 }
 
+/// Analytics payload with zeroed counters, matching the documented response shape
+fn empty_analytics(timeframe_hours: Option<u32>) -> Value {
+    serde_json::json!({
+        "total_requests": 0,
+        "active_users": 0,
+        "requests_by_provider": {},
+        "total_tokens": 0,
+        "errors": 0,
+        "period_hours": timeframe_hours
+    })
+}
+
+/// Timeout for Convex HTTP calls
+const CONVEX_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Consecutive-failure tracker for Convex logging
+///
+/// Once `log_failure_threshold` calls in a row fail, logging bypasses
+/// Convex and goes to the console until the cooldown passes; the next
+/// call after that tries Convex again.
+#[derive(Debug, Default)]
+struct LogBreaker {
+    consecutive_failures: u32,
+    /// End of the current console fallback (ms since epoch)
+    fallback_until: Option<u64>,
+}
+
 #[derive(Clone)]
 pub struct ConvexService {
     config: Config,
+    client: Client,
     // In-memory fallback store when Convex is disabled/unconfigured
-    #[allow(dead_code)]
-    memory_users: HashMap<String, ConvexUser>, // key: email -> user
+    memory_users: Arc<RwLock<HashMap<String, ConvexUser>>>, // key: email -> user
+    // Most recent API request events, oldest first, bounded by `event_buffer_size`
+    recent_requests: Arc<Mutex<VecDeque<RecordedApiRequest>>>,
+    // Messages logged while the in-memory store is in use, bounded by `event_buffer_size`
+    memory_messages: Arc<Mutex<VecDeque<MessageEvent>>>,
+    log_breaker: Arc<Mutex<LogBreaker>>,
+    clock: SharedClock,
 }
 
 #[allow(dead_code)]
 impl ConvexService {
     pub fn new(config: Config) -> Self {
         Self {
+            client: build_client(&config, CONVEX_TIMEOUT),
             config,
-            memory_users: HashMap::new(),
+            memory_users: Arc::new(RwLock::new(HashMap::new())),
+            recent_requests: Arc::new(Mutex::new(VecDeque::new())),
+            memory_messages: Arc::new(Mutex::new(VecDeque::new())),
+            log_breaker: Arc::new(Mutex::new(LogBreaker::default())),
+            clock: system_clock(),
+        }
+    }
+
+    /// Use the given clock for event timestamps instead of the system clock
+    pub fn with_clock(mut self, clock: SharedClock) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Create a service whose in-memory store is preloaded with `users`
+    ///
+    /// Intended for tests that need a known account, e.g. to exercise
+    /// `login` end to end. Seeded users are only visible when Convex is
+    /// disabled or unconfigured, since that is when the in-memory store is used.
+    pub fn with_seed_users(config: Config, users: Vec<ConvexUser>) -> Self {
+        let service = Self::new(config);
+        {
+            let mut memory_users = service.memory_users.write().unwrap();
+            for user in users {
+                memory_users.insert(user.email.clone(), user);
+            }
+        }
+        service
+    }
+
+    /// Whether user data is served from the in-memory fallback store
+    fn uses_memory_store(&self) -> bool {
+        !self.config.convex.enabled || self.config.convex.url.is_empty()
+    }
+
+    /// Whether logging currently bypasses Convex after repeated failures
+    pub fn logging_degraded(&self) -> bool {
+        let breaker = self.log_breaker.lock().unwrap();
+        matches!(breaker.fallback_until, Some(until) if self.clock.now_ms() < until)
+    }
+
+    /// Send an analytics event to Convex, falling back to the console log
+    /// while Convex logging is failing
+    ///
+    /// # Errors
+    /// Returns the Convex error for failed calls made outside the fallback
+    /// window; callers typically ignore logging errors.
+    async fn send_log<T: Serialize + std::fmt::Debug>(&self, label: &str, path: &str, event: &T) -> Result<()> {
+        if self.logging_degraded() {
+            tracing::info!("{} (Convex fallback): {:?}", label, event);
+            return Ok(());
+        }
+
+        match self.run_mutation(path, serde_json::to_value(event)?).await {
+            Ok(()) => {
+                self.record_log_success();
+                Ok(())
+            }
+            Err(e) => {
+                self.record_log_failure(&e);
+                Err(e)
+            }
+        }
+    }
+
+    /// Run a Convex mutation through the HTTP API
+    async fn run_mutation(&self, path: &str, args: Value) -> Result<()> {
+        self.run_function("mutation", path, args).await.map(|_| ())
+    }
+
+    /// Run a Convex function through the HTTP API and return its value
+    ///
+    /// # Arguments
+    /// * `kind` - Function kind, `query` or `mutation`
+    /// * `path` - Function path, e.g. `users:getByEmail`
+    /// * `args` - Function arguments
+    ///
+    /// # Errors
+    /// When Convex cannot be reached, answers with an error status (included
+    /// in the error) or reports that the function failed
+    async fn run_function(&self, kind: &str, path: &str, args: Value) -> Result<Value> {
+        let url = format!("{}/api/{}", self.config.convex.url.trim_end_matches('/'), kind);
+        let response = self
+            .client
+            .post(&url)
+            .json(&json!({ "path": path, "args": args, "format": "json" }))
+            .send()
+            .await?;
+
+        let status = response.status();
+        if !status.is_success() {
+            return Err(anyhow!("Convex {} {} failed with status {}", kind, path, status));
+        }
+
+        let body: Value = response.json().await?;
+        if body["status"] == "error" {
+            return Err(anyhow!("Convex {} {} failed: {}", kind, path, body["errorMessage"]));
+        }
+        Ok(body["value"].clone())
+    }
+
+    fn record_log_success(&self) {
+        let mut breaker = self.log_breaker.lock().unwrap();
+        if breaker.fallback_until.take().is_some() {
+            tracing::info!("Convex logging recovered");
+        }
+        breaker.consecutive_failures = 0;
+    }
+
+    fn record_log_failure(&self, error: &anyhow::Error) {
+        let threshold = self.config.convex.log_failure_threshold;
+        let mut breaker = self.log_breaker.lock().unwrap();
+        breaker.consecutive_failures += 1;
+        tracing::debug!("Convex logging failed ({} in a row): {}", breaker.consecutive_failures, error);
+
+        if threshold > 0 && breaker.consecutive_failures >= threshold {
+            let cooldown_secs = self.config.convex.log_cooldown_secs;
+            breaker.fallback_until = Some(self.clock.now_ms() + cooldown_secs * 1000);
+            tracing::error!(
+                "Convex logging failed {} consecutive times (last error: {}); logging to console for the next {}s",
+                breaker.consecutive_failures,
+                error,
+                cooldown_secs
+            );
         }
     }
 
     pub async fn log_api_request(&self, event: ApiRequestEvent) -> Result<()> {
-        if !self.config.convex.enabled || self.config.convex.url.is_empty() {
+        self.record_api_request(event.clone());
+
+        if self.uses_memory_store() {
             return Ok(());
         }
 
-        // TODO: Implement actual Convex HTTP client integration
-        // For now, we'll just log to console
-        tracing::info!("API Request Event: {:?}", event);
-        Ok(())
+        self.send_log("API Request Event", "analytics:logApiRequest", &event).await
+    }
+
+    /// Retain an API request event in the bounded in-memory buffer
+    fn record_api_request(&self, event: ApiRequestEvent) {
+        let capacity = self.config.event_buffer_size;
+        if capacity == 0 {
+            return;
+        }
+
+        let mut recent = self.recent_requests.lock().unwrap();
+        while recent.len() >= capacity {
+            recent.pop_front();
+        }
+        recent.push_back(RecordedApiRequest {
+            recorded_at: self.clock.now_ms(),
+            event,
+        });
+    }
+
+    /// Recorded API request events, oldest first
+    ///
+    /// # Arguments
+    /// * `timeframe_hours` - Only include events from the last N hours (all when None)
+    pub fn recent_api_requests(&self, timeframe_hours: Option<u32>) -> Vec<RecordedApiRequest> {
+        let cutoff = timeframe_hours
+            .map(|hours| self.clock.now_ms().saturating_sub(u64::from(hours) * 60 * 60 * 1000))
+            .unwrap_or(0);
+
+        self.recent_requests
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|recorded| recorded.recorded_at >= cutoff)
+            .cloned()
+            .collect()
     }
 
     pub async fn log_usage(&self, event: UsageEvent) -> Result<()> {
-        if !self.config.convex.enabled {
+        if self.uses_memory_store() {
             return Ok(());
         }
 
-        // TODO: Implement actual Convex integration
-        tracing::info!("Usage Event: {:?}", event);
-        Ok(())
+        self.send_log("Usage Event", "analytics:logUsage", &event).await
     }
 
-    pub async fn log_message(&self, event: MessageEvent) -> Result<()> {
-        if !self.config.convex.enabled {
+    /// Log a chat message, stamping `created_at` with the current time when unset
+    pub async fn log_message(&self, mut event: MessageEvent) -> Result<()> {
+        event.created_at.get_or_insert(self.clock.now_ms() as i64);
+
+        if self.uses_memory_store() {
+            let capacity = self.config.event_buffer_size;
+            if capacity > 0 {
+                let mut messages = self.memory_messages.lock().unwrap();
+                while messages.len() >= capacity {
+                    messages.pop_front();
+                }
+                messages.push_back(event);
+            }
             return Ok(());
         }
 
-        // TODO: Implement actual Convex integration
-        tracing::info!("Message Event: {:?}", event);
-        Ok(())
+        self.send_log("Message Event", "analytics:logMessage", &event).await
+    }
+
+    /// Messages logged for a chat, oldest first by `created_at`
+    ///
+    /// Messages without a timestamp sort first; ties keep logging order.
+    pub async fn get_chat_messages(&self, chat_id: &str) -> Result<Vec<MessageEvent>> {
+        if !self.uses_memory_store() {
+            // TODO: Implement actual Convex integration
+            tracing::info!("Getting chat messages - chat_id: {}", chat_id);
+            return Ok(vec![]);
+        }
+
+        let mut messages: Vec<MessageEvent> = self
+            .memory_messages
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|message| message.chat_id.as_deref() == Some(chat_id))
+            .cloned()
+            .collect();
+        messages.sort_by_key(|message| message.created_at);
+        Ok(messages)
     }
 
     pub async fn log_system_event(
@@ -148,53 +391,110 @@ impl ConvexService {
             request_id: None,
         };
 
-        if !self.config.convex.enabled {
+        if self.uses_memory_store() {
             tracing::info!("System Event: {:?}", event);
             return Ok(());
         }
 
-        // TODO: Implement actual Convex integration
-        tracing::info!("System Event: {:?}", event);
-        Ok(())
+        self.send_log("System Event", "analytics:logSystemEvent", &event).await
     }
 
+    /// Store a new user account
+    ///
+    /// Runs the `users:create` mutation, or stores the user in memory when
+    /// Convex is disabled or unconfigured. The in-memory store is keyed by
+    /// email and shared by clones of the service; the existence check and
+    /// insert happen under one lock, so concurrent registrations for the
+    /// same email cannot both succeed.
+    ///
+    /// # Returns
+    /// ID of the new user
+    ///
+    /// # Errors
+    /// When the Convex call fails or returns no user ID, or `UserExists`
+    /// when the in-memory store already holds a user with the same email
     pub async fn create_user(&self, user_account: UserAccount) -> Result<String> {
-        let user_id = Uuid::new_v4().to_string();
-        
-        if !self.config.convex.enabled {
+        if self.uses_memory_store() {
             // Use in-memory storage as fallback
             let user = ConvexUser {
-                id: user_id.clone(),
-                email: user_account.email.clone(),
+                id: Uuid::new_v4().to_string(),
+                email: user_account.email,
                 password_hash: user_account.password_hash,
                 subscription_tier: user_account.subscription_tier,
                 api_key: user_account.api_key,
                 is_active: user_account.is_active,
                 created_at: Some(Utc::now()),
             };
-            
-            // Note: In a real implementation, we'd need to make this thread-safe
-            // For now, this is just a placeholder
-            tracing::info!("Creating user in memory store: {:?}", user);
+            let user_id = user.id.clone();
+            let mut memory_users = self.memory_users.write().unwrap();
+            if memory_users.contains_key(&user.email) {
+                return Err(UserExists(user.email).into());
+            }
+            tracing::info!("Creating user in memory store: {}", user.email);
+            memory_users.insert(user.email.clone(), user);
             return Ok(user_id);
         }
 
-        // TODO: Implement actual Convex integration
-        tracing::info!("Creating user: {:?}", user_account);
-        Ok(user_id)
+        let value = self
+            .run_function("mutation", "users:create", serde_json::to_value(&user_account)?)
+            .await?;
+        value
+            .as_str()
+            .map(str::to_string)
+            .ok_or_else(|| anyhow!("Convex mutation users:create returned no user ID"))
     }
 
+    /// Look up a user account by email
+    ///
+    /// Runs the `users:getByEmail` query, or reads the in-memory store when
+    /// Convex is disabled or unconfigured.
+    ///
+    /// # Errors
+    /// When the Convex call fails or returns a malformed user
     pub async fn get_user(&self, email: &str) -> Result<Option<ConvexUser>> {
-        if !self.config.convex.enabled {
+        if self.uses_memory_store() {
             // Use in-memory storage as fallback
-            // Note: This would return None in current implementation
-            // as we're not actually storing in memory
+            return Ok(self.memory_users.read().unwrap().get(email).cloned());
+        }
+
+        let value = self
+            .run_function("query", "users:getByEmail", json!({ "email": email }))
+            .await?;
+        if value.is_null() {
             return Ok(None);
         }
+        serde_json::from_value(value)
+            .map(Some)
+            .map_err(|e| anyhow!("Convex query users:getByEmail returned a malformed user: {}", e))
+    }
 
-        // TODO: Implement actual Convex integration
-        tracing::info!("Getting user by email: {}", email);
-        Ok(None)
+    /// Replace the password hash of a user account
+    ///
+    /// Runs the `users:updatePassword` mutation, or updates the in-memory
+    /// store when Convex is disabled or unconfigured.
+    ///
+    /// # Errors
+    /// When the Convex call fails, or the in-memory store has no user with
+    /// that id and email
+    pub async fn update_password_hash(&self, user_id: &str, email: &str, password_hash: &str) -> Result<()> {
+        if self.uses_memory_store() {
+            let mut memory_users = self.memory_users.write().unwrap();
+            return match memory_users.get_mut(email) {
+                Some(user) if user.id == user_id => {
+                    user.password_hash = password_hash.to_string();
+                    Ok(())
+                }
+                _ => Err(anyhow!("No user {} with email {}", user_id, email)),
+            };
+        }
+
+        self.run_function(
+            "mutation",
+            "users:updatePassword",
+            json!({ "user_id": user_id, "password_hash": password_hash }),
+        )
+        .await?;
+        Ok(())
     }
 
     pub async fn update_user_usage(
@@ -221,11 +521,7 @@ impl ConvexService {
         timeframe_hours: Option<u32>,
     ) -> Result<Value> {
         if !self.config.convex.enabled {
-            return Ok(serde_json::json!({
-                "requests": 0,
-                "tokens": 0,
-                "errors": 0
-            }));
+            return Ok(empty_analytics(timeframe_hours));
         }
 
         // TODO: Implement actual Convex integration
@@ -234,11 +530,7 @@ impl ConvexService {
             user_id, timeframe_hours
         );
         
-        Ok(serde_json::json!({
-            "requests": 0,
-            "tokens": 0,
-            "errors": 0
-        }))
+        Ok(empty_analytics(timeframe_hours))
     }
 
     pub async fn create_chat(
@@ -286,12 +578,15 @@ impl ConvexService {
 mod tests {
     use super::*;
     use crate::config::{Config, ConvexConfig};
+    use crate::test_support::spawn_mock;
     
     fn create_test_config(enabled: bool) -> Config {
         let mut config = Config::from_env();
         config.convex = ConvexConfig {
             url: if enabled { "https://test.convex.dev".to_string() } else { "".to_string() },
             enabled,
+            log_failure_threshold: 5,
+            log_cooldown_secs: 60,
         };
         config
     }
@@ -302,7 +597,7 @@ mod tests {
         let service = ConvexService::new(config.clone());
         
         // Should create service regardless of configuration
-        assert_eq!(service.config.convex.enabled, true);
+        assert!(service.config.convex.enabled);
         assert_eq!(service.config.convex.url, "https://test.convex.dev");
     }
     
@@ -311,7 +606,7 @@ mod tests {
         let config = create_test_config(false);
         let service = ConvexService::new(config);
         
-        assert_eq!(service.config.convex.enabled, false);
+        assert!(!service.config.convex.enabled);
         assert_eq!(service.config.convex.url, "");
     }
     
@@ -344,6 +639,119 @@ mod tests {
         assert!(result.is_ok());
     }
     
+    #[tokio::test]
+    async fn test_recent_api_requests_bounded_and_filtered() {
+        use crate::clock::MockClock;
+        use std::time::Duration;
+        
+        let mut config = create_test_config(false);
+        config.event_buffer_size = 2;
+        let clock = Arc::new(MockClock::new(10 * 60 * 60 * 1000));
+        let service = ConvexService::new(config).with_clock(clock.clone());
+        
+        let event = |request_id: &str| ApiRequestEvent {
+            request_id: request_id.to_string(),
+            user_id: None,
+            operation: "chat".to_string(),
+            tier: "fast".to_string(),
+            provider: "openai".to_string(),
+            model: "gpt-4o-mini".to_string(),
+            temperature: None,
+            max_tokens: None,
+            response_status: 200,
+            response_time_ms: 10,
+            input_messages: None,
+            input_tokens: None,
+            output_tokens: None,
+            error_message: None,
+            user_agent: None,
+            ip_address: None,
+        };
+        
+        service.log_api_request(event("req_1")).await.unwrap();
+        clock.advance(Duration::from_secs(2 * 60 * 60));
+        service.log_api_request(event("req_2")).await.unwrap();
+        service.log_api_request(event("req_3")).await.unwrap();
+        
+        // Oldest event is evicted once the buffer is full
+        let ids: Vec<_> = service
+            .recent_api_requests(None)
+            .into_iter()
+            .map(|recorded| recorded.event.request_id)
+            .collect();
+        assert_eq!(ids, ["req_2", "req_3"]);
+        
+        clock.advance(Duration::from_secs(2 * 60 * 60));
+        assert_eq!(service.recent_api_requests(Some(3)).len(), 2);
+        assert!(service.recent_api_requests(Some(1)).is_empty());
+    }
+    
+    /// Fake Convex deployment whose mutations always fail, counting calls
+    async fn spawn_failing_convex() -> (String, Arc<std::sync::atomic::AtomicU32>) {
+        use axum::{http::StatusCode, routing::post, Router};
+        use std::sync::atomic::{AtomicU32, Ordering};
+        
+        let calls = Arc::new(AtomicU32::new(0));
+        let counter = calls.clone();
+        let app = Router::new().route(
+            "/api/mutation",
+            post(move || {
+                let counter = counter.clone();
+                async move {
+                    counter.fetch_add(1, Ordering::SeqCst);
+                    StatusCode::INTERNAL_SERVER_ERROR
+                }
+            }),
+        );
+        
+        let addr = spawn_mock(app).await;
+        (format!("http://{}", addr), calls)
+    }
+    
+    #[tokio::test]
+    async fn test_repeated_log_failures_fall_back_to_console() {
+        use crate::clock::MockClock;
+        use std::sync::atomic::Ordering;
+        
+        let (url, calls) = spawn_failing_convex().await;
+        let mut config = create_test_config(true);
+        config.convex.url = url;
+        config.convex.log_failure_threshold = 3;
+        config.convex.log_cooldown_secs = 60;
+        let clock = Arc::new(MockClock::new(1_000_000));
+        let service = ConvexService::new(config).with_clock(clock.clone());
+        
+        let event = || UsageEvent {
+            user_id: None,
+            provider: "openai".to_string(),
+            model: "gpt-4o-mini".to_string(),
+            operation: "chat".to_string(),
+            input_tokens: 1,
+            output_tokens: 1,
+            cost_usd: None,
+        };
+        
+        // Failures below the threshold reach Convex and surface the error
+        for _ in 0..3 {
+            assert!(service.log_usage(event()).await.is_err());
+        }
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+        assert!(service.logging_degraded());
+        
+        // While degraded, events go to the console and Convex is not called
+        for _ in 0..5 {
+            assert!(service.log_usage(event()).await.is_ok());
+        }
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+        
+        // After the cooldown Convex is tried again, and re-trips on failure
+        clock.advance(Duration::from_secs(61));
+        assert!(!service.logging_degraded());
+        assert!(service.log_usage(event()).await.is_err());
+        assert_eq!(calls.load(Ordering::SeqCst), 4);
+        assert!(service.logging_degraded());
+    }
+    
     #[tokio::test]
     async fn test_log_usage_disabled() {
         let config = create_test_config(false);
@@ -385,6 +793,42 @@ mod tests {
         assert!(result.is_ok());
     }
     
+    #[tokio::test]
+    async fn test_logged_messages_are_timestamped_and_ordered() {
+        use crate::clock::MockClock;
+
+        let clock = Arc::new(MockClock::new(1_700_000_000_000));
+        let service = ConvexService::new(create_test_config(false)).with_clock(clock.clone());
+        let message = |message_type: &str, content: &str, created_at: Option<i64>| MessageEvent {
+            request_id: "req_1".to_string(),
+            chat_id: Some("chat_1".to_string()),
+            user_id: Some("user_1".to_string()),
+            message_type: message_type.to_string(),
+            content: content.to_string(),
+            provider: None,
+            model: None,
+            token_count: None,
+            created_at,
+            attachments: None,
+        };
+
+        service.log_message(message("user", "Hi", None)).await.unwrap();
+        clock.advance(Duration::from_millis(5));
+        service.log_message(message("assistant", "Hello!", None)).await.unwrap();
+        // Logged late but stamped earlier by the caller
+        service.log_message(message("system", "Be brief.", Some(1_600_000_000_000))).await.unwrap();
+
+        let messages = service.get_chat_messages("chat_1").await.unwrap();
+        let created: Vec<Option<i64>> = messages.iter().map(|message| message.created_at).collect();
+        assert_eq!(
+            created,
+            [Some(1_600_000_000_000), Some(1_700_000_000_000), Some(1_700_000_000_005)]
+        );
+        assert_eq!(messages[1].content, "Hi");
+        assert_eq!(messages[2].content, "Hello!");
+        assert!(service.get_chat_messages("chat_2").await.unwrap().is_empty());
+    }
+
     #[tokio::test]
     async fn test_get_user_disabled() {
         let config = create_test_config(false);
@@ -395,6 +839,28 @@ mod tests {
         assert!(result.unwrap().is_none()); // Should return None when disabled
     }
     
+    #[tokio::test]
+    async fn test_with_seed_users() {
+        let user = ConvexUser {
+            id: "user_seeded".to_string(),
+            email: "seeded@example.com".to_string(),
+            password_hash: "hash123".to_string(),
+            subscription_tier: "premium".to_string(),
+            api_key: "ak_seeded".to_string(),
+            is_active: true,
+            created_at: None,
+        };
+        let service = ConvexService::with_seed_users(create_test_config(false), vec![user]);
+        
+        let found = service.get_user("seeded@example.com").await.unwrap().unwrap();
+        assert_eq!(found.id, "user_seeded");
+        assert_eq!(found.subscription_tier, "premium");
+        assert!(service.get_user("other@example.com").await.unwrap().is_none());
+        
+        // Clones share the same store
+        assert!(service.clone().get_user("seeded@example.com").await.unwrap().is_some());
+    }
+    
     #[tokio::test]
     async fn test_create_user_disabled() {
         let config = create_test_config(false);
@@ -413,6 +879,129 @@ mod tests {
         assert!(!result.unwrap().is_empty()); // Should return non-empty string when disabled
     }
     
+    #[tokio::test]
+    async fn test_create_user_disabled_is_stored() {
+        let service = ConvexService::new(create_test_config(false));
+        let user_id = service
+            .create_user(UserAccount {
+                email: "stored@example.com".to_string(),
+                password_hash: "hash123".to_string(),
+                subscription_tier: "pro".to_string(),
+                api_key: "key123".to_string(),
+                is_active: true,
+            })
+            .await
+            .unwrap();
+        
+        let stored = service.clone().get_user("stored@example.com").await.unwrap().unwrap();
+        assert_eq!(stored.id, user_id);
+        assert_eq!(stored.subscription_tier, "pro");
+        
+        // A second account with the same email is refused, also through clones
+        let error = service
+            .clone()
+            .create_user(UserAccount {
+                email: "stored@example.com".to_string(),
+                password_hash: "other".to_string(),
+                subscription_tier: "free".to_string(),
+                api_key: "key456".to_string(),
+                is_active: true,
+            })
+            .await
+            .unwrap_err();
+        assert!(error.to_string().contains("already exists"));
+        assert_eq!(service.get_user("stored@example.com").await.unwrap().unwrap().api_key, "key123");
+    }
+    
+    /// Fake Convex deployment implementing `users:create` and `users:getByEmail`
+    async fn spawn_user_convex() -> String {
+        use axum::{routing::post, Json, Router};
+        
+        let users: Arc<Mutex<HashMap<String, Value>>> = Arc::default();
+        let created = users.clone();
+        let app = Router::new()
+            .route(
+                "/api/mutation",
+                post(move |Json(body): Json<Value>| {
+                    let users = created.clone();
+                    async move {
+                        assert_eq!(body["path"], "users:create");
+                        let mut user = body["args"].clone();
+                        user["_id"] = json!("jd7abc");
+                        let email = user["email"].as_str().unwrap().to_string();
+                        users.lock().unwrap().insert(email, user);
+                        Json(json!({ "status": "success", "value": "jd7abc" }))
+                    }
+                }),
+            )
+            .route(
+                "/api/query",
+                post(move |Json(body): Json<Value>| {
+                    let users = users.clone();
+                    async move {
+                        if body["path"] != "users:getByEmail" {
+                            return Json(json!({ "status": "error", "errorMessage": "unknown function" }));
+                        }
+                        let email = body["args"]["email"].as_str().unwrap();
+                        let user = users.lock().unwrap().get(email).cloned().unwrap_or(Value::Null);
+                        Json(json!({ "status": "success", "value": user }))
+                    }
+                }),
+            );
+        
+        let addr = spawn_mock(app).await;
+        format!("http://{}", addr)
+    }
+    
+    #[tokio::test]
+    async fn test_users_round_trip_through_convex() {
+        let mut config = create_test_config(true);
+        config.convex.url = spawn_user_convex().await;
+        let service = ConvexService::new(config);
+        
+        let user_id = service
+            .create_user(UserAccount {
+                email: "remote@example.com".to_string(),
+                password_hash: "hash123".to_string(),
+                subscription_tier: "free".to_string(),
+                api_key: "key123".to_string(),
+                is_active: true,
+            })
+            .await
+            .unwrap();
+        assert_eq!(user_id, "jd7abc");
+        
+        let user = service.get_user("remote@example.com").await.unwrap().unwrap();
+        assert_eq!(user.id, "jd7abc");
+        assert_eq!(user.api_key, "key123");
+        assert!(user.created_at.is_none());
+        assert!(service.get_user("missing@example.com").await.unwrap().is_none());
+    }
+    
+    #[tokio::test]
+    async fn test_convex_user_errors_include_status() {
+        let (url, _) = spawn_failing_convex().await;
+        let mut config = create_test_config(true);
+        config.convex.url = url;
+        let service = ConvexService::new(config);
+        
+        let error = service
+            .create_user(UserAccount {
+                email: "remote@example.com".to_string(),
+                password_hash: "hash123".to_string(),
+                subscription_tier: "free".to_string(),
+                api_key: "key123".to_string(),
+                is_active: true,
+            })
+            .await
+            .unwrap_err();
+        assert!(error.to_string().contains("500"), "{}", error);
+        
+        // The failing deployment has no query route at all
+        let error = service.get_user("remote@example.com").await.unwrap_err();
+        assert!(error.to_string().contains("404"), "{}", error);
+    }
+    
     #[tokio::test]
     async fn test_log_system_event_disabled() {
         let config = create_test_config(false);
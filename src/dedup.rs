@@ -0,0 +1,174 @@
+//! Request Deduplication Module
+//!
+//! Absorbs accidental double-submits of identical invoke requests.
+//! Requests are keyed by caller identity plus a hash of the normalized
+//! request body; an identical request that is still in flight, or that
+//! completed within the dedup window, shares the original result instead
+//! of triggering a second provider call.
+//!
+//! Failed requests are never cached, so a retry after an error always
+//! performs fresh work.
+
+use serde::Serialize;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::future::Future;
+use std::hash::{Hash, Hasher};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::OnceCell;
+
+/// Shared slot holding the result of the first request for a key
+/// along with the time it completed
+type DedupSlot<T> = Arc<OnceCell<(T, Instant)>>;
+
+/// Deduplicates identical requests within a short time window
+#[derive(Clone)]
+pub struct RequestDeduplicator<T> {
+    /// How long a completed result is reused for identical requests
+    window: Duration,
+    /// In-flight and recently completed requests by dedup key
+    entries: Arc<Mutex<HashMap<String, DedupSlot<T>>>>,
+}
+
+impl<T: Clone> RequestDeduplicator<T> {
+    /// Create a deduplicator with the given window in milliseconds
+    ///
+    /// A window of zero disables deduplication entirely.
+    pub fn new(window_ms: u64) -> Self {
+        Self {
+            window: Duration::from_millis(window_ms),
+            entries: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// Run `op` unless an identical request is in flight or recently completed
+    ///
+    /// Concurrent callers with the same key wait for the first caller's
+    /// result. If the first caller fails, the error is returned to it alone
+    /// and the next waiter performs the work itself.
+    ///
+    /// # Arguments
+    /// * `key` - Dedup key (see `dedup_key`)
+    /// * `op` - Work to perform when no reusable result exists
+    pub async fn run<E, F, Fut>(&self, key: String, op: F) -> Result<T, E>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<T, E>>,
+    {
+        if self.window.is_zero() {
+            return op().await;
+        }
+
+        let slot = {
+            let mut entries = self.entries.lock().unwrap();
+
+            // Drop completed results that have aged out of the window;
+            // in-flight entries are kept until they complete
+            let window = self.window;
+            entries.retain(|_, slot| match slot.get() {
+                Some((_, completed_at)) => completed_at.elapsed() < window,
+                None => true,
+            });
+
+            entries.entry(key).or_default().clone()
+        };
+
+        slot.get_or_try_init(|| async { op().await.map(|value| (value, Instant::now())) })
+            .await
+            .map(|(value, _)| value.clone())
+    }
+}
+
+/// Build a dedup key from the caller identity and request body
+///
+/// The body is serialized through `serde_json::Value`, whose object keys
+/// are sorted, so logically identical bodies produce the same key
+/// regardless of field order.
+pub fn dedup_key<B: Serialize>(caller_id: &str, body: &B) -> Option<String> {
+    let normalized = serde_json::to_value(body).ok()?.to_string();
+
+    let mut hasher = DefaultHasher::new();
+    caller_id.hash(&mut hasher);
+    normalized.hash(&mut hasher);
+
+    Some(format!("{}:{:016x}", caller_id, hasher.finish()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    async fn counted_call(calls: &AtomicU32, delay_ms: u64) -> Result<u32, String> {
+        let call = calls.fetch_add(1, Ordering::SeqCst) + 1;
+        tokio::time::sleep(Duration::from_millis(delay_ms)).await;
+        Ok(call)
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_identical_requests_share_one_call() {
+        let dedup = RequestDeduplicator::new(2000);
+        let calls = AtomicU32::new(0);
+
+        let (first, second) = tokio::join!(
+            dedup.run("user:abc".to_string(), || counted_call(&calls, 50)),
+            dedup.run("user:abc".to_string(), || counted_call(&calls, 50)),
+        );
+
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+        assert_eq!(first.unwrap(), 1);
+        assert_eq!(second.unwrap(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_completed_result_reused_within_window() {
+        let dedup = RequestDeduplicator::new(2000);
+        let calls = AtomicU32::new(0);
+
+        dedup.run("k".to_string(), || counted_call(&calls, 0)).await.unwrap();
+        dedup.run("k".to_string(), || counted_call(&calls, 0)).await.unwrap();
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+        // Different keys are independent
+        dedup.run("other".to_string(), || counted_call(&calls, 0)).await.unwrap();
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_expired_and_failed_results_are_not_reused() {
+        let dedup = RequestDeduplicator::new(20);
+        let calls = AtomicU32::new(0);
+
+        dedup.run("k".to_string(), || counted_call(&calls, 0)).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(40)).await;
+        dedup.run("k".to_string(), || counted_call(&calls, 0)).await.unwrap();
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+
+        let failed: Result<u32, String> = dedup
+            .run("err".to_string(), || async { Err("boom".to_string()) })
+            .await;
+        assert!(failed.is_err());
+        let retried = dedup.run("err".to_string(), || counted_call(&calls, 0)).await;
+        assert_eq!(retried.unwrap(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_zero_window_disables_dedup() {
+        let dedup = RequestDeduplicator::new(0);
+        let calls = AtomicU32::new(0);
+
+        dedup.run("k".to_string(), || counted_call(&calls, 0)).await.unwrap();
+        dedup.run("k".to_string(), || counted_call(&calls, 0)).await.unwrap();
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn test_dedup_key_ignores_field_order() {
+        let a = serde_json::json!({"op": "chat", "input": {"a": 1, "b": 2}});
+        let b: serde_json::Value = serde_json::from_str(r#"{"input": {"b": 2, "a": 1}, "op": "chat"}"#).unwrap();
+
+        assert_eq!(dedup_key("user_1", &a), dedup_key("user_1", &b));
+        assert_ne!(dedup_key("user_1", &a), dedup_key("user_2", &a));
+    }
+}
@@ -0,0 +1,293 @@
+//! Dispatch Queue Module
+//!
+//! Bounds how many requests are dispatched to providers at once and
+//! decides who goes next when the gateway is saturated.
+//!
+//! Requests that cannot be dispatched immediately wait in a priority queue
+//! ordered by the caller's subscription tier (pro > free > anonymous), and
+//! first-come first-served within a tier. When the queue is full further
+//! requests are shed so the caller can retry instead of piling up.
+
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+use std::sync::{Arc, Mutex};
+use tokio::sync::oneshot;
+
+/// Dispatch priority derived from the caller's subscription tier
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum DispatchPriority {
+    /// Guests and unauthenticated callers
+    Anonymous,
+    /// Registered users on the free tier
+    Free,
+    /// Registered users on any paid tier
+    Pro,
+}
+
+impl DispatchPriority {
+    /// Priority for a registered user's subscription tier
+    ///
+    /// Every tier other than `free` is a paid tier.
+    pub fn for_tier(tier: &str) -> Self {
+        if tier.eq_ignore_ascii_case("free") {
+            Self::Free
+        } else {
+            Self::Pro
+        }
+    }
+}
+
+/// Request waiting for a dispatch slot
+struct Waiter {
+    priority: DispatchPriority,
+    /// Arrival order, to keep each priority first-come first-served
+    seq: u64,
+    /// Receives the slot when it is handed to this waiter
+    ready: oneshot::Sender<DispatchPermit>,
+}
+
+impl PartialEq for Waiter {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Waiter {}
+
+impl PartialOrd for Waiter {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Waiter {
+    /// Max-heap order: higher priority first, then earlier arrival
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.priority
+            .cmp(&other.priority)
+            .then_with(|| other.seq.cmp(&self.seq))
+    }
+}
+
+/// Shared queue state
+#[derive(Default)]
+struct QueueState {
+    /// Slots currently held
+    active: usize,
+    /// Requests waiting for a slot
+    waiting: BinaryHeap<Waiter>,
+    /// Sequence number for the next waiter
+    next_seq: u64,
+}
+
+/// Priority queue in front of provider dispatch
+#[derive(Clone)]
+pub struct DispatchQueue {
+    /// Requests dispatched at once (0 disables the limit)
+    max_concurrency: usize,
+    /// Requests allowed to wait for a slot
+    max_queued: usize,
+    state: Arc<Mutex<QueueState>>,
+}
+
+impl DispatchQueue {
+    /// Create a queue
+    ///
+    /// # Arguments
+    /// * `max_concurrency` - Requests dispatched at once (0 for unlimited)
+    /// * `max_queued` - Requests allowed to wait before new ones are shed
+    pub fn new(max_concurrency: usize, max_queued: usize) -> Self {
+        Self {
+            max_concurrency,
+            max_queued,
+            state: Arc::new(Mutex::new(QueueState::default())),
+        }
+    }
+
+    /// Wait for a dispatch slot
+    ///
+    /// # Returns
+    /// A permit holding the slot until dropped, or `None` when the queue is
+    /// full and the request should be shed
+    pub async fn acquire(&self, priority: DispatchPriority) -> Option<DispatchPermit> {
+        if self.max_concurrency == 0 {
+            return Some(DispatchPermit { queue: None });
+        }
+
+        let ready = {
+            let mut state = self.state.lock().unwrap();
+            // Waiters that gave up no longer count against the queue size
+            state.waiting.retain(|waiter| !waiter.ready.is_closed());
+
+            if state.active < self.max_concurrency && state.waiting.is_empty() {
+                state.active += 1;
+                return Some(self.permit());
+            }
+            if state.waiting.len() >= self.max_queued {
+                return None;
+            }
+
+            let (sender, receiver) = oneshot::channel();
+            let seq = state.next_seq;
+            state.next_seq += 1;
+            state.waiting.push(Waiter { priority, seq, ready: sender });
+            receiver
+        };
+
+        // The releasing permit hands its slot over directly
+        ready.await.ok()
+    }
+
+    /// Number of requests waiting for a slot
+    #[cfg(test)]
+    pub fn queued(&self) -> usize {
+        self.state.lock().unwrap().waiting.len()
+    }
+
+    fn permit(&self) -> DispatchPermit {
+        DispatchPermit {
+            queue: Some(self.state.clone()),
+        }
+    }
+}
+
+/// Dispatch slot, released to the next waiter when dropped
+pub struct DispatchPermit {
+    queue: Option<Arc<Mutex<QueueState>>>,
+}
+
+impl Drop for DispatchPermit {
+    fn drop(&mut self) {
+        let Some(queue) = self.queue.take() else { return };
+        let mut state = queue.lock().unwrap();
+
+        // Hand the slot to the next live waiter. A waiter that is cancelled
+        // after this drops the permit unused, which passes the slot on again.
+        let mut permit = DispatchPermit {
+            queue: Some(queue.clone()),
+        };
+        while let Some(waiter) = state.waiting.pop() {
+            match waiter.ready.send(permit) {
+                Ok(()) => return,
+                Err(unsent) => permit = unsent,
+            }
+        }
+
+        // Nobody is waiting; disarm the unsent permit and free the slot
+        permit.queue = None;
+        state.active -= 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    /// Wait until `count` requests are queued
+    async fn until_queued(queue: &DispatchQueue, count: usize) {
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while queue.queued() < count {
+                tokio::task::yield_now().await;
+            }
+        })
+        .await
+        .expect("requests were not queued");
+    }
+
+    #[tokio::test]
+    async fn test_higher_priority_requests_are_dispatched_first() {
+        let queue = DispatchQueue::new(1, 10);
+        let held = queue.acquire(DispatchPriority::Free).await.unwrap();
+
+        let dispatched = Arc::new(Mutex::new(Vec::new()));
+        let mut tasks = Vec::new();
+        for (label, priority) in [
+            ("guest-1", DispatchPriority::Anonymous),
+            ("free-1", DispatchPriority::Free),
+            ("pro-1", DispatchPriority::Pro),
+            ("guest-2", DispatchPriority::Anonymous),
+            ("pro-2", DispatchPriority::Pro),
+        ] {
+            let waiting = queue.clone();
+            let dispatched = dispatched.clone();
+            tasks.push(tokio::spawn(async move {
+                let _permit = waiting.acquire(priority).await.unwrap();
+                dispatched.lock().unwrap().push(label);
+            }));
+            // Enqueue one at a time so arrival order is deterministic
+            until_queued(&queue, tasks.len()).await;
+        }
+
+        drop(held);
+        for task in tasks {
+            task.await.unwrap();
+        }
+        assert_eq!(
+            *dispatched.lock().unwrap(),
+            ["pro-1", "pro-2", "free-1", "guest-1", "guest-2"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_full_queue_sheds_requests() {
+        let queue = DispatchQueue::new(1, 1);
+        let held = queue.acquire(DispatchPriority::Pro).await.unwrap();
+
+        let waiter = {
+            let queue = queue.clone();
+            tokio::spawn(async move { queue.acquire(DispatchPriority::Free).await.is_some() })
+        };
+        until_queued(&queue, 1).await;
+        assert!(queue.acquire(DispatchPriority::Pro).await.is_none());
+
+        drop(held);
+        assert!(waiter.await.unwrap());
+        // All slots were released
+        assert!(queue.acquire(DispatchPriority::Anonymous).await.is_some());
+    }
+
+    #[tokio::test]
+    async fn test_abandoned_waiters_release_their_place() {
+        let queue = DispatchQueue::new(1, 1);
+        let held = queue.acquire(DispatchPriority::Free).await.unwrap();
+
+        let abandoned = {
+            let queue = queue.clone();
+            tokio::spawn(async move { queue.acquire(DispatchPriority::Pro).await.is_some() })
+        };
+        until_queued(&queue, 1).await;
+        abandoned.abort();
+        let _ = abandoned.await;
+
+        // The cancelled waiter's spot is reused and its slot is not leaked
+        let next = {
+            let queue = queue.clone();
+            tokio::spawn(async move { queue.acquire(DispatchPriority::Anonymous).await.is_some() })
+        };
+        until_queued(&queue, 1).await;
+        drop(held);
+        assert!(next.await.unwrap());
+        assert_eq!(queue.state.lock().unwrap().active, 0);
+    }
+
+    #[test]
+    fn test_priority_for_tier() {
+        assert_eq!(DispatchPriority::for_tier("free"), DispatchPriority::Free);
+        assert_eq!(DispatchPriority::for_tier("pro"), DispatchPriority::Pro);
+        assert_eq!(DispatchPriority::for_tier("premium"), DispatchPriority::Pro);
+        assert!(DispatchPriority::Pro > DispatchPriority::Free);
+        assert!(DispatchPriority::Free > DispatchPriority::Anonymous);
+    }
+
+    #[tokio::test]
+    async fn test_unlimited_queue_never_waits() {
+        let queue = DispatchQueue::new(0, 0);
+        let permits: Vec<_> = futures::future::join_all(
+            (0..5).map(|_| queue.acquire(DispatchPriority::Anonymous)),
+        )
+        .await;
+        assert!(permits.iter().all(Option::is_some));
+        assert_eq!(queue.queued(), 0);
+    }
+}
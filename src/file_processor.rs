@@ -1,43 +1,401 @@
 use anyhow::{anyhow, Result};
 use base64::prelude::*;
-use reqwest::Client;
+use reqwest::header::{ETAG, IF_NONE_MATCH, LOCATION};
+use reqwest::redirect::Policy;
+use reqwest::{Client, Url};
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
 
+use crate::callback::{is_public_ip, resolve_host};
+use crate::clock::{system_clock, SharedClock};
+use crate::config::Config;
+use crate::http_client::tls_client_builder;
 use crate::types::Attachment;
 
+/// Default time processed attachment content is reused
+pub const DEFAULT_ATTACHMENT_CACHE_TTL: Duration = Duration::from_secs(10 * 60);
+
+/// Default number of redirects followed when fetching an attachment URL
+pub const DEFAULT_ATTACHMENT_MAX_REDIRECTS: usize = 5;
+
+/// Default number of attachments accepted in one request
+pub const DEFAULT_MAX_ATTACHMENTS: usize = 10;
+
+/// Default combined size of one request's attachments in bytes
+pub const DEFAULT_MAX_ATTACHMENT_BYTES: usize = 25 * 1024 * 1024;
+
+/// Default number of characters kept from a text attachment
+pub const DEFAULT_ATTACHMENT_MAX_TEXT_CHARS: usize = 100_000;
+
+/// Largest response body read when fetching an attachment URL
+const MAX_FETCH_BYTES: usize = 10 * 1024 * 1024;
+
+/// Characters of a text attachment shown in the model context
+const PREVIEW_CHARS: usize = 2000;
+
+/// How many attachments one request may carry, how large they may be
+/// together, and how much of each text attachment is kept
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AttachmentLimits {
+    /// Attachments accepted per request
+    pub max_attachments: usize,
+    /// Combined estimated size in bytes
+    pub max_total_bytes: usize,
+    /// Characters kept from each text attachment; the rest is never read
+    /// into memory (0 keeps everything)
+    pub max_text_chars: usize,
+    /// Whether CRLF line endings in text attachments become LF
+    pub normalize_newlines: bool,
+}
+
+impl Default for AttachmentLimits {
+    fn default() -> Self {
+        Self {
+            max_attachments: DEFAULT_MAX_ATTACHMENTS,
+            max_total_bytes: DEFAULT_MAX_ATTACHMENT_BYTES,
+            max_text_chars: DEFAULT_ATTACHMENT_MAX_TEXT_CHARS,
+            normalize_newlines: true,
+        }
+    }
+}
+
+impl AttachmentLimits {
+    /// Limits from `MAX_ATTACHMENTS`, `MAX_ATTACHMENT_BYTES`,
+    /// `ATTACHMENT_MAX_TEXT_CHARS` and `ATTACHMENT_NORMALIZE_NEWLINES` in
+    /// `config`
+    pub fn from_config(config: &Config) -> Self {
+        Self {
+            max_attachments: config.max_attachments,
+            max_total_bytes: config.max_total_attachment_bytes,
+            max_text_chars: config.attachment_max_text_chars,
+            normalize_newlines: config.attachment_normalize_newlines,
+        }
+    }
+
+    /// Bytes of a text attachment needed to hold `max_text_chars`
+    /// characters (UTF-8 uses at most four bytes per character)
+    fn max_text_bytes(&self) -> usize {
+        match self.max_text_chars {
+            0 => usize::MAX,
+            chars => chars.saturating_mul(4),
+        }
+    }
+
+    /// Fail when `attachments` are too many or too large together
+    ///
+    /// Runs before anything is decoded or fetched: data URLs count with
+    /// their decoded size estimated from the payload length, other URLs
+    /// with their declared `size`.
+    fn check(&self, attachments: &[Attachment]) -> Result<()> {
+        if attachments.len() > self.max_attachments {
+            return Err(anyhow!(
+                "Too many attachments: {} (MAX_ATTACHMENTS is {})",
+                attachments.len(),
+                self.max_attachments
+            ));
+        }
+        let total = attachments
+            .iter()
+            .map(|attachment| match attachment.url.strip_prefix("data:") {
+                Some(data) => estimated_data_url_size(data),
+                None => attachment.size.map_or(0, |size| usize::try_from(size).unwrap_or(usize::MAX)),
+            })
+            .fold(0usize, usize::saturating_add);
+        if total > self.max_total_bytes {
+            return Err(anyhow!(
+                "Attachments too large: about {} bytes in total (MAX_ATTACHMENT_BYTES is {})",
+                total,
+                self.max_total_bytes
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Decoded size of a data URL (without its `data:` prefix), estimated from
+/// the payload length without decoding it
+///
+/// Base64 payloads decode to three bytes per four characters; other
+/// payloads decode to at most their own length.
+fn estimated_data_url_size(data: &str) -> usize {
+    let Some((meta, payload)) = data.split_once(',') else {
+        return 0;
+    };
+    if meta.contains(";base64") {
+        let padding = payload.bytes().rev().take_while(|&byte| byte == b'=').count();
+        (payload.len() / 4 * 3).saturating_sub(padding)
+    } else {
+        payload.len()
+    }
+}
+
+/// Where attachment URLs may be fetched from
+///
+/// The first URL and every redirect target are checked against it, so a
+/// public URL cannot redirect the fetch into the internal network.
+#[derive(Debug, Clone)]
+pub struct FetchPolicy {
+    /// Redirects followed before the fetch fails (0 follows none)
+    pub max_redirects: usize,
+    /// Hosts redirects may point to (empty allows any host that passes
+    /// the address check)
+    pub redirect_hosts: Vec<String>,
+    /// Whether loopback and private network addresses may be fetched
+    pub allow_private: bool,
+}
+
+impl Default for FetchPolicy {
+    fn default() -> Self {
+        Self {
+            max_redirects: DEFAULT_ATTACHMENT_MAX_REDIRECTS,
+            redirect_hosts: Vec::new(),
+            allow_private: false,
+        }
+    }
+}
+
+impl FetchPolicy {
+    /// Fetch policy from the attachment settings in `config`
+    pub fn from_config(config: &Config) -> Self {
+        Self {
+            max_redirects: config.attachment_max_redirects,
+            redirect_hosts: config.attachment_redirect_hosts.clone(),
+            allow_private: config.attachment_allow_private_networks,
+        }
+    }
+
+    /// Whether a redirect to `url` may be followed, before its address check
+    fn allows_redirect_to(&self, url: &Url) -> bool {
+        let Some(host) = url.host_str() else {
+            return false;
+        };
+        self.redirect_hosts.is_empty()
+            || self.redirect_hosts.iter().any(|allowed| allowed.eq_ignore_ascii_case(host))
+    }
+
+    /// Fail unless `url` is http(s) and its host resolves only to
+    /// addresses this policy allows
+    ///
+    /// # Returns
+    /// The checked address the request must connect to
+    async fn check_address(&self, url: &Url) -> Result<SocketAddr> {
+        if !matches!(url.scheme(), "http" | "https") {
+            return Err(anyhow!("Unsupported URL scheme: {}", url));
+        }
+        let host = url.host_str().ok_or_else(|| anyhow!("URL has no host: {}", url))?;
+        let port = url.port_or_known_default().ok_or_else(|| anyhow!("URL has no port: {}", url))?;
+        let addrs = resolve_host(host, port)
+            .await
+            .map_err(|e| anyhow!("Failed to resolve {}: {}", host, e))?;
+        if addrs.is_empty() {
+            return Err(anyhow!("Failed to resolve {}", host));
+        }
+        if !self.allow_private && !addrs.iter().all(|addr| is_public_ip(addr.ip())) {
+            return Err(anyhow!("Refusing to fetch non-public address: {}", url));
+        }
+        Ok(addrs[0])
+    }
+}
+
+/// Builds the HTTP clients for attachment fetches
+///
+/// Redirects are not followed by the clients; `FetchPolicy` decides which
+/// ones the fetch follows. Each request to a named host gets a client
+/// pinned to the address that passed the policy's check, so a second DNS
+/// lookup cannot point the connection somewhere else.
+#[derive(Debug, Clone, Default)]
+pub struct AttachmentClient {
+    /// Refuse connections older than TLS 1.2 (`ENFORCE_MIN_TLS`)
+    enforce_min_tls: bool,
+}
+
+impl AttachmentClient {
+    /// Attachment clients with the outbound settings from `config`
+    pub fn new(config: &Config) -> Self {
+        Self {
+            enforce_min_tls: config.enforce_min_tls,
+        }
+    }
+
+    /// Client for one request to `url` that connects to `addr`
+    fn pinned(&self, url: &Url, addr: SocketAddr) -> Result<Client> {
+        let mut builder = tls_client_builder(self.enforce_min_tls).redirect(Policy::none());
+        if let Some(domain) = url.domain() {
+            builder = builder.resolve(domain, addr);
+        }
+        builder
+            .build()
+            .map_err(|e| anyhow!("Failed to create attachment client: {}", e))
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ProcessedAttachment {
     pub name: String,
     pub content_type: String,
     pub content: String,
     pub is_image: bool,
+    /// Hex-encoded SHA-256 of the attachment's decoded bytes
+    pub content_hash: String,
+}
+
+/// Where a cached attachment came from and what it hashed to
+#[derive(Debug, Clone)]
+struct CachedSource {
+    content_hash: String,
+    /// Validator the server sent with the content, for conditional refetches
+    etag: Option<String>,
+    /// When the source was stored (ms since epoch)
+    stored_at: u64,
+}
+
+/// Cache of processed attachment content keyed by content hash
+///
+/// Identical files attached across requests are only processed once
+/// within the TTL. Sources are remembered as well, so a repeated data URL
+/// is not decoded again and a repeated URL is revalidated with its ETag
+/// instead of downloaded.
+#[derive(Clone)]
+pub struct AttachmentCache {
+    ttl: Duration,
+    /// Processed content and the time it was stored (ms since epoch)
+    entries: Arc<Mutex<HashMap<String, (String, u64)>>>,
+    /// Content hash of each source, by `source_key`
+    sources: Arc<Mutex<HashMap<String, CachedSource>>>,
+    clock: SharedClock,
+}
+
+impl AttachmentCache {
+    /// Create a cache that reuses processed content for `ttl`
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            entries: Arc::new(Mutex::new(HashMap::new())),
+            sources: Arc::new(Mutex::new(HashMap::new())),
+            clock: system_clock(),
+        }
+    }
+
+    /// Use the given clock for expiry instead of the system clock
+    pub fn with_clock(mut self, clock: SharedClock) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Whether an entry stored at `stored_at` (ms) is still fresh
+    fn is_fresh(&self, stored_at: u64) -> bool {
+        (self.clock.now_ms().saturating_sub(stored_at) as u128) < self.ttl.as_millis()
+    }
+
+    /// Get processed content for a hash if it has not expired
+    pub fn get(&self, content_hash: &str) -> Option<String> {
+        let entries = self.entries.lock().unwrap();
+        entries
+            .get(content_hash)
+            .filter(|(_, stored_at)| self.is_fresh(*stored_at))
+            .map(|(content, _)| content.clone())
+    }
+
+    /// Store processed content under its hash, evicting expired entries
+    pub fn insert(&self, content_hash: String, content: String) {
+        let mut entries = self.entries.lock().unwrap();
+        entries.retain(|_, (_, stored_at)| self.is_fresh(*stored_at));
+        entries.insert(content_hash, (content, self.clock.now_ms()));
+    }
+
+    /// Content hash and ETag last seen for `key`, with the content itself
+    /// when it is still cached
+    fn source(&self, key: &str) -> Option<(String, Option<String>, String)> {
+        let source = self
+            .sources
+            .lock()
+            .unwrap()
+            .get(key)
+            .filter(|source| self.is_fresh(source.stored_at))
+            .cloned()?;
+        let content = self.get(&source.content_hash)?;
+        Some((source.content_hash, source.etag, content))
+    }
+
+    /// Remember that `key` produced the content stored under `content_hash`
+    fn insert_source(&self, key: String, content_hash: String, etag: Option<String>) {
+        let mut sources = self.sources.lock().unwrap();
+        sources.retain(|_, source| self.is_fresh(source.stored_at));
+        let stored_at = self.clock.now_ms();
+        sources.insert(key, CachedSource { content_hash, etag, stored_at });
+    }
+
+    /// Number of cached entries, including any not yet evicted
+    #[cfg(test)]
+    fn len(&self) -> usize {
+        self.entries.lock().unwrap().len()
+    }
+}
+
+impl Default for AttachmentCache {
+    fn default() -> Self {
+        Self::new(DEFAULT_ATTACHMENT_CACHE_TTL)
+    }
+}
+
+/// Hex-encoded SHA-256 of the given bytes
+pub fn content_hash(bytes: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(bytes))
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ProcessResult {
     pub processed_attachments: Vec<ProcessedAttachment>,
     pub context_prompt: String,
+    /// Failure reasons per attachment, only filled when details are reported
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub failures: Vec<String>,
 }
 
 /// Process file attachments for AI model consumption
-#[allow(dead_code)]
+///
+/// Each attachment gets at most `timeout` (`ATTACHMENT_PROCESS_TIMEOUT_MS`);
+/// one that takes longer is reported as failed so a slow upload cannot
+/// stall the whole request. URLs are fetched under `policy` with clients
+/// from `client`.
+///
+/// With `error_details` (`ATTACHMENT_ERROR_DETAILS`) a failed attachment is
+/// marked with its failure reason and listed in `failures`; otherwise only a
+/// generic marker is inserted so internal details are not leaked.
+///
+/// # Errors
+/// When the attachments exceed `limits`, naming the limit that was hit
 pub async fn process_file_attachments(
-    client: &Client,
+    client: &AttachmentClient,
     attachments: &[Attachment],
+    cache: &AttachmentCache,
+    timeout: Duration,
+    policy: &FetchPolicy,
+    limits: &AttachmentLimits,
+    error_details: bool,
 ) -> Result<ProcessResult> {
+    limits.check(attachments)?;
+
     let mut processed_attachments = Vec::new();
     let mut context_parts = Vec::new();
+    let mut failures = Vec::new();
 
     for attachment in attachments {
-        match process_file_attachment(client, attachment).await {
+        let outcome = tokio::time::timeout(timeout, process_file_attachment(client, attachment, cache, policy, limits))
+            .await
+            .unwrap_or_else(|_| Err(anyhow!("Timed out after {}ms", timeout.as_millis())));
+        match outcome {
             Ok(processed) => {
                 if processed.is_image {
                     context_parts.push(format!("[Image: {}]", processed.name));
                 } else {
-                    let content_preview = if processed.content.len() > 2000 {
-                        format!("{}... (truncated)", &processed.content[..2000])
-                    } else {
-                        processed.content.clone()
+                    let content_preview = match processed.content.char_indices().nth(PREVIEW_CHARS) {
+                        Some((end, _)) => format!("{}... (truncated)", &processed.content[..end]),
+                        None => processed.content.clone(),
                     };
 
                     let context_entry = if !content_preview.is_empty() {
@@ -55,7 +413,13 @@ pub async fn process_file_attachments(
             }
             Err(error) => {
                 tracing::error!("Failed to process attachment {}: {}", attachment.name, error);
-                context_parts.push(format!("[File: {} - Processing failed]", attachment.name));
+                if error_details {
+                    let failure = format!("[File: {} - failed: {}]", attachment.name, failure_reason(&error));
+                    context_parts.push(failure.clone());
+                    failures.push(failure);
+                } else {
+                    context_parts.push(format!("[File: {} - Processing failed]", attachment.name));
+                }
             }
         }
     }
@@ -72,48 +436,186 @@ pub async fn process_file_attachments(
     Ok(ProcessResult {
         processed_attachments,
         context_prompt,
+        failures,
     })
 }
 
+/// Lowercase the leading letter of an error message for use mid-sentence
+fn failure_reason(error: &anyhow::Error) -> String {
+    let message = error.to_string();
+    let mut chars = message.chars();
+    match chars.next() {
+        Some(first) => first.to_lowercase().chain(chars).collect(),
+        None => message,
+    }
+}
+
 /// Process a single file attachment
-#[allow(dead_code)]
+///
+/// Text content is capped at `limits.max_text_chars` while it is decoded
+/// or fetched, so only the kept prefix is ever held in memory; the content
+/// hash covers that prefix.
 async fn process_file_attachment(
-    client: &Client,
+    client: &AttachmentClient,
     attachment: &Attachment,
+    cache: &AttachmentCache,
+    policy: &FetchPolicy,
+    limits: &AttachmentLimits,
 ) -> Result<ProcessedAttachment> {
     let is_image = attachment.content_type.starts_with("image/");
 
     // For images, we'll just pass the URL (models like OpenRouter handle image URLs directly)
     if is_image {
+        let bytes = if attachment.url.starts_with("data:") {
+            decode_data_url_bytes(&attachment.url)?
+        } else {
+            attachment.url.as_bytes().to_vec()
+        };
+
         return Ok(ProcessedAttachment {
             name: attachment.name.clone(),
             content_type: attachment.content_type.clone(),
             content: attachment.url.clone(), // Pass URL for image processing
             is_image: true,
+            content_hash: content_hash(&bytes),
         });
     }
 
-    // For text-based files, try to fetch and read content
-    let content = if attachment.url.starts_with("data:") {
+    let processed = |content: String, content_hash: String| ProcessedAttachment {
+        name: attachment.name.clone(),
+        content_type: attachment.content_type.clone(),
+        content,
+        is_image: false,
+        content_hash,
+    };
+
+    // A source seen before is answered from the cache before any decoding
+    // or download; URLs are only trusted again if the server confirms
+    // they are unchanged
+    let key = source_key(&attachment.url);
+    let cached = cache.source(&key);
+    let max_bytes = limits.max_text_bytes();
+    let (bytes, etag) = if attachment.url.starts_with("data:") {
+        if let Some((hash, _, content)) = cached {
+            return Ok(processed(content, hash));
+        }
         // Decode data URL inline (data:[mime][;base64],payload)
-        decode_data_url(&attachment.url)?
+        (decode_data_url_prefix(&attachment.url, max_bytes)?, None)
     } else if attachment.url.starts_with("http") {
-        // Fetch from HTTP URL
-        fetch_url_content(client, &attachment.url).await?
+        let validator = cached.as_ref().and_then(|(_, etag, _)| etag.as_deref());
+        match fetch_url_bytes(client, &attachment.url, policy, max_bytes, validator).await? {
+            Fetched::NotModified => {
+                let (hash, _, content) = cached.ok_or_else(|| anyhow!("Unexpected 304 for {}", attachment.url))?;
+                return Ok(processed(content, hash));
+            }
+            Fetched::Body { bytes, etag } => (bytes, etag),
+        }
     } else {
         // Local file path or unsupported scheme
         return Err(anyhow!("Unsupported URL scheme: {}", attachment.url));
     };
 
-    Ok(ProcessedAttachment {
-        name: attachment.name.clone(),
-        content_type: attachment.content_type.clone(),
-        content,
-        is_image: false,
-    })
+    // Reuse prior processing of identical content
+    let hash = content_hash(&bytes);
+    let content = match cache.get(&hash) {
+        Some(content) => content,
+        None => {
+            let content = capped_text(bytes, max_bytes, limits.max_text_chars)?;
+            let content = normalize_text(content, limits.normalize_newlines);
+            cache.insert(hash.clone(), content.clone());
+            content
+        }
+    };
+    cache.insert_source(key, hash.clone(), etag);
+
+    Ok(processed(content, hash))
+}
+
+/// Cache key for an attachment's source: data URLs by the hash of the
+/// whole URL, so large payloads are not kept as keys, and other URLs as is
+fn source_key(url: &str) -> String {
+    if url.starts_with("data:") {
+        format!("data:{}", content_hash(url.as_bytes()))
+    } else {
+        url.to_string()
+    }
 }
 
+#[allow(dead_code)]
 fn decode_data_url(data_url: &str) -> Result<String> {
+    String::from_utf8(decode_data_url_bytes(data_url)?)
+        .map_err(|e| anyhow!("Failed to convert to UTF-8: {}", e))
+}
+
+/// Text from `bytes` holding at most `max_bytes` of a possibly longer file,
+/// cut to `max_chars` characters (0 keeps all)
+///
+/// A character split by the byte cap is dropped rather than treated as
+/// invalid UTF-8.
+fn capped_text(mut bytes: Vec<u8>, max_bytes: usize, max_chars: usize) -> Result<String> {
+    if bytes.len() >= max_bytes {
+        if let Err(error) = std::str::from_utf8(&bytes) {
+            if error.error_len().is_none() {
+                bytes.truncate(error.valid_up_to());
+            }
+        }
+    }
+    let mut content = String::from_utf8(bytes).map_err(|e| anyhow!("Failed to convert to UTF-8: {}", e))?;
+    if max_chars > 0 {
+        if let Some((end, _)) = content.char_indices().nth(max_chars) {
+            content.truncate(end);
+        }
+    }
+    Ok(content)
+}
+
+/// Strip a leading UTF-8 byte order mark and, with `normalize_newlines`,
+/// turn CRLF line endings into LF
+///
+/// Editors on Windows often save both, and neither carries meaning for
+/// the model.
+fn normalize_text(content: String, normalize_newlines: bool) -> String {
+    let content = match content.strip_prefix('\u{feff}') {
+        Some(rest) => rest.to_string(),
+        None => content,
+    };
+    if normalize_newlines && content.contains("\r\n") {
+        content.replace("\r\n", "\n")
+    } else {
+        content
+    }
+}
+
+/// Decode at most `max_bytes` from the start of a data URL
+///
+/// Only the base64 or percent-encoded characters needed for `max_bytes`
+/// are decoded, so a huge payload is never decoded in full.
+fn decode_data_url_prefix(data_url: &str, max_bytes: usize) -> Result<Vec<u8>> {
+    let comma_idx = data_url
+        .find(',')
+        .ok_or_else(|| anyhow!("Invalid data URL format"))?;
+    let meta = &data_url[5..comma_idx]; // strip 'data:'
+    let payload = &data_url[comma_idx + 1..];
+
+    let mut bytes = if meta.contains(";base64") {
+        // Whole four-character groups, so the prefix is itself valid base64
+        let chars = max_bytes.div_ceil(3).saturating_mul(4);
+        let prefix = payload.get(..chars).unwrap_or(payload);
+        BASE64_STANDARD
+            .decode(prefix)
+            .map_err(|e| anyhow!("Failed to decode base64: {}", e))?
+    } else {
+        // Each byte takes at most three characters (`%XX`); an escape cut
+        // off at the end only affects bytes past `max_bytes`
+        let chars = max_bytes.saturating_mul(3);
+        let prefix = payload.as_bytes().get(..chars).unwrap_or(payload.as_bytes());
+        urlencoding::decode_binary(prefix).into_owned()
+    };
+    bytes.truncate(max_bytes);
+    Ok(bytes)
+}
+
+fn decode_data_url_bytes(data_url: &str) -> Result<Vec<u8>> {
     let comma_idx = data_url
         .find(',')
         .ok_or_else(|| anyhow!("Invalid data URL format"))?;
@@ -124,27 +626,77 @@ fn decode_data_url(data_url: &str) -> Result<String> {
     let is_base64 = meta.contains(";base64");
     
     if is_base64 {
-        let decoded_bytes = BASE64_STANDARD
+        BASE64_STANDARD
             .decode(payload)
-            .map_err(|e| anyhow!("Failed to decode base64: {}", e))?;
-        
-        String::from_utf8(decoded_bytes)
-            .map_err(|e| anyhow!("Failed to convert to UTF-8: {}", e))
+            .map_err(|e| anyhow!("Failed to decode base64: {}", e))
     } else {
         // URL decoded content
-        urlencoding::decode(payload)
-            .map(|s| s.into_owned())
-            .map_err(|e| anyhow!("Failed to URL decode: {}", e))
+        Ok(urlencoding::decode_binary(payload.as_bytes()).into_owned())
     }
 }
 
-#[allow(dead_code)]
-async fn fetch_url_content(client: &Client, url: &str) -> Result<String> {
-    let response = client
-        .get(url)
-        .send()
-        .await
-        .map_err(|e| anyhow!("Failed to fetch URL: {}", e))?;
+/// Result of fetching an attachment URL
+#[derive(Debug, PartialEq, Eq)]
+enum Fetched {
+    /// The server confirmed the copy matching `If-None-Match` is current
+    NotModified,
+    /// The body, read up to the size cap, and its ETag if the server sent one
+    Body { bytes: Vec<u8>, etag: Option<String> },
+}
+
+/// Fetch a text file, following redirects only as `policy` allows
+///
+/// Every URL in the redirect chain must pass the address check, and
+/// redirect targets must also be on the allowed hosts. Each request
+/// connects to the address that was checked. Reading stops after
+/// `max_bytes`; bodies beyond that are never downloaded in full. With
+/// `if_none_match` the request is conditional and the server may answer
+/// that the cached copy is still current.
+async fn fetch_url_bytes(
+    client: &AttachmentClient,
+    url: &str,
+    policy: &FetchPolicy,
+    max_bytes: usize,
+    if_none_match: Option<&str>,
+) -> Result<Fetched> {
+    let mut url = Url::parse(url).map_err(|e| anyhow!("Invalid URL {}: {}", url, e))?;
+    let mut addr = policy.check_address(&url).await?;
+
+    let mut redirects = 0;
+    let mut response = loop {
+        let mut request = client.pinned(&url, addr)?.get(url.clone());
+        if let Some(etag) = if_none_match {
+            request = request.header(IF_NONE_MATCH, etag);
+        }
+        let response = request
+            .send()
+            .await
+            .map_err(|e| anyhow!("Failed to fetch URL: {}", e))?;
+        if if_none_match.is_some() && response.status() == reqwest::StatusCode::NOT_MODIFIED {
+            return Ok(Fetched::NotModified);
+        }
+        if !response.status().is_redirection() {
+            break response;
+        }
+
+        let location = response
+            .headers()
+            .get(LOCATION)
+            .and_then(|location| location.to_str().ok())
+            .ok_or_else(|| anyhow!("Redirect without a Location header: {}", url))?;
+        let target = url
+            .join(location)
+            .map_err(|e| anyhow!("Invalid redirect from {}: {}", url, e))?;
+        if redirects == policy.max_redirects {
+            return Err(anyhow!("Too many redirects fetching {}", url));
+        }
+        if !policy.allows_redirect_to(&target) {
+            return Err(anyhow!("Redirect to a host that is not allowed: {}", target));
+        }
+        addr = policy.check_address(&target).await?;
+        redirects += 1;
+        url = target;
+    };
 
     if !response.status().is_success() {
         return Err(anyhow!("HTTP error {}: {}", response.status(), url));
@@ -159,20 +711,33 @@ async fn fetch_url_content(client: &Client, url: &str) -> Result<String> {
     if !is_text_content_type(content_type) {
         return Err(anyhow!("Non-text content type: {}", content_type));
     }
+    let etag = response
+        .headers()
+        .get(ETAG)
+        .and_then(|etag| etag.to_str().ok())
+        .map(str::to_string);
 
-    let bytes = response
-        .bytes()
-        .await
-        .map_err(|e| anyhow!("Failed to read response body: {}", e))?;
+    // Reading one byte past the size limit is enough to reject the file
+    let limit = max_bytes.min(MAX_FETCH_BYTES + 1);
+    let mut bytes = Vec::new();
+    while bytes.len() < limit {
+        let Some(chunk) = response
+            .chunk()
+            .await
+            .map_err(|e| anyhow!("Failed to read response body: {}", e))?
+        else {
+            break;
+        };
+        let take = chunk.len().min(limit - bytes.len());
+        bytes.extend_from_slice(&chunk[..take]);
+    }
 
     // Limit file size to prevent memory issues
-    if bytes.len() > 10 * 1024 * 1024 {
-        // 10MB limit
-        return Err(anyhow!("File too large: {} bytes", bytes.len()));
+    if bytes.len() > MAX_FETCH_BYTES {
+        return Err(anyhow!("File too large: more than {} bytes", MAX_FETCH_BYTES));
     }
 
-    String::from_utf8(bytes.to_vec())
-        .map_err(|e| anyhow!("Failed to convert to UTF-8: {}", e))
+    Ok(Fetched::Body { bytes, etag })
 }
 
 fn is_text_content_type(content_type: &str) -> bool {
@@ -245,6 +810,7 @@ pub fn create_messages_with_file_context(
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::test_support::spawn_mock;
 
     #[test]
     fn test_supports_multimodal() {
@@ -409,6 +975,539 @@ mod tests {
         assert_eq!(decode_data_url(&data_url).unwrap(), unicode_text);
     }
     
+    #[tokio::test]
+    async fn test_identical_attachments_share_hash_and_cache() {
+        let client = AttachmentClient::default();
+        let cache = AttachmentCache::default();
+        let attachments = vec![
+            Attachment {
+                name: "a.txt".to_string(),
+                url: "data:text/plain;base64,SGVsbG8gV29ybGQ=".to_string(),
+                content_type: "text/plain".to_string(),
+                size: None,
+            },
+            Attachment {
+                name: "b.txt".to_string(),
+                url: "data:text/plain,Hello%20World".to_string(),
+                content_type: "text/plain".to_string(),
+                size: None,
+            },
+        ];
+
+        let result = process_file_attachments(
+            &client,
+            &attachments,
+            &cache,
+            Duration::from_secs(10),
+            &FetchPolicy::default(),
+            &AttachmentLimits::default(),
+            false,
+        )
+        .await
+        .unwrap();
+        let [first, second] = &result.processed_attachments[..] else {
+            panic!("expected two processed attachments");
+        };
+
+        assert_eq!(
+            first.content_hash,
+            "a591a6d40bf420404a011733cfb7b190d62c65bf0bcda32b57b277d9ad9f146e"
+        );
+        assert_eq!(first.content_hash, second.content_hash);
+        assert_eq!(second.name, "b.txt");
+        assert_eq!(second.content, "Hello World");
+
+        // Second attachment reused the first one's processed content
+        assert_eq!(cache.len(), 1);
+        assert_eq!(cache.get(&first.content_hash).as_deref(), Some("Hello World"));
+    }
+
+    #[tokio::test]
+    async fn test_slow_attachment_times_out_without_blocking_others() {
+        // Upstream that answers long after the attachment timeout
+        let app = axum::Router::new().route(
+            "/slow.txt",
+            axum::routing::get(|| async {
+                tokio::time::sleep(Duration::from_secs(5)).await;
+                "late"
+            }),
+        );
+        let addr = spawn_mock(app).await;
+
+        let attachments = vec![
+            Attachment {
+                name: "slow.txt".to_string(),
+                url: format!("http://{}/slow.txt", addr),
+                content_type: "text/plain".to_string(),
+                size: None,
+            },
+            Attachment {
+                name: "fast.txt".to_string(),
+                url: "data:text/plain,Hello".to_string(),
+                content_type: "text/plain".to_string(),
+                size: None,
+            },
+        ];
+
+        let started = std::time::Instant::now();
+        let result = process_file_attachments(
+            &AttachmentClient::default(),
+            &attachments,
+            &AttachmentCache::default(),
+            Duration::from_millis(200),
+            &FetchPolicy {
+                allow_private: true,
+                ..FetchPolicy::default()
+            },
+            &AttachmentLimits::default(),
+            false,
+        )
+        .await
+        .unwrap();
+        assert!(started.elapsed() < Duration::from_secs(2), "{:?}", started.elapsed());
+
+        assert!(result.context_prompt.contains("[File: slow.txt - Processing failed]"));
+        assert!(result.context_prompt.contains("[File: fast.txt (text/plain)]\nHello"));
+        let names: Vec<_> = result.processed_attachments.iter().map(|a| a.name.as_str()).collect();
+        assert_eq!(names, ["fast.txt"]);
+    }
+
+    #[test]
+    fn test_estimated_data_url_size_matches_decoded_size() {
+        for text in ["", "a", "ab", "abc", "Hello World", "longer text spanning several base64 groups"] {
+            let data_url = format!("data:text/plain;base64,{}", BASE64_STANDARD.encode(text));
+            assert_eq!(estimated_data_url_size(&data_url[5..]), text.len(), "{:?}", text);
+        }
+        // Percent-encoded payloads are estimated by their encoded length
+        assert_eq!(estimated_data_url_size("text/plain,Hello%20World"), 13);
+        assert_eq!(estimated_data_url_size("no comma"), 0);
+    }
+
+    #[test]
+    fn test_data_url_prefix_stops_at_the_byte_limit() {
+        let base64 = format!("data:text/plain;base64,{}", BASE64_STANDARD.encode("Hello World"));
+        assert_eq!(decode_data_url_prefix(&base64, 5).unwrap(), b"Hello");
+
+        for max_bytes in 0..=12 {
+            let decoded = decode_data_url_prefix("data:text/plain,Hello%20W%C3%B6rld", max_bytes).unwrap();
+            let expected = "Hello W\u{f6}rld".as_bytes();
+            assert_eq!(decoded, expected[..max_bytes.min(expected.len())], "{}", max_bytes);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_attachment_limits_reject_before_processing() {
+        let attachment = |name: &str, url: String, size| Attachment {
+            name: name.to_string(),
+            url,
+            content_type: "text/plain".to_string(),
+            size,
+        };
+        let limits = AttachmentLimits {
+            max_attachments: 2,
+            max_total_bytes: 1000,
+            ..AttachmentLimits::default()
+        };
+        let process = |attachments: Vec<Attachment>| async move {
+            let (client, cache) = (AttachmentClient::default(), AttachmentCache::default());
+            process_file_attachments(
+                &client,
+                &attachments,
+                &cache,
+                Duration::from_secs(5),
+                &FetchPolicy::default(),
+                &limits,
+                false,
+            )
+            .await
+        };
+
+        let small = || attachment("a.txt", "data:text/plain,Hello".to_string(), None);
+        let result = process(vec![small(), small()]).await.unwrap();
+        assert_eq!(result.processed_attachments.len(), 2);
+
+        let error = process(vec![small(), small(), small()]).await.unwrap_err().to_string();
+        assert!(error.contains("MAX_ATTACHMENTS"), "{}", error);
+        assert!(error.contains("3"), "{}", error);
+
+        // 1200 decoded bytes, measured without decoding
+        let large = format!("data:text/plain;base64,{}", BASE64_STANDARD.encode([b'x'; 1200]));
+        let error = process(vec![attachment("big.txt", large, None)]).await.unwrap_err().to_string();
+        assert!(error.contains("MAX_ATTACHMENT_BYTES"), "{}", error);
+        assert!(error.contains("1200"), "{}", error);
+
+        // Fetched files count with their declared size; nothing is fetched
+        let remote = attachment("remote.txt", "http://files.example/remote.txt".to_string(), Some(600));
+        let error = process(vec![remote.clone(), remote]).await.unwrap_err().to_string();
+        assert!(error.contains("MAX_ATTACHMENT_BYTES"), "{}", error);
+    }
+
+    #[test]
+    fn test_capped_text_drops_split_character() {
+        // "é" is two bytes; a cap of five bytes splits the third one
+        let text = capped_text("ééé".as_bytes()[..5].to_vec(), 5, 0).unwrap();
+        assert_eq!(text, "éé");
+        assert_eq!(capped_text("ééé".as_bytes().to_vec(), usize::MAX, 2).unwrap(), "éé");
+        // Invalid bytes inside the kept prefix are still an error
+        assert!(capped_text(vec![b'a', 0xff, b'b'], 3, 0).is_err());
+    }
+
+    #[test]
+    fn test_normalize_text_strips_bom_and_crlf() {
+        let text = "\u{feff}line one\r\nline two\r\n".to_string();
+        assert_eq!(normalize_text(text.clone(), true), "line one\nline two\n");
+        assert_eq!(normalize_text(text, false), "line one\r\nline two\r\n");
+        // Only a leading BOM is removed, and lone CRs are kept
+        assert_eq!(normalize_text("a\u{feff}b\rc".to_string(), true), "a\u{feff}b\rc");
+    }
+
+    #[tokio::test]
+    async fn test_decoded_text_attachments_are_normalized() {
+        let crlf_with_bom = "\u{feff}name,score\r\nada,3\r\n";
+        let attachments = vec![
+            Attachment {
+                name: "scores.csv".to_string(),
+                url: format!("data:text/csv;base64,{}", BASE64_STANDARD.encode(crlf_with_bom)),
+                content_type: "text/csv".to_string(),
+                size: None,
+            },
+        ];
+        let process = |normalize_newlines| {
+            let attachments = attachments.clone();
+            async move {
+                let limits = AttachmentLimits {
+                    normalize_newlines,
+                    ..AttachmentLimits::default()
+                };
+                process_file_attachments(
+                    &AttachmentClient::default(),
+                    &attachments,
+                    &AttachmentCache::default(),
+                    Duration::from_secs(5),
+                    &FetchPolicy::default(),
+                    &limits,
+                    false,
+                )
+                .await
+                .unwrap()
+            }
+        };
+
+        let normalized = process(true).await;
+        assert_eq!(normalized.processed_attachments[0].content, "name,score\nada,3\n");
+        let kept = process(false).await;
+        assert_eq!(kept.processed_attachments[0].content, "name,score\r\nada,3\r\n");
+    }
+
+    #[tokio::test]
+    async fn test_large_text_attachment_read_only_up_to_cap() {
+        // 4MB body, served in full to anyone who reads it
+        let body = format!("{}{}", "start ", "x".repeat(4 * 1024 * 1024));
+        let served = body.clone();
+        let app = axum::Router::new().route("/big.txt", axum::routing::get(move || async move { served }));
+        let addr = spawn_mock(app).await;
+
+        let attachments = vec![
+            Attachment {
+                name: "big.txt".to_string(),
+                url: format!("http://{}/big.txt", addr),
+                content_type: "text/plain".to_string(),
+                size: None,
+            },
+            Attachment {
+                name: "inline.txt".to_string(),
+                url: format!("data:text/plain;base64,{}", BASE64_STANDARD.encode(&body)),
+                content_type: "text/plain".to_string(),
+                size: None,
+            },
+        ];
+        let limits = AttachmentLimits {
+            max_text_chars: 5000,
+            ..AttachmentLimits::default()
+        };
+        let result = process_file_attachments(
+            &AttachmentClient::default(),
+            &attachments,
+            &AttachmentCache::default(),
+            Duration::from_secs(10),
+            &FetchPolicy {
+                allow_private: true,
+                ..FetchPolicy::default()
+            },
+            &limits,
+            false,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(result.processed_attachments.len(), 2);
+        for processed in &result.processed_attachments {
+            assert_eq!(processed.content.len(), 5000, "{}", processed.name);
+            assert!(processed.content.starts_with("start xxx"));
+        }
+        let preview = format!("[File: big.txt (text/plain)]\n{}... (truncated)", &body[..PREVIEW_CHARS]);
+        assert!(result.context_prompt.contains(&preview));
+    }
+
+    #[tokio::test]
+    async fn test_failure_reason_reported_only_with_error_details() {
+        // Upstream serving binary content for a text attachment
+        let app = axum::Router::new().route(
+            "/blob.bin",
+            axum::routing::get(|| async {
+                ([(axum::http::header::CONTENT_TYPE, "application/octet-stream")], vec![0u8, 1, 2])
+            }),
+        );
+        let addr = spawn_mock(app).await;
+
+        let attachments = vec![Attachment {
+            name: "blob.bin".to_string(),
+            url: format!("http://{}/blob.bin", addr),
+            content_type: "text/plain".to_string(),
+            size: None,
+        }];
+        let policy = FetchPolicy {
+            allow_private: true,
+            ..FetchPolicy::default()
+        };
+        let client = AttachmentClient::default();
+        let cache = AttachmentCache::default();
+        let limits = AttachmentLimits::default();
+        let process = |error_details| {
+            process_file_attachments(
+                &client,
+                &attachments,
+                &cache,
+                Duration::from_secs(5),
+                &policy,
+                &limits,
+                error_details,
+            )
+        };
+
+        let detailed = process(true).await.unwrap();
+        let expected = "[File: blob.bin - failed: non-text content type: application/octet-stream]";
+        assert!(detailed.context_prompt.contains(expected), "{}", detailed.context_prompt);
+        assert_eq!(detailed.failures, [expected]);
+
+        let generic = process(false).await.unwrap();
+        assert!(generic.context_prompt.contains("[File: blob.bin - Processing failed]"));
+        assert!(!generic.context_prompt.contains("content type"));
+        assert!(generic.failures.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_fetch_refuses_redirects_to_blocked_hosts() {
+        use axum::http::header::LOCATION;
+        use axum::response::IntoResponse;
+
+        // Upstream redirecting to the cloud metadata address, and to itself
+        let app = axum::Router::new()
+            .route(
+                "/metadata.txt",
+                axum::routing::get(|| async {
+                    (
+                        axum::http::StatusCode::FOUND,
+                        [(LOCATION, "http://169.254.169.254/latest/meta-data")],
+                    )
+                        .into_response()
+                }),
+            )
+            .route(
+                "/moved.txt",
+                axum::routing::get(|| async {
+                    (axum::http::StatusCode::MOVED_PERMANENTLY, [(LOCATION, "/file.txt")]).into_response()
+                }),
+            )
+            .route("/file.txt", axum::routing::get(|| async { "Hello" }));
+        let addr = spawn_mock(app).await;
+        let client = AttachmentClient::default();
+        let url = |path: &str| format!("http://{}{}", addr, path);
+
+        // Only the test server itself is an allowed redirect target
+        let policy = FetchPolicy {
+            redirect_hosts: vec!["127.0.0.1".to_string()],
+            allow_private: true,
+            ..FetchPolicy::default()
+        };
+        let error = fetch_url_bytes(&client, &url("/metadata.txt"), &policy, usize::MAX, None).await.unwrap_err();
+        assert!(error.to_string().contains("not allowed"), "{}", error);
+        assert_eq!(
+            fetch_url_bytes(&client, &url("/moved.txt"), &policy, usize::MAX, None).await.unwrap(),
+            Fetched::Body { bytes: b"Hello".to_vec(), etag: None }
+        );
+
+        // Allowed hosts still have to pass the address check
+        let policy = FetchPolicy {
+            redirect_hosts: vec!["169.254.169.254".to_string(), "127.0.0.1".to_string()],
+            ..policy
+        };
+        let public_only = FetchPolicy {
+            allow_private: false,
+            ..policy.clone()
+        };
+        assert!(public_only.check_address(&Url::parse("http://169.254.169.254/").unwrap()).await.is_err());
+        assert!(fetch_url_bytes(&client, &url("/file.txt"), &public_only, usize::MAX, None).await.is_err());
+
+        // Redirects beyond the limit are not followed
+        let no_redirects = FetchPolicy {
+            max_redirects: 0,
+            ..policy
+        };
+        let error = fetch_url_bytes(&client, &url("/moved.txt"), &no_redirects, usize::MAX, None).await.unwrap_err();
+        assert!(error.to_string().contains("Too many redirects"), "{}", error);
+    }
+
+    #[tokio::test]
+    async fn test_fetch_connects_to_the_checked_address() {
+        let app = axum::Router::new().route("/file.txt", axum::routing::get(|| async { "Hello" }));
+        let addr = spawn_mock(app).await;
+
+        // The name never resolves, so the request can only reach the pinned address
+        let url = Url::parse(&format!("http://attachments.invalid:{}/file.txt", addr.port())).unwrap();
+        let client = AttachmentClient::default();
+        let response = client.pinned(&url, addr).unwrap().get(url.clone()).send().await.unwrap();
+        assert_eq!(response.text().await.unwrap(), "Hello");
+
+        // Pinned to an address with nothing listening, the request fails
+        let elsewhere = SocketAddr::new("127.0.0.2".parse().unwrap(), addr.port());
+        assert!(client.pinned(&url, elsewhere).unwrap().get(url).send().await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_repeated_url_is_revalidated_with_its_etag() {
+        use axum::http::{header, HeaderMap, StatusCode};
+        use axum::response::IntoResponse;
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        // Upstream counting full downloads and honouring If-None-Match
+        let downloads = Arc::new(AtomicUsize::new(0));
+        let counter = downloads.clone();
+        let app = axum::Router::new().route(
+            "/notes.txt",
+            axum::routing::get(move |headers: HeaderMap| {
+                let counter = counter.clone();
+                async move {
+                    if headers.get(header::IF_NONE_MATCH).is_some_and(|etag| etag == "\"v1\"") {
+                        return StatusCode::NOT_MODIFIED.into_response();
+                    }
+                    counter.fetch_add(1, Ordering::SeqCst);
+                    ([(header::ETAG, "\"v1\"")], "Meeting notes").into_response()
+                }
+            }),
+        );
+        let addr = spawn_mock(app).await;
+
+        let client = AttachmentClient::default();
+        let cache = AttachmentCache::default();
+        let policy = FetchPolicy { allow_private: true, ..FetchPolicy::default() };
+        let attachments = [Attachment {
+            name: "notes.txt".to_string(),
+            url: format!("http://{}/notes.txt", addr),
+            content_type: "text/plain".to_string(),
+            size: None,
+        }];
+        for _ in 0..2 {
+            let result = process_file_attachments(
+                &client,
+                &attachments,
+                &cache,
+                Duration::from_secs(10),
+                &policy,
+                &AttachmentLimits::default(),
+                false,
+            )
+            .await
+            .unwrap();
+            assert_eq!(result.processed_attachments[0].content, "Meeting notes");
+        }
+        assert_eq!(downloads.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_repeated_data_url_is_looked_up_before_decoding() {
+        let cache = AttachmentCache::default();
+        let url = "data:text/plain;base64,SGVsbG8gV29ybGQ=";
+        let attachment = Attachment {
+            name: "a.txt".to_string(),
+            url: url.to_string(),
+            content_type: "text/plain".to_string(),
+            size: None,
+        };
+        let process = |attachment: &Attachment| {
+            let (client, cache) = (AttachmentClient::default(), cache.clone());
+            let attachment = attachment.clone();
+            async move {
+                process_file_attachment(&client, &attachment, &cache, &FetchPolicy::default(), &AttachmentLimits::default())
+                    .await
+                    .unwrap()
+            }
+        };
+        let first = process(&attachment).await;
+
+        // The second lookup is keyed by the URL, so the content comes from
+        // the cache even though it is never decoded again
+        cache.insert(first.content_hash.clone(), "From cache".to_string());
+        assert_eq!(process(&attachment).await.content, "From cache");
+        assert!(cache.source(&source_key(url)).is_some());
+        assert!(!source_key(url).contains("SGVsbG8"));
+    }
+
+    #[tokio::test]
+    async fn test_preview_cuts_multibyte_text_on_character_boundaries() {
+        let text = "日本語🙂".repeat(PREVIEW_CHARS);
+        let attachments = [Attachment {
+            name: "cjk.txt".to_string(),
+            url: format!("data:text/plain,{}", urlencoding::encode(&text)),
+            content_type: "text/plain".to_string(),
+            size: None,
+        }];
+        let result = process_file_attachments(
+            &AttachmentClient::default(),
+            &attachments,
+            &AttachmentCache::default(),
+            Duration::from_secs(10),
+            &FetchPolicy::default(),
+            &AttachmentLimits::default(),
+            false,
+        )
+        .await
+        .unwrap();
+
+        let preview: String = text.chars().take(PREVIEW_CHARS).collect();
+        assert!(result.context_prompt.contains(&format!("{}... (truncated)", preview)));
+
+        // Text of exactly the preview length is shown whole
+        let attachments = [Attachment {
+            url: format!("data:text/plain,{}", urlencoding::encode(&preview)),
+            ..attachments[0].clone()
+        }];
+        let result = process_file_attachments(
+            &AttachmentClient::default(),
+            &attachments,
+            &AttachmentCache::default(),
+            Duration::from_secs(10),
+            &FetchPolicy::default(),
+            &AttachmentLimits::default(),
+            false,
+        )
+        .await
+        .unwrap();
+        assert!(result.context_prompt.contains(&preview));
+        assert!(!result.context_prompt.contains("(truncated)"));
+    }
+
+    #[test]
+    fn test_attachment_cache_expires_entries() {
+        let clock = Arc::new(crate::clock::MockClock::new(0));
+        let cache = AttachmentCache::new(Duration::from_secs(60)).with_clock(clock.clone());
+        cache.insert("hash".to_string(), "content".to_string());
+        assert_eq!(cache.get("hash").as_deref(), Some("content"));
+
+        clock.advance(Duration::from_secs(60));
+        assert!(cache.get("hash").is_none());
+    }
+
     #[test]
     fn test_create_messages_with_file_context() {
         use crate::types::{ChatMessage, MessageRole};
@@ -420,7 +1519,7 @@ mod tests {
             }
         ];
         
-        let _file_contents = vec![
+        let _file_contents = [
             ("test.txt".to_string(), "This is a test file with some content.".to_string()),
             ("data.json".to_string(), r#"{"key": "value"}"#.to_string()),
         ];
@@ -473,7 +1572,7 @@ mod tests {
         
         // Create a very large file content
         let large_content = "x".repeat(10000);
-        let _file_contents = vec![
+        let _file_contents = [
             ("large.txt".to_string(), large_content),
         ];
         
@@ -0,0 +1,172 @@
+//! Outbound HTTP Client Module
+//!
+//! Central place where `reqwest` clients for provider, search and
+//! attachment calls are built, so transport security settings apply
+//! uniformly to all outbound traffic.
+//!
+//! Also provides `send_with_retry`, which retries upstream calls that fail
+//! with a retryable status (`RETRYABLE_STATUS_CODES`) using the shared
+//! backoff policy.
+
+use reqwest::{tls, Client, ClientBuilder, RequestBuilder, Response};
+use std::time::Duration;
+
+use crate::backoff::{retry, RetryPolicy};
+use crate::config::Config;
+
+/// Statuses retried by default: rate limiting and transient server errors
+pub const DEFAULT_RETRYABLE_STATUS_CODES: [u16; 5] = [429, 500, 502, 503, 504];
+
+/// Create a client builder with the configured outbound security settings
+///
+/// When `enforce_min_tls` is enabled, connections negotiating anything
+/// older than TLS 1.2 are refused.
+///
+/// # Arguments
+/// * `config` - Application configuration
+pub fn client_builder(config: &Config) -> ClientBuilder {
+    tls_client_builder(config.enforce_min_tls)
+}
+
+/// Create a client builder that refuses anything older than TLS 1.2 when
+/// `enforce_min_tls` is set
+pub fn tls_client_builder(enforce_min_tls: bool) -> ClientBuilder {
+    let builder = Client::builder();
+    if enforce_min_tls {
+        builder.min_tls_version(tls::Version::TLS_1_2)
+    } else {
+        builder
+    }
+}
+
+/// Build a client with the configured security settings and a request timeout
+///
+/// # Panics
+/// Panics if the TLS backend cannot be initialized
+pub fn build_client(config: &Config, timeout: Duration) -> Client {
+    client_builder(config)
+        .timeout(timeout)
+        .build()
+        .expect("Failed to create HTTP client")
+}
+
+/// Send a request, retrying while the response status is retryable
+///
+/// Retries follow the default `RetryPolicy`. The last response is returned
+/// as-is, so callers still check its status. Transport errors are not
+/// retried, and requests with a streaming body cannot be cloned and are
+/// sent once.
+///
+/// # Arguments
+/// * `request` - Request to send
+/// * `retryable` - Statuses that trigger a retry
+///
+/// # Errors
+/// Returns the transport error if a request cannot be sent
+pub async fn send_with_retry(request: RequestBuilder, retryable: &[u16]) -> reqwest::Result<Response> {
+    if request.try_clone().is_none() {
+        return request.send().await;
+    }
+
+    /// Failed attempt: a retryable response, or a transport error
+    enum Failure {
+        Status(Response),
+        Transport(reqwest::Error),
+    }
+
+    let attempt = || async {
+        let request = request.try_clone().expect("request was cloneable");
+        let response = request.send().await.map_err(Failure::Transport)?;
+        if retryable.contains(&response.status().as_u16()) {
+            tracing::warn!("Upstream returned {}", response.status());
+            Err(Failure::Status(response))
+        } else {
+            Ok(response)
+        }
+    };
+
+    match retry(&RetryPolicy::default(), attempt, |failure| matches!(failure, Failure::Status(_))).await {
+        Ok(response) | Err(Failure::Status(response)) => Ok(response),
+        Err(Failure::Transport(error)) => Err(error),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::spawn_mock;
+    use axum::http::StatusCode;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    /// Serve `statuses` in order (repeating the last) and count the hits
+    async fn spawn_upstream(statuses: Vec<u16>) -> (String, Arc<AtomicUsize>) {
+        let hits = Arc::new(AtomicUsize::new(0));
+        let counter = hits.clone();
+        let app = axum::Router::new().route(
+            "/",
+            axum::routing::get(move || {
+                let hit = counter.fetch_add(1, Ordering::SeqCst);
+                let status = statuses[hit.min(statuses.len() - 1)];
+                async move { StatusCode::from_u16(status).unwrap() }
+            }),
+        );
+
+        let addr = spawn_mock(app).await;
+        (format!("http://{}/", addr), hits)
+    }
+
+    #[tokio::test]
+    async fn test_configured_status_is_retried() {
+        let (url, hits) = spawn_upstream(vec![408, 200]).await;
+        let retryable = [408, 429, 500, 502, 503, 504];
+
+        let response = send_with_retry(Client::new().get(&url), &retryable).await.unwrap();
+        assert_eq!(response.status(), 200);
+        assert_eq!(hits.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_unlisted_status_is_not_retried() {
+        let (url, hits) = spawn_upstream(vec![404, 200]).await;
+
+        let response = send_with_retry(Client::new().get(&url), &DEFAULT_RETRYABLE_STATUS_CODES)
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 404);
+        assert_eq!(hits.load(Ordering::SeqCst), 1);
+
+        // 408 is only retried when configured
+        let (url, hits) = spawn_upstream(vec![408, 200]).await;
+        let response = send_with_retry(Client::new().get(&url), &DEFAULT_RETRYABLE_STATUS_CODES)
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 408);
+        assert_eq!(hits.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_retries_are_bounded() {
+        let (url, hits) = spawn_upstream(vec![503]).await;
+
+        let response = send_with_retry(Client::new().get(&url), &DEFAULT_RETRYABLE_STATUS_CODES)
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 503);
+        assert_eq!(hits.load(Ordering::SeqCst), RetryPolicy::default().max_attempts as usize);
+    }
+
+    #[test]
+    fn test_client_builder_min_tls_version() {
+        let mut config = Config::from_env();
+
+        config.enforce_min_tls = true;
+        let debug = format!("{:?}", client_builder(&config));
+        let expected = format!("min_tls_version: {:?}", tls::Version::TLS_1_2);
+        assert!(debug.contains(&expected), "{}", debug);
+
+        config.enforce_min_tls = false;
+        let debug = format!("{:?}", client_builder(&config));
+        assert!(!debug.contains("min_tls_version"), "{}", debug);
+    }
+}
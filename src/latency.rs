@@ -0,0 +1,194 @@
+//! Provider Latency Module
+//!
+//! Tracks an exponentially weighted moving average (EWMA) of response
+//! times per AI provider, fed from the `response_time_ms` recorded for
+//! each upstream call.
+//!
+//! Failed calls count as a sample of the failure penalty (the provider
+//! timeout), so a provider that keeps failing does not keep the score of
+//! its last fast reply.
+//!
+//! With `ADAPTIVE_ROUTING` enabled, a route with several candidate
+//! targets is attempted fastest-first by recent latency instead of in
+//! configured order. Providers without samples are tried first, in
+//! configured order, so every provider gets measured.
+
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::types::RouteTarget;
+
+/// Weight given to each new sample; higher reacts faster to changes
+const EWMA_ALPHA: f64 = 0.3;
+
+/// Recent response time per provider
+#[derive(Clone)]
+pub struct LatencyTracker {
+    /// Smoothed latency in milliseconds by provider id
+    averages: Arc<Mutex<HashMap<String, f64>>>,
+    /// Sample recorded for a failed call, in milliseconds
+    failure_penalty_ms: u64,
+}
+
+impl LatencyTracker {
+    /// Create a tracker with no samples
+    ///
+    /// # Arguments
+    /// * `failure_penalty` - Response time counted for a failed call
+    pub fn new(failure_penalty: Duration) -> Self {
+        Self {
+            averages: Arc::default(),
+            failure_penalty_ms: failure_penalty.as_millis() as u64,
+        }
+    }
+
+    /// Record a response time for `provider`
+    pub fn record(&self, provider: &str, response_time_ms: u64) {
+        let sample = response_time_ms as f64;
+        let mut averages = self.averages.lock().unwrap();
+        averages
+            .entry(provider.to_string())
+            .and_modify(|average| *average = EWMA_ALPHA * sample + (1.0 - EWMA_ALPHA) * *average)
+            .or_insert(sample);
+    }
+
+    /// Record a failed or timed out call to `provider` as a penalty sample
+    pub fn record_failure(&self, provider: &str) {
+        self.record(provider, self.failure_penalty_ms);
+    }
+
+    /// Smoothed latency for `provider` in milliseconds, if sampled
+    #[cfg(test)]
+    pub fn average_ms(&self, provider: &str) -> Option<f64> {
+        self.averages.lock().unwrap().get(provider).copied()
+    }
+
+    /// Order route targets for attempts
+    ///
+    /// # Arguments
+    /// * `targets` - Candidate targets in configured order
+    /// * `adaptive` - Whether to reorder by recent latency
+    ///
+    /// # Returns
+    /// Targets fastest-first when `adaptive` is set, otherwise unchanged.
+    /// Unmeasured providers come first; ties among them and among equal
+    /// latencies keep their configured order.
+    pub fn attempt_order<'a>(&self, targets: &'a [RouteTarget], adaptive: bool) -> Vec<&'a RouteTarget> {
+        let mut ordered: Vec<&RouteTarget> = targets.iter().collect();
+        if !adaptive {
+            return ordered;
+        }
+
+        let averages = self.averages.lock().unwrap();
+        let latency = |target: &RouteTarget| averages.get(target.provider.as_str()).copied();
+        // Stable sort, so equal and unmeasured entries stay in configured order
+        ordered.sort_by(|a, b| match (latency(a), latency(b)) {
+            (Some(a), Some(b)) => a.partial_cmp(&b).unwrap_or(Ordering::Equal),
+            (Some(_), None) => Ordering::Greater,
+            (None, Some(_)) => Ordering::Less,
+            (None, None) => Ordering::Equal,
+        });
+        ordered
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{InvokeOptions, Provider};
+
+    fn target(provider: Provider, model: &str) -> RouteTarget {
+        RouteTarget {
+            provider,
+            model: model.to_string(),
+            defaults: InvokeOptions::default(),
+        }
+    }
+
+    fn chain() -> Vec<RouteTarget> {
+        vec![
+            target(Provider::OpenAI, "gpt-4o-mini"),
+            target(Provider::Anthropic, "claude-3-5-haiku"),
+            target(Provider::Groq, "llama-3.1-8b-instant"),
+            target(Provider::Mistral, "mistral-small"),
+        ]
+    }
+
+    fn tracker() -> LatencyTracker {
+        LatencyTracker::new(Duration::from_secs(30))
+    }
+
+    fn models(ordered: &[&RouteTarget]) -> Vec<String> {
+        ordered.iter().map(|target| target.model.clone()).collect()
+    }
+
+    #[test]
+    fn test_ewma_smooths_samples() {
+        let tracker = tracker();
+        assert_eq!(tracker.average_ms("openai"), None);
+
+        tracker.record("openai", 1000);
+        assert_eq!(tracker.average_ms("openai"), Some(1000.0));
+
+        // 0.3 * 2000 + 0.7 * 1000
+        tracker.record("openai", 2000);
+        assert!((tracker.average_ms("openai").unwrap() - 1300.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_attempt_order_prefers_lowest_recent_latency() {
+        let tracker = tracker();
+        for ms in [900, 1100, 1000] {
+            tracker.record("openai", ms);
+        }
+        for ms in [300, 250] {
+            tracker.record("groq", ms);
+        }
+        for ms in [400, 5000] {
+            tracker.record("anthropic", ms); // one slow response pushes it back
+        }
+        let targets = chain();
+
+        // Mistral has no samples yet, so it is tried first to get one
+        let ordered = tracker.attempt_order(&targets, true);
+        assert_eq!(
+            models(&ordered),
+            ["mistral-small", "llama-3.1-8b-instant", "gpt-4o-mini", "claude-3-5-haiku"]
+        );
+
+        // Disabled: configured order is kept
+        let ordered = tracker.attempt_order(&targets, false);
+        assert_eq!(
+            models(&ordered),
+            ["gpt-4o-mini", "claude-3-5-haiku", "llama-3.1-8b-instant", "mistral-small"]
+        );
+    }
+
+    #[test]
+    fn test_failures_push_a_provider_back() {
+        let tracker = tracker();
+        tracker.record("openai", 200);
+        tracker.record("groq", 500);
+        tracker.record("anthropic", 800);
+        tracker.record("mistral", 900);
+        let targets = chain();
+        assert_eq!(models(&tracker.attempt_order(&targets, true))[0], "gpt-4o-mini");
+
+        // 0.3 * 30000 + 0.7 * 200
+        tracker.record_failure("openai");
+        assert!((tracker.average_ms("openai").unwrap() - 9140.0).abs() < 1e-9);
+        assert_eq!(
+            models(&tracker.attempt_order(&targets, true)),
+            ["llama-3.1-8b-instant", "claude-3-5-haiku", "mistral-small", "gpt-4o-mini"]
+        );
+    }
+
+    #[test]
+    fn test_attempt_order_without_samples_is_configured_order() {
+        let targets = chain();
+        let ordered = tracker().attempt_order(&targets, true);
+        assert_eq!(models(&ordered), models(&targets.iter().collect::<Vec<_>>()));
+    }
+}
@@ -19,11 +19,35 @@
 
 // Public module exports for external usage
 pub mod auth;              // Authentication and user management
+pub mod backoff;           // Jittered exponential backoff for retries
+pub mod callback;          // Result delivery to client callback URLs
+pub mod client_ip;         // Client IP resolution behind trusted proxies
+pub mod clock;             // Injectable time source for expiry and resets
 pub mod config;            // Configuration from environment variables  
+pub mod content_filter;    // Screening of client messages before dispatch
 pub mod convex_service;    // Database abstraction layer
+pub mod dedup;             // Duplicate request detection
+pub mod dispatch_queue;    // Tier-prioritized queue for provider dispatch
 pub mod file_processor;    // File upload and processing utilities
+pub mod http_client;       // Shared outbound HTTP client configuration
+pub mod latency;           // Recent response times per AI provider
+pub mod log_sampling;      // Sampled per-request logging
+pub mod payload;           // Provider-specific chat request bodies
+pub mod prompt;            // System prompt injection for outbound conversations
+pub mod provider_limit;    // Outbound request limits per AI provider
+pub mod providers;         // Chat dispatch to each AI provider's API
+pub mod rate_limit;        // Request quota storage and per-user limits
+pub mod request_timing;    // Per-phase timing breakdown of invoke requests
+pub mod response_cache;    // Cached responses for deterministic requests
 pub mod routing;           // AI provider routing logic
 pub mod search_service;    // Web search integration
+pub mod secret;            // Redacted, zeroized secret values
+pub mod self_check;        // Preflight validation for `rust-ai check`
+pub mod shutdown;          // Shutdown notification for long-lived responses
+pub mod sse_relay;         // Keep-alive for streamed SSE responses
+pub mod stream_deadline;   // Maximum lifetime for streaming responses
+#[cfg(test)]
+mod test_support;          // Mock servers shared by unit tests
 pub mod types;             // Shared type definitions
 
 
@@ -0,0 +1,81 @@
+//! Log Sampling Module
+//!
+//! Per-request logging for the HTTP tracing middleware, sampled by
+//! `LOG_SAMPLE_RATE` so busy deployments do not flood their log
+//! aggregator.
+//!
+//! Each request is logged once, when its response is sent, inside a span
+//! carrying the method and URI. Successful and client-error responses are
+//! kept with probability `LOG_SAMPLE_RATE`; server errors and failures are
+//! always logged.
+
+use axum::http::StatusCode;
+use rand::Rng;
+use std::time::Duration;
+use tower_http::classify::ServerErrorsFailureClass;
+use tracing::Level;
+
+/// Whether a log event at `level` is emitted under `sample_rate`
+///
+/// Errors always pass. Otherwise the event is kept with probability
+/// `sample_rate`, clamped to `0.0..=1.0`.
+pub fn should_log(sample_rate: f64, level: Level) -> bool {
+    if level == Level::ERROR || sample_rate >= 1.0 {
+        return true;
+    }
+    sample_rate > 0.0 && rand::thread_rng().gen_bool(sample_rate)
+}
+
+/// Log a finished request, subject to sampling
+///
+/// Server errors are logged at error level and never dropped.
+pub fn log_response(sample_rate: f64, status: StatusCode, latency: Duration) {
+    let latency_ms = latency.as_millis() as u64;
+    if status.is_server_error() {
+        tracing::error!(status = status.as_u16(), latency_ms, "finished processing request");
+    } else if should_log(sample_rate, Level::INFO) {
+        tracing::info!(status = status.as_u16(), latency_ms, "finished processing request");
+    }
+}
+
+/// Log a request that failed without a response status (e.g. a body error)
+///
+/// Error statuses are already reported by `log_response`.
+pub fn log_failure(failure: ServerErrorsFailureClass, latency: Duration) {
+    if let ServerErrorsFailureClass::Error(error) = failure {
+        tracing::error!(latency_ms = latency.as_millis() as u64, "request failed: {}", error);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sampled(sample_rate: f64, level: Level, trials: usize) -> usize {
+        (0..trials).filter(|_| should_log(sample_rate, level)).count()
+    }
+
+    #[test]
+    fn test_sampling_keeps_roughly_the_configured_fraction() {
+        let kept = sampled(0.1, Level::INFO, 20_000);
+        // 2000 expected; the standard deviation is about 42
+        assert!((1700..=2300).contains(&kept), "{}", kept);
+
+        let kept = sampled(0.5, Level::WARN, 20_000);
+        assert!((9400..=10_600).contains(&kept), "{}", kept);
+    }
+
+    #[test]
+    fn test_rate_bounds() {
+        assert_eq!(sampled(1.0, Level::INFO, 1000), 1000);
+        assert_eq!(sampled(0.0, Level::INFO, 1000), 0);
+        assert_eq!(sampled(-1.0, Level::DEBUG, 1000), 0);
+        assert_eq!(sampled(2.0, Level::DEBUG, 1000), 1000);
+    }
+
+    #[test]
+    fn test_errors_are_always_logged() {
+        assert_eq!(sampled(0.0, Level::ERROR, 1000), 1000);
+        assert_eq!(sampled(0.01, Level::ERROR, 1000), 1000);
+    }
+}
@@ -11,45 +11,95 @@
 
 // Module declarations - each module handles a specific domain of functionality
 mod auth;              // Authentication and user management
+mod backoff;           // Jittered exponential backoff for retries
+mod callback;          // Result delivery to client callback URLs
+mod client_ip;         // Client IP resolution behind trusted proxies
+mod clock;             // Injectable time source for expiry and resets
 mod config;            // Configuration loading from environment variables
+mod content_filter;    // Screening of client messages before dispatch
 mod convex_service;    // Database abstraction layer for Convex backend
+mod dedup;             // Duplicate request detection within a short window
+mod dispatch_queue;    // Tier-prioritized queue for provider dispatch
 mod file_processor;    // File upload and processing utilities
+mod http_client;       // Shared outbound HTTP client configuration
+mod latency;           // Recent response times per AI provider
+mod log_sampling;      // Sampled per-request logging
+mod payload;           // Provider-specific chat request bodies
+mod prompt;            // System prompt injection for outbound conversations
+mod provider_limit;    // Outbound request limits per AI provider
+mod providers;         // Chat dispatch to each AI provider's API
+mod rate_limit;        // Request quota storage and per-user limits
+mod request_timing;    // Per-phase timing breakdown of invoke requests
+mod response_cache;    // Cached responses for deterministic requests
 mod routing;           // Provider routing and AI request handling
 mod search_service;    // Web search integration for enhanced AI responses
+mod secret;            // Redacted, zeroized secret values
+mod self_check;        // Preflight validation for `rust-ai check`
+mod shutdown;          // Shutdown notification for long-lived responses
+mod sse_relay;         // Keep-alive for streamed SSE responses
+mod stream_deadline;   // Maximum lifetime for streaming responses
+#[cfg(test)]
+mod test_support;      // Mock servers shared by unit tests
 mod types;             // Type definitions and serialization structs
 
 // Standard library and external crate imports
 use anyhow::Result;
 use axum::{
-    extract::{Query, State},
-    http::{HeaderMap, StatusCode},
-    response::Json,
-    routing::{get, post},
-    Router,
+    extract::{DefaultBodyLimit, FromRef, Path, Query, Request, State},
+    http::{header::{CONTENT_LENGTH, CONTENT_TYPE, RETRY_AFTER}, HeaderMap, HeaderValue, StatusCode},
+    middleware::{self, Next},
+    response::{sse::Event, IntoResponse, Json, Response},
+    routing::{delete, get, post},
+    Extension, Router,
 };
+use futures::StreamExt;
 use serde::Deserialize;
 use serde_json::{json, Value};
+use socket2::{Domain, Protocol, Socket, TcpKeepalive, Type};
 use std::collections::HashMap;
+use std::future::Future;
 use std::net::SocketAddr;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, Mutex};
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::time::{Duration, Instant};
 use tokio::signal;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
 use tower::ServiceBuilder;
 use tower_http::cors::{Any, CorsLayer};
-use tower_http::trace::TraceLayer;
-use tracing::info;
+use tower_http::trace::{DefaultMakeSpan, TraceLayer};
+use tracing::{info, Level};
+use tracing_subscriber::EnvFilter;
 use uuid::Uuid;
+use validator::Validate;
 
 // Internal module imports
-use auth::{AuthService, CreateUserRequest, LoginRequest};
-use config::Config;
-use convex_service::ConvexService;
-use search_service::SearchService;
-use types::{ApiResponse, InvokeRequest, AuthUser};
+use auth::{extract_bearer, is_guest_user_id, AuthService, Claims, CreateUserRequest, LoginRequest, PASSWORD_RESET_TTL_SECS};
+use callback::{deliver_callback, validate_callback_url};
+use client_ip::{ClientIp, TrustedProxies};
+use clock::{next_utc_day_start_ms, system_clock, Clock, SharedClock};
+use config::{Config, ErrorVerbosity, SearchDisabledBehavior, SearchFailedBehavior};
+use content_filter::{BlocklistFilter, ContentFilter, FilterVerdict, ResponseStripper};
+use convex_service::{ConvexService, MessageEvent};
+use dedup::{dedup_key, RequestDeduplicator};
+use dispatch_queue::{DispatchPermit, DispatchPriority, DispatchQueue};
+use file_processor::{process_file_attachments, AttachmentCache, AttachmentClient, AttachmentLimits, FetchPolicy};
+use http_client::build_client;
+use latency::LatencyTracker;
+use prompt::{build_conversation, check_prompt_size};
+use provider_limit::ProviderRateLimiter;
+use providers::{build_provider, race_complete, ChatProvider, CompletionResult, DeltaStream, RACE_WIDTH};
+use rate_limit::{InMemoryRateLimitStore, IpRateLimiter, RateLimitDecision, RateLimitStore, UserRateLimiter};
+use request_timing::RequestTimings;
+use response_cache::{is_deterministic, response_cache_key, ResponseCache};
+use routing::{is_blocked_model, resolve_multimodal_route, resolve_route, RoutingMap, SharedRouting};
+use search_service::{search_context, SearchService, SEARCH_UNAVAILABLE_NOTE};
+use shutdown::{until_shutdown, ShutdownSignal};
+use sse_relay::sse_with_keepalive;
+use stream_deadline::with_max_duration;
+use types::{ApiResponse, ChatMessage, InvokeOptions, InvokeRequest, AuthUser, MessageRole, Operation, RouteTarget, GUEST_TIER};
 
 // Rate limiting configuration for guest users
 // This prevents abuse while allowing trial usage without registration
-#[allow(dead_code)]
 const MAX_GUEST_MESSAGES_PER_DAY: u32 = 5;
 
 /// Guest usage tracking structure for rate limiting
@@ -76,7 +126,6 @@ type GuestUsageMap = Arc<Mutex<HashMap<String, GuestUsage>>>;
 /// Contains all services and configuration needed to process requests.
 /// Cloned cheaply due to Arc wrappers in underlying services.
 #[derive(Clone)]
-#[allow(dead_code)]
 struct AppState {
     /// Application configuration loaded from environment
     config: Config,
@@ -88,6 +137,113 @@ struct AppState {
     search_service: SearchService,
     /// In-memory rate limiting for guest users
     guest_usage: GuestUsageMap,
+    /// Daily rate limiting for registered users by subscription tier
+    user_limiter: UserRateLimiter,
+    /// Per-minute rate limiting by client IP (`PER_IP_RPM`)
+    ip_limiter: IpRateLimiter,
+    /// Shared results for identical requests submitted within the dedup window
+    deduplicator: RequestDeduplicator<Value>,
+    /// Provider routes keyed by `op.tier`, replaced by route reloads
+    routing: SharedRouting,
+    /// Outbound request limits per provider
+    provider_limiter: ProviderRateLimiter,
+    /// Recent response times per provider, for `ADAPTIVE_ROUTING`
+    latency: LatencyTracker,
+    /// Time source for guest limit resets
+    clock: SharedClock,
+    /// Proxies allowed to report the client IP via `X-Forwarded-For`
+    trusted_proxies: TrustedProxies,
+    /// Screening applied to client messages before dispatch
+    content_filter: Arc<dyn ContentFilter>,
+    /// Phrases removed from assistant replies (`RESPONSE_STRIPPERS`)
+    response_stripper: Arc<ResponseStripper>,
+    /// Signalled when graceful shutdown begins, to close open streams
+    shutdown: ShutdownSignal,
+    /// Provider responses to deterministic requests
+    response_cache: ResponseCache,
+    /// Tier-prioritized admission to provider dispatch
+    dispatch_queue: DispatchQueue,
+    /// Slots for open streaming invokes (`MAX_CONCURRENT_STREAMS`)
+    stream_slots: Arc<Semaphore>,
+    /// HTTP client for provider chat requests
+    provider_client: reqwest::Client,
+    /// Clients for attachment URLs, pinned to the addresses the fetch policy checked
+    attachment_client: AttachmentClient,
+    /// Processed attachment content reused across requests
+    attachment_cache: AttachmentCache,
+}
+
+impl AppState {
+    /// Services, limits and clients for `config`, serving `routes`
+    fn new(config: Config, routes: RoutingMap) -> Self {
+        // Shared time source for expiry, resets and cache TTLs
+        let clock = system_clock();
+        
+        // Initialize all services with dependency injection
+        // Order matters: ConvexService first (used by others)
+        let convex_service = ConvexService::new(config.clone()).with_clock(clock.clone());
+        let auth_service = AuthService::new(config.clone(), convex_service.clone())
+            .with_clock(clock.clone());
+        let search_service = SearchService::new(config.clone()).with_clock(clock.clone());
+        
+        // Initialize in-memory rate limiting for guest users
+        let guest_usage = Arc::new(Mutex::new(HashMap::new()));
+        
+        // Initialize per-tier daily limits for registered users and per-IP
+        // limits for everyone, sharing one counter store
+        let rate_limit_store: Arc<dyn RateLimitStore> = Arc::new(InMemoryRateLimitStore::new());
+        let user_limiter = UserRateLimiter::new(rate_limit_store.clone(), config.tier_limits.clone())
+            .with_clock(clock.clone());
+        let ip_limiter = IpRateLimiter::new(rate_limit_store, config.per_ip_rpm).with_clock(clock.clone());
+        
+        // Initialize duplicate request detection
+        let deduplicator = RequestDeduplicator::new(config.dedup_window_ms);
+        
+        // Initialize outbound per-provider limits
+        let provider_limiter =
+            ProviderRateLimiter::new(config.provider_rpm.clone(), config.provider_queue_ms);
+        let latency = LatencyTracker::new(Duration::from_secs(config.provider_timeout_secs));
+        let trusted_proxies = TrustedProxies::new(&config.trusted_proxies);
+        let content_filter: Arc<dyn ContentFilter> = Arc::new(BlocklistFilter::new(&config.content_blocklist));
+        let response_stripper = Arc::new(ResponseStripper::new(&config.response_strippers));
+        let response_cache = ResponseCache::new(config.response_cache_ttl_secs, config.response_cache_max_entries).with_clock(clock.clone());
+        let dispatch_queue = DispatchQueue::new(config.max_concurrent_dispatches, config.dispatch_queue_size);
+        let stream_slots = stream_slots(config.max_concurrent_streams);
+        let provider_client = build_client(&config, Duration::from_secs(config.provider_timeout_secs));
+        let attachment_client = AttachmentClient::new(&config);
+        let attachment_cache = AttachmentCache::default().with_clock(clock.clone());
+        
+        Self {
+            config,
+            auth_service,
+            convex_service,
+            search_service,
+            guest_usage,
+            user_limiter,
+            ip_limiter,
+            deduplicator,
+            routing: SharedRouting::new(routes),
+            provider_limiter,
+            latency,
+            clock,
+            trusted_proxies,
+            content_filter,
+            response_stripper,
+            shutdown: ShutdownSignal::new(),
+            response_cache,
+            dispatch_queue,
+            stream_slots,
+            provider_client,
+            attachment_client,
+            attachment_cache,
+        }
+    }
+}
+
+impl FromRef<AppState> for TrustedProxies {
+    fn from_ref(state: &AppState) -> Self {
+        state.trusted_proxies.clone()
+    }
 }
 
 /// Request payload for user registration endpoint
@@ -97,10 +253,8 @@ struct AppState {
 struct CreateUserParams {
     /// User's email address (used as unique identifier)
     email: String,
-    /// Plain text password (will be hashed with bcrypt)
+    /// Plain text password (will be hashed before storage)
     password: String,
-    /// Optional subscription tier (defaults to "free" if not provided)
-    subscription_tier: Option<String>,
 }
 
 /// Request payload for user login endpoint
@@ -110,10 +264,41 @@ struct CreateUserParams {
 struct LoginParams {
     /// User's registered email address
     email: String,
-    /// User's password (compared against stored bcrypt hash)
+    /// User's password (compared against stored password hash)
+    password: String,
+}
+
+/// Request payload for the token refresh endpoint
+#[derive(Debug, Deserialize)]
+struct RefreshParams {
+    /// Refresh token issued at login
+    refresh_token: String,
+}
+
+/// Request payload for the password reset request endpoint
+#[derive(Debug, Deserialize)]
+struct PasswordResetRequestParams {
+    /// Email address of the account to reset
+    email: String,
+}
+
+/// Request payload for the password reset confirmation endpoint
+#[derive(Debug, Deserialize)]
+struct PasswordResetConfirmParams {
+    /// Reset token delivered for the account
+    token: String,
+    /// New password (at least 8 characters)
     password: String,
 }
 
+/// Request payload for token introspection endpoint
+#[derive(Debug, Deserialize)]
+struct VerifyTokenParams {
+    /// Token issued by this gateway (falls back to the Authorization header)
+    #[serde(default)]
+    token: Option<String>,
+}
+
 /// Query parameters for analytics endpoint
 /// 
 /// Allows filtering analytics data by time range
@@ -124,22 +309,13 @@ struct AnalyticsQuery {
     hours: Option<u32>,
 }
 
-/// Calculate the start of the next day in milliseconds since Unix epoch
-/// 
-/// Used for rate limiting reset times. Ensures all users get their
-/// quota reset at the same time (start of day in UTC).
-/// 
-/// # Arguments
-/// * `timestamp` - Current timestamp in milliseconds
-/// 
-/// # Returns
-/// Timestamp in milliseconds representing the start of the next day
-#[allow(dead_code)]
-fn start_of_next_day(timestamp: u64) -> u64 {
-    // Add 24 hours to current time, then round down to start of day
-    let next_day = timestamp + (24 * 60 * 60 * 1000);
-    let next_day_start = (next_day / (24 * 60 * 60 * 1000)) * (24 * 60 * 60 * 1000);
-    next_day_start
+/// Query parameters for the analytics event export endpoint
+#[derive(Debug, Deserialize)]
+struct AnalyticsEventsQuery {
+    /// Number of hours back to export events (optional, defaults to all retained)
+    hours: Option<u32>,
+    /// Export format; only "jsonl" is supported (default)
+    format: Option<String>,
 }
 
 /// Generate a unique key for guest user tracking
@@ -147,7 +323,9 @@ fn start_of_next_day(timestamp: u64) -> u64 {
 /// Creates a consistent key for rate limiting that can identify
 /// unique guests across requests using available identifiers.
 /// 
-/// Priority: user_id (for anonymous users) > fingerprint + IP > fallback
+/// Priority: IP > fingerprint > guest user_id > fallback. The client IP
+/// comes first because the other identifiers are chosen by the client, and
+/// a new guest token or fingerprint must not bring a fresh quota.
 /// 
 /// # Arguments
 /// * `fingerprint` - Browser fingerprint (optional)
@@ -156,395 +334,211 @@ fn start_of_next_day(timestamp: u64) -> u64 {
 /// 
 /// # Returns
 /// String key for tracking this guest in the usage map
-#[allow(dead_code)]
 fn get_guest_key(fingerprint: Option<&str>, ip_address: Option<&str>, user_id: Option<&str>) -> String {
-    // If we have an anonymous user ID, use that for consistency
-    if let Some(uid) = user_id {
-        if uid.starts_with("anon-") {
-            return format!("anon:{}", uid);
+    if let Some(ip) = ip_address {
+        return format!("ip:{}", ip);
+    }
+    if let Some(fingerprint) = fingerprint {
+        return format!("fp:{}", fingerprint);
+    }
+    match user_id {
+        Some(uid) if is_guest_user_id(uid) => format!("anon:{}", uid),
+        _ => "unknown".to_string(),
     }
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use axum_test::TestServer;
-    use serde_json::{json, Value};
+/// Check and enforce daily rate limits for guest users
+/// 
+/// This is the primary rate limiting mechanism for unauthenticated users.
+/// It prevents abuse while allowing genuine trial usage.
+/// 
+/// The function is thread-safe and handles concurrent access through mutex locking.
+/// It automatically resets counters at the start of each new day.
+/// 
+/// # Arguments
+/// * `guest_usage` - Shared map of guest usage tracking
+/// * `clock` - Time source for daily resets
+/// * `fingerprint` - Browser fingerprint for identification
+/// * `ip_address` - Client IP address for identification  
+/// * `user_id` - Anonymous user ID if available
+/// 
+/// # Returns
+/// Tuple containing:
+/// * `bool` - Whether request is allowed (under rate limit)
+/// * `u32` - Remaining requests for today
+/// * `u64` - Timestamp when limit resets (milliseconds since epoch)
+/// * `String` - Status message for logging/debugging
+fn check_guest_daily_limit(
+    guest_usage: &GuestUsageMap,
+    clock: &dyn Clock,
+    fingerprint: Option<&str>,
+    ip_address: Option<&str>,
+    user_id: Option<&str>,
+) -> (bool, u32, u64, String) {
+    let now = clock.now_ms();
+    let key = get_guest_key(fingerprint, ip_address, user_id);
+
+    // Lock the usage map for thread-safe access
+    let mut usage_map = guest_usage.lock().unwrap();
     
-    fn create_test_app_state() -> AppState {
-        let config = Config::from_env();
-        let convex_service = ConvexService::new(config.clone());
-        let auth_service = AuthService::new(config.clone(), convex_service.clone());
-        let search_service = SearchService::new(config.clone());
-        
-        AppState {
-            config,
-            auth_service,
-            convex_service,
-            search_service,
-            guest_usage: Arc::new(Mutex::new(HashMap::new())),
+    if let Some(entry) = usage_map.get_mut(&key) {
+        // Check if we need to reset for a new day
+        if now >= entry.reset_at {
+            let reset_at = next_utc_day_start_ms(now);
+            let remaining = MAX_GUEST_MESSAGES_PER_DAY.saturating_sub(1);
+            *entry = GuestUsage { count: 1, reset_at };
+            return (true, remaining, reset_at, "fallback_ok".to_string());
         }
-    }
-    
-    #[tokio::test]
-    async fn test_health_check() {
-        let state = create_test_app_state();
-        let app = create_router(state);
-        let server = TestServer::new(app).unwrap();
-        
-        let response = server.get("/health").await;
-        
-        response.assert_status_ok();
-        response.assert_json(&json!({
-            "status": "ok",
-            "service": "rust-ai-api",
-            "version": env!("CARGO_PKG_VERSION")
-        }));
-    }
-    
-    #[tokio::test]
-    async fn test_invoke_endpoint_structure() {
-        let state = create_test_app_state();
-        let app = create_router(state);
-        let server = TestServer::new(app).unwrap();
         
-        let request_body = json!({
-            "operation": "chat",
-            "tier": "fast",
-            "messages": [
-                {
-                    "role": "user",
-                    "content": "Hello, AI!"
-                }
-            ]
-        });
-        
-        let response = server.post("/v1/invoke").json(&request_body).await;
-        
-        response.assert_status_ok();
+        // Check if user has exceeded daily limit
+        if entry.count >= MAX_GUEST_MESSAGES_PER_DAY {
+            return (false, 0, entry.reset_at, "fallback_limit".to_string());
+        }
         
-        let body: Value = response.json();
-        assert_eq!(body["status"], "success");
-        assert!(body["data"]["request_id"].is_string());
-        assert_eq!(body["data"]["status"], "processed");
+        // Increment usage counter
+        entry.count += 1;
+        let remaining = MAX_GUEST_MESSAGES_PER_DAY.saturating_sub(entry.count);
+        (true, remaining, entry.reset_at, "fallback_ok".to_string())
+    } else {
+        // First request from this guest - create new tracking entry
+        let reset_at = next_utc_day_start_ms(now);
+        let remaining = MAX_GUEST_MESSAGES_PER_DAY.saturating_sub(1);
+        usage_map.insert(key, GuestUsage { count: 1, reset_at });
+        (true, remaining, reset_at, "fallback_ok".to_string())
     }
-    
-    #[tokio::test]
-    async fn test_invoke_endpoint_invalid_request() {
-        let state = create_test_app_state();
-        let app = create_router(state);
-        let server = TestServer::new(app).unwrap();
-        
-        let invalid_request = json!({
-            "invalid_field": "value"
-        });
-        
-        let response = server.post("/v1/invoke").json(&invalid_request).await;
-        
-        // Should return 400 Bad Request or similar for malformed JSON
-        assert!(response.status_code().is_client_error());
+}
+
+/// Health check endpoint for monitoring and load balancer probes
+/// 
+/// Returns server status, service name, version and build metadata. Used by:
+/// - Load balancers for health checks
+/// - Monitoring systems for uptime tracking
+/// - Developers for quick service verification
+/// 
+/// Always returns 200 OK with JSON response.
+async fn health_check() -> Json<Value> {
+    Json(json!({
+        "status": "ok",
+        "service": "rust-ai-api",
+        "version": env!("CARGO_PKG_VERSION"),
+        "build": build_info()
+    }))
+}
+
+/// Commit and build time embedded by the build script
+/// 
+/// Either is `null` when unavailable (e.g. built outside a git checkout).
+fn build_info() -> Value {
+    let built_at = option_env!("RUST_AI_BUILD_TIMESTAMP")
+        .and_then(|secs| secs.parse::<i64>().ok())
+        .and_then(|secs| chrono::DateTime::from_timestamp(secs, 0))
+        .map(|time| time.to_rfc3339());
+    json!({
+        "git_sha": option_env!("RUST_AI_GIT_SHA"),
+        "built_at": built_at
+    })
+}
+
+/// Readiness probe
+/// 
+/// Unlike `/health`, reports whether the service is correctly configured
+/// to serve traffic. Returns 503 when authentication is required but no
+/// JWT secret is configured; non-fatal issues are listed as warnings.
+/// 
+/// # Response
+/// ```json
+/// { "status": "ready", "warnings": [] }
+/// ```
+async fn readiness_check(State(state): State<AppState>) -> Response {
+    match state.config.check_jwt_config() {
+        Ok(warning) => Json(json!({
+            "status": "ready",
+            "warnings": warning.into_iter().collect::<Vec<_>>()
+        }))
+        .into_response(),
+        Err(error) => (
+            StatusCode::SERVICE_UNAVAILABLE,
+            Json(json!({
+                "status": "not_ready",
+                "errors": [error]
+            })),
+        )
+            .into_response(),
     }
+}
+
+/// Configuration diagnostics endpoint (admin only)
+/// 
+/// Reports likely misconfigurations detected in the running config,
+/// such as a provider base URL and API key that do not match.
+/// 
+/// # Response
+/// ```json
+/// {
+///   "status": "success",
+///   "data": { "warnings": ["OPENAI_BASE_URL points at a custom endpoint (...) but OPENAI_API_KEY is empty"] }
+/// }
+/// ```
+/// 
+/// # Errors
+/// - 401 UNAUTHORIZED: Missing or invalid token
+/// - 403 FORBIDDEN: Caller is not an admin
+async fn diagnostics(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Json<ApiResponse<Value>>, Response> {
+    require_admin(&state, &headers).map_err(|(status, message)| error_response(status, message))?;
     
-    #[tokio::test]
-    async fn test_analytics_endpoint() {
-        let state = create_test_app_state();
-        let app = create_router(state);
-        let server = TestServer::new(app).unwrap();
-        
-        let response = server.get("/v1/analytics").await;
-        
-        response.assert_status_ok();
-        
-        let body: Value = response.json();
-        assert_eq!(body["status"], "success");
-        assert!(body["data"].is_object());
-        // Should contain analytics data structure
-        assert!(body["data"]["total_requests"].is_number());
-        assert!(body["data"]["active_users"].is_number());
-    }
-    
-    #[tokio::test]
-    async fn test_anonymous_session_creation() {
-        let state = create_test_app_state();
-        let app = create_router(state);
-        let server = TestServer::new(app).unwrap();
-        
-        let response = server.post("/v1/auth/anonymous").await;
-        
-        response.assert_status_ok();
-        
-        let body: Value = response.json();
-        assert_eq!(body["status"], "success");
-        assert!(body["data"]["token"].is_string());
-        assert!(body["data"]["user"]["is_anonymous"].as_bool().unwrap());
-        assert!(body["data"]["user"]["id"].as_str().unwrap().starts_with("anon-"));
-    }
-    
-    #[tokio::test]
-    async fn test_user_registration_endpoint() {
-        let state = create_test_app_state();
-        let app = create_router(state);
-        let server = TestServer::new(app).unwrap();
-        
-        let registration_request = json!({
-            "email": "test@example.com",
-            "password": "securepassword123"
-        });
-        
-        let response = server.post("/v1/auth/register").json(&registration_request).await;
-        
-        // Note: This might fail in the actual implementation due to validation
-        // or database constraints, but we're testing the endpoint structure
-        response.assert_status_ok();
-        
-        let body: Value = response.json();
-        // Should return success or appropriate validation error
-        assert!(body["status"].is_string());
-    }
-    
-    #[tokio::test]
-    async fn test_login_endpoint() {
-        let state = create_test_app_state();
-        let app = create_router(state);
-        let server = TestServer::new(app).unwrap();
-        
-        let login_request = json!({
-            "email": "test@example.com",
-            "password": "password123"
-        });
-        
-        let response = server.post("/v1/auth/login").json(&login_request).await;
-        
-        response.assert_status_ok();
-        
-        let body: Value = response.json();
-        assert_eq!(body["status"], "success");
-        // Login should fail for non-existent user but return proper structure
-        assert!(body["data"].is_object());
-    }
-    
-    #[tokio::test]
-    async fn test_cors_headers() {
-        let state = create_test_app_state();
-        let app = create_router(state);
-        let server = TestServer::new(app).unwrap();
-        
-        let response = server.get("/health").await;
-        
-        // Check that CORS headers are present
-        assert!(response.headers().contains_key("access-control-allow-origin"));
-    }
-    
-    #[tokio::test]
-    async fn test_nonexistent_route() {
-        let state = create_test_app_state();
-        let app = create_router(state);
-        let server = TestServer::new(app).unwrap();
-        
-        let response = server.get("/nonexistent").await;
-        
-        response.assert_status_not_found();
-    }
-    
-    #[tokio::test]
-    async fn test_invoke_with_attachments() {
-        let state = create_test_app_state();
-        let app = create_router(state);
-        let server = TestServer::new(app).unwrap();
-        
-        let request_body = json!({
-            "operation": "chat",
-            "tier": "smart",
-            "messages": [
-                {
-                    "role": "user",
-                    "content": "Analyze this file"
-                }
-            ],
-            "attachments": [
-                {
-                    "filename": "test.txt",
-                    "content_type": "text/plain",
-                    "data": "SGVsbG8gV29ybGQ=" // Base64: "Hello World"
-                }
-            ],
-            "temperature": 0.7,
-            "max_tokens": 1000
-        });
-        
-        let response = server.post("/v1/invoke").json(&request_body).await;
-        
-        response.assert_status_ok();
-        
-        let body: Value = response.json();
-        assert_eq!(body["status"], "success");
-        assert!(body["data"]["request_id"].is_string());
-    }
-    
-    #[test]
-    fn test_guest_usage_functions() {
-        // Test get_guest_key function
-        let key1 = get_guest_key(Some("fingerprint123"), Some("192.168.1.1"), None);
-        assert_eq!(key1, "fingerprint123|192.168.1.1");
-        
-        let key2 = get_guest_key(None, Some("192.168.1.1"), Some("anon-123"));
-        assert_eq!(key2, "anon:anon-123");
-        
-        let key3 = get_guest_key(None, Some("192.168.1.1"), None);
-        assert_eq!(key3, "ip:192.168.1.1");
-        
-        // Test start_of_next_day function
-        let timestamp = 1640995200000; // Jan 1, 2022 00:00:00 UTC
-        let next_day = start_of_next_day(timestamp);
-        let expected_next_day = 1641081600000; // Jan 2, 2022 00:00:00 UTC
-        assert_eq!(next_day, expected_next_day);
-    }
-    
-    #[test]
-    fn test_check_guest_daily_limit() {
-        let guest_usage = Arc::new(Mutex::new(HashMap::new()));
-        
-        // First request should be allowed
-        let (allowed, remaining, reset_at, _message) = check_guest_daily_limit(
-            &guest_usage,
-            Some("fingerprint123"),
-            Some("192.168.1.1"),
-            None
-        );
-        
-        assert!(allowed);
-        assert_eq!(remaining, 4); // 5 - 1 = 4 remaining
-        assert!(reset_at > 0);
-    }
-}
-    // Otherwise combine fingerprint and IP for best guest tracking
-    format!("{}|{}", 
-        fingerprint.unwrap_or("unknown"), 
-        ip_address.unwrap_or("unknown")
-    )
-}
-
-/// Check and enforce daily rate limits for guest users
-/// 
-/// This is the primary rate limiting mechanism for unauthenticated users.
-/// It prevents abuse while allowing genuine trial usage.
-/// 
-/// The function is thread-safe and handles concurrent access through mutex locking.
-/// It automatically resets counters at the start of each new day.
-/// 
-/// # Arguments
-/// * `guest_usage` - Shared map of guest usage tracking
-/// * `fingerprint` - Browser fingerprint for identification
-/// * `ip_address` - Client IP address for identification  
-/// * `user_id` - Anonymous user ID if available
-/// 
-/// # Returns
-/// Tuple containing:
-/// * `bool` - Whether request is allowed (under rate limit)
-/// * `u32` - Remaining requests for today
-/// * `u64` - Timestamp when limit resets (milliseconds since epoch)
-/// * `String` - Status message for logging/debugging
-#[allow(dead_code)]
-fn check_guest_daily_limit(
-    guest_usage: &GuestUsageMap,
-    fingerprint: Option<&str>,
-    ip_address: Option<&str>,
-    user_id: Option<&str>,
-) -> (bool, u32, u64, String) {
-    let key = get_guest_key(fingerprint, ip_address, user_id);
-    let now = SystemTime::now()
-        .duration_since(UNIX_EPOCH)
-        .unwrap()
-        .as_millis() as u64;
-
-    // Lock the usage map for thread-safe access
-    let mut usage_map = guest_usage.lock().unwrap();
-    
-    if let Some(entry) = usage_map.get_mut(&key) {
-        // Check if we need to reset for a new day
-        if now >= entry.reset_at {
-            let reset_at = start_of_next_day(now);
-            let remaining = MAX_GUEST_MESSAGES_PER_DAY.saturating_sub(1);
-            *entry = GuestUsage { count: 1, reset_at };
-            return (true, remaining, reset_at, "fallback_ok".to_string());
-        }
-        
-        // Check if user has exceeded daily limit
-        if entry.count >= MAX_GUEST_MESSAGES_PER_DAY {
-            return (false, 0, entry.reset_at, "fallback_limit".to_string());
-        }
-        
-        // Increment usage counter
-        entry.count += 1;
-        let remaining = MAX_GUEST_MESSAGES_PER_DAY.saturating_sub(entry.count);
-        (true, remaining, entry.reset_at, "fallback_ok".to_string())
-    } else {
-        // First request from this guest - create new tracking entry
-        let reset_at = start_of_next_day(now);
-        let remaining = MAX_GUEST_MESSAGES_PER_DAY.saturating_sub(1);
-        usage_map.insert(key, GuestUsage { count: 1, reset_at });
-        (true, remaining, reset_at, "fallback_ok".to_string())
-    }
-}
-
-/// Health check endpoint for monitoring and load balancer probes
-/// 
-/// Returns server status and current timestamp. Used by:
-/// - Load balancers for health checks
-/// - Monitoring systems for uptime tracking
-/// - Developers for quick service verification
-/// 
-/// Always returns 200 OK with JSON response.
-async fn health_check() -> Json<Value> {
-    Json(json!({
-        "status": "healthy",
-        "timestamp": chrono::Utc::now().to_rfc3339()
-    }))
-}
-
-/// User registration endpoint
-/// 
-/// Creates a new user account with email/password authentication.
-/// Passwords are automatically hashed with bcrypt before storage.
-/// 
-/// # Request Body
-/// ```json
-/// {
-///   "email": "user@example.com",
-///   "password": "secure_password",
-///   "subscription_tier": "free" // optional, defaults to "free"
-/// }
-/// ```
-/// 
-/// # Response
-/// Returns created user data with API key for immediate use.
-/// 
-/// # Errors
-/// - 400 BAD_REQUEST: Email already exists or validation failed
-/// - 500 INTERNAL_SERVER_ERROR: Database or service error
-async fn create_user(
-    State(state): State<AppState>,
-    Json(params): Json<CreateUserParams>,
-) -> Result<Json<ApiResponse<AuthUser>>, StatusCode> {
-    let request = CreateUserRequest {
-        email: params.email,
-        password: params.password,
-        subscription_tier: params.subscription_tier,
-    };
-
-    match state.auth_service.create_user(request).await {
-        Ok(result) => {
-            if result.success {
-                if let Some(user) = result.user {
-                    Ok(Json(ApiResponse::success(user)))
-                } else {
-                    Err(StatusCode::INTERNAL_SERVER_ERROR)
-                }
-            } else {
-                Err(StatusCode::BAD_REQUEST)
-            }
-        }
-        Err(_) => Err(StatusCode::INTERNAL_SERVER_ERROR),
+    Ok(Json(ApiResponse::success(json!({
+        "warnings": state.config.provider_warnings()
+    }))))
+}
+
+/// User registration endpoint
+/// 
+/// Creates a new user account with email/password authentication.
+/// Passwords are automatically hashed (`PASSWORD_HASH_ALGO`) before storage.
+/// 
+/// # Request Body
+/// ```json
+/// {
+///   "email": "user@example.com",
+///   "password": "secure_password"
+/// }
+/// ```
+/// 
+/// New accounts always start on the "free" tier; a `subscription_tier`
+/// in the body is ignored, since tiers set quotas and dispatch priority.
+/// 
+/// # Response
+/// Returns created user data with API key for immediate use.
+/// 
+/// # Errors
+/// - 400 BAD_REQUEST: Email already exists or validation failed
+/// - 500 INTERNAL_SERVER_ERROR: Database or service error
+async fn create_user(
+    State(state): State<AppState>,
+    Json(params): Json<CreateUserParams>,
+) -> Result<Json<ApiResponse<AuthUser>>, Response> {
+    let request = CreateUserRequest {
+        email: params.email,
+        password: params.password,
+        subscription_tier: None,
+    };
+
+    match state.auth_service.create_user(request).await {
+        Ok(result) => {
+            if result.success {
+                if let Some(user) = result.user {
+                    Ok(Json(ApiResponse::success(user)))
+                } else {
+                    Err(StatusCode::INTERNAL_SERVER_ERROR.into_response())
+                }
+            } else {
+                Err(StatusCode::BAD_REQUEST.into_response())
+            }
+        }
+        Err(e) => Err(internal_error_response(&state.config, StatusCode::INTERNAL_SERVER_ERROR, &e)),
     }
 }
 
@@ -562,7 +556,7 @@ async fn create_user(
 /// ```
 /// 
 /// # Response
-/// Returns JWT token and user information on successful login.
+/// Returns JWT token, refresh token and user information on successful login.
 /// 
 /// # Errors
 /// - 401 UNAUTHORIZED: Invalid credentials
@@ -570,7 +564,7 @@ async fn create_user(
 async fn login(
     State(state): State<AppState>,
     Json(params): Json<LoginParams>,
-) -> Result<Json<ApiResponse<Value>>, StatusCode> {
+) -> Result<Json<ApiResponse<Value>>, Response> {
     let request = LoginRequest {
         email: params.email,
         password: params.password,
@@ -581,36 +575,36 @@ async fn login(
             if result.success {
                 let response_data = json!({
                     "token": result.token,
+                    "refresh_token": result.refresh_token,
                     "user": result.user
                 });
                 Ok(Json(ApiResponse::success(response_data)))
             } else {
-                Err(StatusCode::UNAUTHORIZED)
+                Err(StatusCode::UNAUTHORIZED.into_response())
             }
         }
-        Err(_) => Err(StatusCode::INTERNAL_SERVER_ERROR),
+        Err(e) => Err(internal_error_response(&state.config, StatusCode::INTERNAL_SERVER_ERROR, &e)),
     }
 }
 
-/// Anonymous session creation endpoint
+/// Token refresh endpoint
 /// 
-/// Creates a temporary guest user with limited capabilities.
-/// Useful for trials and demos without requiring registration.
-/// 
-/// Guest users have:
-/// - Limited daily request quota (5 requests/day)
-/// - Temporary session (no persistent data)
-/// - Basic AI access without advanced features
+/// Exchanges a refresh token from login for a new access token, so clients
+/// can keep a session alive without asking for the password again.
 /// 
-/// # Response  
-/// Returns JWT token for the guest session.
+/// # Request Body
+/// ```json
+/// { "refresh_token": "refresh-jwt-token" }
+/// ```
 /// 
 /// # Errors
-/// - 500 INTERNAL_SERVER_ERROR: Service error
-async fn create_anonymous_session(
+/// - 401 UNAUTHORIZED: Refresh token invalid or expired, or account inactive
+/// - 500 INTERNAL_SERVER_ERROR: Database or service error
+async fn refresh_token(
     State(state): State<AppState>,
-) -> Result<Json<ApiResponse<Value>>, StatusCode> {
-    match state.auth_service.create_guest_user().await {
+    Json(params): Json<RefreshParams>,
+) -> Result<Json<ApiResponse<Value>>, Response> {
+    match state.auth_service.refresh_session(&params.refresh_token).await {
         Ok(result) => {
             if result.success {
                 let response_data = json!({
@@ -619,20 +613,347 @@ async fn create_anonymous_session(
                 });
                 Ok(Json(ApiResponse::success(response_data)))
             } else {
-                Err(StatusCode::INTERNAL_SERVER_ERROR)
+                Err(error_response(
+                    StatusCode::UNAUTHORIZED,
+                    result.error.as_deref().unwrap_or("Invalid refresh token"),
+                ))
             }
         }
-        Err(_) => Err(StatusCode::INTERNAL_SERVER_ERROR),
+        Err(e) => Err(internal_error_response(&state.config, StatusCode::INTERNAL_SERVER_ERROR, &e)),
     }
 }
 
-/// Analytics data retrieval endpoint
+/// Password reset request endpoint
 /// 
-/// Provides usage statistics and system metrics.
-/// Useful for monitoring, billing, and system optimization.
+/// Issues a one-hour reset token for the account and POSTs it to
+/// `PASSWORD_RESET_WEBHOOK_URL` (e.g. a mailer) as
+/// `{ "email", "reset_token", "expires_in" }`. The token is never returned
+/// to the caller, and the response is the same whether or not the account
+/// exists, so the endpoint cannot be used to discover accounts.
 /// 
-/// # Query Parameters
-/// - `hours`: Optional number of hours back to fetch data
+/// # Request Body
+/// ```json
+/// { "email": "user@example.com" }
+/// ```
+/// 
+/// # Response
+/// 202 ACCEPTED with a generic message
+async fn request_password_reset(
+    State(state): State<AppState>,
+    Json(params): Json<PasswordResetRequestParams>,
+) -> Response {
+    match state.auth_service.request_password_reset(&params.email).await {
+        Ok(reset_token) => match state.config.password_reset_webhook_url.clone() {
+            Some(url) => {
+                let body = json!({
+                    "email": params.email,
+                    "reset_token": reset_token,
+                    "expires_in": PASSWORD_RESET_TTL_SECS,
+                });
+                // Delivered in the background so response time doesn't reveal the account
+                tokio::spawn(async move {
+                    let delivered = match validate_callback_url(&url, true).await {
+                        Ok(target) => deliver_callback(&state.config, &target, &body).await,
+                        Err(message) => {
+                            tracing::error!("PASSWORD_RESET_WEBHOOK_URL is unusable: {}", message);
+                            false
+                        }
+                    };
+                    if !delivered {
+                        tracing::warn!("Failed to deliver a password reset token");
+                    }
+                });
+            }
+            None => tracing::warn!("Password reset requested but PASSWORD_RESET_WEBHOOK_URL is not set"),
+        },
+        Err(error) => tracing::debug!("Password reset not issued: {}", error),
+    }
+    
+    let accepted = ApiResponse::success(json!({
+        "message": "If the account exists, a password reset link has been sent"
+    }));
+    (StatusCode::ACCEPTED, Json(accepted)).into_response()
+}
+
+/// Password reset confirmation endpoint
+/// 
+/// Sets a new password using a reset token. The token is single-use, and
+/// every session opened before the reset is revoked.
+/// 
+/// # Request Body
+/// ```json
+/// { "token": "reset-jwt-token", "password": "new password" }
+/// ```
+/// 
+/// # Response
+/// Returns a new JWT token, refresh token and user information, like login.
+/// 
+/// # Errors
+/// - 400 BAD_REQUEST: Reset token invalid, expired or used, or password too short
+/// - 500 INTERNAL_SERVER_ERROR: Database or service error
+async fn confirm_password_reset(
+    State(state): State<AppState>,
+    Json(params): Json<PasswordResetConfirmParams>,
+) -> Result<Json<ApiResponse<Value>>, Response> {
+    if params.password.len() < 8 {
+        return Err(error_response(
+            StatusCode::BAD_REQUEST,
+            "Password must be at least 8 characters long",
+        ));
+    }
+    match state.auth_service.reset_password(&params.token, &params.password).await {
+        Ok(result) if result.success => Ok(Json(ApiResponse::success(json!({
+            "token": result.token,
+            "refresh_token": result.refresh_token,
+            "user": result.user
+        })))),
+        Ok(result) => Err(error_response(
+            StatusCode::BAD_REQUEST,
+            result.error.as_deref().unwrap_or("Invalid reset token"),
+        )),
+        Err(e) => Err(internal_error_response(&state.config, StatusCode::INTERNAL_SERVER_ERROR, &e)),
+    }
+}
+
+/// Anonymous session creation endpoint
+/// 
+/// Creates a temporary guest user with limited capabilities.
+/// Useful for trials and demos without requiring registration.
+/// 
+/// Guest users have:
+/// - Limited daily request quota (5 requests/day)
+/// - Temporary session (no persistent data)
+/// - Basic AI access without advanced features
+/// 
+/// # Response  
+/// Returns JWT token for the guest session.
+/// 
+/// # Errors
+/// - 403 FORBIDDEN: Anonymous sessions are disabled
+/// - 500 INTERNAL_SERVER_ERROR: Service error
+async fn create_anonymous_session(
+    State(state): State<AppState>,
+) -> Result<Json<ApiResponse<Value>>, Response> {
+    if !state.config.allow_anonymous {
+        return Err(error_response(StatusCode::FORBIDDEN, "anonymous sessions disabled"));
+    }
+    
+    match state.auth_service.create_guest_user().await {
+        Ok(result) => {
+            if result.success {
+                let response_data = json!({
+                    "token": result.token,
+                    "user": result.user
+                });
+                Ok(Json(ApiResponse::success(response_data)))
+            } else {
+                Err(StatusCode::INTERNAL_SERVER_ERROR.into_response())
+            }
+        }
+        Err(e) => Err(internal_error_response(&state.config, StatusCode::INTERNAL_SERVER_ERROR, &e)),
+    }
+}
+
+/// Token introspection endpoint
+/// 
+/// Lets other services validate a token issued by this gateway without
+/// decoding the JWT themselves.
+/// 
+/// # Response
+/// `{valid, user_id, email, is_anonymous, expires_at}` for a valid token.
+/// Invalid tokens only get `{"valid": false}`, without saying why.
+async fn verify_token(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(params): Json<VerifyTokenParams>,
+) -> Json<ApiResponse<Value>> {
+    let token = match params.token.as_deref().or(extract_bearer(&headers)) {
+        Some(token) => token,
+        None => return Json(ApiResponse::success(json!({ "valid": false }))),
+    };
+    
+    let auth = &state.auth_service;
+    let verified = match auth.verify_jwt_claims(token) {
+        Some(claims) => Some((
+            claims.user_id,
+            claims.email,
+            chrono::DateTime::from_timestamp(claims.exp, 0).map(|at| at.to_rfc3339()),
+        )),
+        // Tokens from other methods carry no expiry we can report
+        None => match auth.verify_token(token).await {
+            Ok((true, Some(user_id), Some(email))) => Some((user_id, email, None)),
+            _ => None,
+        },
+    };
+    
+    let response_data = match verified {
+        Some((user_id, email, expires_at)) => json!({
+            "valid": true,
+            "is_anonymous": is_guest_user_id(&user_id),
+            "user_id": user_id,
+            "email": email,
+            "expires_at": expires_at
+        }),
+        None => json!({ "valid": false }),
+    };
+    Json(ApiResponse::success(response_data))
+}
+
+/// Current user endpoint
+/// 
+/// Returns the user behind the bearer token, so clients can check a
+/// stored token and load the profile in one call. Guest tokens return
+/// `is_anonymous: true` and no subscription tier.
+/// 
+/// # Response
+/// ```json
+/// {
+///   "status": "success",
+///   "data": {
+///     "id": "user_123",
+///     "email": "user@example.com",
+///     "is_anonymous": false,
+///     "created_at": "2024-01-01T00:00:00Z",
+///     "subscription_tier": "free"
+///   }
+/// }
+/// ```
+/// 
+/// # Errors
+/// - 401 UNAUTHORIZED: Missing, invalid or expired token, or the account
+///   is no longer active
+async fn current_user(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Json<ApiResponse<AuthUser>>, Response> {
+    let unauthorized = || error_response(StatusCode::UNAUTHORIZED, "Authentication required");
+    let token = extract_bearer(&headers).ok_or_else(unauthorized)?;
+    let user = state
+        .auth_service
+        .get_user_from_token(token)
+        .await
+        .ok_or_else(unauthorized)?;
+    Ok(Json(ApiResponse::success(user)))
+}
+
+/// Session listing endpoint
+/// 
+/// Lists the caller's active session tokens: those issued to the same
+/// user that are neither expired nor revoked. The session making the
+/// request is marked `current`.
+/// 
+/// # Response
+/// ```json
+/// {
+///   "status": "success",
+///   "data": {
+///     "sessions": [
+///       { "jti": "…", "issued_at": 1700000000, "expires_at": 1700604800, "current": true }
+///     ]
+///   }
+/// }
+/// ```
+/// 
+/// # Errors
+/// - 401 UNAUTHORIZED: Missing or invalid token
+async fn list_sessions(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Json<ApiResponse<Value>>, Response> {
+    let claims = session_claims(&state, &headers)
+        .ok_or_else(|| error_response(StatusCode::UNAUTHORIZED, "Authentication required"))?;
+    let sessions: Vec<Value> = state
+        .auth_service
+        .list_sessions(&claims.user_id)
+        .into_iter()
+        .map(|session| {
+            json!({
+                "current": session.jti == claims.jti,
+                "jti": session.jti,
+                "issued_at": session.issued_at,
+                "expires_at": session.expires_at
+            })
+        })
+        .collect();
+    
+    Ok(Json(ApiResponse::success(json!({ "sessions": sessions }))))
+}
+
+/// Session revocation endpoint
+/// 
+/// Revokes one of the caller's sessions; its token stops verifying
+/// immediately. Callers may revoke their current session (logout).
+/// 
+/// # Errors
+/// - 401 UNAUTHORIZED: Missing or invalid token
+/// - 404 NOT_FOUND: No active session with that id belongs to the caller
+async fn revoke_session(
+    State(state): State<AppState>,
+    Path(jti): Path<String>,
+    headers: HeaderMap,
+) -> Result<Json<ApiResponse<Value>>, Response> {
+    let claims = session_claims(&state, &headers)
+        .ok_or_else(|| error_response(StatusCode::UNAUTHORIZED, "Authentication required"))?;
+    if !state.auth_service.revoke_session(&claims.user_id, &jti) {
+        return Err(error_response(StatusCode::NOT_FOUND, "Session not found"));
+    }
+    
+    Ok(Json(ApiResponse::success(json!({ "revoked": jti }))))
+}
+
+/// Read `attachments` and append what they hold to the last user message
+/// 
+/// Text files are included up to `ATTACHMENT_MAX_TEXT_CHARS`; images are
+/// only named, as the route was already picked for them.
+/// 
+/// # Returns
+/// Why attachments failed, when `ATTACHMENT_ERROR_DETAILS` is set
+/// 
+/// # Errors
+/// 400 BAD_REQUEST when the attachments exceed `MAX_ATTACHMENTS` or
+/// `MAX_ATTACHMENT_BYTES`
+async fn add_attachment_context(
+    state: &AppState,
+    attachments: &[types::Attachment],
+    messages: &mut [ChatMessage],
+) -> Result<Vec<String>, Response> {
+    let result = process_file_attachments(
+        &state.attachment_client,
+        attachments,
+        &state.attachment_cache,
+        Duration::from_millis(state.config.attachment_process_timeout_ms),
+        &FetchPolicy::from_config(&state.config),
+        &AttachmentLimits::from_config(&state.config),
+        state.config.attachment_error_details,
+    )
+    .await
+    .map_err(|error| error_response(StatusCode::BAD_REQUEST, &error.to_string()))?;
+    if let Some(message) = messages.iter_mut().rev().find(|message| message.role == MessageRole::User) {
+        message.content.push_str(&result.context_prompt);
+    }
+    Ok(result.failures)
+}
+
+/// Report attachment failures to the client under `diagnostics` in the
+/// response data
+fn add_attachment_diagnostics(data: &mut Value, failures: &[String]) {
+    if !failures.is_empty() {
+        data["diagnostics"] = json!({ "attachment_failures": failures });
+    }
+}
+
+/// Claims of the caller's bearer token, if present and valid
+fn session_claims(state: &AppState, headers: &HeaderMap) -> Option<Claims> {
+    extract_bearer(headers).and_then(|token| state.auth_service.verify_jwt_claims(token))
+}
+
+/// Analytics data retrieval endpoint
+/// 
+/// Provides usage statistics and system metrics.
+/// Useful for monitoring, billing, and system optimization.
+/// 
+/// # Query Parameters
+/// - `hours`: Optional number of hours back to fetch data
 /// 
 /// # Example
 /// ```
@@ -651,27 +972,179 @@ async fn create_anonymous_session(
 async fn get_analytics(
     State(state): State<AppState>,
     Query(query): Query<AnalyticsQuery>,
-) -> Result<Json<ApiResponse<Value>>, StatusCode> {
+) -> Result<Json<ApiResponse<Value>>, Response> {
     let hours = query.hours;
     
     match state.convex_service.get_analytics(None, hours).await {
         Ok(analytics_data) => Ok(Json(ApiResponse::success(analytics_data))),
-        Err(_) => Err(StatusCode::INTERNAL_SERVER_ERROR),
+        Err(e) => Err(internal_error_response(&state.config, StatusCode::INTERNAL_SERVER_ERROR, &e)),
+    }
+}
+
+/// Analytics event export endpoint (admin only)
+/// 
+/// Returns the retained API request events as newline-delimited JSON,
+/// one event per line, oldest first. Only the most recent events are
+/// kept in memory (see `EVENT_BUFFER_SIZE`).
+/// 
+/// # Query Parameters
+/// - `hours`: Optional number of hours back to export
+/// - `format`: Export format, currently only `jsonl`
+/// 
+/// # Example
+/// ```
+/// GET /v1/analytics/events?hours=24&format=jsonl
+/// ```
+/// 
+/// # Errors
+/// - 400 BAD_REQUEST: Unsupported format
+/// - 401 UNAUTHORIZED: Missing or invalid token
+/// - 403 FORBIDDEN: Caller is not an admin
+async fn export_analytics_events(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(query): Query<AnalyticsEventsQuery>,
+) -> Result<Response, Response> {
+    require_admin(&state, &headers).map_err(|(status, message)| error_response(status, message))?;
+    
+    if !matches!(query.format.as_deref(), None | Some("jsonl")) {
+        return Err(error_response(StatusCode::BAD_REQUEST, "Unsupported format, expected jsonl"));
+    }
+    
+    let mut body = String::new();
+    for event in state.convex_service.recent_api_requests(query.hours) {
+        let line = serde_json::to_string(&event)
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR.into_response())?;
+        body.push_str(&line);
+        body.push('\n');
+    }
+    
+    Ok(([(CONTENT_TYPE, "application/x-ndjson")], body).into_response())
+}
+
+/// Search cache flush endpoint (admin only)
+/// 
+/// Drops every cached search response, including ones that have not
+/// expired yet, so the next query for any term hits the providers.
+/// 
+/// # Response
+/// ```json
+/// { "status": "success", "data": { "cleared": 12 } }
+/// ```
+/// 
+/// # Errors
+/// - 401 UNAUTHORIZED: Missing or invalid token
+/// - 403 FORBIDDEN: Caller is not an admin
+async fn clear_search_cache(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Json<ApiResponse<Value>>, Response> {
+    require_admin(&state, &headers).map_err(|(status, message)| error_response(status, message))?;
+    
+    let cleared = state.search_service.clear_cache();
+    info!("Cleared {} search cache entries", cleared);
+    
+    Ok(Json(ApiResponse::success(json!({ "cleared": cleared }))))
+}
+
+/// Route reload endpoint (admin only)
+/// 
+/// Re-reads the routing configuration from `ROUTES_FILE`, or the `ROUTES`
+/// environment variable without one, and swaps it in for new requests.
+/// A configuration that raises any warning or yields no routes is
+/// rejected and the current routes stay in place.
+/// 
+/// # Response
+/// ```json
+/// { "status": "success", "data": { "routes": 3 } }
+/// ```
+/// 
+/// # Errors
+/// - 400 BAD_REQUEST: The new configuration is invalid
+/// - 401 UNAUTHORIZED: Missing or invalid token
+/// - 403 FORBIDDEN: Caller is not an admin
+/// - 500 INTERNAL_SERVER_ERROR: `ROUTES_FILE` could not be read
+async fn reload_routes(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Json<ApiResponse<Value>>, Response> {
+    require_admin(&state, &headers).map_err(|(status, message)| error_response(status, message))?;
+    
+    let routes_raw = config::read_routes(state.config.routes_file.as_deref()).map_err(|e| {
+        tracing::error!("Failed to read ROUTES_FILE: {}", e);
+        error_response(StatusCode::INTERNAL_SERVER_ERROR, "Failed to read routes file")
+    })?;
+    let build = routing::build_routing_with_defaults(
+        &routes_raw,
+        state.config.max_routes,
+        &state.config.provider_default_models,
+    );
+    if let Err(error) = build.check_nonempty(true) {
+        return Err(error_response(StatusCode::BAD_REQUEST, &error));
     }
+    if !build.warnings.is_empty() {
+        let message = format!("Routes not reloaded: {}", build.warnings.join("; "));
+        return Err(error_response(StatusCode::BAD_REQUEST, &message));
+    }
+    
+    let count = state.routing.replace(build.routes);
+    info!("Reloaded {} provider routes", count);
+    
+    Ok(Json(ApiResponse::success(json!({ "routes": count }))))
+}
+
+/// Effective configuration endpoint (admin only)
+/// 
+/// Returns the configuration the process actually loaded from the
+/// environment, secret files and defaults. Secrets are serialized as
+/// `[REDACTED]`.
+/// 
+/// # Errors
+/// - 401 UNAUTHORIZED: Missing or invalid token
+/// - 403 FORBIDDEN: Caller is not an admin
+async fn get_config(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Json<ApiResponse<Value>>, Response> {
+    require_admin(&state, &headers).map_err(|(status, message)| error_response(status, message))?;
+    
+    let config = serde_json::to_value(&state.config)
+        .map_err(|error| internal_error_response(&state.config, StatusCode::INTERNAL_SERVER_ERROR, &error))?;
+    Ok(Json(ApiResponse::success(config)))
 }
 
-/// Main AI invocation endpoint (PLACEHOLDER IMPLEMENTATION)
+/// Daily request charged to a registered user for one invoke
 /// 
-/// This is the core endpoint for AI requests. Currently a placeholder
-/// that demonstrates the expected structure and response format.
+/// Dropping the charge before `keep` gives the request back, so invokes
+/// that are rejected or fail do not use up the caller's quota.
+struct QuotaCharge {
+    limiter: UserRateLimiter,
+    user_id: String,
+    /// Tier the request was counted against, `None` when nothing was counted
+    tier: Option<String>,
+}
+
+impl QuotaCharge {
+    /// Keep the charge once the request has been answered
+    fn keep(mut self) {
+        self.tier = None;
+    }
+}
+
+impl Drop for QuotaCharge {
+    fn drop(&mut self) {
+        if let Some(tier) = &self.tier {
+            self.limiter.refund_user_daily_limit(&self.user_id, tier);
+        }
+    }
+}
+
+/// Main AI invocation endpoint
 /// 
-/// TODO: Full implementation needed with:
-/// - JWT token validation and user authentication
-/// - Rate limiting enforcement (guest vs. registered users)
-/// - Provider routing based on request and availability
-/// - Request/response logging and analytics
-/// - Error handling and fallback logic
-/// - Context injection from file uploads and search
+/// This is the core endpoint for AI requests. The `op.tier` route picks
+/// the provider and model; the conversation from `input.messages`, with
+/// the server system prompt and any search context applied, is sent to
+/// the provider's chat endpoint and the assistant text is returned.
 /// 
 /// # Request Body
 /// ```json
@@ -689,72 +1162,1116 @@ async fn get_analytics(
 /// - Authorization: Bearer <JWT_TOKEN> (required)
 /// 
 /// # Response
-/// Returns AI provider response with metadata.
+/// Returns the assistant text (`message`) with the provider, model and
+/// whether it was served from the response cache. Text `attachments` are
+/// read and appended to the last user message; with
+/// `ATTACHMENT_ERROR_DETAILS` set, those that failed are listed under
+/// `diagnostics.attachment_failures`.
+/// 
+/// With `callback_url` set, returns 202 ACCEPTED with the `request_id`
+/// right away and POSTs the final response to the callback instead.
+/// With `stream: true`, the response data is sent as a server-sent event.
+/// With `race: true`, the first two route targets are asked at once and
+/// the first reply wins; both providers are billed. Streams are not raced.
 /// 
 /// # Errors
-/// - 401 UNAUTHORIZED: Missing/invalid token or rate limit exceeded
-/// - 400 BAD_REQUEST: Invalid request format
-/// - 503 SERVICE_UNAVAILABLE: All providers unavailable
+/// - 400 BAD_REQUEST: `callback_url` is invalid or not a public address
+/// - 401 UNAUTHORIZED: Missing/invalid token
+/// - 403 FORBIDDEN: Every target of the route is a model in `BLOCKED_MODELS`
+/// - 429 TOO_MANY_REQUESTS: Daily quota for the user's tier exceeded
+///   (only answered requests count towards it)
+/// - 400 BAD_REQUEST: Invalid request format or malformed `input.messages`
+/// - 400 BAD_REQUEST: `options` out of range (`temperature` 0.0-2.0, `max_tokens` at least 1)
+/// - 400 BAD_REQUEST: `attachments` over `MAX_ATTACHMENTS` or `MAX_ATTACHMENT_BYTES`
+/// - 502 BAD_GATEWAY: The last route target failed or returned no assistant text
+/// - 503 SERVICE_UNAVAILABLE: No route for `op.tier`, all providers unavailable,
+///   or too many open streams
 /// - 500 INTERNAL_SERVER_ERROR: Service error
 async fn invoke(
-    State(_state): State<AppState>,
-    _headers: HeaderMap,
+    State(state): State<AppState>,
+    Extension(user): Extension<AuthUser>,
+    Extension(timings): Extension<RequestTimings>,
+    client_ip: Option<ClientIp>,
     Json(request): Json<InvokeRequest>,
-) -> Result<Json<ApiResponse<Value>>, StatusCode> {
+) -> Result<Response, Response> {
     let request_id = Uuid::new_v4().to_string();
+    // Logged on whichever path finishes the request
+    let summary = state.config.request_timing.then(|| timings.log_on_drop(&request_id));
+    // Guests keep their own id; the unauthenticated placeholder has none
+    let caller = (user.id != ANONYMOUS_USER_ID).then(|| user.id.clone());
+
+    // Enforce daily quotas for registered users (guests are tracked separately).
+    // The charge is given back on every path that does not answer the request.
+    let (priority, charge) = timings
+        .time("auth", async {
+            let mut charge = QuotaCharge {
+                limiter: state.user_limiter.clone(),
+                user_id: user.id.clone(),
+                tier: None,
+            };
+            if user.is_anonymous {
+                return Ok((DispatchPriority::Anonymous, charge));
+            }
+            let tier = &user.subscription_tier;
+            if let Some(decision) = state.user_limiter.check_user_daily_limit(&user.id, tier) {
+                if !decision.allowed {
+                    return Err(rate_limited_response(&decision, state.clock.now_ms()));
+                }
+                charge.tier = Some(tier.clone());
+            }
+            Ok((DispatchPriority::for_tier(tier), charge))
+        })
+        .await?;
     
-    // TODO: Implement full invoke logic with provider routing, authentication, etc.
-    // This is a placeholder that demonstrates the structure
+    // Tell the client search is unavailable instead of quietly answering without it
+    if request.enable_search == Some(true)
+        && !state.config.search.enabled
+        && state.config.search.disabled_behavior == SearchDisabledBehavior::Error
+    {
+        return Err(error_response(
+            StatusCode::CONFLICT,
+            "Web search is disabled on this server",
+        ));
+    }
     
-    tracing::info!("Processing invoke request: {:?}", request);
+    let op = request.op.as_str();
+    if !state.config.operation_enabled(op) {
+        return Err(error_response(StatusCode::FORBIDDEN, "operation not enabled"));
+    }
+    if let Some(options) = &request.options {
+        options.validate().map_err(|errors| {
+            let mut fields: Vec<String> = errors
+                .field_errors()
+                .into_keys()
+                .map(|field| format!("options.{}", field))
+                .collect();
+            fields.sort();
+            error_response(StatusCode::BAD_REQUEST, &format!("{} out of range", fields.join(", ")))
+        })?;
+    }
+    let tier = request.tier.clone().unwrap_or_else(|| request.op.default_tier());
+    let tier = tier.as_str();
     
-    // For now, return a simple response
-    let response_data = json!({
-        "request_id": request_id,
-        "status": "processed",
-        "message": "This is a placeholder response - full implementation needed"
-    });
+    // Only metadata is logged; prompts and attachments stay out of the logs
+    if log_sampling::should_log(state.config.log_sample_rate, Level::DEBUG) {
+        let attachments = request.attachments.as_deref().unwrap_or_default();
+        tracing::debug!(
+            request_id = %request_id,
+            op,
+            tier,
+            messages = request.input.get("messages").and_then(serde_json::Value::as_array).map_or(0, Vec::len),
+            attachments = attachments.len(),
+            attachment_bytes = attachments
+                .iter()
+                .map(|attachment| attachment.size.unwrap_or(attachment.url.len() as u64))
+                .sum::<u64>(),
+            "Processing invoke request from {}",
+            client_ip.map_or_else(|| "unknown".to_string(), |ClientIp(ip)| ip.to_string())
+        );
+    }
+    let routing = state.routing.load();
+    let targets = if request.has_image_attachments() {
+        // Images need a model that accepts them; fall back to the vision route
+        resolve_multimodal_route(
+            &routing,
+            op,
+            tier,
+            &state.config.vision_tier,
+            &state.config.multimodal_models,
+        )
+        .map_err(|message| error_response(StatusCode::BAD_REQUEST, &message))?
+    } else {
+        resolve_route(&routing, op, tier).map(|targets| targets.iter().collect())
+    };
+    let Some(targets) = targets else {
+        let message = if routing.is_empty() {
+            "No routes configured; set ROUTES to at least one op.tier=provider:model entry".to_string()
+        } else {
+            format!("No provider is configured for {}.{}", op, tier)
+        };
+        return Err(error_response(StatusCode::SERVICE_UNAVAILABLE, &message));
+    };
+    // Blocked models are never tried; with none left the request is refused
+    let (blocked, permitted): (Vec<&RouteTarget>, Vec<&RouteTarget>) = targets
+        .into_iter()
+        .partition(|target| is_blocked_model(target, &state.config.blocked_models));
+    for route in &blocked {
+        tracing::warn!("Request {} routed to blocked model {}:{}", request_id, route.provider.as_str(), route.model);
+    }
+    if permitted.is_empty() {
+        return Err(error_response(StatusCode::FORBIDDEN, "model not permitted"));
+    }
+    let permitted: Vec<RouteTarget> = permitted.into_iter().cloned().collect();
+    let targets: Vec<RouteTarget> = state
+        .latency
+        .attempt_order(&permitted, state.config.adaptive_routing)
+        .into_iter()
+        .cloned()
+        .collect();
+    if request.op == Operation::ImageGen {
+        let (_, mut response) = timings
+            .time("provider", try_targets(&targets, |route| invoke_image(&state, &request_id, route, &request, priority)))
+            .await?;
+        charge.keep();
+        add_server_timing(&state.config, &timings, &mut response);
+        return Ok(response);
+    }
+    // A bad callback is refused before any attachment, search or provider work
+    let callback_target = match &request.callback_url {
+        Some(_) if request.stream == Some(true) => {
+            return Err(error_response(
+                StatusCode::BAD_REQUEST,
+                "stream cannot be combined with callback_url",
+            ));
+        }
+        Some(callback_url) => Some(
+            validate_callback_url(callback_url, state.config.callback_allow_private_networks)
+                .await
+                .map_err(|message| error_response(StatusCode::BAD_REQUEST, &message))?,
+        ),
+        None => None,
+    };
+    let preparing = Instant::now();
+    request
+        .parse_messages()
+        .map_err(|message| error_response(StatusCode::BAD_REQUEST, &message))?;
+    
+    // Screen what the client sent before anything reaches a provider
+    if let FilterVerdict::Block { reason } = state.content_filter.check(&request.messages()) {
+        tracing::warn!("Request {} blocked by content filter: {}", request_id, reason);
+        return Err(error_response(
+            StatusCode::UNPROCESSABLE_ENTITY,
+            "Request blocked by content filter",
+        ));
+    }
+    
+    // Conversation as it will be sent upstream, with the server system prompt applied
+    let mut messages = build_conversation(&state.config, &request)
+        .map_err(|message| error_response(StatusCode::BAD_REQUEST, &message))?;
+    timings.record("prepare", preparing.elapsed());
+    let attachment_failures = match request.attachments.as_deref() {
+        Some(attachments) if !attachments.is_empty() => {
+            timings.time("files", add_attachment_context(&state, attachments, &mut messages)).await?
+        }
+        _ => Vec::new(),
+    };
+    if request.enable_search == Some(true) && state.config.search_enabled_for(op) {
+        let session = request.session_id.as_deref().map(|session_id| match &caller {
+            // Conversations are scoped to their caller
+            Some(user_id) => format!("{}/{}", user_id, session_id),
+            None => session_id.to_string(),
+        });
+        timings
+            .time("search", add_search_context(&state, session.as_deref(), &mut messages))
+            .await
+            .map_err(|message| error_response(StatusCode::SERVICE_UNAVAILABLE, &message))?;
+    }
+    // Bound provider cost once everything the server adds is in place
+    check_prompt_size(&state.config, &messages)
+        .map_err(|message| error_response(StatusCode::BAD_REQUEST, &message))?;
+    tracing::debug!("Prepared {} messages for {}.{}", messages.len(), op, tier);
+    
+    // Each target's route defaults fill in whatever the client left unset
+    let options = request.options.clone().unwrap_or_default();
+    let race = request.race == Some(true);
+    
+    // Streams are long-lived and each holds a provider connection, so their
+    // number is capped separately from the request limits
+    let stream_slot = match request.stream {
+        Some(true) => match state.stream_slots.clone().try_acquire_owned() {
+            Ok(slot) => Some(slot),
+            Err(_) => {
+                return Err(error_response(
+                    StatusCode::SERVICE_UNAVAILABLE,
+                    "Too many concurrent streams, please retry later",
+                ));
+            }
+        },
+        _ => None,
+    };
+    
+    // The prompt and the reply are kept as chat history, under the target that answered
+    let prompt = request
+        .messages()
+        .into_iter()
+        .rev()
+        .find(|message| message.role == MessageRole::User);
+    let record_history = |route: &RouteTarget| {
+        let state = state.clone();
+        let request_id = request_id.clone();
+        let user_id = caller.clone();
+        let chat_id = request.session_id.clone();
+        let route = route.clone();
+        let prompt = prompt.clone();
+        move |reply: Option<&str>| {
+            log_conversation_turn(
+                &state,
+                &request_id,
+                user_id.as_deref(),
+                chat_id.as_deref(),
+                &route,
+                prompt,
+                reply,
+            )
+        }
+    };
+    
+    if let Some(slot) = stream_slot {
+        let (route, (deltas, dispatch_slot)) = timings
+            .time("provider", try_targets(&targets, |route| {
+                open_provider_stream(&state, route, &messages, &options, priority)
+            }))
+            .await?;
+        charge.keep();
+        // The summary waits for the stream to end, or to be dropped
+        let record_reply = record_history(route);
+        let (stream_timings, streaming) = (timings.clone(), Instant::now());
+        let on_complete = move |reply: Option<&str>| {
+            stream_timings.record("stream", streaming.elapsed());
+            record_reply(reply);
+            drop(summary);
+        };
+        let mut response = stream_response(&state, route, deltas, dispatch_slot, slot, on_complete);
+        add_server_timing(&state.config, &timings, &mut response);
+        return Ok(response);
+    }
+    
+    // Long jobs can hand the result to a callback instead of holding the connection
+    if let Some(target) = callback_target {
+        let accepted = ApiResponse::success(json!({ "request_id": request_id, "status": "accepted" }));
+        let mut response = (StatusCode::ACCEPTED, Json(accepted)).into_response();
+        add_server_timing(&state.config, &timings, &mut response);
+        
+        let state = state.clone();
+        let id = request_id.clone();
+        let task_timings = timings.clone();
+        tokio::spawn(async move {
+            let result = task_timings
+                .time("provider", dispatch_targets(&state, &id, &targets, &messages, &options, priority, race))
+                .await;
+            let body = match result.map(|(_, data)| data) {
+                Ok(mut data) => {
+                    charge.keep();
+                    add_attachment_diagnostics(&mut data, &attachment_failures);
+                    json!(ApiResponse::success(data))
+                }
+                Err(response) => response_body(response).await,
+            };
+            if deliver_callback(&state.config, &target, &body).await {
+                tracing::debug!("Delivered result of {} to callback", id);
+            }
+            drop(summary);
+        });
+        return Ok(response);
+    }
+    
+    let answered_here = AtomicBool::new(false);
+    let process = || async {
+        answered_here.store(true, Ordering::Relaxed);
+        dispatch_targets(&state, &request_id, &targets, &messages, &options, priority, race)
+            .await
+            .map(|(_, data)| data)
+    };
     
-    Ok(Json(ApiResponse::success(response_data)))
+    // Identical bodies from the same caller within the dedup window share one result.
+    // Callers without an identity are never deduplicated against each other.
+    let dedup_key = caller
+        .as_deref()
+        .and_then(|user_id| dedup_key(user_id, &request));
+    let mut response_data = timings
+        .time("provider", async {
+            match dedup_key {
+                Some(key) => state.deduplicator.run(key, process).await,
+                None => process().await,
+            }
+        })
+        .await?;
+    // A double-submit answered from the first request's result gives its quota
+    // back, and the first request has already recorded the turn
+    if answered_here.load(Ordering::Relaxed) {
+        charge.keep();
+        let answered = targets
+            .iter()
+            .find(|target| response_data["provider"] == target.provider.as_str() && response_data["model"] == target.model.as_str())
+            .unwrap_or(&targets[0]);
+        record_history(answered)(response_data["message"].as_str());
+    }
+    add_attachment_diagnostics(&mut response_data, &attachment_failures);
+    let mut response = Json(ApiResponse::success(response_data)).into_response();
+    add_server_timing(&state.config, &timings, &mut response);
+    Ok(response)
 }
 
-/// Create and configure the Axum router with all routes and middleware
-/// 
-/// Sets up the complete HTTP service with:
-/// - All API endpoints with proper HTTP methods
-/// - Middleware stack (tracing, CORS)
-/// - Shared application state
-/// 
-/// The middleware stack is applied in reverse order:
-/// 1. CORS (outermost - handles preflight requests)
-/// 2. Tracing (logs all requests and responses)
-/// 3. Route handlers (innermost - actual business logic)
-/// 
-/// # Arguments
-/// * `state` - Application state shared across all handlers
+/// Report the phases timed so far in a `Server-Timing` header when
+/// `REQUEST_TIMING` is set
+fn add_server_timing(config: &Config, timings: &RequestTimings, response: &mut Response) {
+    if !config.request_timing {
+        return;
+    }
+    if let Ok(value) = HeaderValue::from_str(&timings.server_timing()) {
+        response.headers_mut().insert("Server-Timing", value);
+    }
+}
+
+/// Generate images for an `image` invoke request
+/// 
+/// The prompt and options come from `input` and the prompt is screened
+/// like chat messages. Routes to providers without image generation
+/// answer 400.
+async fn invoke_image(
+    state: &AppState,
+    request_id: &str,
+    route: &RouteTarget,
+    request: &InvokeRequest,
+    priority: DispatchPriority,
+) -> Result<Response, Response> {
+    if request.stream == Some(true) || request.callback_url.is_some() {
+        return Err(error_response(
+            StatusCode::BAD_REQUEST,
+            "stream and callback_url are not supported for image generation",
+        ));
+    }
+    let image = request
+        .parse_image_request()
+        .map_err(|message| error_response(StatusCode::BAD_REQUEST, &message))?;
+    let prompt = ChatMessage {
+        role: MessageRole::User,
+        content: image.prompt.clone(),
+    };
+    if let FilterVerdict::Block { reason } = state.content_filter.check(&[prompt]) {
+        tracing::warn!("Request {} blocked by content filter: {}", request_id, reason);
+        return Err(error_response(
+            StatusCode::UNPROCESSABLE_ENTITY,
+            "Request blocked by content filter",
+        ));
+    }
+    
+    let provider = build_provider(&route.provider, &state.config, state.provider_client.clone());
+    if !provider.supports_image_generation() {
+        return Err(error_response(StatusCode::BAD_REQUEST, "operation not supported"));
+    }
+    let Some(_dispatch_slot) = state.dispatch_queue.acquire(priority).await else {
+        return Err(error_response(
+            StatusCode::SERVICE_UNAVAILABLE,
+            "Server is busy, please retry later",
+        ));
+    };
+    let name = route.provider.as_str();
+    if let Err(retry_after) = state.provider_limiter.acquire(name).await {
+        return Err(provider_busy_response(name, retry_after));
+    }
+    let images = provider
+        .generate_image(&route.model, &image)
+        .await
+        .map_err(|error| internal_error_response(&state.config, StatusCode::BAD_GATEWAY, &error))?;
+    
+    Ok(Json(ApiResponse::success(json!({
+        "request_id": request_id,
+        "status": "completed",
+        "provider": name,
+        "model": route.model,
+        "images": images
+    })))
+    .into_response())
+}
+
+/// Log the latest user message and the assistant reply to chat history
+/// 
+/// Runs in the background so logging never delays the response. The two
+/// messages are logged in order, so the reply's `created_at` is never
+/// earlier than the prompt's.
+fn log_conversation_turn(
+    state: &AppState,
+    request_id: &str,
+    user_id: Option<&str>,
+    chat_id: Option<&str>,
+    route: &RouteTarget,
+    prompt: Option<ChatMessage>,
+    reply: Option<&str>,
+) {
+    let event = |message_type: &str, content: String| MessageEvent {
+        request_id: request_id.to_string(),
+        chat_id: chat_id.map(str::to_string),
+        user_id: user_id.map(str::to_string),
+        message_type: message_type.to_string(),
+        content,
+        provider: Some(route.provider.as_str().to_string()),
+        model: Some(route.model.clone()),
+        token_count: None,
+        created_at: None,
+        attachments: None,
+    };
+    let mut events: Vec<MessageEvent> = prompt
+        .map(|prompt| event("user", prompt.content))
+        .into_iter()
+        .collect();
+    if let Some(reply) = reply {
+        events.push(event("assistant", reply.to_string()));
+    }
+    
+    let convex_service = state.convex_service.clone();
+    tokio::spawn(async move {
+        for event in events {
+            if let Err(error) = convex_service.log_message(event).await {
+                tracing::warn!("Failed to log chat message: {}", error);
+            }
+        }
+    });
+}
+
+/// Search the web for the latest user message and add the results as
+/// context just before it
+/// 
+/// Turns of one conversation (`session`) reuse recent results for the
+/// same query. Without results the conversation is left unchanged; when
+/// every provider fails, `SEARCH_ALL_FAILED_BEHAVIOR` decides.
+/// 
+/// # Errors
+/// A client-facing message when every provider failed and the behavior is `error`
+async fn add_search_context(
+    state: &AppState,
+    session: Option<&str>,
+    messages: &mut Vec<ChatMessage>,
+) -> Result<(), String> {
+    let Some(index) = messages.iter().rposition(|message| message.role == MessageRole::User) else {
+        return Ok(());
+    };
+    let query = messages[index].content.clone();
+    let search = match session {
+        Some(session) => state.search_service.perform_session_search(session, &query).await,
+        None => state.search_service.perform_web_search(&query).await,
+    };
+    match search {
+        Ok(response) if !response.results.is_empty() => {
+            tracing::debug!("Adding {} {} results as context", response.results.len(), response.provider);
+            messages.insert(index, ChatMessage {
+                role: MessageRole::System,
+                content: search_context(&response),
+            });
+        }
+        Ok(_) => {}
+        Err(error) => {
+            tracing::warn!("Web search failed: {}", error);
+            match state.config.search.all_failed_behavior {
+                SearchFailedBehavior::Proceed => {}
+                SearchFailedBehavior::Error => {
+                    return Err("Web search is unavailable, please retry later".to_string());
+                }
+                SearchFailedBehavior::Degrade => messages.insert(index, ChatMessage {
+                    role: MessageRole::System,
+                    content: SEARCH_UNAVAILABLE_NOTE.to_string(),
+                }),
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Number of stream slots for `MAX_CONCURRENT_STREAMS` (0 for unlimited)
+fn stream_slots(max_concurrent_streams: usize) -> Arc<Semaphore> {
+    match max_concurrent_streams {
+        0 => Arc::new(Semaphore::new(Semaphore::MAX_PERMITS)),
+        max => Arc::new(Semaphore::new(max)),
+    }
+}
+
+/// Open a provider stream for `route`
+/// 
+/// The route's defaults fill in unset `options`. The returned dispatch
+/// slot must be held until the stream ends.
+/// 
+/// # Errors
+/// - 503 SERVICE_UNAVAILABLE: Dispatch queue full or provider rate limited
+/// - 502 BAD_GATEWAY: The provider stream could not be opened
+async fn open_provider_stream(
+    state: &AppState,
+    route: &RouteTarget,
+    messages: &[ChatMessage],
+    options: &InvokeOptions,
+    priority: DispatchPriority,
+) -> Result<(DeltaStream, DispatchPermit), Response> {
+    let Some(dispatch_slot) = state.dispatch_queue.acquire(priority).await else {
+        return Err(error_response(
+            StatusCode::SERVICE_UNAVAILABLE,
+            "Server is busy, please retry later",
+        ));
+    };
+    let provider = route.provider.as_str();
+    if let Err(retry_after) = state.provider_limiter.acquire(provider).await {
+        return Err(provider_busy_response(provider, retry_after));
+    }
+    let options = options.clone().with_defaults(&route.defaults);
+    let deltas = build_provider(&route.provider, &state.config, state.provider_client.clone())
+        .stream(&route.model, messages, &options)
+        .await
+        .map_err(|error| {
+            state.latency.record_failure(provider);
+            internal_error_response(&state.config, StatusCode::BAD_GATEWAY, &error)
+        })?;
+    Ok((deltas, dispatch_slot))
+}
+
+/// Stream the provider's reply to the client as server-sent events
+/// 
+/// Each text delta is sent as `{"delta": "..."}` and a successful stream
+/// ends with `{"done": true}`, after which `on_complete` receives the full
+/// reply. A provider failure mid-stream ends it with an `event: error`
+/// instead. The stream is capped at `SSE_MAX_DURATION_SECS`, closed on
+/// shutdown and kept alive while quiet. `slot` and the dispatch slot are
+/// held until the stream ends.
+fn stream_response(
+    state: &AppState,
+    route: &RouteTarget,
+    deltas: DeltaStream,
+    dispatch_slot: DispatchPermit,
+    slot: OwnedSemaphorePermit,
+    on_complete: impl FnOnce(Option<&str>) + Send + 'static,
+) -> Response {
+    let provider = route.provider.as_str();
+    let events = deltas
+        .map(Some)
+        .chain(futures::stream::once(async { None }))
+        .scan(
+            (String::new(), Some(on_complete), false),
+            move |(reply, on_complete, failed), delta| {
+                let _slots = (&slot, &dispatch_slot);
+                let event = match delta {
+                    Some(Ok(delta)) => {
+                        reply.push_str(&delta);
+                        Some(Event::default().data(json!({ "delta": delta }).to_string()))
+                    }
+                    Some(Err(error)) => {
+                        tracing::error!("Stream from {} failed: {}", provider, error);
+                        *failed = true;
+                        let error = json!({ "error": "Provider stream failed" });
+                        Some(Event::default().event("error").data(error.to_string()))
+                    }
+                    None if *failed => None,
+                    None => {
+                        if let Some(on_complete) = on_complete.take() {
+                            on_complete(Some(reply));
+                        }
+                        Some(Event::default().data(json!({ "done": true }).to_string()))
+                    }
+                };
+                futures::future::ready(Some(event))
+            },
+        )
+        .filter_map(|event| futures::future::ready(event.map(Ok)));
+    let events = with_max_duration(
+        events,
+        Duration::from_secs(state.config.sse_max_duration_secs),
+        provider,
+    );
+    let events = until_shutdown(events, &state.shutdown);
+    sse_with_keepalive(events, Duration::from_secs(state.config.sse_keepalive_interval_secs)).into_response()
+}
+
+/// Whether a failed attempt may be retried on the route's next target
+/// 
+/// Provider failures (502) and unavailability (503) fall through; other
+/// errors would fail the same way on every target.
+fn should_try_next_target(response: &Response) -> bool {
+    matches!(response.status(), StatusCode::BAD_GATEWAY | StatusCode::SERVICE_UNAVAILABLE)
+}
+
+/// Run `attempt` on each route target in turn until one succeeds
+/// 
+/// # Returns
+/// The target that succeeded with its result, or the error from the last
+/// target tried
+async fn try_targets<'a, T, F, Fut>(targets: &'a [RouteTarget], mut attempt: F) -> Result<(&'a RouteTarget, T), Response>
+where
+    F: FnMut(&'a RouteTarget) -> Fut,
+    Fut: Future<Output = Result<T, Response>>,
+{
+    let Some((last, fallbacks)) = targets.split_last() else {
+        return Err(error_response(StatusCode::SERVICE_UNAVAILABLE, "No provider is configured"));
+    };
+    for route in fallbacks {
+        match attempt(route).await {
+            Ok(result) => return Ok((route, result)),
+            Err(response) if should_try_next_target(&response) => {
+                tracing::warn!(
+                    "{}:{} failed with {}; trying the next route target",
+                    route.provider.as_str(),
+                    route.model,
+                    response.status()
+                );
+            }
+            Err(response) => return Err(response),
+        }
+    }
+    attempt(last).await.map(|result| (last, result))
+}
+
+/// Dispatch a prepared invoke and build its response data
+/// 
+/// # Arguments
+/// * `request_id` - Id reported back to the client
+/// * `route` - Route target to dispatch to
+/// * `messages` - Conversation as sent to the provider
+/// * `options` - Client generation options; the route's defaults fill in the rest
+/// * `priority` - Caller's place in the dispatch queue
+async fn dispatch_invoke(
+    state: &AppState,
+    request_id: &str,
+    route: &RouteTarget,
+    messages: &[ChatMessage],
+    options: &InvokeOptions,
+    priority: DispatchPriority,
+) -> Result<Value, Response> {
+    let options = options.clone().with_defaults(&route.defaults);
+    let cache_key = (state.config.cache_deterministic_responses && is_deterministic(&options))
+        .then(|| response_cache_key(route.provider.as_str(), &route.model, messages, &options));
+    let cache_key = cache_key.as_deref();
+    
+    // Deterministic requests answered before are served without a provider call
+    let cached = cache_key.and_then(|key| state.response_cache.get(key));
+    let from_cache = cached.is_some();
+    let completion = match cached {
+        Some(completion) => completion,
+        None => {
+            // Wait our turn by tier, then stay within upstream quotas.
+            // The slot is held until the provider call completes.
+            let Some(_dispatch_slot) = state.dispatch_queue.acquire(priority).await else {
+                return Err(error_response(
+                    StatusCode::SERVICE_UNAVAILABLE,
+                    "Server is busy, please retry later",
+                ));
+            };
+            let provider = route.provider.as_str();
+            if let Err(retry_after) = state.provider_limiter.acquire(provider).await {
+                return Err(provider_busy_response(provider, retry_after));
+            }
+            
+            let provider = build_provider(&route.provider, &state.config, state.provider_client.clone());
+            let started = Instant::now();
+            let result = provider.complete(&route.model, messages, &options).await.map_err(|error| {
+                state.latency.record_failure(route.provider.as_str());
+                internal_error_response(&state.config, StatusCode::BAD_GATEWAY, &error)
+            })?;
+            state.latency.record(route.provider.as_str(), started.elapsed().as_millis() as u64);
+            let completion = completion_data(state, result);
+            if let Some(key) = cache_key {
+                state.response_cache.insert(key.to_string(), completion.clone());
+            }
+            completion
+        }
+    };
+    
+    Ok(invoke_response_data(request_id, route, &completion, from_cache))
+}
+
+/// Dispatch a prepared invoke to the route's targets
+/// 
+/// With `race` set and at least two targets, the first `RACE_WIDTH`
+/// targets are raced; when the race fails the remaining targets are
+/// tried in turn. Otherwise the targets are tried in fallback order.
+/// 
+/// # Returns
+/// The target that answered with its response data
+async fn dispatch_targets<'a>(
+    state: &AppState,
+    request_id: &str,
+    targets: &'a [RouteTarget],
+    messages: &[ChatMessage],
+    options: &InvokeOptions,
+    priority: DispatchPriority,
+    race: bool,
+) -> Result<(&'a RouteTarget, Value), Response> {
+    if !race || targets.len() < 2 {
+        return try_targets(targets, |route| dispatch_invoke(state, request_id, route, messages, options, priority)).await;
+    }
+    let (raced, rest) = targets.split_at(RACE_WIDTH.min(targets.len()));
+    match race_invoke(state, request_id, raced, messages, options, priority).await {
+        Err(response) if should_try_next_target(&response) && !rest.is_empty() => {
+            tracing::warn!("Raced route targets failed with {}; trying the next route target", response.status());
+            try_targets(rest, |route| dispatch_invoke(state, request_id, route, messages, options, priority)).await
+        }
+        result => result,
+    }
+}
+
+/// Race one prepared invoke across `targets` and build the winner's
+/// response data
+/// 
+/// The race takes one dispatch slot. A target whose provider is over its
+/// rate limit sits the race out. Each target's route defaults fill in
+/// the options the client left unset, and raced replies are not cached.
+async fn race_invoke<'a>(
+    state: &AppState,
+    request_id: &str,
+    targets: &'a [RouteTarget],
+    messages: &[ChatMessage],
+    options: &InvokeOptions,
+    priority: DispatchPriority,
+) -> Result<(&'a RouteTarget, Value), Response> {
+    let Some(_dispatch_slot) = state.dispatch_queue.acquire(priority).await else {
+        return Err(error_response(
+            StatusCode::SERVICE_UNAVAILABLE,
+            "Server is busy, please retry later",
+        ));
+    };
+    let mut racing = Vec::new();
+    let mut busy = None;
+    for route in targets {
+        let provider = route.provider.as_str();
+        match state.provider_limiter.acquire(provider).await {
+            Ok(()) => racing.push(route),
+            Err(retry_after) => busy = Some(provider_busy_response(provider, retry_after)),
+        }
+    }
+    if racing.is_empty() {
+        return Err(busy.unwrap_or_else(|| error_response(StatusCode::SERVICE_UNAVAILABLE, "No provider is configured")));
+    }
+    
+    let providers: Vec<Box<dyn ChatProvider>> = racing
+        .iter()
+        .map(|route| build_provider(&route.provider, &state.config, state.provider_client.clone()))
+        .collect();
+    let contenders: Vec<(&dyn ChatProvider, &str, &InvokeOptions)> = providers
+        .iter()
+        .zip(&racing)
+        .map(|(provider, route)| (provider.as_ref(), route.model.as_str(), &route.defaults))
+        .collect();
+    let started = Instant::now();
+    let (winner, result) = race_complete(&contenders, messages, options).await.map_err(|error| {
+        for route in &racing {
+            state.latency.record_failure(route.provider.as_str());
+        }
+        internal_error_response(&state.config, StatusCode::BAD_GATEWAY, &error)
+    })?;
+    let route = racing[winner];
+    state.latency.record(route.provider.as_str(), started.elapsed().as_millis() as u64);
+    
+    let completion = completion_data(state, result);
+    Ok((route, invoke_response_data(request_id, route, &completion, false)))
+}
+
+/// Stripped assistant text and any reasoning of a provider reply, as
+/// kept in the response cache
+fn completion_data(state: &AppState, result: CompletionResult) -> Value {
+    let mut completion = json!({ "message": state.response_stripper.strip(&result.text) });
+    if let Some(reasoning) = result.reasoning {
+        completion["reasoning"] = json!(reasoning);
+    }
+    completion
+}
+
+/// Response data of an invoke answered by `route`
+fn invoke_response_data(request_id: &str, route: &RouteTarget, completion: &Value, cached: bool) -> Value {
+    let mut response = json!({
+        "request_id": request_id,
+        "status": "completed",
+        "provider": route.provider.as_str(),
+        "model": route.model,
+        "message": completion["message"],
+        "cached": cached
+    });
+    if let Some(reasoning) = completion.get("reasoning") {
+        response["reasoning"] = reasoning.clone();
+    }
+    response
+}
+
+/// JSON body of a response built by this server
+async fn response_body(response: Response) -> Value {
+    let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap_or_default();
+    serde_json::from_slice(&bytes).unwrap_or(Value::Null)
+}
+
+/// Build a JSON error response with the given status
+fn error_response(status: StatusCode, message: &str) -> Response {
+    (status, Json(ApiResponse::<Value>::error(message.to_string()))).into_response()
+}
+
+/// Build a JSON response for an internal or upstream failure
+/// 
+/// The full error is logged under a fresh correlation id, which is also
+/// returned to the client. With `ERROR_VERBOSITY=public` the client only
+/// sees "internal error"; `detailed` includes the error text.
+fn internal_error_response(config: &Config, status: StatusCode, error: &dyn std::fmt::Display) -> Response {
+    let correlation_id = Uuid::new_v4().to_string();
+    tracing::error!("Internal error [{}]: {}", correlation_id, error);
+    
+    let message = match config.error_verbosity {
+        ErrorVerbosity::Public => "internal error".to_string(),
+        ErrorVerbosity::Detailed => error.to_string(),
+    };
+    let body = ApiResponse::<Value>::error(message).with_correlation_id(correlation_id);
+    (status, Json(body)).into_response()
+}
+
+/// Ensure the request carries a bearer token for a configured admin
+/// 
+/// Admins are identified by email (`ADMIN_EMAILS`). With no admins
+/// configured, every admin endpoint is forbidden.
+/// 
+/// # Errors
+/// Status and message for the rejection:
+/// - 401 UNAUTHORIZED: Missing or invalid token
+/// - 403 FORBIDDEN: Token is valid but the user is not an admin
+fn require_admin(state: &AppState, headers: &HeaderMap) -> Result<(), (StatusCode, &'static str)> {
+    let (_, email) = extract_bearer(headers)
+        .and_then(|token| state.auth_service.verify_jwt(token))
+        .ok_or((StatusCode::UNAUTHORIZED, "Authentication required"))?;
+    
+    if state.config.admin_emails.iter().any(|admin| admin.eq_ignore_ascii_case(&email)) {
+        Ok(())
+    } else {
+        Err((StatusCode::FORBIDDEN, "Admin access required"))
+    }
+}
+
+/// Build a 429 response carrying rate limit headers
+/// 
+/// Headers follow the documented `X-RateLimit-*` convention, with the
+/// reset time expressed in milliseconds since epoch. `Retry-After` carries
+/// the whole seconds left until the reset, measured from `now_ms`.
+fn rate_limited_response(decision: &RateLimitDecision, now_ms: u64) -> Response {
+    let wait_ms = decision.reset_at.saturating_sub(now_ms);
+    let retry_after_secs = wait_ms.div_ceil(1000);
+
+    let mut headers = HeaderMap::new();
+    insert_rate_limit_headers(&mut headers, decision);
+    headers.insert(RETRY_AFTER, HeaderValue::from(retry_after_secs));
+
+    (
+        StatusCode::TOO_MANY_REQUESTS,
+        headers,
+        Json(ApiResponse::<Value>::error("Rate limit exceeded".to_string())),
+    )
+        .into_response()
+}
+
+/// Add the `X-RateLimit-*` headers describing `decision`
+fn insert_rate_limit_headers(headers: &mut HeaderMap, decision: &RateLimitDecision) {
+    headers.insert("X-RateLimit-Limit", HeaderValue::from(decision.limit));
+    headers.insert("X-RateLimit-Remaining", HeaderValue::from(decision.remaining));
+    headers.insert("X-RateLimit-Reset", HeaderValue::from(decision.reset_at));
+}
+
+/// Build a 503 response for a provider that is over its outbound rate limit
+fn provider_busy_response(provider: &str, retry_after: Duration) -> Response {
+    let retry_after_secs = retry_after.as_secs() + u64::from(retry_after.subsec_nanos() > 0);
+
+    (
+        StatusCode::SERVICE_UNAVAILABLE,
+        [(RETRY_AFTER, HeaderValue::from(retry_after_secs))],
+        Json(ApiResponse::<Value>::error(format!(
+            "Provider {} is at capacity, please retry later",
+            provider
+        ))),
+    )
+        .into_response()
+}
+
+/// Reject requests that declare a body larger than `JSON_LIMIT`
+/// 
+/// Answers 413 from the `Content-Length` header alone, before any of the
+/// body is read. Chunked bodies without the header are still capped by the
+/// body limit layer as they are read.
+async fn reject_oversized_body(State(state): State<AppState>, request: Request, next: Next) -> Response {
+    let declared_length = request
+        .headers()
+        .get(CONTENT_LENGTH)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse::<u64>().ok());
+    if declared_length.is_some_and(|length| length > state.config.json_limit as u64) {
+        return error_response(StatusCode::PAYLOAD_TOO_LARGE, "Request body too large");
+    }
+    next.run(request).await
+}
+
+/// ID of the placeholder user inserted for unauthenticated requests
+const ANONYMOUS_USER_ID: &str = "anonymous";
+
+/// Authenticate the caller from the `Authorization` header
+/// 
+/// Inserts the caller as an `AuthUser` request extension so handlers can
+/// take `Extension<AuthUser>` instead of parsing the header themselves.
+/// Without a valid token the request is rejected with 401 when
+/// `AUTH_REQUIRED` is set; otherwise an anonymous placeholder is inserted.
+/// The time taken is recorded as the `auth` phase of a `RequestTimings`
+/// extension.
+async fn authenticate(State(state): State<AppState>, mut request: Request, next: Next) -> Response {
+    let timings = RequestTimings::new();
+    let user = match extract_bearer(request.headers()) {
+        Some(token) => timings.time("auth", state.auth_service.get_user_from_token(token)).await,
+        None => None,
+    };
+    let user = match user {
+        Some(user) => user,
+        None if state.config.auth_required => {
+            return error_response(StatusCode::UNAUTHORIZED, "Authentication required");
+        }
+        None => AuthUser {
+            id: ANONYMOUS_USER_ID.to_string(),
+            email: None,
+            is_anonymous: true,
+            created_at: chrono::Utc::now(),
+            subscription_tier: GUEST_TIER.to_string(),
+        },
+    };
+    request.extensions_mut().insert(user);
+    request.extensions_mut().insert(timings);
+    next.run(request).await
+}
+
+/// Enforce the per-IP request rate (`PER_IP_RPM`) on invoke requests
+/// 
+/// Runs before `authenticate` and the guest quota, so it applies to every
+/// caller. Requests whose client IP cannot be resolved pass through.
+/// Over the limit the request gets 429 with `Retry-After` set to when the
+/// oldest counted request leaves the window.
+async fn ip_rate_limit(
+    State(state): State<AppState>,
+    client_ip: Option<ClientIp>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let decision = client_ip.and_then(|ClientIp(ip)| state.ip_limiter.check_ip(&ip.to_string()));
+    if let Some(decision) = decision.filter(|decision| !decision.allowed) {
+        tracing::info!("Client IP over per-minute request limit");
+        return rate_limited_response(&decision, state.clock.now_ms());
+    }
+    next.run(request).await
+}
+
+/// Header carrying the browser fingerprint used to tell guests apart
+const X_FINGERPRINT: &str = "x-fingerprint";
+
+/// Enforce the guest daily quota on anonymous callers
+/// 
+/// Runs after `authenticate`; callers with a registered user's token pass
+/// straight through (their quota is checked per tier in `invoke`). Guests are identified by their
+/// client IP (resolved from `X-Forwarded-For` behind trusted proxies), or
+/// without one by the `X-Fingerprint` header or guest user ID. Over quota the
+/// request gets 429; otherwise the response carries the remaining quota in
+/// `X-RateLimit-*` headers.
+async fn guest_quota(
+    State(state): State<AppState>,
+    Extension(user): Extension<AuthUser>,
+    client_ip: Option<ClientIp>,
+    request: Request,
+    next: Next,
+) -> Response {
+    if !user.is_anonymous || !state.config.guest_rate_limit_enabled {
+        return next.run(request).await;
+    }
+
+    let fingerprint = request
+        .headers()
+        .get(X_FINGERPRINT)
+        .and_then(|value| value.to_str().ok())
+        .map(str::trim)
+        .filter(|value| !value.is_empty())
+        .map(str::to_string);
+    let ip = client_ip.map(|ClientIp(ip)| ip.to_string());
+    let (allowed, remaining, reset_at, status) = check_guest_daily_limit(
+        &state.guest_usage,
+        state.clock.as_ref(),
+        fingerprint.as_deref(),
+        ip.as_deref(),
+        Some(&user.id),
+    );
+    let decision = RateLimitDecision {
+        allowed,
+        limit: MAX_GUEST_MESSAGES_PER_DAY,
+        remaining,
+        reset_at,
+    };
+    if !allowed {
+        tracing::info!("Guest over daily limit ({})", status);
+        return rate_limited_response(&decision, state.clock.now_ms());
+    }
+
+    let mut response = next.run(request).await;
+    insert_rate_limit_headers(response.headers_mut(), &decision);
+    response
+}
+
+/// Create and configure the Axum router with all routes and middleware
+/// 
+/// Sets up the complete HTTP service with:
+/// - All API endpoints with proper HTTP methods
+/// - Middleware stack (tracing, CORS, body size limits)
+/// - Caller authentication on the invoke and analytics endpoints
+/// - Per-IP request rate on the invoke endpoint, checked before authentication
+/// - Guest daily quota on the invoke endpoint
+/// - Shared application state
+/// 
+/// The middleware stack is applied in reverse order:
+/// 1. CORS (outermost - handles preflight requests)
+/// 2. Tracing (logs all requests and responses)
+/// 3. Body size limits (`Content-Length` check, then a cap while reading)
+/// 4. Route handlers (innermost - actual business logic)
+/// 
+/// # Arguments
+/// * `state` - Application state shared across all handlers
 /// 
 /// # Returns
 /// Configured Axum Router ready for serving
 fn create_router(state: AppState) -> Router {
+    let log_sample_rate = state.config.log_sample_rate;
+    let json_limit = state.config.json_limit;
+    let auth = middleware::from_fn_with_state(state.clone(), authenticate);
     Router::new()
         // Health and monitoring endpoints
         .route("/health", get(health_check))
+        .route("/ready", get(readiness_check))
+        .route("/diagnostics", get(diagnostics))
         
         // Authentication endpoints
         .route("/v1/auth/register", post(create_user))
         .route("/v1/auth/login", post(login))
+        .route("/v1/auth/refresh", post(refresh_token))
+        .route("/v1/auth/reset/request", post(request_password_reset))
+        .route("/v1/auth/reset/confirm", post(confirm_password_reset))
         .route("/v1/auth/anonymous", post(create_anonymous_session))
+        .route("/v1/auth/verify", post(verify_token))
+        .route("/v1/auth/me", get(current_user))
+        .route("/v1/auth/sessions", get(list_sessions))
+        .route("/v1/auth/sessions/:jti", delete(revoke_session))
         
         // Analytics and monitoring
-        .route("/v1/analytics", get(get_analytics))
+        .route("/v1/analytics", get(get_analytics).layer(auth.clone()))
+        .route("/v1/analytics/events", get(export_analytics_events))
         
         // Core AI functionality 
-        .route("/v1/invoke", post(invoke))
+        .route(
+            "/v1/invoke",
+            post(invoke)
+                .layer(middleware::from_fn_with_state(state.clone(), guest_quota))
+                .layer(auth)
+                .layer(middleware::from_fn_with_state(state.clone(), ip_rate_limit)),
+        )
+        
+        // Administration
+        .route("/v1/config", get(get_config))
+        .route("/v1/search/cache/clear", post(clear_search_cache))
+        .route("/v1/routes/reload", post(reload_routes))
         
         // Middleware stack (applied in reverse order)
+        .layer(DefaultBodyLimit::max(json_limit))
+        .layer(middleware::from_fn_with_state(state.clone(), reject_oversized_body))
         .layer(
             ServiceBuilder::new()
-                .layer(TraceLayer::new_for_http())
+                .layer(
+                    // One sampled log line per request, in a span naming it
+                    TraceLayer::new_for_http()
+                        .make_span_with(DefaultMakeSpan::new().level(Level::INFO))
+                        .on_request(())
+                        .on_response(move |response: &Response, latency: Duration, _: &tracing::Span| {
+                            log_sampling::log_response(log_sample_rate, response.status(), latency)
+                        })
+                        .on_failure(|failure, latency: Duration, _: &tracing::Span| {
+                            log_sampling::log_failure(failure, latency)
+                        })
+                )
                 .layer(
                     CorsLayer::new()
                         .allow_origin(Any) // TODO: Configure proper CORS based on config
@@ -765,14 +2282,30 @@ fn create_router(state: AppState) -> Router {
         .with_state(state)
 }
 
+/// Build the tracing filter from `RUST_LOG` and the configured log level
+/// 
+/// `RUST_LOG` directives win when set and valid; otherwise the configured
+/// `LOG_LEVEL` is used, falling back to `info` if that is invalid too.
+/// 
+/// # Arguments
+/// * `rust_log` - Value of the `RUST_LOG` environment variable, if set
+/// * `log_level` - Configured default level (e.g. "info", "debug")
+fn build_log_filter(rust_log: Option<&str>, log_level: &str) -> EnvFilter {
+    rust_log
+        .filter(|directives| !directives.trim().is_empty())
+        .and_then(|directives| EnvFilter::try_new(directives).ok())
+        .or_else(|| EnvFilter::try_new(log_level).ok())
+        .unwrap_or_else(|| EnvFilter::new("info"))
+}
+
 /// Application entry point
 /// 
 /// Initializes all services, configures the HTTP server, and starts
 /// listening for requests with graceful shutdown support.
 /// 
 /// Startup sequence:
-/// 1. Initialize structured logging with tracing
-/// 2. Load configuration from environment variables
+/// 1. Load configuration from environment variables
+/// 2. Initialize structured logging with tracing
 /// 3. Create all service instances with dependency injection
 /// 4. Build the HTTP router with middleware stack
 /// 5. Start the server with graceful shutdown handling
@@ -785,34 +2318,58 @@ fn create_router(state: AppState) -> Router {
 /// Result indicating successful startup or initialization error
 #[tokio::main]
 async fn main() -> Result<()> {
-    // Initialize structured logging for observability
-    // Uses environment variable RUST_LOG for level control
-    tracing_subscriber::fmt::init();
-    
     // Load all configuration from environment variables
     // Validates required settings and provides sensible defaults
     let config = Config::from_env();
     
+    // `rust-ai check` prints a preflight report instead of serving
+    if std::env::args().nth(1).as_deref() == Some("check") {
+        let report = config.self_check().await;
+        println!("{}", serde_json::to_string_pretty(&report)?);
+        std::process::exit(if report.ok() { 0 } else { 1 });
+    }
+    
+    // Initialize structured logging for observability
+    // RUST_LOG takes precedence; LOG_LEVEL from config is the fallback
+    let rust_log = std::env::var("RUST_LOG").ok();
+    tracing_subscriber::fmt()
+        .with_env_filter(build_log_filter(rust_log.as_deref(), &config.log_level))
+        .init();
+    
     info!("Starting Rust-AI server...");
     info!("Bind address: {}", config.bind_address);
     
-    // Initialize all services with dependency injection
-    // Order matters: ConvexService first (used by others)
-    let convex_service = ConvexService::new(config.clone());
-    let auth_service = AuthService::new(config.clone(), convex_service.clone());
-    let search_service = SearchService::new(config.clone());
+    // Refuse to start when auth is required but tokens cannot work
+    match config.check_jwt_config() {
+        Ok(Some(warning)) => tracing::warn!("{}", warning),
+        Ok(None) => {}
+        Err(error) => {
+            tracing::error!("{}", error);
+            anyhow::bail!(error);
+        }
+    }
     
-    // Initialize in-memory rate limiting for guest users
-    let guest_usage = Arc::new(Mutex::new(HashMap::new()));
+    // Parse provider routes, surfacing any configuration warnings
+    let routing = routing::build_routing_with_defaults(&config.routes_raw, config.max_routes, &config.provider_default_models);
+    for warning in &routing.warnings {
+        tracing::warn!("{}", warning);
+    }
+    match routing.check_nonempty(config.strict_routes) {
+        Ok(Some(warning)) => tracing::warn!("{}", warning),
+        Ok(None) => {}
+        Err(error) => {
+            tracing::error!("{}", error);
+            anyhow::bail!(error);
+        }
+    }
+    info!("Loaded {} provider routes", routing.routes.len());
+    for warning in config.provider_warnings() {
+        tracing::warn!("{}", warning);
+    }
     
     // Create shared application state for all request handlers
-    let state = AppState {
-        config: config.clone(),
-        auth_service,
-        convex_service,
-        search_service,
-        guest_usage,
-    };
+    let state = AppState::new(config.clone(), routing.routes);
+    let shutdown = state.shutdown.clone();
     
     // Build the complete HTTP router with middleware
     let app = create_router(state);
@@ -824,15 +2381,61 @@ async fn main() -> Result<()> {
     info!("Server listening on {}", addr);
     
     // Start the HTTP server with graceful shutdown support
-    let listener = tokio::net::TcpListener::bind(addr).await?;
+    let settings = ListenerSettings::from_config(&config);
+    let listener = bind_listener(addr, settings)?;
     
-    axum::serve(listener, app)
-        .with_graceful_shutdown(shutdown_signal())
+    // Peer addresses are needed to resolve client IPs
+    axum::serve(listener, app.into_make_service_with_connect_info::<SocketAddr>())
+        .tcp_nodelay(settings.nodelay)
+        .with_graceful_shutdown(async move {
+            shutdown_signal().await;
+            // Let open streams finish so in-flight connections can drain
+            shutdown.trigger();
+        })
         .await?;
     
     Ok(())
 }
 
+/// TCP options for the HTTP listener and the connections it accepts
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct ListenerSettings {
+    /// Idle time before keepalive probes, `None` for the OS default
+    keepalive: Option<Duration>,
+    /// Whether `TCP_NODELAY` is set on accepted connections
+    nodelay: bool,
+}
+
+impl ListenerSettings {
+    /// Settings from `HTTP_KEEPALIVE_SECS` and `TCP_NODELAY`
+    fn from_config(config: &Config) -> Self {
+        Self {
+            keepalive: (config.http_keepalive_secs > 0)
+                .then(|| Duration::from_secs(config.http_keepalive_secs)),
+            nodelay: config.tcp_nodelay,
+        }
+    }
+}
+
+/// Bind the HTTP listener with `settings` applied
+/// 
+/// Keepalive is set on the listening socket and inherited by accepted
+/// connections; `TCP_NODELAY` is applied per connection by `axum::serve`.
+/// Without keepalive the socket matches `TcpListener::bind`.
+fn bind_listener(addr: SocketAddr, settings: ListenerSettings) -> std::io::Result<tokio::net::TcpListener> {
+    let socket = Socket::new(Domain::for_address(addr), Type::STREAM, Some(Protocol::TCP))?;
+    // Same as tokio, so restarts can rebind while old connections linger
+    #[cfg(not(windows))]
+    socket.set_reuse_address(true)?;
+    if let Some(idle) = settings.keepalive {
+        socket.set_tcp_keepalive(&TcpKeepalive::new().with_time(idle))?;
+    }
+    socket.bind(&addr.into())?;
+    socket.listen(1024)?;
+    socket.set_nonblocking(true)?;
+    tokio::net::TcpListener::from_std(socket.into())
+}
+
 /// Graceful shutdown signal handler
 /// 
 /// Listens for system signals that indicate the server should shut down:
@@ -875,4 +2478,2437 @@ async fn shutdown_signal() {
             info!("Received SIGTERM, shutting down...");
         },
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::{header::AUTHORIZATION, HeaderName};
+    use axum_test::TestServer;
+    use convex_service::UserAccount;
+    use secret::Secret;
+    use serde_json::{json, Value};
+    use types::Provider;
+    use test_support::{closed_addr, spawn_mock};
+    
+    fn create_test_config() -> Config {
+        let mut config = Config::from_env();
+        config.action_token_secret = Some(Secret::from("test_secret_key_1234567890"));
+        // Providers answer from the local stub instead of the real APIs
+        let provider_url = mock_provider_url().to_string();
+        config.cloudflare.base_url = provider_url.clone();
+        config.mistral.base_url = provider_url.clone();
+        config.openai.base_url = provider_url.clone();
+        config.xai.base_url = provider_url.clone();
+        config.groq.base_url = provider_url.clone();
+        config.openrouter.base_url = provider_url.clone();
+        config.meta.base_url = provider_url.clone();
+        config.anthropic.base_url = provider_url;
+        config
+    }
+    
+    fn create_test_app_state() -> AppState {
+        create_test_app_state_with(create_test_config())
+    }
+    
+    fn create_test_app_state_with(config: Config) -> AppState {
+        let routing = routing::build_routing_with_defaults(&config.routes_raw, config.max_routes, &config.provider_default_models);
+        AppState::new(config, routing.routes)
+    }
+    
+    /// Stub provider answering OpenAI-compatible and Anthropic chat
+    /// requests with "Echo: <last message>", streamed word by word when
+    /// the request asks for a stream
+    fn mock_provider_router() -> Router {
+        fn last_message(body: &Value) -> String {
+            let content = body["messages"]
+                .as_array()
+                .and_then(|messages| messages.last())
+                .and_then(|message| message["content"].as_str())
+                .unwrap_or_default();
+            format!("Echo: {}", content)
+        }
+        fn event_stream(events: Vec<String>) -> Response {
+            let body: String = events.iter().map(|event| format!("data: {}\n\n", event)).collect();
+            ([(axum::http::header::CONTENT_TYPE, "text/event-stream")], body).into_response()
+        }
+        fn words(text: &str) -> Vec<String> {
+            text.split_inclusive(' ').map(str::to_string).collect()
+        }
+        let openai = post(|Json(body): Json<Value>| async move {
+            let reply = last_message(&body);
+            if body["stream"] == true {
+                let mut events: Vec<String> = words(&reply)
+                    .into_iter()
+                    .map(|word| json!({"choices": [{"index": 0, "delta": {"content": word}}]}).to_string())
+                    .collect();
+                events.push("[DONE]".to_string());
+                return event_stream(events);
+            }
+            Json(json!({
+                "choices": [{"index": 0, "message": {"role": "assistant", "content": reply}}]
+            }))
+            .into_response()
+        });
+        Router::new()
+            .route("/v1/chat/completions", openai.clone())
+            .route("/accounts/:account_id/ai/v1/chat/completions", openai)
+            .route(
+                "/v1/images/generations",
+                post(|Json(body): Json<Value>| async move {
+                    let name = body["prompt"].as_str().unwrap_or_default().replace(' ', "-");
+                    Json(json!({ "data": [{"url": format!("https://images.example/{}.png", name)}] }))
+                }),
+            )
+            .route(
+                "/v1/messages",
+                post(|Json(body): Json<Value>| async move {
+                    let reply = last_message(&body);
+                    if body["stream"] == true {
+                        let events = words(&reply)
+                            .into_iter()
+                            .map(|word| {
+                                json!({"type": "content_block_delta", "index": 0, "delta": {"type": "text_delta", "text": word}})
+                                    .to_string()
+                            })
+                            .collect();
+                        return event_stream(events);
+                    }
+                    Json(json!({ "content": [{"type": "text", "text": reply}] })).into_response()
+                }),
+            )
+    }
+    
+    /// Base URL of the stub provider, started once for all tests on its own
+    /// runtime so it outlives each test's runtime
+    fn mock_provider_url() -> &'static str {
+        static URL: std::sync::OnceLock<String> = std::sync::OnceLock::new();
+        URL.get_or_init(|| {
+            let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+            listener.set_nonblocking(true).unwrap();
+            let url = format!("http://{}", listener.local_addr().unwrap());
+            std::thread::spawn(move || {
+                let runtime = tokio::runtime::Builder::new_current_thread()
+                    .enable_all()
+                    .build()
+                    .unwrap();
+                runtime.block_on(async move {
+                    let listener = tokio::net::TcpListener::from_std(listener).unwrap();
+                    axum::serve(listener, mock_provider_router()).await.unwrap();
+                });
+            });
+            url
+        })
+    }
+    
+    fn bearer(token: &str) -> HeaderValue {
+        HeaderValue::from_str(&format!("Bearer {}", token)).unwrap()
+    }
+    
+    /// Session token for a new free-tier account
+    async fn registered_user_token(state: &AppState, email: &str) -> String {
+        let user_id = state
+            .convex_service
+            .create_user(UserAccount {
+                email: email.to_string(),
+                password_hash: String::new(),
+                subscription_tier: "free".to_string(),
+                api_key: format!("ak_{}", email),
+                is_active: true,
+            })
+            .await
+            .unwrap();
+        state.auth_service.generate_jwt(&user_id, email).unwrap()
+    }
+    
+    fn chat_request_body(content: &str) -> Value {
+        json!({
+            "op": "chat",
+            "tier": "fast",
+            "input": {
+                "messages": [
+                    {
+                        "role": "user",
+                        "content": content
+                    }
+                ]
+            }
+        })
+    }
+    
+    #[tokio::test]
+    async fn test_health_check() {
+        let state = create_test_app_state();
+        let app = create_router(state);
+        let server = TestServer::new(app).unwrap();
+        
+        let response = server.get("/health").await;
+        
+        response.assert_status_ok();
+        let body: Value = response.json();
+        assert_eq!(body.as_object().unwrap().len(), 4);
+        assert_eq!(body["status"], "ok");
+        assert_eq!(body["service"], "rust-ai-api");
+        assert_eq!(body["version"], env!("CARGO_PKG_VERSION"));
+        assert_eq!(body["build"]["git_sha"], json!(option_env!("RUST_AI_GIT_SHA")));
+        
+        // The build script always records a build time
+        let built_at = body["build"]["built_at"].as_str().unwrap();
+        assert!(chrono::DateTime::parse_from_rfc3339(built_at).is_ok(), "{}", built_at);
+    }
+    
+    #[tokio::test]
+    async fn test_invoke_endpoint_structure() {
+        let state = create_test_app_state();
+        let app = create_router(state);
+        let server = TestServer::new(app).unwrap();
+        
+        let request_body = chat_request_body("Hello, AI!");
+        
+        let response = server.post("/v1/invoke").json(&request_body).await;
+        
+        response.assert_status_ok();
+        
+        let body: Value = response.json();
+        assert_eq!(body["status"], "success");
+        assert!(body["data"]["request_id"].is_string());
+        assert_eq!(body["data"]["status"], "completed");
+        assert_eq!(body["data"]["provider"], "openai");
+        assert_eq!(body["data"]["message"], "Echo: Hello, AI!");
+    }
+    
+    #[tokio::test]
+    async fn test_invoke_endpoint_invalid_request() {
+        let state = create_test_app_state();
+        let app = create_router(state);
+        let server = TestServer::new(app).unwrap();
+        
+        let invalid_request = json!({
+            "invalid_field": "value"
+        });
+        
+        let response = server.post("/v1/invoke").json(&invalid_request).await;
+        
+        // Should return 400 Bad Request or similar for malformed JSON
+        assert!(response.status_code().is_client_error());
+    }
+    
+    #[tokio::test]
+    async fn test_auth_middleware_requires_token_when_auth_required() {
+        let mut config = create_test_config();
+        config.auth_required = true;
+        let state = create_test_app_state_with(config);
+        let guest = state.auth_service.create_guest_user().await.unwrap().token.unwrap();
+        let unknown = state.auth_service.generate_jwt("user_gone", "gone@example.com").unwrap();
+        let server = TestServer::new(create_router(state)).unwrap();
+        
+        server.get("/v1/analytics").await.assert_status(StatusCode::UNAUTHORIZED);
+        let response = server.post("/v1/invoke").json(&chat_request_body("Hello")).await;
+        response.assert_status(StatusCode::UNAUTHORIZED);
+        let body: Value = response.json();
+        assert_eq!(body["error"], "Authentication required");
+        
+        // Tokens for accounts that no longer exist are refused
+        let response = server
+            .post("/v1/invoke")
+            .add_header(AUTHORIZATION, bearer(&unknown))
+            .json(&chat_request_body("Hello"))
+            .await;
+        response.assert_status(StatusCode::UNAUTHORIZED);
+        
+        let response = server
+            .post("/v1/invoke")
+            .add_header(AUTHORIZATION, bearer(&guest))
+            .json(&chat_request_body("Hello"))
+            .await;
+        response.assert_status_ok();
+        
+        // Unprotected endpoints
+        server.get("/health").await.assert_status_ok();
+        server.post("/v1/auth/anonymous").await.assert_status_ok();
+    }
+    
+    #[tokio::test]
+    async fn test_auth_middleware_inserts_user_extension() {
+        let state = create_test_app_state();
+        let guest = state.auth_service.create_guest_user().await.unwrap().token.unwrap();
+        let app = Router::new()
+            .route(
+                "/whoami",
+                get(|Extension(user): Extension<AuthUser>| async move { Json(user) }),
+            )
+            .layer(middleware::from_fn_with_state(state.clone(), authenticate))
+            .with_state(state);
+        let server = TestServer::new(app).unwrap();
+        
+        let body: Value = server.get("/whoami").add_header(AUTHORIZATION, bearer(&guest)).await.json();
+        assert!(body["id"].as_str().unwrap().starts_with("anon-"));
+        
+        // Optional auth falls back to an anonymous placeholder
+        let body: Value = server.get("/whoami").await.json();
+        assert_eq!(body["id"], ANONYMOUS_USER_ID);
+        assert_eq!(body["is_anonymous"], true);
+    }
+    
+    #[tokio::test]
+    async fn test_analytics_endpoint() {
+        let state = create_test_app_state();
+        let app = create_router(state);
+        let server = TestServer::new(app).unwrap();
+        
+        let response = server.get("/v1/analytics").await;
+        
+        response.assert_status_ok();
+        
+        let body: Value = response.json();
+        assert_eq!(body["status"], "success");
+        assert!(body["data"].is_object());
+        // Should contain analytics data structure
+        assert!(body["data"]["total_requests"].is_number());
+        assert!(body["data"]["active_users"].is_number());
+    }
+    
+    #[tokio::test]
+    async fn test_anonymous_session_creation() {
+        let state = create_test_app_state();
+        let app = create_router(state);
+        let server = TestServer::new(app).unwrap();
+        
+        let response = server.post("/v1/auth/anonymous").await;
+        
+        response.assert_status_ok();
+        
+        let body: Value = response.json();
+        assert_eq!(body["status"], "success");
+        assert!(body["data"]["token"].is_string());
+        assert!(body["data"]["user"]["is_anonymous"].as_bool().unwrap());
+        assert!(body["data"]["user"]["id"].as_str().unwrap().starts_with("anon-"));
+    }
+    
+    #[tokio::test]
+    async fn test_verify_token_endpoint() {
+        use clock::MockClock;
+        
+        let state = create_test_app_state();
+        let valid = state.auth_service.generate_jwt("user_1", "user@example.com").unwrap();
+        // Issued 30 days ago, so well past the 7 day lifetime
+        let month_ago = system_clock().now_ms() - 30 * 24 * 60 * 60 * 1000;
+        let expired = state
+            .auth_service
+            .clone()
+            .with_clock(Arc::new(MockClock::new(month_ago)))
+            .generate_jwt("user_1", "user@example.com")
+            .unwrap();
+        let server = TestServer::new(create_router(state)).unwrap();
+        
+        let response = server.post("/v1/auth/verify").json(&json!({"token": valid})).await;
+        response.assert_status_ok();
+        let body: Value = response.json();
+        assert_eq!(body["data"]["valid"], true);
+        assert_eq!(body["data"]["user_id"], "user_1");
+        assert_eq!(body["data"]["email"], "user@example.com");
+        assert_eq!(body["data"]["is_anonymous"], false);
+        let expires_at = body["data"]["expires_at"].as_str().unwrap();
+        assert!(chrono::DateTime::parse_from_rfc3339(expires_at).unwrap() > chrono::Utc::now());
+        
+        // The token may also be sent as a bearer token
+        let response = server
+            .post("/v1/auth/verify")
+            .add_header(AUTHORIZATION, bearer(&valid))
+            .json(&json!({}))
+            .await;
+        let body: Value = response.json();
+        assert_eq!(body["data"]["valid"], true);
+        
+        // Expired and malformed tokens are both just invalid
+        for token in [expired.as_str(), "not-a-jwt"] {
+            let response = server.post("/v1/auth/verify").json(&json!({"token": token})).await;
+            response.assert_status_ok();
+            let body: Value = response.json();
+            assert_eq!(body["data"], json!({"valid": false}));
+        }
+    }
+    
+    #[tokio::test]
+    async fn test_list_and_revoke_sessions() {
+        let state = create_test_app_state();
+        let laptop = state.auth_service.generate_jwt("user_1", "user@example.com").unwrap();
+        let phone = state.auth_service.generate_jwt("user_1", "user@example.com").unwrap();
+        let other = state.auth_service.generate_jwt("user_2", "other@example.com").unwrap();
+        let server = TestServer::new(create_router(state)).unwrap();
+        
+        let response = server
+            .get("/v1/auth/sessions")
+            .add_header(AUTHORIZATION, bearer(&laptop))
+            .await;
+        response.assert_status_ok();
+        let body: Value = response.json();
+        let sessions = body["data"]["sessions"].as_array().unwrap();
+        assert_eq!(sessions.len(), 2);
+        assert_eq!(sessions[0]["current"], true);
+        assert_eq!(sessions[1]["current"], false);
+        let phone_jti = sessions[1]["jti"].as_str().unwrap().to_string();
+        
+        // Other users cannot revoke the session
+        server
+            .delete(&format!("/v1/auth/sessions/{}", phone_jti))
+            .add_header(AUTHORIZATION, bearer(&other))
+            .await
+            .assert_status(StatusCode::NOT_FOUND);
+        
+        let response = server
+            .delete(&format!("/v1/auth/sessions/{}", phone_jti))
+            .add_header(AUTHORIZATION, bearer(&laptop))
+            .await;
+        response.assert_status_ok();
+        let body: Value = response.json();
+        assert_eq!(body["data"]["revoked"], phone_jti.as_str());
+        
+        // Only the revoked session stops verifying
+        let verify = |token: String| {
+            let request = server.post("/v1/auth/verify").json(&json!({ "token": token }));
+            async move { request.await.json::<Value>()["data"]["valid"].clone() }
+        };
+        assert_eq!(verify(phone.clone()).await, false);
+        assert_eq!(verify(laptop.clone()).await, true);
+        server
+            .get("/v1/auth/sessions")
+            .add_header(AUTHORIZATION, bearer(&phone))
+            .await
+            .assert_status(StatusCode::UNAUTHORIZED);
+        
+        let body: Value = server
+            .get("/v1/auth/sessions")
+            .add_header(AUTHORIZATION, bearer(&laptop))
+            .await
+            .json();
+        assert_eq!(body["data"]["sessions"].as_array().unwrap().len(), 1);
+        
+        // Revoking twice finds nothing
+        server
+            .delete(&format!("/v1/auth/sessions/{}", phone_jti))
+            .add_header(AUTHORIZATION, bearer(&laptop))
+            .await
+            .assert_status(StatusCode::NOT_FOUND);
+    }
+    
+    #[tokio::test]
+    async fn test_refresh_fails_after_session_is_revoked() {
+        let server = TestServer::new(create_router(create_test_app_state())).unwrap();
+        let credentials = json!({"email": "revoke@example.com", "password": "securepassword123"});
+        server.post("/v1/auth/register").json(&credentials).await.assert_status_ok();
+        let login: Value = server.post("/v1/auth/login").json(&credentials).await.json();
+        let token = login["data"]["token"].as_str().unwrap().to_string();
+        let refresh = json!({"refresh_token": login["data"]["refresh_token"]});
+        
+        // A session minted from the refresh token is paired with it too
+        let response = server.post("/v1/auth/refresh").json(&refresh).await;
+        response.assert_status_ok();
+        let refreshed = response.json::<Value>()["data"]["token"].as_str().unwrap().to_string();
+        let body: Value = server
+            .get("/v1/auth/sessions")
+            .add_header(AUTHORIZATION, bearer(&refreshed))
+            .await
+            .json();
+        let sessions = body["data"]["sessions"].as_array().unwrap();
+        let current = sessions.iter().find(|session| session["current"] == true).unwrap();
+        
+        server
+            .delete(&format!("/v1/auth/sessions/{}", current["jti"].as_str().unwrap()))
+            .add_header(AUTHORIZATION, bearer(&token))
+            .await
+            .assert_status_ok();
+        server.post("/v1/auth/refresh").json(&refresh).await.assert_status(StatusCode::UNAUTHORIZED);
+        
+        // The other session is left alone
+        let body: Value = server.post("/v1/auth/verify").json(&json!({"token": token})).await.json();
+        assert_eq!(body["data"]["valid"], true);
+    }
+    
+    #[tokio::test]
+    async fn test_anonymous_sessions_disabled() {
+        // A guest token issued while anonymous sessions were allowed
+        let guest = create_test_app_state()
+            .auth_service
+            .create_guest_user()
+            .await
+            .unwrap();
+        let guest_token = guest.token.unwrap();
+        
+        let mut config = create_test_config();
+        config.allow_anonymous = false;
+        let state = create_test_app_state_with(config);
+        
+        // Existing guest tokens no longer authenticate
+        assert!(state.auth_service.verify_jwt(&guest_token).is_none());
+        
+        // Registered user tokens are unaffected
+        let user_token = state.auth_service.generate_jwt("user_1", "user@example.com").unwrap();
+        assert!(state.auth_service.verify_jwt(&user_token).is_some());
+        
+        let server = TestServer::new(create_router(state)).unwrap();
+        let response = server.post("/v1/auth/anonymous").await;
+        
+        response.assert_status(StatusCode::FORBIDDEN);
+        let body: Value = response.json();
+        assert_eq!(body["status"], "error");
+        assert_eq!(body["error"], "anonymous sessions disabled");
+    }
+    
+    #[tokio::test]
+    async fn test_export_analytics_events_as_jsonl() {
+        let mut config = create_test_config();
+        config.admin_emails = vec!["admin@example.com".to_string()];
+        let state = create_test_app_state_with(config);
+        
+        for (request_id, provider) in [("req_1", "openai"), ("req_2", "anthropic")] {
+            state.convex_service.log_api_request(convex_service::ApiRequestEvent {
+                request_id: request_id.to_string(),
+                user_id: Some("user_1".to_string()),
+                operation: "chat".to_string(),
+                tier: "fast".to_string(),
+                provider: provider.to_string(),
+                model: "model".to_string(),
+                temperature: None,
+                max_tokens: None,
+                response_status: 200,
+                response_time_ms: 42,
+                input_messages: Some(1),
+                input_tokens: None,
+                output_tokens: None,
+                error_message: None,
+                user_agent: None,
+                ip_address: None,
+            }).await.unwrap();
+        }
+        
+        let admin_token = state.auth_service.generate_jwt("admin_1", "admin@example.com").unwrap();
+        let user_token = state.auth_service.generate_jwt("user_1", "user@example.com").unwrap();
+        let server = TestServer::new(create_router(state)).unwrap();
+        
+        let response = server
+            .get("/v1/analytics/events")
+            .add_query_param("hours", 1)
+            .add_query_param("format", "jsonl")
+            .add_header(AUTHORIZATION, bearer(&admin_token))
+            .await;
+        response.assert_status_ok();
+        assert_eq!(response.header(CONTENT_TYPE), "application/x-ndjson");
+        
+        let events: Vec<Value> = response
+            .text()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0]["request_id"], "req_1");
+        assert_eq!(events[1]["provider"], "anthropic");
+        assert!(events[1]["recorded_at"].as_u64().unwrap() > 0);
+        
+        // Admin gating
+        server.get("/v1/analytics/events").await.assert_status(StatusCode::UNAUTHORIZED);
+        server
+            .get("/v1/analytics/events")
+            .add_header(AUTHORIZATION, bearer(&user_token))
+            .await
+            .assert_status(StatusCode::FORBIDDEN);
+        server
+            .get("/v1/analytics/events")
+            .add_query_param("format", "csv")
+            .add_header(AUTHORIZATION, bearer(&admin_token))
+            .await
+            .assert_status(StatusCode::BAD_REQUEST);
+    }
+    
+    #[tokio::test]
+    async fn test_ready_reflects_jwt_config() {
+        // Configured secret
+        let server = TestServer::new(create_router(create_test_app_state())).unwrap();
+        let body: Value = server.get("/ready").await.json();
+        assert_eq!(body, json!({"status": "ready", "warnings": []}));
+        
+        // Optional auth without a secret is ready with a warning
+        let mut config = create_test_config();
+        config.auth_required = false;
+        config.action_token_secret = None;
+        let server = TestServer::new(create_router(create_test_app_state_with(config))).unwrap();
+        let response = server.get("/ready").await;
+        response.assert_status_ok();
+        let body: Value = response.json();
+        assert!(body["warnings"][0].as_str().unwrap().contains("ACTION_TOKEN_SECRET"));
+        
+        // Required auth without a secret is not ready
+        let mut config = create_test_config();
+        config.auth_required = true;
+        config.action_token_secret = None;
+        let server = TestServer::new(create_router(create_test_app_state_with(config))).unwrap();
+        let response = server.get("/ready").await;
+        response.assert_status(StatusCode::SERVICE_UNAVAILABLE);
+        let body: Value = response.json();
+        assert_eq!(body["status"], "not_ready");
+    }
+    
+    #[tokio::test]
+    async fn test_diagnostics_reports_provider_mismatch() {
+        let mut config = create_test_config();
+        config.admin_emails = vec!["admin@example.com".to_string()];
+        config.openai.base_url = "https://gateway.example.com/v1".to_string();
+        config.openai.api_key = Secret::default();
+        let state = create_test_app_state_with(config);
+        let admin_token = state.auth_service.generate_jwt("admin_1", "admin@example.com").unwrap();
+        let server = TestServer::new(create_router(state)).unwrap();
+        
+        server.get("/diagnostics").await.assert_status(StatusCode::UNAUTHORIZED);
+        
+        let response = server
+            .get("/diagnostics")
+            .add_header(AUTHORIZATION, bearer(&admin_token))
+            .await;
+        response.assert_status_ok();
+        let body: Value = response.json();
+        let warnings = body["data"]["warnings"].as_array().unwrap();
+        assert!(warnings
+            .iter()
+            .any(|warning| warning.as_str().unwrap().contains("OPENAI_API_KEY is empty")));
+    }
+    
+    #[tokio::test]
+    async fn test_config_endpoint_reports_effective_config_without_secrets() {
+        std::env::set_var("SEARCH_SESSION_WINDOW_SECS", "45");
+        let mut config = create_test_config();
+        std::env::remove_var("SEARCH_SESSION_WINDOW_SECS");
+        config.admin_emails = vec!["admin@example.com".to_string()];
+        config.openai.api_key = Secret::from("sk-proj-effective-config");
+        let state = create_test_app_state_with(config);
+        let admin_token = state.auth_service.generate_jwt("admin_1", "admin@example.com").unwrap();
+        let user_token = state.auth_service.generate_jwt("user_1", "user@example.com").unwrap();
+        let server = TestServer::new(create_router(state)).unwrap();
+        
+        server.get("/v1/config").await.assert_status(StatusCode::UNAUTHORIZED);
+        server
+            .get("/v1/config")
+            .add_header(AUTHORIZATION, bearer(&user_token))
+            .await
+            .assert_status(StatusCode::FORBIDDEN);
+        
+        let response = server
+            .get("/v1/config")
+            .add_header(AUTHORIZATION, bearer(&admin_token))
+            .await;
+        response.assert_status_ok();
+        let body: Value = response.json();
+        assert_eq!(body["data"]["search"]["session_window_secs"], 45);
+        assert_eq!(body["data"]["openai"]["api_key"], "[REDACTED]");
+        assert_eq!(body["data"]["action_token_secret"], "[REDACTED]");
+        assert!(!response.text().contains("sk-proj-effective-config"));
+        assert!(!response.text().contains("test_secret_key_1234567890"));
+    }
+    
+    #[tokio::test]
+    async fn test_clear_search_cache_endpoint() {
+        let mut config = create_test_config();
+        config.admin_emails = vec!["admin@example.com".to_string()];
+        // Enabled search with no reachable providers still caches the empty response
+        config.search.enabled = true;
+        config.search.tavily.api_key = Secret::default();
+        config.search.brave.api_key = Secret::default();
+        config.search.searxng.enabled = false;
+        let state = create_test_app_state_with(config);
+        state.search_service.perform_web_search("first").await.unwrap();
+        state.search_service.perform_web_search("second").await.unwrap();
+        
+        let admin_token = state.auth_service.generate_jwt("admin_1", "admin@example.com").unwrap();
+        let user_token = state.auth_service.generate_jwt("user_1", "user@example.com").unwrap();
+        let server = TestServer::new(create_router(state)).unwrap();
+        
+        server
+            .post("/v1/search/cache/clear")
+            .add_header(AUTHORIZATION, bearer(&user_token))
+            .await
+            .assert_status(StatusCode::FORBIDDEN);
+        
+        let response = server
+            .post("/v1/search/cache/clear")
+            .add_header(AUTHORIZATION, bearer(&admin_token))
+            .await;
+        response.assert_status_ok();
+        let body: Value = response.json();
+        assert_eq!(body["data"]["cleared"], 2);
+        
+        let body: Value = server
+            .post("/v1/search/cache/clear")
+            .add_header(AUTHORIZATION, bearer(&admin_token))
+            .await
+            .json();
+        assert_eq!(body["data"]["cleared"], 0);
+    }
+    
+    #[tokio::test]
+    async fn test_reload_routes_endpoint() {
+        let path = std::env::temp_dir().join(format!("rust-ai-routes-{}", uuid::Uuid::new_v4()));
+        std::fs::write(&path, "chat.fast=openai:gpt-4o-mini").unwrap();
+        let mut config = create_test_config();
+        config.admin_emails = vec!["admin@example.com".to_string()];
+        config.routes_raw = "chat.fast=openai:gpt-4o-mini".to_string();
+        config.routes_file = Some(path.to_string_lossy().into_owned());
+        let state = create_test_app_state_with(config);
+        let routing = state.routing.clone();
+        let admin_token = state.auth_service.generate_jwt("admin_1", "admin@example.com").unwrap();
+        let user_token = state.auth_service.generate_jwt("user_1", "user@example.com").unwrap();
+        let server = TestServer::new(create_router(state)).unwrap();
+        
+        std::fs::write(&path, "chat.fast=groq:llama-3.1-8b-instant, chat.smart=anthropic:claude-3-5-sonnet").unwrap();
+        server
+            .post("/v1/routes/reload")
+            .add_header(AUTHORIZATION, bearer(&user_token))
+            .await
+            .assert_status(StatusCode::FORBIDDEN);
+        assert!(!routing.load().contains_key("chat.smart"));
+        
+        let response = server
+            .post("/v1/routes/reload")
+            .add_header(AUTHORIZATION, bearer(&admin_token))
+            .await;
+        response.assert_status_ok();
+        let body: Value = response.json();
+        assert_eq!(body["data"]["routes"], 2);
+        let routes = routing.load();
+        assert_eq!(routes["chat.fast"][0].model, "llama-3.1-8b-instant");
+        assert_eq!(routes["chat.smart"][0].model, "claude-3-5-sonnet");
+        
+        // Invalid configurations leave the current routes in place
+        for invalid in ["", "chat.fast=openai:gpt-4o;temperature=hot"] {
+            std::fs::write(&path, invalid).unwrap();
+            server
+                .post("/v1/routes/reload")
+                .add_header(AUTHORIZATION, bearer(&admin_token))
+                .await
+                .assert_status(StatusCode::BAD_REQUEST);
+            assert_eq!(routing.load().len(), 2);
+        }
+        std::fs::remove_file(&path).unwrap();
+    }
+    
+    #[tokio::test]
+    async fn test_user_registration_endpoint() {
+        let state = create_test_app_state();
+        let app = create_router(state);
+        let server = TestServer::new(app).unwrap();
+        
+        let registration_request = json!({
+            "email": "test@example.com",
+            "password": "securepassword123"
+        });
+        
+        let response = server.post("/v1/auth/register").json(&registration_request).await;
+        
+        // Note: This might fail in the actual implementation due to validation
+        // or database constraints, but we're testing the endpoint structure
+        response.assert_status_ok();
+        
+        let body: Value = response.json();
+        // Should return success or appropriate validation error
+        assert!(body["status"].is_string());
+    }
+    
+    #[tokio::test]
+    async fn test_registration_ignores_requested_tier() {
+        let server = TestServer::new(create_router(create_test_app_state())).unwrap();
+        let response = server
+            .post("/v1/auth/register")
+            .json(&json!({
+                "email": "upgrade@example.com",
+                "password": "securepassword123",
+                "subscription_tier": "premium"
+            }))
+            .await;
+        response.assert_status_ok();
+        let body: Value = response.json();
+        assert_eq!(body["data"]["subscription_tier"], "free");
+    }
+    
+    #[tokio::test]
+    async fn test_register_then_login_with_memory_store() {
+        let state = create_test_app_state();
+        let auth_service = state.auth_service.clone();
+        let server = TestServer::new(create_router(state)).unwrap();
+        let credentials = json!({
+            "email": "new@example.com",
+            "password": "securepassword123"
+        });
+        
+        let response = server.post("/v1/auth/register").json(&credentials).await;
+        response.assert_status_ok();
+        let registered: Value = response.json();
+        let user_id = registered["data"]["id"].as_str().unwrap().to_string();
+        
+        let response = server.post("/v1/auth/login").json(&credentials).await;
+        response.assert_status_ok();
+        let body: Value = response.json();
+        assert_eq!(body["data"]["user"]["id"], user_id.as_str());
+        let token = body["data"]["token"].as_str().unwrap();
+        assert_eq!(
+            auth_service.verify_jwt(token),
+            Some((user_id, "new@example.com".to_string()))
+        );
+        
+        // The email is taken now, and the password is still checked
+        server.post("/v1/auth/register").json(&credentials).await.assert_status(StatusCode::BAD_REQUEST);
+        let response = server
+            .post("/v1/auth/login")
+            .json(&json!({"email": "new@example.com", "password": "wrongpassword"}))
+            .await;
+        response.assert_status(StatusCode::UNAUTHORIZED);
+    }
+    
+    #[tokio::test]
+    async fn test_password_reset_flow() {
+        // Mailer stub receiving the reset tokens
+        let (sender, mut received) = tokio::sync::mpsc::unbounded_channel::<Value>();
+        let mailer = Router::new().route(
+            "/reset",
+            post(move |Json(body): Json<Value>| {
+                let sender = sender.clone();
+                async move {
+                    sender.send(body).unwrap();
+                    StatusCode::OK
+                }
+            }),
+        );
+        let webhook_url = format!("http://{}/reset", spawn_mock(mailer).await);
+        
+        let mut config = create_test_config();
+        config.password_reset_webhook_url = Some(webhook_url);
+        let server = TestServer::new(create_router(create_test_app_state_with(config))).unwrap();
+        let credentials = json!({"email": "reset@example.com", "password": "old password 123"});
+        server.post("/v1/auth/register").json(&credentials).await.assert_status_ok();
+        let login: Value = server.post("/v1/auth/login").json(&credentials).await.json();
+        let old_refresh = json!({"refresh_token": login["data"]["refresh_token"]});
+        
+        // Unknown and known accounts get the same answer; only the known one gets a token
+        for email in ["nobody@example.com", "reset@example.com"] {
+            let response = server.post("/v1/auth/reset/request").json(&json!({"email": email})).await;
+            response.assert_status(StatusCode::ACCEPTED);
+            let body: Value = response.json();
+            assert!(body["data"].get("reset_token").is_none());
+        }
+        let delivery = tokio::time::timeout(Duration::from_secs(5), received.recv()).await.unwrap().unwrap();
+        assert_eq!(delivery["email"], "reset@example.com");
+        assert_eq!(delivery["expires_in"], 3600);
+        let reset_token = delivery["reset_token"].as_str().unwrap().to_string();
+        assert!(received.try_recv().is_err());
+        
+        // Reset tokens don't work as sessions
+        let body: Value = server.post("/v1/auth/verify").json(&json!({"token": reset_token})).await.json();
+        assert_eq!(body["data"]["valid"], false);
+        
+        let confirm = |password: &str| json!({"token": reset_token, "password": password});
+        server
+            .post("/v1/auth/reset/confirm")
+            .json(&confirm("short"))
+            .await
+            .assert_status(StatusCode::BAD_REQUEST);
+        let response = server.post("/v1/auth/reset/confirm").json(&confirm("new password 456")).await;
+        response.assert_status_ok();
+        let body: Value = response.json();
+        assert!(body["data"]["token"].is_string());
+        server
+            .post("/v1/auth/reset/confirm")
+            .json(&confirm("another password"))
+            .await
+            .assert_status(StatusCode::BAD_REQUEST);
+        server.post("/v1/auth/refresh").json(&old_refresh).await.assert_status(StatusCode::UNAUTHORIZED);
+        
+        server.post("/v1/auth/login").json(&credentials).await.assert_status(StatusCode::UNAUTHORIZED);
+        server
+            .post("/v1/auth/login")
+            .json(&json!({"email": "reset@example.com", "password": "new password 456"}))
+            .await
+            .assert_status_ok();
+    }
+    
+    #[tokio::test]
+    async fn test_current_user_endpoint() {
+        let state = create_test_app_state();
+        let password_hash = state.auth_service.hash_password("securepassword123").await.unwrap();
+        state
+            .convex_service
+            .create_user(UserAccount {
+                email: "me@example.com".to_string(),
+                password_hash,
+                subscription_tier: "pro".to_string(),
+                api_key: "ak_me".to_string(),
+                is_active: true,
+            })
+            .await
+            .unwrap();
+        let server = TestServer::new(create_router(state.clone())).unwrap();
+        let credentials = json!({"email": "me@example.com", "password": "securepassword123"});
+        let login: Value = server.post("/v1/auth/login").json(&credentials).await.json();
+        let token = login["data"]["token"].as_str().unwrap();
+        
+        let response = server.get("/v1/auth/me").add_header(AUTHORIZATION, bearer(token)).await;
+        response.assert_status_ok();
+        let body: Value = response.json();
+        assert_eq!(body["data"]["id"], login["data"]["user"]["id"]);
+        assert_eq!(body["data"]["email"], "me@example.com");
+        assert_eq!(body["data"]["is_anonymous"], false);
+        assert_eq!(body["data"]["subscription_tier"], "pro");
+        
+        let guest: Value = server.post("/v1/auth/anonymous").await.json();
+        let guest_token = guest["data"]["token"].as_str().unwrap();
+        let body: Value = server
+            .get("/v1/auth/me")
+            .add_header(AUTHORIZATION, bearer(guest_token))
+            .await
+            .json();
+        assert_eq!(body["data"]["is_anonymous"], true);
+        assert_eq!(body["data"]["subscription_tier"], "guest");
+        
+        server.get("/v1/auth/me").await.assert_status(StatusCode::UNAUTHORIZED);
+        server
+            .get("/v1/auth/me")
+            .add_header(AUTHORIZATION, bearer("not-a-jwt"))
+            .await
+            .assert_status(StatusCode::UNAUTHORIZED);
+        let refresh = login["data"]["refresh_token"].as_str().unwrap();
+        server
+            .get("/v1/auth/me")
+            .add_header(AUTHORIZATION, bearer(refresh))
+            .await
+            .assert_status(StatusCode::UNAUTHORIZED);
+    }
+    
+    #[tokio::test]
+    async fn test_login_endpoint() {
+        let state = create_test_app_state();
+        let app = create_router(state);
+        let server = TestServer::new(app).unwrap();
+        
+        let login_request = json!({
+            "email": "test@example.com",
+            "password": "password123"
+        });
+        
+        let response = server.post("/v1/auth/login").json(&login_request).await;
+        
+        // Login should fail for non-existent user with invalid credentials
+        response.assert_status(StatusCode::UNAUTHORIZED);
+    }
+    
+    #[tokio::test]
+    async fn test_refresh_endpoint() {
+        let state = create_test_app_state();
+        let (session, refresh) = state.auth_service.generate_session_tokens("user_1", "user@example.com").unwrap();
+        let server = TestServer::new(create_router(state)).unwrap();
+        
+        // Unknown user
+        let response = server
+            .post("/v1/auth/refresh")
+            .json(&json!({"refresh_token": refresh}))
+            .await;
+        response.assert_status(StatusCode::UNAUTHORIZED);
+        
+        // Access tokens cannot be used as refresh tokens
+        let response = server
+            .post("/v1/auth/refresh")
+            .json(&json!({"refresh_token": session}))
+            .await;
+        response.assert_status(StatusCode::UNAUTHORIZED);
+        
+        // Refresh tokens cannot be used as access tokens
+        let response = server
+            .get("/v1/auth/sessions")
+            .add_header(AUTHORIZATION, bearer(&refresh))
+            .await;
+        response.assert_status(StatusCode::UNAUTHORIZED);
+    }
+    
+    #[tokio::test]
+    async fn test_cors_headers() {
+        let state = create_test_app_state();
+        let app = create_router(state);
+        let server = TestServer::new(app).unwrap();
+        
+        let response = server.get("/health").await;
+        
+        // Check that CORS headers are present
+        assert!(response.headers().contains_key("access-control-allow-origin"));
+    }
+    
+    #[tokio::test]
+    async fn test_oversized_content_length_is_rejected() {
+        let mut config = create_test_config();
+        config.json_limit = 1024;
+        let server = TestServer::new(create_router(create_test_app_state_with(config))).unwrap();
+        
+        // Rejected from the header alone, whatever the body
+        let response = server
+            .post("/v1/invoke")
+            .add_header(CONTENT_LENGTH, HeaderValue::from(1_000_000u64))
+            .json(&chat_request_body("Hello"))
+            .await;
+        response.assert_status(StatusCode::PAYLOAD_TOO_LARGE);
+        let body: Value = response.json();
+        assert_eq!(body["error"], "Request body too large");
+        
+        // Bodies within the limit are unaffected
+        let response = server.post("/v1/invoke").json(&chat_request_body("Hello")).await;
+        response.assert_status_ok();
+    }
+    
+    #[tokio::test]
+    async fn test_nonexistent_route() {
+        let state = create_test_app_state();
+        let app = create_router(state);
+        let server = TestServer::new(app).unwrap();
+        
+        let response = server.get("/nonexistent").await;
+        
+        response.assert_status_not_found();
+    }
+    
+    #[tokio::test]
+    async fn test_invoke_dispatches_to_routed_provider() {
+        // Provider stub recording what it was sent
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let recorder = received.clone();
+        let provider = Router::new().route(
+            "/v1/messages",
+            post(move |headers: HeaderMap, Json(body): Json<Value>| {
+                recorder.lock().unwrap().push((headers, body));
+                async { Json(json!({ "content": [{"type": "text", "text": "Bonjour!"}] })) }
+            }),
+        );
+        let base_url = format!("http://{}", spawn_mock(provider).await);
+        
+        let mut config = create_test_config();
+        config.routes_raw = "chat.smart=anthropic:claude-3-5-sonnet-20241022".to_string();
+        config.anthropic.base_url = base_url;
+        config.anthropic.api_key = Secret::from("sk-ant-test");
+        let server = TestServer::new(create_router(create_test_app_state_with(config))).unwrap();
+        
+        let mut request_body = chat_request_body("Say hello in French");
+        request_body["tier"] = json!("smart");
+        request_body["options"] = json!({ "temperature": 0.3, "max_tokens": 64 });
+        let response = server.post("/v1/invoke").json(&request_body).await;
+        response.assert_status_ok();
+        let body: Value = response.json();
+        assert_eq!(body["data"]["message"], "Bonjour!");
+        assert_eq!(body["data"]["provider"], "anthropic");
+        assert_eq!(body["data"]["model"], "claude-3-5-sonnet-20241022");
+        
+        let received = received.lock().unwrap();
+        let (headers, sent) = &received[0];
+        assert_eq!(headers["x-api-key"], "sk-ant-test");
+        assert_eq!(sent["model"], "claude-3-5-sonnet-20241022");
+        assert_eq!(sent["temperature"], 0.3);
+        assert_eq!(sent["max_tokens"], 64);
+        assert_eq!(sent["messages"].as_array().unwrap().last().unwrap()["content"], "Say hello in French");
+    }
+    
+    #[tokio::test]
+    async fn test_invoke_logs_timestamped_chat_messages() {
+        let state = create_test_app_state();
+        let convex_service = state.convex_service.clone();
+        let server = TestServer::new(create_router(state)).unwrap();
+        
+        let mut request_body = chat_request_body("What is Rust?");
+        request_body["session_id"] = json!("chat-history-1");
+        server.post("/v1/invoke").json(&request_body).await.assert_status_ok();
+        
+        // Messages are logged in the background
+        let mut messages = Vec::new();
+        for _ in 0..50 {
+            messages = convex_service.get_chat_messages("chat-history-1").await.unwrap();
+            if messages.len() == 2 {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert_eq!(messages.len(), 2);
+        assert_eq!(messages[0].message_type, "user");
+        assert_eq!(messages[0].content, "What is Rust?");
+        assert_eq!(messages[1].message_type, "assistant");
+        assert_eq!(messages[1].content, "Echo: What is Rust?");
+        let first = messages[0].created_at.unwrap();
+        assert!(first > 1_577_836_800_000);
+        assert!(messages[1].created_at.unwrap() >= first);
+    }
+    
+    #[tokio::test]
+    async fn test_invoke_reports_missing_routing_map() {
+        let mut config = create_test_config();
+        config.routes_raw = "chat.fast,openai:gpt-4o-mini".to_string();
+        let server = TestServer::new(create_router(create_test_app_state_with(config))).unwrap();
+        
+        let response = server.post("/v1/invoke").json(&chat_request_body("Hello")).await;
+        response.assert_status(StatusCode::SERVICE_UNAVAILABLE);
+        let body: Value = response.json();
+        assert!(body["error"].as_str().unwrap().starts_with("No routes configured"));
+    }
+    
+    #[tokio::test]
+    async fn test_invoke_rejects_unroutable_and_malformed_requests() {
+        let mut config = create_test_config();
+        config.routes_raw = "chat.fast=openai:gpt-4o-mini".to_string();
+        let server = TestServer::new(create_router(create_test_app_state_with(config.clone()))).unwrap();
+        
+        let mut request_body = chat_request_body("Hello");
+        request_body["tier"] = json!("unknown");
+        let response = server.post("/v1/invoke").json(&request_body).await;
+        response.assert_status(StatusCode::SERVICE_UNAVAILABLE);
+        let body: Value = response.json();
+        assert_eq!(body["error"], "No provider is configured for chat.unknown");
+        
+        for input in [json!({}), json!({"messages": []}), json!({"messages": "Hello"})] {
+            let response = server
+                .post("/v1/invoke")
+                .json(&json!({ "op": "chat", "input": input }))
+                .await;
+            response.assert_status(StatusCode::BAD_REQUEST);
+        }
+        
+        // A provider that cannot be reached is reported as a bad gateway
+        let closed = closed_addr();
+        config.openai.base_url = format!("http://{}", closed);
+        let server = TestServer::new(create_router(create_test_app_state_with(config))).unwrap();
+        let response = server.post("/v1/invoke").json(&chat_request_body("Hello")).await;
+        response.assert_status(StatusCode::BAD_GATEWAY);
+    }
+    
+    #[tokio::test]
+    async fn test_invoke_falls_back_to_next_route_target() {
+        let closed = closed_addr();
+        let mut config = create_test_config();
+        config.anthropic.base_url = format!("http://{}", closed);
+        config.routes_raw = "chat.fast=anthropic:claude-3-5-haiku|openai:gpt-4o-mini".to_string();
+        let server = TestServer::new(create_router(create_test_app_state_with(config.clone()))).unwrap();
+        
+        let response = server.post("/v1/invoke").json(&chat_request_body("Hello")).await;
+        response.assert_status_ok();
+        let body: Value = response.json();
+        assert_eq!(body["data"]["provider"], "openai");
+        assert_eq!(body["data"]["model"], "gpt-4o-mini");
+        assert_eq!(body["data"]["message"], "Echo: Hello");
+        
+        // Blocked targets are skipped rather than refusing the request
+        config.anthropic.base_url = config.openai.base_url.clone();
+        config.blocked_models = vec!["anthropic:claude-3-5-haiku".to_string()];
+        let server = TestServer::new(create_router(create_test_app_state_with(config))).unwrap();
+        let response = server.post("/v1/invoke").json(&chat_request_body("Hello")).await;
+        response.assert_status_ok();
+        let body: Value = response.json();
+        assert_eq!(body["data"]["provider"], "openai");
+    }
+    
+    #[tokio::test]
+    async fn test_invoke_rejects_out_of_range_options() {
+        let server = TestServer::new(create_router(create_test_app_state_with(create_test_config()))).unwrap();
+        let invoke = |options: Value| {
+            let mut request_body = chat_request_body("Hello");
+            request_body["options"] = options;
+            server.post("/v1/invoke").json(&request_body)
+        };
+        
+        let response = invoke(json!({ "temperature": 5.0 })).await;
+        response.assert_status(StatusCode::BAD_REQUEST);
+        let body: Value = response.json();
+        assert_eq!(body["error"], "options.temperature out of range");
+        
+        let response = invoke(json!({ "temperature": -0.5, "max_tokens": 0 })).await;
+        response.assert_status(StatusCode::BAD_REQUEST);
+        let body: Value = response.json();
+        assert_eq!(body["error"], "options.max_tokens, options.temperature out of range");
+        
+        invoke(json!({ "temperature": 2.0, "max_tokens": 1 })).await.assert_status_ok();
+    }
+    
+    #[tokio::test]
+    async fn test_invoke_race_keeps_the_faster_target() {
+        // Two stub providers answering with their name after a delay
+        async fn delayed_provider(name: &'static str, delay: Duration) -> String {
+            let provider = Router::new().route(
+                "/v1/chat/completions",
+                post(move || async move {
+                    tokio::time::sleep(delay).await;
+                    Json(json!({
+                        "choices": [{"index": 0, "message": {"role": "assistant", "content": name}}]
+                    }))
+                }),
+            );
+            let base_url = format!("http://{}", spawn_mock(provider).await);
+            base_url
+        }
+        let slow = Duration::from_millis(1500);
+        let mut config = create_test_config();
+        config.openai.base_url = delayed_provider("slow", slow).await;
+        config.groq.base_url = delayed_provider("fast", Duration::from_millis(50)).await;
+        config.routes_raw = "chat.fast=openai:gpt-4o-mini|groq:llama-3.1-8b-instant".to_string();
+        let server = TestServer::new(create_router(create_test_app_state_with(config))).unwrap();
+        
+        // Without the flag the first target answers, however slow
+        let response = server.post("/v1/invoke").json(&chat_request_body("Hello")).await;
+        response.assert_status_ok();
+        let body: Value = response.json();
+        assert_eq!(body["data"]["provider"], "openai");
+        assert_eq!(body["data"]["message"], "slow");
+        
+        let mut request_body = chat_request_body("Hello");
+        request_body["race"] = json!(true);
+        let started = Instant::now();
+        let response = server.post("/v1/invoke").json(&request_body).await;
+        response.assert_status_ok();
+        let body: Value = response.json();
+        assert_eq!(body["data"]["provider"], "groq");
+        assert_eq!(body["data"]["model"], "llama-3.1-8b-instant");
+        assert_eq!(body["data"]["message"], "fast");
+        assert!(started.elapsed() < slow, "the race waited for the slower target");
+    }
+    
+    #[tokio::test]
+    async fn test_invoke_race_applies_each_targets_defaults() {
+        // Stub providers noting the temperature they were sent
+        async fn recording_provider(delay: Duration) -> (String, Arc<Mutex<Vec<Value>>>) {
+            let received = Arc::new(Mutex::new(Vec::new()));
+            let recorder = received.clone();
+            let provider = Router::new().route(
+                "/v1/chat/completions",
+                post(move |Json(body): Json<Value>| async move {
+                    recorder.lock().unwrap().push(body["temperature"].clone());
+                    tokio::time::sleep(delay).await;
+                    Json(json!({ "choices": [{"index": 0, "message": {"role": "assistant", "content": "Hi"}}] }))
+                }),
+            );
+            let base_url = format!("http://{}", spawn_mock(provider).await);
+            (base_url, received)
+        }
+        let (openai_url, openai_received) = recording_provider(Duration::from_millis(300)).await;
+        let (groq_url, groq_received) = recording_provider(Duration::ZERO).await;
+        let mut config = create_test_config();
+        config.openai.base_url = openai_url;
+        config.groq.base_url = groq_url;
+        let state = create_test_app_state_with(config);
+        let target = |provider: Provider, model: &str, temperature: f32| RouteTarget {
+            provider,
+            model: model.to_string(),
+            defaults: InvokeOptions {
+                temperature: Some(temperature),
+                ..InvokeOptions::default()
+            },
+        };
+        state.routing.replace(HashMap::from([(
+            "chat.fast".to_string(),
+            vec![
+                target(Provider::OpenAI, "gpt-4o-mini", 0.1),
+                target(Provider::Groq, "llama-3.1-8b-instant", 0.9),
+            ],
+        )]));
+        let server = TestServer::new(create_router(state)).unwrap();
+        
+        let mut request_body = chat_request_body("Hello");
+        request_body["race"] = json!(true);
+        server.post("/v1/invoke").json(&request_body).await.assert_status_ok();
+        assert_eq!(*openai_received.lock().unwrap(), [json!(0.1)]);
+        assert_eq!(*groq_received.lock().unwrap(), [json!(0.9)]);
+    }
+    
+    #[tokio::test]
+    async fn test_invoke_with_attachments() {
+        let mut config = create_test_config();
+        config.routes_raw = "chat.smart=openai:gpt-4o".to_string();
+        let state = create_test_app_state_with(config);
+        let app = create_router(state);
+        let server = TestServer::new(app).unwrap();
+        
+        let request_body = json!({
+            "op": "chat",
+            "tier": "smart",
+            "input": {
+                "messages": [
+                    {
+                        "role": "user",
+                        "content": "Analyze this file"
+                    }
+                ]
+            },
+            "attachments": [
+                {
+                    "name": "test.txt",
+                    "content_type": "text/plain",
+                    "url": "data:text/plain;base64,SGVsbG8gV29ybGQ=" // Base64: "Hello World"
+                }
+            ],
+            "options": {
+                "temperature": 0.7,
+                "max_tokens": 1000
+            }
+        });
+        
+        let response = server.post("/v1/invoke").json(&request_body).await;
+        
+        response.assert_status_ok();
+        
+        let body: Value = response.json();
+        assert_eq!(body["status"], "success");
+        assert!(body["data"]["request_id"].is_string());
+    }
+    
+    #[tokio::test]
+    async fn test_invoke_reads_text_attachments_into_the_prompt() {
+        let mut config = create_test_config();
+        config.max_attachments = 1;
+        let server = TestServer::new(create_router(create_test_app_state_with(config))).unwrap();
+        let attachment = json!({
+            "name": "notes.txt",
+            "content_type": "text/plain",
+            "url": "data:text/plain;base64,SGVsbG8gV29ybGQ="
+        });
+        
+        // The echo provider repeats the prompt, file content included
+        let mut request_body = chat_request_body("Summarize this");
+        request_body["attachments"] = json!([attachment]);
+        let response = server.post("/v1/invoke").json(&request_body).await;
+        response.assert_status_ok();
+        let body: Value = response.json();
+        let message = body["data"]["message"].as_str().unwrap();
+        assert!(message.starts_with("Echo: Summarize this"), "{}", message);
+        assert!(message.contains("[File: notes.txt (text/plain)]\nHello World"), "{}", message);
+        
+        request_body["attachments"] = json!([attachment, attachment]);
+        let response = server.post("/v1/invoke").json(&request_body).await;
+        response.assert_status(StatusCode::BAD_REQUEST);
+        let body: Value = response.json();
+        assert!(body["error"].as_str().unwrap().contains("MAX_ATTACHMENTS"));
+    }
+    
+    #[tokio::test]
+    async fn test_invoke_reports_attachment_failures_with_error_details() {
+        let mut request_body = chat_request_body("Read this");
+        request_body["attachments"] = json!([{
+            "name": "report.txt",
+            "content_type": "text/plain",
+            "url": "ftp://example.com/report.txt"
+        }]);
+        
+        let mut config = create_test_config();
+        config.attachment_error_details = true;
+        let server = TestServer::new(create_router(create_test_app_state_with(config.clone()))).unwrap();
+        let response = server.post("/v1/invoke").json(&request_body).await;
+        response.assert_status_ok();
+        let body: Value = response.json();
+        assert_eq!(
+            body["data"]["diagnostics"]["attachment_failures"],
+            json!(["[File: report.txt - failed: unsupported URL scheme: ftp://example.com/report.txt]"])
+        );
+        
+        // Off by default, so internal details stay out of responses
+        config.attachment_error_details = false;
+        let server = TestServer::new(create_router(create_test_app_state_with(config))).unwrap();
+        let body: Value = server.post("/v1/invoke").json(&request_body).await.json();
+        assert_eq!(body["status"], "success");
+        assert!(body["data"].get("diagnostics").is_none());
+    }
+    
+    #[test]
+    fn test_guest_usage_functions() {
+        // Test get_guest_key function
+        let key1 = get_guest_key(Some("fingerprint123"), Some("192.168.1.1"), Some("anon-123"));
+        assert_eq!(key1, "ip:192.168.1.1");
+        
+        let key2 = get_guest_key(Some("fingerprint123"), None, Some("anon-123"));
+        assert_eq!(key2, "fp:fingerprint123");
+        
+        let key3 = get_guest_key(None, None, Some("anon-123"));
+        assert_eq!(key3, "anon:anon-123");
+        assert_eq!(get_guest_key(None, None, None), "unknown");
+        
+        // Test next_utc_day_start_ms function
+        let timestamp = 1640995200000; // Jan 1, 2022 00:00:00 UTC
+        let next_day = next_utc_day_start_ms(timestamp);
+        let expected_next_day = 1641081600000; // Jan 2, 2022 00:00:00 UTC
+        assert_eq!(next_day, expected_next_day);
+    }
+    
+    #[test]
+    fn test_check_guest_daily_limit() {
+        let guest_usage = Arc::new(Mutex::new(HashMap::new()));
+        
+        // First request should be allowed
+        let (allowed, remaining, reset_at, _message) = check_guest_daily_limit(
+            &guest_usage,
+            &clock::SystemClock,
+            Some("fingerprint123"),
+            Some("192.168.1.1"),
+            None
+        );
+        
+        assert!(allowed);
+        assert_eq!(remaining, 4); // 5 - 1 = 4 remaining
+        assert!(reset_at > 0);
+    }
+    
+    #[tokio::test]
+    async fn test_guest_daily_limit_disabled_allows_beyond_limit() {
+        let mut config = create_test_config();
+        config.guest_rate_limit_enabled = false;
+        let state = create_test_app_state_with(config);
+        let guest_usage = state.guest_usage.clone();
+        let server = TestServer::new(create_router(state)).unwrap();
+        
+        for _ in 0..MAX_GUEST_MESSAGES_PER_DAY * 3 {
+            let response = server.post("/v1/invoke").json(&chat_request_body("Hello")).await;
+            response.assert_status_ok();
+            assert!(response.maybe_header("X-RateLimit-Remaining").is_none());
+        }
+        
+        // Nothing was tracked
+        assert!(guest_usage.lock().unwrap().is_empty());
+    }
+    
+    #[test]
+    fn test_guest_daily_limit_resets_with_mock_clock() {
+        let guest_usage = Arc::new(Mutex::new(HashMap::new()));
+        let clock = clock::MockClock::new(1640995200000 + 1000); // Jan 1, 2022 00:00:01 UTC
+        let check = || check_guest_daily_limit(&guest_usage, &clock, Some("fp"), Some("10.0.0.1"), None);
+        
+        for _ in 0..MAX_GUEST_MESSAGES_PER_DAY {
+            assert!(check().0);
+        }
+        let (allowed, remaining, reset_at, _message) = check();
+        assert!(!allowed);
+        assert_eq!(remaining, 0);
+        assert_eq!(reset_at, 1641081600000);
+        
+        // Still blocked one millisecond before midnight UTC
+        clock.set(reset_at - 1);
+        assert!(!check().0);
+        
+        // Quota is restored at the start of the next day
+        clock.set(reset_at);
+        let (allowed, remaining, reset_at, _message) = check();
+        assert!(allowed);
+        assert_eq!(remaining, MAX_GUEST_MESSAGES_PER_DAY - 1);
+        assert_eq!(reset_at, 1641168000000);
+    }
+    
+    #[tokio::test]
+    async fn test_invoke_blocks_registered_user_over_daily_limit() {
+        let mut config = create_test_config();
+        config.tier_limits = HashMap::from([("free".to_string(), 2)]);
+        let state = create_test_app_state_with(config);
+        let token = registered_user_token(&state, "capped@example.com").await;
+        let server = TestServer::new(create_router(state)).unwrap();
+        
+        // Distinct prompts, so no request is answered from another's result
+        for prompt in ["Hello", "Hello again"] {
+            let response = server
+                .post("/v1/invoke")
+                .add_header(AUTHORIZATION, bearer(&token))
+                .json(&chat_request_body(prompt))
+                .await;
+            response.assert_status_ok();
+        }
+        
+        let response = server
+            .post("/v1/invoke")
+            .add_header(AUTHORIZATION, bearer(&token))
+            .json(&chat_request_body("Hello once more"))
+            .await;
+        
+        response.assert_status(StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(response.header("X-RateLimit-Remaining"), "0");
+        assert!(response.header("X-RateLimit-Reset").to_str().unwrap().parse::<u64>().unwrap() > 0);
+        let retry_after: u64 = response.header(RETRY_AFTER).to_str().unwrap().parse().unwrap();
+        assert!(retry_after > 0 && retry_after <= 24 * 60 * 60);
+        
+        let body: Value = response.json();
+        assert_eq!(body["status"], "error");
+        
+        // Requests without a token are not subject to the registered-user quota
+        server.post("/v1/invoke").json(&chat_request_body("Hello")).await.assert_status_ok();
+    }
+    
+    #[tokio::test]
+    async fn test_duplicate_invoke_does_not_use_quota() {
+        let mut config = create_test_config();
+        config.tier_limits = HashMap::from([("free".to_string(), 2)]);
+        let state = create_test_app_state_with(config);
+        let token = registered_user_token(&state, "dup@example.com").await;
+        let server = TestServer::new(create_router(state)).unwrap();
+        let invoke = |prompt: &str| {
+            server
+                .post("/v1/invoke")
+                .add_header(AUTHORIZATION, bearer(&token))
+                .json(&chat_request_body(prompt))
+        };
+        
+        // Repeats inside the dedup window share the first result and cost one unit
+        invoke("Hello").await.assert_status_ok();
+        invoke("Hello").await.assert_status_ok();
+        invoke("Hello").await.assert_status_ok();
+        
+        invoke("Something else").await.assert_status_ok();
+        invoke("A third prompt").await.assert_status(StatusCode::TOO_MANY_REQUESTS);
+    }
+    
+    #[tokio::test]
+    async fn test_rejected_and_failed_invokes_do_not_use_quota() {
+        let closed = closed_addr();
+        let mut config = create_test_config();
+        config.tier_limits = HashMap::from([("free".to_string(), 1)]);
+        config.groq.base_url = format!("http://{}", closed);
+        config.routes_raw = "chat.fast=openai:gpt-4o-mini,chat.smart=groq:llama-3.1-8b-instant".to_string();
+        let state = create_test_app_state_with(config);
+        let token = registered_user_token(&state, "refund@example.com").await;
+        let server = TestServer::new(create_router(state)).unwrap();
+        let invoke = |body: Value| server.post("/v1/invoke").add_header(AUTHORIZATION, bearer(&token)).json(&body);
+        
+        // Malformed messages are rejected with 400
+        let response = invoke(json!({ "op": "chat", "input": { "messages": [] } })).await;
+        response.assert_status(StatusCode::BAD_REQUEST);
+        
+        // An unreachable provider fails with 502
+        let mut failing = chat_request_body("Hello");
+        failing["tier"] = json!("smart");
+        invoke(failing).await.assert_status(StatusCode::BAD_GATEWAY);
+        
+        // Neither counted: the single daily request is still available
+        invoke(chat_request_body("Hello")).await.assert_status_ok();
+        invoke(chat_request_body("Hello again")).await.assert_status(StatusCode::TOO_MANY_REQUESTS);
+    }
+    
+    #[test]
+    fn test_listener_settings_from_config() {
+        let mut config = create_test_config();
+        config.http_keepalive_secs = 0;
+        config.tcp_nodelay = false;
+        assert_eq!(
+            ListenerSettings::from_config(&config),
+            ListenerSettings { keepalive: None, nodelay: false }
+        );
+        
+        config.http_keepalive_secs = 45;
+        config.tcp_nodelay = true;
+        assert_eq!(
+            ListenerSettings::from_config(&config),
+            ListenerSettings { keepalive: Some(Duration::from_secs(45)), nodelay: true }
+        );
+    }
+    
+    #[tokio::test]
+    async fn test_bind_listener_applies_keepalive_to_connections() {
+        let settings = ListenerSettings { keepalive: Some(Duration::from_secs(45)), nodelay: true };
+        let listener = bind_listener("127.0.0.1:0".parse().unwrap(), settings).unwrap();
+        let addr = listener.local_addr().unwrap();
+        
+        let _client = tokio::net::TcpStream::connect(addr).await.unwrap();
+        let (accepted, _) = listener.accept().await.unwrap();
+        let socket = socket2::SockRef::from(&accepted);
+        assert!(socket.keepalive().unwrap());
+        assert_eq!(socket.keepalive_time().unwrap(), Duration::from_secs(45));
+        
+        // Without keepalive the OS default is left alone
+        let settings = ListenerSettings { keepalive: None, nodelay: false };
+        let listener = bind_listener("127.0.0.1:0".parse().unwrap(), settings).unwrap();
+        let _client = tokio::net::TcpStream::connect(listener.local_addr().unwrap()).await.unwrap();
+        let (accepted, _) = listener.accept().await.unwrap();
+        assert!(!socket2::SockRef::from(&accepted).keepalive().unwrap());
+    }
+    
+    #[tokio::test]
+    async fn test_invoke_enforces_guest_daily_limit() {
+        let mut config = create_test_config();
+        config.guest_rate_limit_enabled = true;
+        let server = TestServer::new(create_router(create_test_app_state_with(config))).unwrap();
+        let request_body = chat_request_body("Hello");
+        
+        for expected_remaining in (0..MAX_GUEST_MESSAGES_PER_DAY).rev() {
+            let response = server
+                .post("/v1/invoke")
+                .add_header(HeaderName::from_static(X_FINGERPRINT), HeaderValue::from_static("fp-1"))
+                .json(&request_body)
+                .await;
+            response.assert_status_ok();
+            assert_eq!(response.header("X-RateLimit-Remaining"), expected_remaining.to_string().as_str());
+            assert!(response.header("X-RateLimit-Reset").to_str().unwrap().parse::<u64>().unwrap() > 0);
+        }
+        
+        let response = server
+            .post("/v1/invoke")
+            .add_header(HeaderName::from_static(X_FINGERPRINT), HeaderValue::from_static("fp-1"))
+            .json(&request_body)
+            .await;
+        response.assert_status(StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(response.header("X-RateLimit-Remaining"), "0");
+        let body: Value = response.json();
+        assert_eq!(body["status"], "error");
+        
+        // Another browser has its own quota
+        let response = server
+            .post("/v1/invoke")
+            .add_header(HeaderName::from_static(X_FINGERPRINT), HeaderValue::from_static("fp-2"))
+            .json(&request_body)
+            .await;
+        response.assert_status_ok();
+        assert_eq!(
+            response.header("X-RateLimit-Remaining"),
+            (MAX_GUEST_MESSAGES_PER_DAY - 1).to_string().as_str()
+        );
+    }
+    
+    #[tokio::test]
+    async fn test_new_guest_tokens_share_their_ips_daily_limit() {
+        let mut config = create_test_config();
+        config.guest_rate_limit_enabled = true;
+        let state = create_test_app_state_with(config);
+        let auth_service = state.auth_service.clone();
+        // Connections come from the address in X-Test-Peer, as if accepted by the listener
+        let app = create_router(state).layer(middleware::from_fn(|mut request: Request, next: Next| async move {
+            let peer = request.headers()["x-test-peer"].to_str().unwrap().parse().unwrap();
+            request.extensions_mut().insert(axum::extract::ConnectInfo(SocketAddr::new(peer, 4000)));
+            next.run(request).await
+        }));
+        let server = TestServer::new(app).unwrap();
+        let invoke_as_new_guest = |ip: &'static str| {
+            let (server, auth_service) = (&server, auth_service.clone());
+            async move {
+                let token = auth_service.create_guest_user().await.unwrap().token.unwrap();
+                server
+                    .post("/v1/invoke")
+                    .add_header(HeaderName::from_static("x-test-peer"), HeaderValue::from_static(ip))
+                    .add_header(AUTHORIZATION, bearer(&token))
+                    .json(&chat_request_body("Hello"))
+                    .await
+            }
+        };
+        
+        for _ in 0..MAX_GUEST_MESSAGES_PER_DAY {
+            invoke_as_new_guest("203.0.113.7").await.assert_status_ok();
+        }
+        invoke_as_new_guest("203.0.113.7").await.assert_status(StatusCode::TOO_MANY_REQUESTS);
+        invoke_as_new_guest("198.51.100.1").await.assert_status_ok();
+    }
+    
+    #[tokio::test]
+    async fn test_invoke_enforces_per_ip_rpm() {
+        let mut config = create_test_config();
+        config.per_ip_rpm = 3;
+        // Connections come from the address in X-Test-Peer, as if accepted by the listener
+        let app = create_router(create_test_app_state_with(config)).layer(middleware::from_fn(
+            |mut request: Request, next: Next| async move {
+                let peer = request.headers()["x-test-peer"].to_str().unwrap().parse().unwrap();
+                request.extensions_mut().insert(axum::extract::ConnectInfo(SocketAddr::new(peer, 4000)));
+                next.run(request).await
+            },
+        ));
+        let server = TestServer::new(app).unwrap();
+        let peer = |ip: &'static str| (HeaderName::from_static("x-test-peer"), HeaderValue::from_static(ip));
+        
+        for _ in 0..3 {
+            let (name, value) = peer("203.0.113.7");
+            server.post("/v1/invoke").add_header(name, value).json(&chat_request_body("Hello")).await.assert_status_ok();
+        }
+        let (name, value) = peer("203.0.113.7");
+        let response = server.post("/v1/invoke").add_header(name, value).json(&chat_request_body("Hello")).await;
+        response.assert_status(StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(response.header("X-RateLimit-Limit"), "3");
+        assert!(response.header(RETRY_AFTER).to_str().unwrap().parse::<u64>().unwrap() <= 60);
+        
+        // Other addresses and the health check are unaffected
+        let (name, value) = peer("198.51.100.1");
+        server.post("/v1/invoke").add_header(name, value).json(&chat_request_body("Hello")).await.assert_status_ok();
+        let (name, value) = peer("203.0.113.7");
+        server.get("/health").add_header(name, value).await.assert_status_ok();
+    }
+    
+    #[tokio::test]
+    async fn test_invoke_sheds_requests_over_provider_rpm() {
+        let mut config = create_test_config();
+        config.routes_raw = "chat.fast=openai:gpt-4o-mini".to_string();
+        config.provider_rpm = HashMap::from([("openai".to_string(), 2)]);
+        config.provider_queue_ms = 0;
+        let server = TestServer::new(create_router(create_test_app_state_with(config))).unwrap();
+        
+        for content in ["first", "second"] {
+            server
+                .post("/v1/invoke")
+                .json(&chat_request_body(content))
+                .await
+                .assert_status_ok();
+        }
+        
+        let response = server
+            .post("/v1/invoke")
+            .json(&chat_request_body("third"))
+            .await;
+        
+        response.assert_status(StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(response.header("Retry-After"), "30");
+        let body: Value = response.json();
+        assert_eq!(body["status"], "error");
+        assert!(body["error"].as_str().unwrap().contains("openai"));
+    }
+    
+    /// Error response for a failed provider call under the given verbosity
+    async fn provider_failure_body(verbosity: ErrorVerbosity) -> (StatusCode, Value) {
+        let mut config = create_test_config();
+        config.error_verbosity = verbosity;
+        let error = anyhow::anyhow!("openai returned 502 Bad Gateway: upstream connect error to 10.0.3.7:443");
+        let response = internal_error_response(&config, StatusCode::BAD_GATEWAY, &error);
+        let status = response.status();
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        (status, serde_json::from_slice(&bytes).unwrap())
+    }
+    
+    #[tokio::test]
+    async fn test_provider_failure_is_generic_in_public_mode() {
+        let (status, body) = provider_failure_body(ErrorVerbosity::Public).await;
+        assert_eq!(status, StatusCode::BAD_GATEWAY);
+        assert_eq!(body["status"], "error");
+        assert_eq!(body["error"], "internal error");
+        assert!(!body.to_string().contains("10.0.3.7"));
+        
+        let correlation_id = body["correlation_id"].as_str().unwrap();
+        assert!(Uuid::parse_str(correlation_id).is_ok());
+    }
+    
+    #[tokio::test]
+    async fn test_provider_failure_includes_detail_in_detailed_mode() {
+        let (status, body) = provider_failure_body(ErrorVerbosity::Detailed).await;
+        assert_eq!(status, StatusCode::BAD_GATEWAY);
+        assert_eq!(
+            body["error"],
+            "openai returned 502 Bad Gateway: upstream connect error to 10.0.3.7:443"
+        );
+        assert!(body["correlation_id"].is_string());
+        
+        // Every failure is logged under its own id
+        let (_, again) = provider_failure_body(ErrorVerbosity::Detailed).await;
+        assert_ne!(again["correlation_id"], body["correlation_id"]);
+    }
+    
+    #[tokio::test]
+    async fn test_invoke_caches_deterministic_responses() {
+        let mut config = create_test_config();
+        config.routes_raw = "chat.fast=openai:gpt-4o-mini".to_string();
+        config.cache_deterministic_responses = true;
+        // Only one provider call is allowed, and dedup must not mask a second one
+        config.provider_rpm = HashMap::from([("openai".to_string(), 1)]);
+        config.provider_queue_ms = 0;
+        config.dedup_window_ms = 0;
+        let server = TestServer::new(create_router(create_test_app_state_with(config))).unwrap();
+        
+        let mut request_body = chat_request_body("What is 2 + 2?");
+        request_body["options"] = json!({"temperature": 0.0});
+        
+        let first: Value = server.post("/v1/invoke").json(&request_body).await.json();
+        assert_eq!(first["data"]["cached"], false);
+        
+        let response = server.post("/v1/invoke").json(&request_body).await;
+        response.assert_status_ok();
+        let second: Value = response.json();
+        assert_eq!(second["data"]["cached"], true);
+        assert_eq!(second["data"]["message"], first["data"]["message"]);
+        assert_ne!(second["data"]["request_id"], first["data"]["request_id"]);
+        
+        // The same prompt at a non-zero temperature bypasses the cache
+        request_body["options"] = json!({"temperature": 0.7});
+        server
+            .post("/v1/invoke")
+            .json(&request_body)
+            .await
+            .assert_status(StatusCode::SERVICE_UNAVAILABLE);
+    }
+    
+    #[tokio::test]
+    async fn test_invoke_with_callback_url_posts_result() {
+        // Mock client endpoint that forwards every callback it receives
+        let (sender, mut received) = tokio::sync::mpsc::unbounded_channel::<Value>();
+        let callback_app = Router::new().route(
+            "/hooks/result",
+            post(move |Json(body): Json<Value>| async move {
+                sender.send(body).unwrap();
+                StatusCode::NO_CONTENT
+            }),
+        );
+        let callback_url = format!("http://{}/hooks/result", spawn_mock(callback_app).await);
+        
+        let mut config = create_test_config();
+        config.routes_raw = "chat.fast=openai:gpt-4o-mini".to_string();
+        config.callback_allow_private_networks = true;
+        let server = TestServer::new(create_router(create_test_app_state_with(config))).unwrap();
+        
+        let mut request_body = chat_request_body("Summarize this batch");
+        request_body["callback_url"] = json!(callback_url);
+        let response = server.post("/v1/invoke").json(&request_body).await;
+        response.assert_status(StatusCode::ACCEPTED);
+        let accepted: Value = response.json();
+        assert_eq!(accepted["data"]["status"], "accepted");
+        let request_id = accepted["data"]["request_id"].clone();
+        assert!(request_id.is_string());
+        
+        let result = tokio::time::timeout(Duration::from_secs(5), received.recv())
+            .await
+            .expect("callback was not delivered")
+            .unwrap();
+        assert_eq!(result["status"], "success");
+        assert_eq!(result["data"]["request_id"], request_id);
+        assert_eq!(result["data"]["model"], "gpt-4o-mini");
+    }
+    
+    /// Log output of subscribers made with `subscriber`
+    #[derive(Clone, Default)]
+    struct CapturedLogs(Arc<Mutex<Vec<u8>>>);
+    
+    impl std::io::Write for CapturedLogs {
+        fn write(&mut self, bytes: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(bytes);
+            Ok(bytes.len())
+        }
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+    
+    impl CapturedLogs {
+        fn subscriber(&self) -> impl tracing::Subscriber + Send + Sync {
+            let writer = self.clone();
+            tracing_subscriber::fmt().with_writer(move || writer.clone()).finish()
+        }
+        
+        /// Wait up to a second for `pattern` to have been logged `count` times
+        async fn wait_for(&self, pattern: &str, count: usize) {
+            for _ in 0..100 {
+                if String::from_utf8_lossy(&self.0.lock().unwrap()).matches(pattern).count() >= count {
+                    return;
+                }
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+            panic!("{:?} not logged {} times", pattern, count);
+        }
+    }
+    
+    #[tokio::test]
+    async fn test_invoke_logs_metadata_without_the_prompt() {
+        let mut config = create_test_config();
+        config.log_sample_rate = 1.0;
+        let server = TestServer::new(create_router(create_test_app_state_with(config))).unwrap();
+        let logs = CapturedLogs::default();
+        let writer = logs.clone();
+        let subscriber = tracing_subscriber::fmt()
+            .with_max_level(Level::DEBUG)
+            .with_ansi(false)
+            .with_writer(move || writer.clone())
+            .finish();
+        let _subscriber = tracing::subscriber::set_default(subscriber);
+        
+        server.post("/v1/invoke").json(&chat_request_body("my private question")).await.assert_status_ok();
+        logs.wait_for("Processing invoke request", 1).await;
+        let output = String::from_utf8_lossy(&logs.0.lock().unwrap()).to_string();
+        assert!(output.contains("op=\"chat\" tier=\"fast\" messages=1"), "{}", output);
+        assert!(!output.contains("my private question"), "{}", output);
+    }
+    
+    #[tokio::test]
+    async fn test_request_timing_is_logged_on_every_path() {
+        let (sender, mut received) = tokio::sync::mpsc::unbounded_channel::<Value>();
+        let callback_app = Router::new().route(
+            "/hooks/result",
+            post(move |Json(body): Json<Value>| async move {
+                sender.send(body).unwrap();
+                StatusCode::NO_CONTENT
+            }),
+        );
+        let callback_url = format!("http://{}/hooks/result", spawn_mock(callback_app).await);
+        
+        let mut config = create_test_config();
+        config.routes_raw = "chat.fast=openai:gpt-4o-mini,image.default=openai:dall-e-3".to_string();
+        config.request_timing = true;
+        config.callback_allow_private_networks = true;
+        let server = TestServer::new(create_router(create_test_app_state_with(config))).unwrap();
+        let logs = CapturedLogs::default();
+        let _subscriber = tracing::subscriber::set_default(logs.subscriber());
+        let server_timing = |response: &axum_test::TestResponse| response.header("Server-Timing").to_str().unwrap().to_string();
+        
+        // Image requests
+        let response = server.post("/v1/invoke").json(&json!({ "op": "image", "input": { "prompt": "red fox" } })).await;
+        response.assert_status_ok();
+        assert!(server_timing(&response).contains("provider;dur="));
+        logs.wait_for("request timing", 1).await;
+        
+        // Requests that fail part-way
+        let response = server.post("/v1/invoke").json(&json!({ "op": "image", "input": {} })).await;
+        response.assert_status(StatusCode::BAD_REQUEST);
+        logs.wait_for("request timing", 2).await;
+        
+        // Attachments are processed in their own phase
+        let mut request_body = chat_request_body("Read this");
+        request_body["attachments"] = json!([{
+            "name": "notes.txt",
+            "content_type": "text/plain",
+            "url": "data:text/plain,Meeting%20notes"
+        }]);
+        let response = server.post("/v1/invoke").json(&request_body).await;
+        response.assert_status_ok();
+        assert!(server_timing(&response).contains("files;dur="));
+        logs.wait_for("request timing", 3).await;
+        
+        // Streams are summed up once the last event is sent
+        let mut request_body = chat_request_body("Stream please");
+        request_body["stream"] = json!(true);
+        let response = server.post("/v1/invoke").json(&request_body).await;
+        response.assert_status_ok();
+        assert!(response.text().ends_with("data: {\"done\":true}\n\n"));
+        logs.wait_for("request timing", 4).await;
+        logs.wait_for("\"stream\":", 1).await;
+        
+        // Callbacks once the result is delivered
+        let mut request_body = chat_request_body("Call me back");
+        request_body["callback_url"] = json!(callback_url);
+        let response = server.post("/v1/invoke").json(&request_body).await;
+        response.assert_status(StatusCode::ACCEPTED);
+        assert!(server_timing(&response).contains("auth;dur="));
+        tokio::time::timeout(Duration::from_secs(5), received.recv()).await.unwrap().unwrap();
+        logs.wait_for("request timing", 5).await;
+    }
+    
+    #[tokio::test]
+    async fn test_stream_requests_beyond_limit_are_rejected() {
+        let mut config = create_test_config();
+        config.routes_raw = "chat.fast=openai:gpt-4o-mini".to_string();
+        config.max_concurrent_streams = 2;
+        let state = create_test_app_state_with(config);
+        let server = TestServer::new(create_router(state.clone())).unwrap();
+        
+        // Two streams are open
+        let open: Vec<_> = (0..2)
+            .map(|_| state.stream_slots.clone().try_acquire_owned().unwrap())
+            .collect();
+        
+        let mut request_body = chat_request_body("Stream me a story");
+        request_body["stream"] = json!(true);
+        let response = server.post("/v1/invoke").json(&request_body).await;
+        response.assert_status(StatusCode::SERVICE_UNAVAILABLE);
+        let body: Value = response.json();
+        assert_eq!(body["error"], "Too many concurrent streams, please retry later");
+        
+        // Non-streaming invokes are not counted against the stream limit
+        server.post("/v1/invoke").json(&chat_request_body("Hi")).await.assert_status_ok();
+        
+        // A closed stream frees its slot, and a finished stream returns it
+        drop(open);
+        for _ in 0..3 {
+            let response = server.post("/v1/invoke").json(&request_body).await;
+            response.assert_status_ok();
+            assert_eq!(response.header("content-type"), "text/event-stream");
+            assert!(response.text().starts_with("data: {"), "{}", response.text());
+        }
+        assert_eq!(state.stream_slots.available_permits(), 2);
+    }
+    
+    #[tokio::test]
+    async fn test_invoke_streams_provider_deltas() {
+        let mut config = create_test_config();
+        config.routes_raw = "chat.fast=openai:gpt-4o-mini,chat.smart=anthropic:claude-3-5-haiku".to_string();
+        let state = create_test_app_state_with(config);
+        let convex_service = state.convex_service.clone();
+        let server = TestServer::new(create_router(state)).unwrap();
+        
+        let mut request_body = chat_request_body("Tell me a story");
+        request_body["stream"] = json!(true);
+        request_body["session_id"] = json!("streamed-chat");
+        for tier in ["fast", "smart"] {
+            request_body["tier"] = json!(tier);
+            let response = server.post("/v1/invoke").json(&request_body).await;
+            response.assert_status_ok();
+            assert_eq!(response.header("content-type"), "text/event-stream");
+            assert_eq!(
+                response.text(),
+                "data: {\"delta\":\"Echo: \"}\n\n\
+                 data: {\"delta\":\"Tell \"}\n\n\
+                 data: {\"delta\":\"me \"}\n\n\
+                 data: {\"delta\":\"a \"}\n\n\
+                 data: {\"delta\":\"story\"}\n\n\
+                 data: {\"done\":true}\n\n",
+                "{}",
+                tier
+            );
+        }
+        
+        // The assembled reply is kept as chat history
+        let mut messages = Vec::new();
+        for _ in 0..50 {
+            messages = convex_service.get_chat_messages("streamed-chat").await.unwrap();
+            if messages.len() == 4 {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert_eq!(messages.len(), 4);
+        assert_eq!(messages[1].content, "Echo: Tell me a story");
+    }
+    
+    #[tokio::test]
+    async fn test_invoke_rejects_private_callback_url() {
+        let mut config = create_test_config();
+        config.max_attachments = 1;
+        let server = TestServer::new(create_router(create_test_app_state_with(config))).unwrap();
+        
+        let mut request_body = chat_request_body("Hello");
+        request_body["callback_url"] = json!("http://169.254.169.254/latest/meta-data");
+        let response = server.post("/v1/invoke").json(&request_body).await;
+        response.assert_status(StatusCode::BAD_REQUEST);
+        let body: Value = response.json();
+        assert_eq!(body["error"], "callback_url must point to a public address");
+        
+        // The callback is checked before the attachments are looked at
+        let attachment = json!({
+            "name": "notes.txt",
+            "content_type": "text/plain",
+            "url": "data:text/plain;base64,SGVsbG8gV29ybGQ="
+        });
+        request_body["attachments"] = json!([attachment, attachment]);
+        let response = server.post("/v1/invoke").json(&request_body).await;
+        response.assert_status(StatusCode::BAD_REQUEST);
+        let body: Value = response.json();
+        assert_eq!(body["error"], "callback_url must point to a public address");
+    }
+    
+    #[tokio::test]
+    async fn test_invoke_routes_images_to_vision_fallback() {
+        let mut config = create_test_config();
+        config.routes_raw = "chat.fast=groq:llama-3.1-8b-instant,chat.vision=openai:gpt-4o".to_string();
+        let server = TestServer::new(create_router(create_test_app_state_with(config))).unwrap();
+        
+        let mut request_body = chat_request_body("What is in this picture?");
+        request_body["attachments"] = json!([{
+            "name": "photo.png",
+            "url": "data:image/png;base64,iVBORw0KGgo=",
+            "content_type": "image/png",
+            "size": 8
+        }]);
+        let response = server.post("/v1/invoke").json(&request_body).await;
+        response.assert_status_ok();
+        let body: Value = response.json();
+        assert_eq!(body["data"]["provider"], "openai");
+        assert_eq!(body["data"]["model"], "gpt-4o");
+        
+        // Text-only requests keep the requested tier
+        let body: Value = server
+            .post("/v1/invoke")
+            .json(&chat_request_body("Just text"))
+            .await
+            .json();
+        assert_eq!(body["data"]["model"], "llama-3.1-8b-instant");
+    }
+    
+    #[tokio::test]
+    async fn test_invoke_rejects_blocked_models() {
+        let mut config = create_test_config();
+        config.routes_raw = "chat.fast=groq:llama-3.1-8b-instant,chat.smart=openai:gpt-4o,chat.vision=openai:gpt-4o".to_string();
+        config.blocked_models = vec!["openai:gpt-4o".to_string()];
+        let server = TestServer::new(create_router(create_test_app_state_with(config))).unwrap();
+        
+        let mut request_body = chat_request_body("Hello");
+        request_body["tier"] = json!("smart");
+        let response = server.post("/v1/invoke").json(&request_body).await;
+        response.assert_status(StatusCode::FORBIDDEN);
+        let body: Value = response.json();
+        assert_eq!(body["error"], "model not permitted");
+        
+        // Also when the image fallback is what resolves to a blocked model
+        let mut request_body = chat_request_body("What is in this picture?");
+        request_body["attachments"] = json!([{
+            "name": "photo.png",
+            "url": "data:image/png;base64,iVBORw0KGgo=",
+            "content_type": "image/png",
+            "size": 8
+        }]);
+        server.post("/v1/invoke").json(&request_body).await.assert_status(StatusCode::FORBIDDEN);
+        
+        let response = server.post("/v1/invoke").json(&chat_request_body("Hello")).await;
+        response.assert_status_ok();
+        let body: Value = response.json();
+        assert_eq!(body["data"]["model"], "llama-3.1-8b-instant");
+    }
+    
+    #[tokio::test]
+    async fn test_invoke_rejects_images_without_vision_route() {
+        let mut config = create_test_config();
+        config.routes_raw = "chat.fast=groq:llama-3.1-8b-instant".to_string();
+        let server = TestServer::new(create_router(create_test_app_state_with(config))).unwrap();
+        
+        let mut request_body = chat_request_body("What is in this picture?");
+        request_body["attachments"] = json!([{
+            "name": "photo.png",
+            "url": "https://example.com/photo.png",
+            "content_type": "image/png",
+            "size": null
+        }]);
+        let response = server.post("/v1/invoke").json(&request_body).await;
+        response.assert_status(StatusCode::BAD_REQUEST);
+        let body: Value = response.json();
+        assert!(body["error"].as_str().unwrap().contains("chat.vision"));
+    }
+    
+    #[tokio::test]
+    async fn test_search_runs_once_per_session_for_repeated_turns() {
+        // SearXNG stub counting upstream searches
+        let searches = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let counter = searches.clone();
+        let searxng = Router::new().route(
+            "/search",
+            get(move || {
+                counter.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+                async { Json(json!([{"title": "Forecast", "url": "https://example.com", "content": "Sunny"}])) }
+            }),
+        );
+        let base_url = format!("http://{}", spawn_mock(searxng).await);
+        
+        let mut config = create_test_config();
+        config.search.enabled = true;
+        config.search.cache_duration = 0;
+        config.search.tavily.api_key = Secret::default();
+        config.search.brave.api_key = Secret::default();
+        config.search.searxng.enabled = true;
+        config.search.searxng.base_url = base_url;
+        config.dedup_window_ms = 0;
+        let server = TestServer::new(create_router(create_test_app_state_with(config))).unwrap();
+        
+        let mut request_body = chat_request_body("What's the weather in Paris today?");
+        request_body["enable_search"] = json!(true);
+        request_body["session_id"] = json!("conversation-1");
+        for _ in 0..2 {
+            server.post("/v1/invoke").json(&request_body).await.assert_status_ok();
+        }
+        assert_eq!(searches.load(std::sync::atomic::Ordering::SeqCst), 1);
+        
+        // A different conversation searches on its own
+        request_body["session_id"] = json!("conversation-2");
+        server.post("/v1/invoke").json(&request_body).await.assert_status_ok();
+        assert_eq!(searches.load(std::sync::atomic::Ordering::SeqCst), 2);
+    }
+    
+    #[tokio::test]
+    async fn test_invoke_reports_phase_timings() {
+        let searxng = Router::new().route(
+            "/search",
+            get(|| async {
+                tokio::time::sleep(Duration::from_millis(20)).await;
+                Json(json!([{"title": "Forecast", "url": "https://example.com", "content": "Sunny"}]))
+            }),
+        );
+        let base_url = format!("http://{}", spawn_mock(searxng).await);
+        
+        let mut config = create_test_config();
+        config.request_timing = true;
+        config.search.enabled = true;
+        config.search.tavily.api_key = Secret::default();
+        config.search.brave.api_key = Secret::default();
+        config.search.searxng.enabled = true;
+        config.search.searxng.base_url = base_url;
+        let server = TestServer::new(create_router(create_test_app_state_with(config.clone()))).unwrap();
+        
+        let mut request_body = chat_request_body("What's the weather in Paris today?");
+        request_body["enable_search"] = json!(true);
+        let response = server.post("/v1/invoke").json(&request_body).await;
+        response.assert_status_ok();
+        let header = response.header("Server-Timing");
+        let phases: HashMap<&str, f64> = header
+            .to_str()
+            .unwrap()
+            .split(", ")
+            .map(|entry| {
+                let (phase, duration) = entry.split_once(";dur=").unwrap();
+                (phase, duration.parse().unwrap())
+            })
+            .collect();
+        for phase in ["auth", "prepare", "search", "provider"] {
+            assert!(phases.get(phase).is_some_and(|ms| *ms > 0.0), "{}: {:?}", phase, phases);
+        }
+        assert!(phases["search"] >= 20.0, "{:?}", phases);
+        
+        // Off by default
+        config.request_timing = false;
+        let server = TestServer::new(create_router(create_test_app_state_with(config))).unwrap();
+        let response = server.post("/v1/invoke").json(&request_body).await;
+        assert!(response.maybe_header("Server-Timing").is_none());
+    }
+    
+    #[tokio::test]
+    async fn test_search_only_runs_for_enabled_operations() {
+        let searches = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let counter = searches.clone();
+        let searxng = Router::new().route(
+            "/search",
+            get(move || {
+                counter.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+                async { Json(json!([{"title": "Docs", "url": "https://example.com", "content": "fn main"}])) }
+            }),
+        );
+        let base_url = format!("http://{}", spawn_mock(searxng).await);
+        
+        let mut config = create_test_config();
+        config.search.enabled = true;
+        config.search.enabled_operations = vec!["chat".to_string()];
+        config.routes_raw = "chat.fast=openai:gpt-4o-mini,fim.fast=mistral:codestral-latest".to_string();
+        config.search.cache_duration = 0;
+        config.search.tavily.api_key = Secret::default();
+        config.search.brave.api_key = Secret::default();
+        config.search.searxng.enabled = true;
+        config.search.searxng.base_url = base_url;
+        config.dedup_window_ms = 0;
+        let server = TestServer::new(create_router(create_test_app_state_with(config))).unwrap();
+        
+        let mut request_body = chat_request_body("How do I read a file in Rust?");
+        request_body["op"] = json!("fim");
+        request_body["enable_search"] = json!(true);
+        server.post("/v1/invoke").json(&request_body).await.assert_status_ok();
+        assert_eq!(searches.load(std::sync::atomic::Ordering::SeqCst), 0);
+        
+        request_body["op"] = json!("chat");
+        server.post("/v1/invoke").json(&request_body).await.assert_status_ok();
+        assert_eq!(searches.load(std::sync::atomic::Ordering::SeqCst), 1);
+    }
+    
+    #[tokio::test]
+    async fn test_search_all_failed_behaviors() {
+        // Every configured provider is unreachable
+        let closed = closed_addr();
+        let mut config = create_test_config();
+        config.search.enabled = true;
+        config.search.tavily.api_key = Secret::from("tavily-key");
+        config.search.tavily.base_url = format!("http://{}", closed);
+        config.search.brave.api_key = Secret::from("brave-key");
+        config.search.brave.base_url = format!("http://{}", closed);
+        config.search.searxng.enabled = true;
+        config.search.searxng.base_url = format!("http://{}", closed);
+        
+        let conversation = || {
+            vec![ChatMessage {
+                role: MessageRole::User,
+                content: "Latest AI news?".to_string(),
+            }]
+        };
+        
+        // Proceed: the conversation goes out unchanged
+        config.search.all_failed_behavior = SearchFailedBehavior::Proceed;
+        let mut messages = conversation();
+        add_search_context(&create_test_app_state_with(config.clone()), None, &mut messages).await.unwrap();
+        assert_eq!(messages.len(), 1);
+        
+        // Degrade: the model is told live information was unavailable
+        config.search.all_failed_behavior = SearchFailedBehavior::Degrade;
+        let mut messages = conversation();
+        add_search_context(&create_test_app_state_with(config.clone()), None, &mut messages).await.unwrap();
+        assert_eq!(messages.len(), 2);
+        assert_eq!(messages[0].role, MessageRole::System);
+        assert_eq!(messages[0].content, SEARCH_UNAVAILABLE_NOTE);
+        assert_eq!(messages[1].content, "Latest AI news?");
+        
+        // Error: the request fails
+        config.search.all_failed_behavior = SearchFailedBehavior::Error;
+        let server = TestServer::new(create_router(create_test_app_state_with(config))).unwrap();
+        let mut request_body = chat_request_body("Latest AI news?");
+        request_body["enable_search"] = json!(true);
+        let response = server.post("/v1/invoke").json(&request_body).await;
+        response.assert_status(StatusCode::SERVICE_UNAVAILABLE);
+        let body: Value = response.json();
+        assert_eq!(body["error"], "Web search is unavailable, please retry later");
+        
+        // Without enable_search the failing providers are never consulted
+        server.post("/v1/invoke").json(&chat_request_body("Hi")).await.assert_status_ok();
+    }
+    
+    #[tokio::test]
+    async fn test_invoke_with_search_while_search_disabled() {
+        let mut request_body = chat_request_body("Latest AI news?");
+        request_body["enable_search"] = json!(true);
+        
+        // Silent (default): proceed without search
+        let mut config = create_test_config();
+        config.search.enabled = false;
+        let server = TestServer::new(create_router(create_test_app_state_with(config.clone()))).unwrap();
+        server.post("/v1/invoke").json(&request_body).await.assert_status_ok();
+        
+        // Error: the client is told search is unavailable
+        config.search.disabled_behavior = SearchDisabledBehavior::Error;
+        let server = TestServer::new(create_router(create_test_app_state_with(config))).unwrap();
+        let response = server.post("/v1/invoke").json(&request_body).await;
+        response.assert_status(StatusCode::CONFLICT);
+        let body: Value = response.json();
+        assert!(body["error"].as_str().unwrap().contains("search is disabled"));
+        
+        // Requests that don't ask for search are unaffected
+        server.post("/v1/invoke").json(&chat_request_body("Hello")).await.assert_status_ok();
+    }
+    
+    #[tokio::test]
+    async fn test_invoke_rejects_prompt_over_total_cap() {
+        let mut config = create_test_config();
+        config.system_prompt = "x".repeat(90);
+        config.max_total_prompt_chars = 100;
+        let server = TestServer::new(create_router(create_test_app_state_with(config))).unwrap();
+        
+        // 90 + 5 fits under the cap
+        server.post("/v1/invoke").json(&chat_request_body("Hello")).await.assert_status_ok();
+        
+        // The user message alone fits, but the injected system prompt pushes it over
+        let response = server.post("/v1/invoke").json(&chat_request_body(&"y".repeat(20))).await;
+        response.assert_status(StatusCode::BAD_REQUEST);
+        let body: Value = response.json();
+        assert_eq!(body["error"], "Prompt is 110 characters, exceeding the limit of 100");
+    }
+    
+    #[tokio::test]
+    async fn test_invoke_content_filter() {
+        let mut config = create_test_config();
+        config.content_blocklist = vec!["buy followers".to_string()];
+        let server = TestServer::new(create_router(create_test_app_state_with(config))).unwrap();
+        
+        let response = server.post("/v1/invoke").json(&chat_request_body("Where do I BUY followers?")).await;
+        response.assert_status(StatusCode::UNPROCESSABLE_ENTITY);
+        let body: Value = response.json();
+        assert_eq!(body["error"], "Request blocked by content filter");
+        
+        server.post("/v1/invoke").json(&chat_request_body("How do I grow an audience?")).await.assert_status_ok();
+    }
+    
+    #[tokio::test]
+    async fn test_invoke_applies_response_strippers() {
+        let mut config = create_test_config();
+        config.response_strippers = vec!["echo:".to_string(), r"/\s*Thanks!$/".to_string()];
+        let server = TestServer::new(create_router(create_test_app_state_with(config))).unwrap();
+        
+        let response = server.post("/v1/invoke").json(&chat_request_body("Rust is fast. Thanks!")).await;
+        response.assert_status_ok();
+        let body: Value = response.json();
+        assert_eq!(body["data"]["message"], "Rust is fast.");
+    }
+    
+    #[tokio::test]
+    async fn test_invoke_rejects_disabled_operation() {
+        let mut config = create_test_config();
+        config.routes_raw = "chat.fast=openai:gpt-4o-mini,fim.fast=mistral:codestral".to_string();
+        config.enabled_operations = vec!["chat".to_string()];
+        let server = TestServer::new(create_router(create_test_app_state_with(config))).unwrap();
+        
+        server.post("/v1/invoke").json(&chat_request_body("Hello")).await.assert_status_ok();
+        
+        // Rejected even though a fim route exists
+        let mut request_body = chat_request_body("fn main() {");
+        request_body["op"] = json!("fim");
+        let response = server.post("/v1/invoke").json(&request_body).await;
+        response.assert_status(StatusCode::FORBIDDEN);
+        let body: Value = response.json();
+        assert_eq!(body["error"], "operation not enabled");
+    }
+    
+    #[tokio::test]
+    async fn test_invoke_generates_images() {
+        let mut config = create_test_config();
+        config.routes_raw = "image.default=openai:dall-e-3,image.cheap=groq:llama-3.1-8b-instant".to_string();
+        let server = TestServer::new(create_router(create_test_app_state_with(config))).unwrap();
+        
+        let response = server
+            .post("/v1/invoke")
+            .json(&json!({
+                "op": "image",
+                "input": { "prompt": "red fox", "size": "1024x1024", "quality": "hd" }
+            }))
+            .await;
+        response.assert_status_ok();
+        let body: Value = response.json();
+        assert_eq!(body["data"]["provider"], "openai");
+        assert_eq!(body["data"]["model"], "dall-e-3");
+        assert_eq!(body["data"]["images"][0]["url"], "https://images.example/red-fox.png");
+        
+        // The route exists, but the provider cannot generate images
+        let response = server
+            .post("/v1/invoke")
+            .json(&json!({ "op": "image", "tier": "cheap", "input": { "prompt": "red fox" } }))
+            .await;
+        response.assert_status(StatusCode::BAD_REQUEST);
+        let body: Value = response.json();
+        assert_eq!(body["error"], "operation not supported");
+        
+        let response = server.post("/v1/invoke").json(&json!({ "op": "image", "input": {} })).await;
+        response.assert_status(StatusCode::BAD_REQUEST);
+        let body: Value = response.json();
+        assert_eq!(body["error"], "input.prompt is required");
+    }
+    
+    #[tokio::test]
+    async fn test_invoke_rejects_unsupported_language() {
+        let server = TestServer::new(create_router(create_test_app_state())).unwrap();
+        
+        let mut request_body = chat_request_body("Bonjour");
+        request_body["language"] = json!("fr");
+        server.post("/v1/invoke").json(&request_body).await.assert_status_ok();
+        
+        request_body["language"] = json!("xx");
+        let response = server.post("/v1/invoke").json(&request_body).await;
+        response.assert_status(StatusCode::BAD_REQUEST);
+        let body: Value = response.json();
+        assert!(body["error"].as_str().unwrap().contains("Unsupported language"));
+    }
+    
+    #[tokio::test]
+    async fn test_invoke_deduplicates_identical_requests() {
+        let state = create_test_app_state();
+        let token = registered_user_token(&state, "dedup@example.com").await;
+        let convex_service = state.convex_service.clone();
+        let server = TestServer::new(create_router(state)).unwrap();
+        
+        let mut request_body = chat_request_body("Hello twice");
+        request_body["session_id"] = json!("dedup-chat");
+        let first: Value = server
+            .post("/v1/invoke")
+            .add_header(AUTHORIZATION, bearer(&token))
+            .json(&request_body)
+            .await
+            .json();
+        let second: Value = server
+            .post("/v1/invoke")
+            .add_header(AUTHORIZATION, bearer(&token))
+            .json(&request_body)
+            .await
+            .json();
+        
+        // The duplicate shares the original result rather than being processed again
+        assert_eq!(first["data"]["request_id"], second["data"]["request_id"]);
+        
+        // and the turn is kept in history once (messages are logged in the background)
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        let messages = convex_service.get_chat_messages("dedup-chat").await.unwrap();
+        let contents: Vec<&str> = messages.iter().map(|message| message.content.as_str()).collect();
+        assert_eq!(contents, ["Hello twice", "Echo: Hello twice"]);
+        
+        let different: Value = server
+            .post("/v1/invoke")
+            .add_header(AUTHORIZATION, bearer(&token))
+            .json(&chat_request_body("Something else"))
+            .await
+            .json();
+        assert_ne!(first["data"]["request_id"], different["data"]["request_id"]);
+    }
+    
+    #[test]
+    fn test_build_log_filter_precedence() {
+        // RUST_LOG wins when set
+        assert_eq!(build_log_filter(Some("debug"), "warn").to_string(), "debug");
+        assert_eq!(
+            build_log_filter(Some("rust_ai=trace"), "warn").to_string(),
+            "rust_ai=trace"
+        );
+        
+        // Configured level applies when RUST_LOG is unset or blank
+        assert_eq!(build_log_filter(None, "warn").to_string(), "warn");
+        assert_eq!(build_log_filter(Some("  "), "error").to_string(), "error");
+        
+        // Invalid values fall through to the next source
+        assert_eq!(build_log_filter(Some("not a [valid filter"), "warn").to_string(), "warn");
+        assert_eq!(build_log_filter(None, "not a [valid filter").to_string(), "info");
+    }
+}
@@ -0,0 +1,536 @@
+//! Provider Payload Module
+//!
+//! Builds provider-specific chat request bodies from the normalized
+//! conversation and options, and reads the assistant text back out of
+//! provider responses. These are pure functions with no HTTP
+//! involved, so the exact wire shape for each provider can be tested
+//! in isolation.
+//!
+//! - OpenAI-compatible providers (OpenAI, Mistral, xAI, Groq, OpenRouter,
+//!   Meta, Cloudflare) take system messages inline
+//! - Anthropic takes system prompts as a top-level `system` field and
+//!   requires `max_tokens`; with `PROMPT_CACHING` the system block is
+//!   marked cacheable
+//! - Reasoning models take `max_completion_tokens` instead of `max_tokens`
+//!   and may return their reasoning separately from the answer
+//!
+//! `build_chat_payload` is the single entry point the providers use;
+//! `InvokeRequest::to_provider_json` applies the whole pipeline to a
+//! client request, showing exactly what would be sent upstream.
+
+use serde_json::{json, Map, Value};
+
+use crate::config::Config;
+use crate::prompt::build_conversation;
+use crate::routing::is_reasoning_model;
+use crate::types::{ChatMessage, InvokeOptions, InvokeRequest, MessageRole, Provider, RouteTarget};
+
+/// `max_tokens` sent to Anthropic when the client does not set one
+pub const ANTHROPIC_DEFAULT_MAX_TOKENS: u32 = 1024;
+
+/// Highest temperature Anthropic accepts
+const ANTHROPIC_MAX_TEMPERATURE: f32 = 1.0;
+
+/// Build the chat request body for `provider`
+///
+/// Models listed in `reasoning_models` get reasoning model parameters on
+/// OpenAI-compatible providers; `prompt_caching` marks Anthropic system
+/// prompts cacheable.
+pub fn build_chat_payload(
+    provider: &Provider,
+    messages: &[ChatMessage],
+    options: Option<&InvokeOptions>,
+    model: &str,
+    reasoning_models: &[String],
+    prompt_caching: bool,
+) -> Value {
+    match provider {
+        Provider::Anthropic => {
+            let mut payload = build_anthropic_payload(messages, options, model);
+            if prompt_caching {
+                use_prompt_caching(&mut payload);
+            }
+            payload
+        }
+        _ => {
+            let mut payload = build_openai_payload(messages, options, model);
+            if is_reasoning_model(model, reasoning_models) {
+                use_reasoning_params(&mut payload);
+            }
+            payload
+        }
+    }
+}
+
+// Debugging aid for library users; the server itself never calls it
+#[allow(dead_code)]
+impl InvokeRequest {
+    /// Provider-native chat body this request would be sent as on `route`
+    ///
+    /// Builds the conversation (server system prompt, language directive),
+    /// fills unset options from the route defaults and applies reasoning
+    /// model parameters, as dispatch does, but sends nothing. Web search
+    /// context is not included.
+    ///
+    /// # Errors
+    /// A client-facing message when the messages or language are invalid
+    pub fn to_provider_json(&self, route: &RouteTarget, config: &Config) -> Result<Value, String> {
+        self.parse_messages()?;
+        let messages = build_conversation(config, self)?;
+        let options = self
+            .options
+            .clone()
+            .unwrap_or_default()
+            .with_defaults(&route.defaults);
+
+        Ok(build_chat_payload(
+            &route.provider,
+            &messages,
+            Some(&options),
+            &route.model,
+            &config.reasoning_models,
+            config.anthropic.prompt_caching,
+        ))
+    }
+}
+
+/// Build an OpenAI chat completions request body
+///
+/// Messages are passed through in order, including system messages.
+/// Unset options are omitted.
+pub fn build_openai_payload(messages: &[ChatMessage], options: Option<&InvokeOptions>, model: &str) -> Value {
+    let mut payload = Map::new();
+    payload.insert("model".to_string(), json!(model));
+    payload.insert(
+        "messages".to_string(),
+        Value::Array(messages.iter().map(message_json).collect()),
+    );
+
+    if let Some(options) = options {
+        if let Some(temperature) = options.temperature {
+            payload.insert("temperature".to_string(), temperature_json(temperature));
+        }
+        if let Some(max_tokens) = options.max_tokens {
+            payload.insert("max_tokens".to_string(), json!(max_tokens));
+        }
+    }
+
+    Value::Object(payload)
+}
+
+/// Switch an OpenAI request body to reasoning model parameters
+///
+/// Reasoning models reject `max_tokens`; the same limit is sent as
+/// `max_completion_tokens` instead.
+pub fn use_reasoning_params(payload: &mut Value) {
+    if let Some(payload) = payload.as_object_mut() {
+        if let Some(max_tokens) = payload.remove("max_tokens") {
+            payload.insert("max_completion_tokens".to_string(), max_tokens);
+        }
+    }
+}
+
+/// Build an Anthropic messages request body
+///
+/// System messages are hoisted into the top-level `system` field (joined
+/// by blank lines), `max_tokens` defaults to `ANTHROPIC_DEFAULT_MAX_TOKENS`
+/// and temperature is capped at Anthropic's maximum of 1.0.
+pub fn build_anthropic_payload(messages: &[ChatMessage], options: Option<&InvokeOptions>, model: &str) -> Value {
+    let (system, conversation): (Vec<&ChatMessage>, Vec<&ChatMessage>) = messages
+        .iter()
+        .partition(|message| message.role == MessageRole::System);
+
+    let mut payload = Map::new();
+    payload.insert("model".to_string(), json!(model));
+
+    if !system.is_empty() {
+        let system_prompt = system
+            .iter()
+            .map(|message| message.content.as_str())
+            .collect::<Vec<_>>()
+            .join("\n\n");
+        payload.insert("system".to_string(), json!(system_prompt));
+    }
+
+    payload.insert(
+        "messages".to_string(),
+        Value::Array(conversation.into_iter().map(message_json).collect()),
+    );
+
+    let max_tokens = options
+        .and_then(|options| options.max_tokens)
+        .unwrap_or(ANTHROPIC_DEFAULT_MAX_TOKENS);
+    payload.insert("max_tokens".to_string(), json!(max_tokens));
+
+    if let Some(temperature) = options.and_then(|options| options.temperature) {
+        payload.insert(
+            "temperature".to_string(),
+            temperature_json(temperature.min(ANTHROPIC_MAX_TEMPERATURE)),
+        );
+    }
+
+    Value::Object(payload)
+}
+
+/// Mark the system prompt of an Anthropic request body as cacheable
+///
+/// The `system` string becomes a single text block tagged with an
+/// ephemeral `cache_control`, so repeated requests sharing the system
+/// prompt and injected context can be served from Anthropic's prompt
+/// cache. Bodies without a system prompt are left unchanged.
+pub fn use_prompt_caching(payload: &mut Value) {
+    let Some(system) = payload.get_mut("system") else {
+        return;
+    };
+    if let Some(text) = system.as_str() {
+        *system = json!([{
+            "type": "text",
+            "text": text,
+            "cache_control": {"type": "ephemeral"}
+        }]);
+    }
+}
+
+/// Assistant text from a provider's chat response
+///
+/// OpenAI-compatible responses carry it in `choices[0].message.content`;
+/// Anthropic returns a list of content blocks whose text blocks are joined.
+/// `None` when the response has no text.
+pub fn completion_text(provider: &Provider, response: &Value) -> Option<String> {
+    match provider {
+        Provider::Anthropic => {
+            let blocks = response["content"].as_array()?;
+            let text: Vec<&str> = blocks
+                .iter()
+                .filter(|block| block["type"] == "text")
+                .filter_map(|block| block["text"].as_str())
+                .collect();
+            (!text.is_empty()).then(|| text.concat())
+        }
+        _ => response["choices"][0]["message"]["content"]
+            .as_str()
+            .map(str::to_string),
+    }
+}
+
+/// Reasoning returned alongside the answer in a provider's chat response
+///
+/// OpenAI-compatible providers serving reasoning models put it in
+/// `choices[0].message.reasoning_content` (or `reasoning`); Anthropic
+/// returns `thinking` content blocks. `None` when the response has none.
+pub fn completion_reasoning(provider: &Provider, response: &Value) -> Option<String> {
+    match provider {
+        Provider::Anthropic => {
+            let blocks = response["content"].as_array()?;
+            let thinking: Vec<&str> = blocks
+                .iter()
+                .filter(|block| block["type"] == "thinking")
+                .filter_map(|block| block["thinking"].as_str())
+                .collect();
+            (!thinking.is_empty()).then(|| thinking.concat())
+        }
+        _ => {
+            let message = &response["choices"][0]["message"];
+            message["reasoning_content"]
+                .as_str()
+                .or_else(|| message["reasoning"].as_str())
+                .filter(|reasoning| !reasoning.is_empty())
+                .map(str::to_string)
+        }
+    }
+}
+
+/// Assistant text added by one chunk of a streaming chat response
+///
+/// OpenAI-compatible chunks carry it in `choices[0].delta.content`;
+/// Anthropic sends it in `content_block_delta` events as `delta.text`.
+/// `None` for chunks without text (role announcements, pings, stop events).
+pub fn stream_delta_text(provider: &Provider, chunk: &Value) -> Option<String> {
+    let text = match provider {
+        Provider::Anthropic if chunk["type"] == "content_block_delta" => chunk["delta"]["text"].as_str(),
+        Provider::Anthropic => None,
+        _ => chunk["choices"][0]["delta"]["content"].as_str(),
+    };
+    text.filter(|text| !text.is_empty()).map(str::to_string)
+}
+
+/// `{ "role", "content" }` object for a chat message
+fn message_json(message: &ChatMessage) -> Value {
+    json!({
+        "role": message.role,
+        "content": message.content,
+    })
+}
+
+/// Temperature as JSON without f32 widening noise (0.7 stays 0.7)
+fn temperature_json(temperature: f32) -> Value {
+    temperature
+        .to_string()
+        .parse::<f64>()
+        .map(|value| json!(value))
+        .unwrap_or_else(|_| json!(temperature))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(role: MessageRole, content: &str) -> ChatMessage {
+        ChatMessage {
+            role,
+            content: content.to_string(),
+        }
+    }
+
+    fn conversation() -> Vec<ChatMessage> {
+        vec![
+            message(MessageRole::System, "Be concise."),
+            message(MessageRole::User, "Hi"),
+            message(MessageRole::Assistant, "Hello!"),
+            message(MessageRole::System, "Answer in English."),
+            message(MessageRole::User, "What is Rust?"),
+        ]
+    }
+
+    fn options(temperature: Option<f32>, max_tokens: Option<u32>) -> InvokeOptions {
+        InvokeOptions {
+            temperature,
+            max_tokens,
+        }
+    }
+
+    #[test]
+    fn test_openai_payload_shape() {
+        let payload = build_openai_payload(&conversation(), Some(&options(Some(0.7), Some(256))), "gpt-4o-mini");
+
+        assert_eq!(
+            payload,
+            json!({
+                "model": "gpt-4o-mini",
+                "messages": [
+                    {"role": "system", "content": "Be concise."},
+                    {"role": "user", "content": "Hi"},
+                    {"role": "assistant", "content": "Hello!"},
+                    {"role": "system", "content": "Answer in English."},
+                    {"role": "user", "content": "What is Rust?"}
+                ],
+                "temperature": 0.7,
+                "max_tokens": 256
+            })
+        );
+    }
+
+    #[test]
+    fn test_openai_payload_omits_unset_options() {
+        let messages = [message(MessageRole::User, "Hi")];
+
+        let payload = build_openai_payload(&messages, None, "gpt-4o-mini");
+        assert_eq!(
+            payload,
+            json!({
+                "model": "gpt-4o-mini",
+                "messages": [{"role": "user", "content": "Hi"}]
+            })
+        );
+
+        let payload = build_openai_payload(&messages, Some(&options(None, Some(10))), "gpt-4o-mini");
+        assert!(payload.get("temperature").is_none());
+        assert_eq!(payload["max_tokens"], 10);
+    }
+
+    #[test]
+    fn test_anthropic_payload_hoists_system_messages() {
+        let payload = build_anthropic_payload(&conversation(), Some(&options(Some(0.2), Some(512))), "claude-3-5-sonnet");
+
+        assert_eq!(
+            payload,
+            json!({
+                "model": "claude-3-5-sonnet",
+                "system": "Be concise.\n\nAnswer in English.",
+                "messages": [
+                    {"role": "user", "content": "Hi"},
+                    {"role": "assistant", "content": "Hello!"},
+                    {"role": "user", "content": "What is Rust?"}
+                ],
+                "max_tokens": 512,
+                "temperature": 0.2
+            })
+        );
+    }
+
+    #[test]
+    fn test_anthropic_payload_defaults_and_caps_options() {
+        let messages = [message(MessageRole::User, "Hi")];
+
+        let payload = build_anthropic_payload(&messages, None, "claude-3-5-haiku");
+        assert_eq!(
+            payload,
+            json!({
+                "model": "claude-3-5-haiku",
+                "messages": [{"role": "user", "content": "Hi"}],
+                "max_tokens": ANTHROPIC_DEFAULT_MAX_TOKENS
+            })
+        );
+
+        let payload = build_anthropic_payload(&messages, Some(&options(Some(1.5), None)), "claude-3-5-haiku");
+        assert_eq!(payload["temperature"], 1.0);
+    }
+
+    #[test]
+    fn test_prompt_caching_marks_anthropic_system_block() {
+        let mut payload = build_anthropic_payload(&conversation(), None, "claude-3-5-sonnet");
+        use_prompt_caching(&mut payload);
+        assert_eq!(
+            payload["system"],
+            json!([{
+                "type": "text",
+                "text": "Be concise.\n\nAnswer in English.",
+                "cache_control": {"type": "ephemeral"}
+            }])
+        );
+
+        // Nothing to cache without a system prompt
+        let mut payload = build_anthropic_payload(&[message(MessageRole::User, "Hi")], None, "claude-3-5-haiku");
+        let unchanged = payload.clone();
+        use_prompt_caching(&mut payload);
+        assert_eq!(payload, unchanged);
+    }
+
+    #[test]
+    fn test_build_chat_payload_dispatches_by_provider() {
+        let messages = conversation();
+
+        let anthropic = build_chat_payload(&Provider::Anthropic, &messages, None, "claude-3-5-sonnet", &[], false);
+        assert_eq!(anthropic["system"], "Be concise.\n\nAnswer in English.");
+        let cached = build_chat_payload(&Provider::Anthropic, &messages, None, "claude-3-5-sonnet", &[], true);
+        assert_eq!(cached["system"][0]["cache_control"], json!({"type": "ephemeral"}));
+
+        let reasoning_models = ["o1".to_string()];
+        for provider in [Provider::OpenAI, Provider::Groq, Provider::Mistral, Provider::OpenRouter] {
+            let payload = build_chat_payload(&provider, &messages, None, "model", &reasoning_models, true);
+            assert!(payload.get("system").is_none());
+            assert_eq!(payload["messages"].as_array().unwrap().len(), 5);
+        }
+        let limited = options(None, Some(64));
+        let reasoning = build_chat_payload(&Provider::OpenAI, &messages, Some(&limited), "o1-mini", &reasoning_models, false);
+        assert_eq!(reasoning["max_completion_tokens"], 64);
+    }
+
+    #[test]
+    fn test_completion_text_reads_each_response_shape() {
+        let openai = json!({
+            "choices": [{"index": 0, "message": {"role": "assistant", "content": "Hello!"}}]
+        });
+        assert_eq!(completion_text(&Provider::OpenAI, &openai).as_deref(), Some("Hello!"));
+        assert_eq!(completion_text(&Provider::Groq, &openai).as_deref(), Some("Hello!"));
+
+        let anthropic = json!({
+            "content": [
+                {"type": "text", "text": "Hello"},
+                {"type": "tool_use", "id": "toolu_1"},
+                {"type": "text", "text": " there"}
+            ]
+        });
+        assert_eq!(completion_text(&Provider::Anthropic, &anthropic).as_deref(), Some("Hello there"));
+
+        assert_eq!(completion_text(&Provider::OpenAI, &json!({"choices": []})), None);
+        assert_eq!(completion_text(&Provider::Anthropic, &json!({"content": []})), None);
+    }
+
+    #[test]
+    fn test_reasoning_params_and_reasoning_text() {
+        let options = InvokeOptions {
+            temperature: None,
+            max_tokens: Some(256),
+        };
+        let mut payload = build_openai_payload(&[], Some(&options), "o1-mini");
+        use_reasoning_params(&mut payload);
+        assert_eq!(payload["max_completion_tokens"], 256);
+        assert!(payload.get("max_tokens").is_none());
+
+        let openai = json!({
+            "choices": [{"message": {"content": "42", "reasoning_content": "6 times 7"}}]
+        });
+        assert_eq!(completion_reasoning(&Provider::OpenRouter, &openai).as_deref(), Some("6 times 7"));
+        let anthropic = json!({
+            "content": [
+                {"type": "thinking", "thinking": "6 times 7"},
+                {"type": "text", "text": "42"}
+            ]
+        });
+        assert_eq!(completion_reasoning(&Provider::Anthropic, &anthropic).as_deref(), Some("6 times 7"));
+        assert_eq!(completion_text(&Provider::Anthropic, &anthropic).as_deref(), Some("42"));
+        assert_eq!(completion_reasoning(&Provider::OpenAI, &json!({"choices": []})), None);
+    }
+
+    #[test]
+    fn test_to_provider_json_per_provider() {
+        let mut config = Config::from_env();
+        config.system_prompt = "Server prompt".to_string();
+        config.reasoning_models = vec!["o1".to_string()];
+        let request: InvokeRequest = serde_json::from_value(json!({
+            "op": "chat",
+            "input": {"messages": [
+                {"role": "system", "content": "Be brief."},
+                {"role": "user", "content": "Hi"}
+            ]},
+            "options": {"temperature": 1.5}
+        }))
+        .unwrap();
+        let route = |provider: Provider, model: &str| RouteTarget {
+            provider,
+            model: model.to_string(),
+            defaults: InvokeOptions {
+                temperature: None,
+                max_tokens: Some(300),
+            },
+        };
+
+        // OpenAI keeps system messages inline and passes options through
+        let openai = request.to_provider_json(&route(Provider::OpenAI, "gpt-4o"), &config).unwrap();
+        assert!(openai.get("system").is_none());
+        let roles: Vec<_> = openai["messages"].as_array().unwrap().iter().map(|m| m["role"].clone()).collect();
+        assert_eq!(roles, [json!("system"), json!("system"), json!("user")]);
+        assert_eq!(openai["temperature"], 1.5);
+        assert_eq!(openai["max_tokens"], 300);
+
+        // Anthropic hoists them, keeps only the conversation and caps temperature
+        let anthropic = request
+            .to_provider_json(&route(Provider::Anthropic, "claude-3-5-sonnet"), &config)
+            .unwrap();
+        assert_eq!(anthropic["system"], "Server prompt\n\nBe brief.");
+        assert_eq!(anthropic["messages"], json!([{"role": "user", "content": "Hi"}]));
+        assert_eq!(anthropic["temperature"], 1.0);
+        assert_eq!(anthropic["max_tokens"], 300);
+        assert_ne!(openai, anthropic);
+
+        // Reasoning models get max_completion_tokens
+        let reasoning = request.to_provider_json(&route(Provider::OpenAI, "o1-mini"), &config).unwrap();
+        assert_eq!(reasoning["max_completion_tokens"], 300);
+        assert!(reasoning.get("max_tokens").is_none());
+
+        // With prompt caching only Anthropic's system block is tagged
+        config.anthropic.prompt_caching = true;
+        let cached = request
+            .to_provider_json(&route(Provider::Anthropic, "claude-3-5-sonnet"), &config)
+            .unwrap();
+        assert_eq!(cached["system"][0]["text"], "Server prompt\n\nBe brief.");
+        assert_eq!(cached["system"][0]["cache_control"], json!({"type": "ephemeral"}));
+        assert_eq!(request.to_provider_json(&route(Provider::OpenAI, "gpt-4o"), &config).unwrap(), openai);
+
+        let invalid: InvokeRequest = serde_json::from_value(json!({"op": "chat", "input": {"messages": "hi"}})).unwrap();
+        assert!(invalid.to_provider_json(&route(Provider::OpenAI, "gpt-4o"), &config).is_err());
+    }
+
+    #[test]
+    fn test_stream_delta_text_reads_each_chunk_shape() {
+        let openai = json!({"choices": [{"index": 0, "delta": {"content": "Hel"}}]});
+        assert_eq!(stream_delta_text(&Provider::OpenAI, &openai).as_deref(), Some("Hel"));
+        let role_only = json!({"choices": [{"index": 0, "delta": {"role": "assistant", "content": ""}}]});
+        assert_eq!(stream_delta_text(&Provider::Mistral, &role_only), None);
+
+        let anthropic = json!({"type": "content_block_delta", "index": 0, "delta": {"type": "text_delta", "text": "lo"}});
+        assert_eq!(stream_delta_text(&Provider::Anthropic, &anthropic).as_deref(), Some("lo"));
+        assert_eq!(stream_delta_text(&Provider::Anthropic, &json!({"type": "message_stop"})), None);
+    }
+}
@@ -0,0 +1,172 @@
+//! Provider Rate Limiting Module
+//!
+//! Self-limits outbound requests per AI provider so the gateway stays
+//! within upstream quotas instead of being rejected with 429s.
+//!
+//! Each provider with a configured requests-per-minute limit gets a token
+//! bucket holding up to one minute of burst. When the bucket is empty a
+//! request may wait briefly for the next token; if the wait would exceed
+//! the configured queue time the request is shed instead.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Token bucket state for a single provider
+#[derive(Debug, Clone)]
+struct TokenBucket {
+    /// Available tokens; negative when requests are queued for future tokens
+    tokens: f64,
+    /// When tokens were last refilled
+    refilled_at: Instant,
+}
+
+/// Per-provider outbound request limiter
+#[derive(Clone)]
+pub struct ProviderRateLimiter {
+    /// Requests per minute allowed for each provider
+    rpm: HashMap<String, u32>,
+    /// Longest a request may wait for a token before being shed
+    max_wait: Duration,
+    /// Bucket state by provider
+    buckets: Arc<Mutex<HashMap<String, TokenBucket>>>,
+}
+
+impl ProviderRateLimiter {
+    /// Create a limiter from per-provider RPM limits
+    ///
+    /// Providers without a configured limit (or with a limit of zero)
+    /// are not limited.
+    ///
+    /// # Arguments
+    /// * `rpm` - Requests per minute by provider id (e.g. "openai")
+    /// * `max_wait_ms` - Longest a request may queue for a token
+    pub fn new(rpm: HashMap<String, u32>, max_wait_ms: u64) -> Self {
+        Self {
+            rpm,
+            max_wait: Duration::from_millis(max_wait_ms),
+            buckets: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// Wait for a request slot for `provider`
+    ///
+    /// # Returns
+    /// `Ok(())` once the request may be dispatched, or `Err(retry_after)`
+    /// when the provider is over capacity and the request was shed
+    pub async fn acquire(&self, provider: &str) -> Result<(), Duration> {
+        let wait = self.reserve_at(provider, Instant::now())?;
+        if !wait.is_zero() {
+            tokio::time::sleep(wait).await;
+        }
+        Ok(())
+    }
+
+    /// Reserve a request slot for `provider` at the given instant
+    ///
+    /// # Returns
+    /// `Ok(wait)` with how long to wait before dispatching, or
+    /// `Err(retry_after)` when the wait would exceed the queue limit
+    pub fn reserve_at(&self, provider: &str, now: Instant) -> Result<Duration, Duration> {
+        let rpm = match self.rpm.get(provider) {
+            Some(&rpm) if rpm > 0 => rpm as f64,
+            _ => return Ok(Duration::ZERO),
+        };
+        let tokens_per_sec = rpm / 60.0;
+
+        let mut buckets = self.buckets.lock().unwrap();
+        let bucket = buckets.entry(provider.to_string()).or_insert(TokenBucket {
+            tokens: rpm,
+            refilled_at: now,
+        });
+
+        // Refill for the time elapsed since the last reservation, capped at capacity
+        let elapsed = now.saturating_duration_since(bucket.refilled_at).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * tokens_per_sec).min(rpm);
+        bucket.refilled_at = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            return Ok(Duration::ZERO);
+        }
+
+        let wait = Duration::from_secs_f64((1.0 - bucket.tokens) / tokens_per_sec);
+        if wait > self.max_wait {
+            return Err(wait);
+        }
+
+        // Claim the next token now so concurrent waiters queue behind this one
+        bucket.tokens -= 1.0;
+        Ok(wait)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limiter(provider: &str, rpm: u32, max_wait_ms: u64) -> ProviderRateLimiter {
+        ProviderRateLimiter::new(
+            [(provider.to_string(), rpm)].into_iter().collect(),
+            max_wait_ms,
+        )
+    }
+
+    fn assert_secs(duration: Duration, secs: f64) {
+        assert!((duration.as_secs_f64() - secs).abs() < 0.001, "{:?} != {}s", duration, secs);
+    }
+
+    #[test]
+    fn test_excess_requests_are_shed() {
+        let limiter = limiter("openai", 2, 0);
+        let now = Instant::now();
+
+        assert_eq!(limiter.reserve_at("openai", now), Ok(Duration::ZERO));
+        assert_eq!(limiter.reserve_at("openai", now), Ok(Duration::ZERO));
+
+        let retry_after = limiter.reserve_at("openai", now).unwrap_err();
+        assert_secs(retry_after, 30.0);
+
+        // One token refills every 30 seconds at 2 RPM
+        let later = now + Duration::from_secs(31);
+        assert_eq!(limiter.reserve_at("openai", later), Ok(Duration::ZERO));
+    }
+
+    #[test]
+    fn test_requests_queue_within_max_wait() {
+        let limiter = limiter("openai", 2, 45_000);
+        let now = Instant::now();
+
+        limiter.reserve_at("openai", now).unwrap();
+        limiter.reserve_at("openai", now).unwrap();
+
+        // Third request waits for the next token
+        assert_secs(limiter.reserve_at("openai", now).unwrap(), 30.0);
+
+        // Fourth would wait a full minute, beyond the queue limit
+        assert_secs(limiter.reserve_at("openai", now).unwrap_err(), 60.0);
+    }
+
+    #[test]
+    fn test_unconfigured_providers_are_unlimited() {
+        let limiter = limiter("openai", 1, 0);
+        let now = Instant::now();
+
+        for _ in 0..10 {
+            assert_eq!(limiter.reserve_at("anthropic", now), Ok(Duration::ZERO));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_acquire_delays_until_token_available() {
+        // 600 RPM refills one token every 100ms
+        let limiter = limiter("groq", 600, 1_000);
+        for _ in 0..600 {
+            limiter.acquire("groq").await.unwrap();
+        }
+
+        let started = Instant::now();
+        limiter.acquire("groq").await.unwrap();
+        assert!(started.elapsed() >= Duration::from_millis(50));
+    }
+}
@@ -0,0 +1,788 @@
+//! Providers Module
+//!
+//! Dispatch of chat requests to AI providers behind one `ChatProvider`
+//! trait, so the invoke handler does not care which API it is talking to.
+//!
+//! - `OpenAiProvider` speaks the OpenAI chat completions API, which OpenAI,
+//!   Mistral, xAI, Groq, OpenRouter, Meta and Cloudflare (through its
+//!   OpenAI-compatible endpoint) all accept; only the endpoint and key differ
+//! - `AnthropicProvider` speaks the Anthropic messages API
+//!
+//! `build_provider` wires up the endpoint and credentials for a `Provider`
+//! from the configuration.
+//!
+//! For latency-critical requests, `race_complete` sends one conversation
+//! to two providers at once and keeps the first reply, at twice the cost.
+//!
+//! OpenAI and xAI also generate images through the OpenAI images API;
+//! other providers report image generation as unsupported.
+//!
+//! Replies can also be streamed as text deltas. Both APIs stream
+//! server-sent events whose `data:` lines are parsed here; a provider
+//! without streaming support falls back to sending its whole reply as
+//! one delta.
+
+use anyhow::{anyhow, Result};
+use axum::async_trait;
+use futures::stream::BoxStream;
+use futures::{Stream, StreamExt};
+use reqwest::{Client, RequestBuilder, Response};
+use serde_json::{json, Value};
+
+use crate::config::Config;
+use crate::http_client::send_with_retry;
+use crate::payload::{build_chat_payload, completion_reasoning, completion_text, stream_delta_text};
+use crate::secret::Secret;
+use crate::types::{ChatMessage, ImageRequest, InvokeOptions, Provider};
+
+/// Assistant reply from a provider
+#[derive(Debug, Clone, PartialEq)]
+pub struct CompletionResult {
+    /// Assistant text
+    pub text: String,
+    /// Reasoning returned separately by reasoning models
+    pub reasoning: Option<String>,
+    /// Model that answered, as reported by the provider (the requested
+    /// model when the response does not say)
+    pub model: String,
+}
+
+/// One generated image, hosted (`url`) or inline (`b64_json`)
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+pub struct GeneratedImage {
+    /// Link to the hosted image
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    /// Base64-encoded image data
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub b64_json: Option<String>,
+    /// Prompt as rewritten by the provider, when it does so
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub revised_prompt: Option<String>,
+}
+
+/// Assistant text deltas of a streamed reply
+///
+/// A failure mid-stream is the last item.
+pub type DeltaStream = BoxStream<'static, Result<String>>;
+
+/// A provider that can answer a chat conversation
+#[async_trait]
+pub trait ChatProvider: Send + Sync {
+    /// Send the conversation to `model` and return the assistant reply
+    ///
+    /// # Errors
+    /// Fails when the provider is not configured, cannot be reached,
+    /// answers with an error status or returns no assistant text
+    async fn complete(&self, model: &str, messages: &[ChatMessage], opts: &InvokeOptions) -> Result<CompletionResult>;
+
+    /// Send the conversation to `model` and stream the assistant reply
+    ///
+    /// The default sends the complete reply as a single delta, for
+    /// providers that cannot stream.
+    ///
+    /// # Errors
+    /// Fails like `complete` when the stream cannot be opened
+    async fn stream(&self, model: &str, messages: &[ChatMessage], opts: &InvokeOptions) -> Result<DeltaStream> {
+        let result = self.complete(model, messages, opts).await?;
+        Ok(futures::stream::once(async move { Ok(result.text) }).boxed())
+    }
+
+    /// Whether `generate_image` is available
+    fn supports_image_generation(&self) -> bool {
+        false
+    }
+
+    /// Generate images for `request` with `model`
+    ///
+    /// The default fails, for providers without image generation.
+    ///
+    /// # Errors
+    /// Fails like `complete`, or when the response carries no images
+    async fn generate_image(&self, _model: &str, _request: &ImageRequest) -> Result<Vec<GeneratedImage>> {
+        Err(anyhow!("operation not supported"))
+    }
+}
+
+/// Provider speaking the OpenAI chat completions API
+pub struct OpenAiProvider {
+    provider: Provider,
+    client: Client,
+    /// Base URL up to (not including) `/v1/chat/completions`
+    base_url: String,
+    api_key: Secret,
+    retryable: Vec<u16>,
+    /// Model name prefixes sent reasoning model parameters
+    reasoning_models: Vec<String>,
+    /// Whether the endpoint serves `/v1/images/generations`
+    image_generation: bool,
+}
+
+impl OpenAiProvider {
+    /// OpenAI-compatible provider at `base_url` authenticated with `api_key`
+    pub fn new(provider: Provider, client: Client, base_url: &str, api_key: Secret, retryable: Vec<u16>) -> Self {
+        Self {
+            provider,
+            client,
+            base_url: base_url.trim_end_matches('/').to_string(),
+            api_key,
+            retryable,
+            reasoning_models: Vec::new(),
+            image_generation: false,
+        }
+    }
+
+    /// Treat models matching these name prefixes as reasoning models
+    pub fn with_reasoning_models(mut self, reasoning_models: Vec<String>) -> Self {
+        self.reasoning_models = reasoning_models;
+        self
+    }
+
+    /// Serve image generation through the OpenAI images API
+    pub fn with_image_generation(mut self, enabled: bool) -> Self {
+        self.image_generation = enabled;
+        self
+    }
+}
+
+#[async_trait]
+impl ChatProvider for OpenAiProvider {
+    async fn complete(&self, model: &str, messages: &[ChatMessage], opts: &InvokeOptions) -> Result<CompletionResult> {
+        let request = self.request(self.payload(model, messages, opts))?;
+        send_chat(&self.provider, request, &self.retryable, model).await
+    }
+
+    async fn stream(&self, model: &str, messages: &[ChatMessage], opts: &InvokeOptions) -> Result<DeltaStream> {
+        let mut payload = self.payload(model, messages, opts);
+        payload["stream"] = json!(true);
+        let request = self.request(payload)?;
+        send_stream(&self.provider, request, &self.retryable).await
+    }
+
+    fn supports_image_generation(&self) -> bool {
+        self.image_generation
+    }
+
+    async fn generate_image(&self, model: &str, request: &ImageRequest) -> Result<Vec<GeneratedImage>> {
+        let name = self.provider.as_str();
+        if !self.image_generation {
+            return Err(anyhow!("operation not supported by {}", name));
+        }
+        let mut payload = serde_json::to_value(request)?;
+        payload["model"] = json!(model);
+        let request = self.post("/v1/images/generations", payload)?;
+        let response = send_checked(&self.provider, request, &self.retryable).await?;
+        let body: Value = response
+            .json()
+            .await
+            .map_err(|error| anyhow!("{} returned an invalid response: {}", name, error))?;
+        let text = |image: &Value, field: &str| image[field].as_str().map(str::to_string);
+        let images: Vec<GeneratedImage> = body["data"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .map(|image| GeneratedImage {
+                url: text(image, "url"),
+                b64_json: text(image, "b64_json"),
+                revised_prompt: text(image, "revised_prompt"),
+            })
+            .filter(|image| image.url.is_some() || image.b64_json.is_some())
+            .collect();
+        if images.is_empty() {
+            return Err(anyhow!("{} returned no images", name));
+        }
+        Ok(images)
+    }
+}
+
+impl OpenAiProvider {
+    /// Request body for `model`, with reasoning model parameters if it is one
+    fn payload(&self, model: &str, messages: &[ChatMessage], opts: &InvokeOptions) -> Value {
+        build_chat_payload(&self.provider, messages, Some(opts), model, &self.reasoning_models, false)
+    }
+
+    /// Chat completions request carrying `payload`
+    fn request(&self, payload: Value) -> Result<RequestBuilder> {
+        self.post("/v1/chat/completions", payload)
+    }
+
+    /// Authenticated request to `path` carrying `payload`
+    fn post(&self, path: &str, payload: Value) -> Result<RequestBuilder> {
+        if self.base_url.is_empty() {
+            return Err(anyhow!("No base URL configured for {}", self.provider.as_str()));
+        }
+        Ok(self
+            .client
+            .post(format!("{}{}", self.base_url, path))
+            .bearer_auth(self.api_key.expose())
+            .json(&payload))
+    }
+}
+
+/// Provider speaking the Anthropic messages API
+pub struct AnthropicProvider {
+    client: Client,
+    base_url: String,
+    api_key: Secret,
+    version: String,
+    retryable: Vec<u16>,
+    prompt_caching: bool,
+}
+
+impl AnthropicProvider {
+    /// Anthropic API at `base_url` using API `version`
+    pub fn new(client: Client, base_url: &str, api_key: Secret, version: &str, retryable: Vec<u16>) -> Self {
+        Self {
+            client,
+            base_url: base_url.trim_end_matches('/').to_string(),
+            api_key,
+            version: version.to_string(),
+            retryable,
+            prompt_caching: false,
+        }
+    }
+
+    /// Mark system prompts as cacheable (`PROMPT_CACHING`)
+    pub fn with_prompt_caching(mut self, prompt_caching: bool) -> Self {
+        self.prompt_caching = prompt_caching;
+        self
+    }
+}
+
+#[async_trait]
+impl ChatProvider for AnthropicProvider {
+    async fn complete(&self, model: &str, messages: &[ChatMessage], opts: &InvokeOptions) -> Result<CompletionResult> {
+        let request = self.request(self.payload(model, messages, opts));
+        send_chat(&Provider::Anthropic, request, &self.retryable, model).await
+    }
+
+    async fn stream(&self, model: &str, messages: &[ChatMessage], opts: &InvokeOptions) -> Result<DeltaStream> {
+        let mut payload = self.payload(model, messages, opts);
+        payload["stream"] = json!(true);
+        send_stream(&Provider::Anthropic, self.request(payload), &self.retryable).await
+    }
+}
+
+impl AnthropicProvider {
+    /// Request body for `model`, with the system prompt cacheable if enabled
+    fn payload(&self, model: &str, messages: &[ChatMessage], opts: &InvokeOptions) -> Value {
+        build_chat_payload(&Provider::Anthropic, messages, Some(opts), model, &[], self.prompt_caching)
+    }
+
+    /// Messages request carrying `payload`
+    fn request(&self, payload: Value) -> RequestBuilder {
+        self.client
+            .post(format!("{}/v1/messages", self.base_url))
+            .header("x-api-key", self.api_key.expose())
+            .header("anthropic-version", &self.version)
+            .json(&payload)
+    }
+}
+
+/// Send a provider request, failing on transport errors and error statuses
+async fn send_checked(provider: &Provider, request: RequestBuilder, retryable: &[u16]) -> Result<Response> {
+    let name = provider.as_str();
+    let response = send_with_retry(request, retryable)
+        .await
+        .map_err(|error| anyhow!("{} request failed: {}", name, error))?;
+    let status = response.status();
+    if !status.is_success() {
+        let body = response.text().await.unwrap_or_default();
+        return Err(anyhow!("{} returned {}: {}", name, status, body));
+    }
+    Ok(response)
+}
+
+/// Send a chat request and read the assistant reply from the response
+async fn send_chat(provider: &Provider, request: RequestBuilder, retryable: &[u16], model: &str) -> Result<CompletionResult> {
+    let name = provider.as_str();
+    let response = send_checked(provider, request, retryable).await?;
+    let body: Value = response
+        .json()
+        .await
+        .map_err(|error| anyhow!("{} returned an invalid response: {}", name, error))?;
+    let text = completion_text(provider, &body).ok_or_else(|| anyhow!("{} returned no assistant text", name))?;
+    Ok(CompletionResult {
+        text,
+        reasoning: completion_reasoning(provider, &body),
+        model: body["model"].as_str().unwrap_or(model).to_string(),
+    })
+}
+
+/// Send a streaming chat request and yield the text deltas of its events
+///
+/// The stream ends at OpenAI's `data: [DONE]`, when the provider closes
+/// the connection, or after the first error (a failed read or an `error`
+/// event).
+async fn send_stream(provider: &Provider, request: RequestBuilder, retryable: &[u16]) -> Result<DeltaStream> {
+    let response = send_checked(provider, request, retryable).await?;
+    let provider = provider.clone();
+    let name = provider.as_str();
+    let deltas = sse_data(response.bytes_stream())
+        .take_while(|data| futures::future::ready(!matches!(data, Ok(data) if data == "[DONE]")))
+        .map(move |data| {
+            let data = data.map_err(|error| anyhow!("{} stream failed: {}", name, error))?;
+            let chunk: Value = serde_json::from_str(&data)
+                .map_err(|error| anyhow!("{} sent an invalid stream event: {}", name, error))?;
+            if let Some(error) = chunk.get("error") {
+                return Err(anyhow!("{} stream failed: {}", name, error));
+            }
+            Ok(stream_delta_text(&provider, &chunk))
+        })
+        // Stop after the first error
+        .scan(false, |failed, item| {
+            let item = (!*failed).then_some(item);
+            *failed = matches!(item, Some(Err(_)));
+            futures::future::ready(item)
+        })
+        .filter_map(|item| futures::future::ready(item.transpose()));
+    Ok(deltas.boxed())
+}
+
+/// `data:` field values of a server-sent event body, one per line
+///
+/// Comments and other fields are skipped. Lines split across network
+/// chunks are reassembled before decoding.
+fn sse_data<S, B, E>(bytes: S) -> impl Stream<Item = Result<String, E>>
+where
+    S: Stream<Item = Result<B, E>>,
+    B: AsRef<[u8]>,
+{
+    bytes
+        .map(Some)
+        .chain(futures::stream::once(async { None }))
+        .scan(Vec::new(), |buffer: &mut Vec<u8>, chunk| {
+            let lines: Vec<Result<String, E>> = match chunk {
+                Some(Ok(chunk)) => {
+                    buffer.extend_from_slice(chunk.as_ref());
+                    let complete = buffer.iter().rposition(|byte| *byte == b'\n').map_or(0, |end| end + 1);
+                    let rest = buffer.split_off(complete);
+                    let lines = std::mem::replace(buffer, rest);
+                    String::from_utf8_lossy(&lines).lines().map(|line| Ok(line.to_string())).collect()
+                }
+                Some(Err(error)) => vec![Err(error)],
+                // End of body: a last line without a terminator
+                None => String::from_utf8_lossy(&std::mem::take(buffer))
+                    .lines()
+                    .map(|line| Ok(line.to_string()))
+                    .collect(),
+            };
+            futures::future::ready(Some(futures::stream::iter(lines)))
+        })
+        .flatten()
+        .filter_map(|line| {
+            let data = match line {
+                Ok(line) => line
+                    .strip_prefix("data:")
+                    .map(|data| Ok(data.strip_prefix(' ').unwrap_or(data).to_string())),
+                Err(error) => Some(Err(error)),
+            };
+            futures::future::ready(data)
+        })
+}
+
+/// Number of targets at the head of a fallback chain that are raced
+pub const RACE_WIDTH: usize = 2;
+
+/// Send the conversation to the first two contenders at once and return
+/// the first successful reply
+///
+/// The slower request is cancelled as soon as a reply arrives. A contender
+/// that fails does not end the race while the other is still running.
+/// Racing pays both providers, so callers should only use it when the
+/// request opted in.
+///
+/// # Arguments
+/// * `contenders` - Providers, models and route defaults in fallback
+///   order; only the first `RACE_WIDTH` are used
+/// * `messages` - Conversation to send
+/// * `opts` - Client generation options; each contender's defaults fill
+///   in the rest
+///
+/// # Returns
+/// Index of the winning contender and its reply
+///
+/// # Errors
+/// When there are no contenders or every raced request fails (the last
+/// failure is returned)
+pub async fn race_complete(
+    contenders: &[(&dyn ChatProvider, &str, &InvokeOptions)],
+    messages: &[ChatMessage],
+    opts: &InvokeOptions,
+) -> Result<(usize, CompletionResult)> {
+    if contenders.is_empty() {
+        return Err(anyhow!("No providers to race"));
+    }
+    let requests = contenders
+        .iter()
+        .take(RACE_WIDTH)
+        .enumerate()
+        .map(|(index, (provider, model, defaults))| {
+            Box::pin(async move {
+                let opts = opts.with_defaults(defaults);
+                provider.complete(model, messages, &opts).await.map(|result| (index, result))
+            })
+        });
+    // Dropping the unfinished request cancels it
+    let (winner, _) = futures::future::select_ok(requests).await?;
+    Ok(winner)
+}
+
+/// Provider client for `provider` with its configured endpoint and key
+///
+/// # Arguments
+/// * `provider` - Provider from the resolved route
+/// * `config` - Application configuration holding endpoints and keys
+/// * `client` - Shared HTTP client for provider requests
+pub fn build_provider(provider: &Provider, config: &Config, client: Client) -> Box<dyn ChatProvider> {
+    let retryable = config.retryable_status_codes.clone();
+    let (base_url, api_key) = match provider {
+        Provider::Anthropic => {
+            return Box::new(AnthropicProvider::new(
+                client,
+                &config.anthropic.base_url,
+                config.anthropic.api_key.clone(),
+                &config.anthropic.version,
+                retryable,
+            )
+            .with_prompt_caching(config.anthropic.prompt_caching));
+        }
+        Provider::Cloudflare => (
+            format!(
+                "{}/accounts/{}/ai",
+                config.cloudflare.base_url.trim_end_matches('/'),
+                config.cloudflare.account_id
+            ),
+            &config.cloudflare.api_token,
+        ),
+        Provider::Mistral => (config.mistral.base_url.clone(), &config.mistral.api_key),
+        Provider::OpenAI => (config.openai.base_url.clone(), &config.openai.api_key),
+        Provider::Xai => (config.xai.base_url.clone(), &config.xai.api_key),
+        Provider::Groq => (config.groq.base_url.clone(), &config.groq.api_key),
+        Provider::OpenRouter => (config.openrouter.base_url.clone(), &config.openrouter.api_key),
+        Provider::Meta => (config.meta.base_url.clone(), &config.meta.api_key),
+    };
+    Box::new(
+        OpenAiProvider::new(provider.clone(), client, &base_url, api_key.clone(), retryable)
+            .with_reasoning_models(config.reasoning_models.clone())
+            .with_image_generation(matches!(provider, Provider::OpenAI | Provider::Xai)),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::MessageRole;
+    use crate::test_support::spawn_mock;
+    use axum::http::HeaderMap;
+    use axum::routing::post;
+    use axum::{Json, Router};
+    use serde_json::json;
+    use std::sync::{Arc, Mutex};
+
+    type Received = Arc<Mutex<Vec<(String, HeaderMap, Value)>>>;
+
+    /// Stub answering every POST with `reply`, recording path, headers and body
+    async fn stub_provider(reply: Value) -> (String, Received) {
+        let received: Received = Arc::default();
+        let recorder = received.clone();
+        let app = Router::new().fallback(post(
+            move |uri: axum::http::Uri, headers: HeaderMap, Json(body): Json<Value>| {
+                recorder.lock().unwrap().push((uri.path().to_string(), headers, body));
+                let reply = reply.clone();
+                async move { Json(reply) }
+            },
+        ));
+        let base_url = format!("http://{}", spawn_mock(app).await);
+        (base_url, received)
+    }
+
+    fn conversation() -> Vec<ChatMessage> {
+        vec![
+            ChatMessage {
+                role: MessageRole::System,
+                content: "Be brief.".to_string(),
+            },
+            ChatMessage {
+                role: MessageRole::User,
+                content: "Hi".to_string(),
+            },
+        ]
+    }
+
+    fn options() -> InvokeOptions {
+        InvokeOptions {
+            temperature: Some(0.5),
+            max_tokens: Some(32),
+        }
+    }
+
+    fn openai_reply() -> Value {
+        json!({
+            "model": "llama-3.1-8b-instant-2024",
+            "choices": [{"index": 0, "message": {"role": "assistant", "content": "Hello!"}}]
+        })
+    }
+
+    #[tokio::test]
+    async fn test_openai_compatible_provider_request_shape() {
+        let (base_url, received) = stub_provider(openai_reply()).await;
+        let mut config = Config::from_env();
+        config.groq.base_url = format!("{}/openai", base_url);
+        config.groq.api_key = Secret::from("gsk-test");
+
+        let provider = build_provider(&Provider::Groq, &config, Client::new());
+        let result = provider.complete("llama-3.1-8b-instant", &conversation(), &options()).await.unwrap();
+        assert_eq!(result.text, "Hello!");
+        assert_eq!(result.model, "llama-3.1-8b-instant-2024");
+
+        let received = received.lock().unwrap();
+        let (path, headers, body) = &received[0];
+        assert_eq!(path, "/openai/v1/chat/completions");
+        assert_eq!(headers["authorization"], "Bearer gsk-test");
+        assert_eq!(body["model"], "llama-3.1-8b-instant");
+        assert_eq!(body["messages"][0]["role"], "system");
+        assert_eq!(body["temperature"], 0.5);
+        assert_eq!(body["max_tokens"], 32);
+    }
+
+    #[tokio::test]
+    async fn test_reasoning_model_request_and_reply() {
+        let (base_url, received) = stub_provider(json!({
+            "choices": [{"message": {"role": "assistant", "content": "42", "reasoning_content": "6 times 7"}}]
+        }))
+        .await;
+        let mut config = Config::from_env();
+        config.openai.base_url = base_url;
+        config.reasoning_models = vec!["o1".to_string()];
+
+        let provider = build_provider(&Provider::OpenAI, &config, Client::new());
+        let result = provider.complete("o1-mini", &conversation(), &options()).await.unwrap();
+        assert_eq!(result.text, "42");
+        assert_eq!(result.reasoning.as_deref(), Some("6 times 7"));
+
+        let received = received.lock().unwrap();
+        let body = &received[0].2;
+        assert_eq!(body["max_completion_tokens"], 32);
+        assert!(body.get("max_tokens").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_cloudflare_uses_account_endpoint() {
+        let (base_url, received) = stub_provider(openai_reply()).await;
+        let mut config = Config::from_env();
+        config.cloudflare.base_url = format!("{}/client/v4", base_url);
+        config.cloudflare.account_id = "acct-123".to_string();
+
+        let provider = build_provider(&Provider::Cloudflare, &config, Client::new());
+        provider.complete("@cf/meta/llama-3.1-8b-instruct", &conversation(), &options()).await.unwrap();
+        assert_eq!(received.lock().unwrap()[0].0, "/client/v4/accounts/acct-123/ai/v1/chat/completions");
+    }
+
+    #[tokio::test]
+    async fn test_openai_image_generation_request_and_reply() {
+        let (base_url, received) = stub_provider(json!({
+            "data": [{"url": "https://images.example/1.png", "revised_prompt": "A red fox, watercolor"}]
+        }))
+        .await;
+        let mut config = Config::from_env();
+        config.openai.base_url = base_url;
+        let request = ImageRequest {
+            prompt: "A red fox".to_string(),
+            size: Some("1024x1024".to_string()),
+            quality: Some("hd".to_string()),
+            response_format: None,
+        };
+
+        let provider = build_provider(&Provider::OpenAI, &config, Client::new());
+        assert!(provider.supports_image_generation());
+        let images = provider.generate_image("dall-e-3", &request).await.unwrap();
+        assert_eq!(images.len(), 1);
+        assert_eq!(images[0].url.as_deref(), Some("https://images.example/1.png"));
+        assert_eq!(images[0].revised_prompt.as_deref(), Some("A red fox, watercolor"));
+
+        let (path, _, body) = received.lock().unwrap()[0].clone();
+        assert_eq!(path, "/v1/images/generations");
+        assert_eq!(body["model"], "dall-e-3");
+        assert_eq!(body["prompt"], "A red fox");
+        assert_eq!(body["size"], "1024x1024");
+        assert_eq!(body["quality"], "hd");
+        assert!(body.get("response_format").is_none());
+
+        // Chat-only providers refuse without calling upstream
+        let groq = build_provider(&Provider::Groq, &config, Client::new());
+        assert!(!groq.supports_image_generation());
+        assert!(groq.generate_image("llama", &request).await.is_err());
+        let anthropic = build_provider(&Provider::Anthropic, &config, Client::new());
+        assert!(!anthropic.supports_image_generation());
+    }
+
+    #[tokio::test]
+    async fn test_anthropic_provider_request_shape() {
+        let (base_url, received) = stub_provider(json!({
+            "content": [{"type": "text", "text": "Hello from Claude"}]
+        }))
+        .await;
+        let mut config = Config::from_env();
+        config.anthropic.base_url = base_url;
+        config.anthropic.api_key = Secret::from("sk-ant-test");
+
+        let provider = build_provider(&Provider::Anthropic, &config, Client::new());
+        let result = provider.complete("claude-3-5-haiku", &conversation(), &options()).await.unwrap();
+        assert_eq!(result.text, "Hello from Claude");
+        assert_eq!(result.model, "claude-3-5-haiku");
+
+        let received = received.lock().unwrap();
+        let (path, headers, body) = &received[0];
+        assert_eq!(path, "/v1/messages");
+        assert_eq!(headers["x-api-key"], "sk-ant-test");
+        assert_eq!(headers["anthropic-version"], config.anthropic.version.as_str());
+        assert_eq!(body["system"], "Be brief.");
+        assert_eq!(body["messages"].as_array().unwrap().len(), 1);
+    }
+
+    /// Stub answering every POST with an SSE body
+    async fn stub_stream(body: &'static str) -> String {
+        let app = Router::new().fallback(post(move || async move {
+            ([(axum::http::header::CONTENT_TYPE, "text/event-stream")], body)
+        }));
+        let base_url = format!("http://{}", spawn_mock(app).await);
+        base_url
+    }
+
+    async fn collect(stream: DeltaStream) -> Vec<std::result::Result<String, String>> {
+        stream.map(|delta| delta.map_err(|error| error.to_string())).collect().await
+    }
+
+    #[tokio::test]
+    async fn test_sse_data_reassembles_split_lines() {
+        let chunks: Vec<std::result::Result<&[u8], ()>> = vec![
+            Ok(b": ping\n\ndata: {\"a\"".as_slice()),
+            Ok(b":1}\r\n\nevent: x\ndata: caf\xc3".as_slice()),
+            Ok(b"\xa9\n\ndata: last".as_slice()),
+        ];
+        let data: Vec<_> = sse_data(futures::stream::iter(chunks)).collect().await;
+        assert_eq!(data, [Ok("{\"a\":1}".to_string()), Ok("café".to_string()), Ok("last".to_string())]);
+    }
+
+    #[tokio::test]
+    async fn test_openai_stream_yields_deltas_until_done() {
+        let base_url = stub_stream(
+            "data: {\"choices\":[{\"delta\":{\"role\":\"assistant\",\"content\":\"\"}}]}\n\n\
+             data: {\"choices\":[{\"delta\":{\"content\":\"Hel\"}}]}\n\n\
+             : keep-alive\n\n\
+             data: {\"choices\":[{\"delta\":{\"content\":\"lo\"}}]}\n\n\
+             data: [DONE]\n\n\
+             data: {\"choices\":[{\"delta\":{\"content\":\"ignored\"}}]}\n\n",
+        )
+        .await;
+        let mut config = Config::from_env();
+        config.openai.base_url = base_url;
+        let provider = build_provider(&Provider::OpenAI, &config, Client::new());
+        let deltas = provider.stream("gpt-4o-mini", &conversation(), &options()).await.unwrap();
+        assert_eq!(collect(deltas).await, [Ok("Hel".to_string()), Ok("lo".to_string())]);
+    }
+
+    #[tokio::test]
+    async fn test_anthropic_stream_stops_at_error_event() {
+        let base_url = stub_stream(
+            "event: message_start\ndata: {\"type\":\"message_start\"}\n\n\
+             event: content_block_delta\ndata: {\"type\":\"content_block_delta\",\"delta\":{\"type\":\"text_delta\",\"text\":\"Hi\"}}\n\n\
+             event: error\ndata: {\"type\":\"error\",\"error\":{\"type\":\"overloaded_error\"}}\n\n\
+             event: content_block_delta\ndata: {\"type\":\"content_block_delta\",\"delta\":{\"type\":\"text_delta\",\"text\":\"!\"}}\n\n",
+        )
+        .await;
+        let mut config = Config::from_env();
+        config.anthropic.base_url = base_url;
+        let provider = build_provider(&Provider::Anthropic, &config, Client::new());
+        let deltas = collect(provider.stream("claude-3-5-haiku", &conversation(), &options()).await.unwrap()).await;
+        assert_eq!(deltas.len(), 2);
+        assert_eq!(deltas[0], Ok("Hi".to_string()));
+        assert!(deltas[1].as_ref().unwrap_err().contains("overloaded_error"), "{:?}", deltas[1]);
+    }
+
+    #[tokio::test]
+    async fn test_provider_failures_are_errors() {
+        let (base_url, _) = stub_provider(json!({"choices": []})).await;
+        let mut config = Config::from_env();
+        config.openai.base_url = base_url;
+        let error = build_provider(&Provider::OpenAI, &config, Client::new())
+            .complete("gpt-4o-mini", &conversation(), &options())
+            .await
+            .unwrap_err();
+        assert!(error.to_string().contains("no assistant text"), "{}", error);
+
+        config.meta.base_url = String::new();
+        let error = build_provider(&Provider::Meta, &config, Client::new())
+            .complete("llama-3", &conversation(), &options())
+            .await
+            .unwrap_err();
+        assert!(error.to_string().contains("No base URL configured for meta"), "{}", error);
+    }
+
+    /// Provider answering after `delay`, noting whether it ever finished
+    struct DelayedProvider {
+        delay: std::time::Duration,
+        reply: Result<&'static str, &'static str>,
+        finished: Arc<std::sync::atomic::AtomicBool>,
+    }
+
+    #[async_trait]
+    impl ChatProvider for DelayedProvider {
+        async fn complete(&self, model: &str, _: &[ChatMessage], _: &InvokeOptions) -> Result<CompletionResult> {
+            tokio::time::sleep(self.delay).await;
+            self.finished.store(true, std::sync::atomic::Ordering::SeqCst);
+            let text = self.reply.map_err(|error| anyhow!(error))?;
+            Ok(CompletionResult {
+                text: text.to_string(),
+                reasoning: None,
+                model: model.to_string(),
+            })
+        }
+    }
+
+    fn delayed(millis: u64, reply: Result<&'static str, &'static str>) -> DelayedProvider {
+        DelayedProvider {
+            delay: std::time::Duration::from_millis(millis),
+            reply,
+            finished: Arc::default(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_race_returns_faster_reply_and_cancels_slower() {
+        use std::sync::atomic::Ordering;
+
+        let slow = delayed(300, Ok("slow"));
+        let fast = delayed(20, Ok("fast"));
+        let spare = delayed(0, Ok("never raced"));
+        let none = InvokeOptions::default();
+        let contenders: [(&dyn ChatProvider, &str, &InvokeOptions); 3] =
+            [(&slow, "slow-model", &none), (&fast, "fast-model", &none), (&spare, "spare", &none)];
+
+        let (winner, result) = race_complete(&contenders, &conversation(), &options()).await.unwrap();
+        assert_eq!(winner, 1);
+        assert_eq!(result.text, "fast");
+        assert_eq!(result.model, "fast-model");
+
+        // The slower request was dropped rather than left running
+        tokio::time::sleep(std::time::Duration::from_millis(400)).await;
+        assert!(!slow.finished.load(Ordering::SeqCst));
+        assert!(!spare.finished.load(Ordering::SeqCst));
+    }
+
+    #[tokio::test]
+    async fn test_race_falls_back_when_faster_contender_fails() {
+        let failing = delayed(0, Err("upstream down"));
+        let healthy = delayed(20, Ok("healthy"));
+        let none = InvokeOptions::default();
+        let contenders: [(&dyn ChatProvider, &str, &InvokeOptions); 2] = [(&failing, "a", &none), (&healthy, "b", &none)];
+        let (winner, result) = race_complete(&contenders, &conversation(), &options()).await.unwrap();
+        assert_eq!((winner, result.text.as_str()), (1, "healthy"));
+
+        let contenders: [(&dyn ChatProvider, &str, &InvokeOptions); 1] = [(&failing, "a", &none)];
+        let error = race_complete(&contenders, &conversation(), &options()).await.unwrap_err();
+        assert_eq!(error.to_string(), "upstream down");
+        assert!(race_complete(&[], &conversation(), &options()).await.is_err());
+    }
+}
@@ -0,0 +1,398 @@
+//! Rate Limiting Module
+//!
+//! This module provides the storage abstraction and limiters used to enforce
+//! request quotas:
+//! - `RateLimitStore` trait for pluggable counter storage
+//! - In-memory store implementation with daily (UTC) and sliding windows
+//! - Per-user daily limiter with tier-specific limits
+//! - Per-IP per-minute limiter (`PER_IP_RPM`)
+//!
+//! Guest (unauthenticated) limits are enforced separately in the HTTP layer;
+//! this module covers registered users keyed by `user_id`.
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+
+use crate::clock::{next_utc_day_start_ms, system_clock, SharedClock};
+
+/// Outcome of a rate limit check
+#[derive(Debug, Clone, PartialEq)]
+pub struct RateLimitDecision {
+    /// Whether the request is allowed (under the limit)
+    pub allowed: bool,
+    /// Configured limit for the window
+    pub limit: u32,
+    /// Remaining requests in the current window
+    pub remaining: u32,
+    /// Timestamp when the window resets (milliseconds since epoch)
+    pub reset_at: u64,
+}
+
+/// Storage backend for rate limit counters
+///
+/// Implementations must be thread-safe since a single store is shared
+/// across all request handlers. The in-memory store is the default;
+/// a shared store (e.g. Redis) can be swapped in for multi-instance deployments.
+pub trait RateLimitStore: Send + Sync {
+    /// Record a request against `key` in the current daily window
+    ///
+    /// # Arguments
+    /// * `key` - Identifier being limited (user id, IP, etc.)
+    /// * `limit` - Maximum requests allowed per window
+    /// * `now_ms` - Current timestamp in milliseconds
+    ///
+    /// # Returns
+    /// Decision describing whether the request is allowed and the window state
+    fn hit_daily(&self, key: &str, limit: u32, now_ms: u64) -> RateLimitDecision;
+
+    /// Record a request against `key` in a sliding window ending at `now_ms`
+    ///
+    /// Only requests from the last `window_ms` count towards `limit`;
+    /// rejected requests are not recorded.
+    ///
+    /// # Returns
+    /// Decision whose `reset_at` is when the oldest counted request leaves
+    /// the window
+    fn hit_sliding(&self, key: &str, limit: u32, window_ms: u64, now_ms: u64) -> RateLimitDecision;
+
+    /// Give back one request recorded by `hit_daily` in the current window
+    ///
+    /// Nothing is refunded once the window has reset.
+    fn refund_daily(&self, key: &str, now_ms: u64);
+}
+
+/// Number of keys in a map after which expired or idle keys are pruned
+const PRUNE_THRESHOLD: usize = 10_000;
+
+/// Usage counter for a single key within a fixed window
+#[derive(Debug, Clone)]
+struct UsageWindow {
+    /// Number of requests counted in this window
+    count: u32,
+    /// Unix timestamp (milliseconds) when the counter resets
+    reset_at: u64,
+}
+
+/// In-memory rate limit store backed by a mutex-protected map
+///
+/// Counters are lost on restart, which is acceptable for daily quotas
+/// on a single instance.
+#[derive(Default)]
+pub struct InMemoryRateLimitStore {
+    windows: Mutex<HashMap<String, UsageWindow>>,
+    /// Timestamps of the requests counted in each key's sliding window
+    recent: Mutex<HashMap<String, VecDeque<u64>>>,
+}
+
+impl InMemoryRateLimitStore {
+    /// Create an empty in-memory store
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl RateLimitStore for InMemoryRateLimitStore {
+    fn hit_daily(&self, key: &str, limit: u32, now_ms: u64) -> RateLimitDecision {
+        let mut windows = self.windows.lock().unwrap();
+
+        // Keep memory bounded by dropping windows that have already reset
+        if windows.len() >= PRUNE_THRESHOLD {
+            windows.retain(|_, window| window.reset_at > now_ms);
+        }
+
+        let entry = windows.entry(key.to_string()).or_insert_with(|| UsageWindow {
+            count: 0,
+            reset_at: next_utc_day_start_ms(now_ms),
+        });
+
+        // Start a fresh window once the previous one has expired
+        if now_ms >= entry.reset_at {
+            *entry = UsageWindow {
+                count: 0,
+                reset_at: next_utc_day_start_ms(now_ms),
+            };
+        }
+
+        if entry.count >= limit {
+            return RateLimitDecision {
+                allowed: false,
+                limit,
+                remaining: 0,
+                reset_at: entry.reset_at,
+            };
+        }
+
+        entry.count += 1;
+        RateLimitDecision {
+            allowed: true,
+            limit,
+            remaining: limit.saturating_sub(entry.count),
+            reset_at: entry.reset_at,
+        }
+    }
+
+    fn hit_sliding(&self, key: &str, limit: u32, window_ms: u64, now_ms: u64) -> RateLimitDecision {
+        let mut recent = self.recent.lock().unwrap();
+        let window_start = now_ms.saturating_sub(window_ms);
+
+        // Keep memory bounded by dropping keys with no requests in the window
+        if recent.len() >= PRUNE_THRESHOLD {
+            recent.retain(|_, hits| hits.back().is_some_and(|&last| last > window_start));
+        }
+
+        let hits = recent.entry(key.to_string()).or_default();
+        while hits.front().is_some_and(|&first| first <= window_start) {
+            hits.pop_front();
+        }
+
+        let allowed = (hits.len() as u64) < u64::from(limit);
+        if allowed {
+            hits.push_back(now_ms);
+        }
+        RateLimitDecision {
+            allowed,
+            limit,
+            remaining: limit.saturating_sub(hits.len() as u32),
+            reset_at: hits.front().map_or(now_ms, |&first| first + window_ms),
+        }
+    }
+
+    fn refund_daily(&self, key: &str, now_ms: u64) {
+        let mut windows = self.windows.lock().unwrap();
+        if let Some(entry) = windows.get_mut(key) {
+            if now_ms < entry.reset_at {
+                entry.count = entry.count.saturating_sub(1);
+            }
+        }
+    }
+}
+
+/// Daily request limiter for registered users
+///
+/// Limits are looked up by subscription tier (e.g. "free", "premium").
+/// Tiers without a configured limit are unlimited.
+#[derive(Clone)]
+pub struct UserRateLimiter {
+    /// Shared counter storage
+    store: Arc<dyn RateLimitStore>,
+    /// Daily request limit per subscription tier
+    tier_limits: HashMap<String, u32>,
+    /// Time source for daily windows
+    clock: SharedClock,
+}
+
+impl UserRateLimiter {
+    /// Create a limiter over the given store and tier limits
+    pub fn new(store: Arc<dyn RateLimitStore>, tier_limits: HashMap<String, u32>) -> Self {
+        Self {
+            store,
+            tier_limits,
+            clock: system_clock(),
+        }
+    }
+
+    /// Use the given clock instead of the system clock
+    pub fn with_clock(mut self, clock: SharedClock) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Check and record a request for a registered user
+    ///
+    /// # Arguments
+    /// * `user_id` - Registered user identifier
+    /// * `tier` - User's subscription tier
+    ///
+    /// # Returns
+    /// `None` when the tier is unlimited, otherwise the limit decision
+    pub fn check_user_daily_limit(&self, user_id: &str, tier: &str) -> Option<RateLimitDecision> {
+        self.check_user_daily_limit_at(user_id, tier, self.clock.now_ms())
+    }
+
+    /// Same as `check_user_daily_limit` with an explicit timestamp
+    pub fn check_user_daily_limit_at(
+        &self,
+        user_id: &str,
+        tier: &str,
+        now_ms: u64,
+    ) -> Option<RateLimitDecision> {
+        let limit = *self.tier_limits.get(tier)?;
+        Some(self.store.hit_daily(&user_key(user_id), limit, now_ms))
+    }
+
+    /// Give back a request counted by `check_user_daily_limit`, e.g. when
+    /// it was answered from another request's result
+    pub fn refund_user_daily_limit(&self, user_id: &str, tier: &str) {
+        if self.tier_limits.contains_key(tier) {
+            self.store.refund_daily(&user_key(user_id), self.clock.now_ms());
+        }
+    }
+}
+
+/// Store key of a registered user's daily counter
+fn user_key(user_id: &str) -> String {
+    format!("user:{}", user_id)
+}
+
+/// Length of the per-IP sliding window in milliseconds
+const IP_WINDOW_MS: u64 = 60_000;
+
+/// Coarse per-minute request limiter keyed by client IP
+///
+/// Applies to every caller regardless of authentication, as a guard
+/// against a single address flooding the service.
+#[derive(Clone)]
+pub struct IpRateLimiter {
+    /// Shared counter storage
+    store: Arc<dyn RateLimitStore>,
+    /// Requests allowed per IP in any 60 second window (0 disables)
+    rpm: u32,
+    /// Time source for the sliding window
+    clock: SharedClock,
+}
+
+impl IpRateLimiter {
+    /// Create a limiter allowing `rpm` requests per minute per IP
+    pub fn new(store: Arc<dyn RateLimitStore>, rpm: u32) -> Self {
+        Self {
+            store,
+            rpm,
+            clock: system_clock(),
+        }
+    }
+
+    /// Use the given clock instead of the system clock
+    pub fn with_clock(mut self, clock: SharedClock) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Check and record a request from `ip`
+    ///
+    /// # Returns
+    /// `None` when the limiter is disabled, otherwise the limit decision
+    pub fn check_ip(&self, ip: &str) -> Option<RateLimitDecision> {
+        self.check_ip_at(ip, self.clock.now_ms())
+    }
+
+    /// Same as `check_ip` with an explicit timestamp
+    pub fn check_ip_at(&self, ip: &str, now_ms: u64) -> Option<RateLimitDecision> {
+        if self.rpm == 0 {
+            return None;
+        }
+        let key = format!("ip:{}", ip);
+        Some(self.store.hit_sliding(&key, self.rpm, IP_WINDOW_MS, now_ms))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limiter(limits: &[(&str, u32)]) -> UserRateLimiter {
+        let tier_limits = limits
+            .iter()
+            .map(|(tier, limit)| (tier.to_string(), *limit))
+            .collect();
+        UserRateLimiter::new(Arc::new(InMemoryRateLimitStore::new()), tier_limits)
+    }
+
+    #[test]
+    fn test_user_blocked_after_daily_limit() {
+        let limiter = limiter(&[("free", 2)]);
+        let now = 1640995200000;
+
+        let first = limiter.check_user_daily_limit_at("user_1", "free", now).unwrap();
+        assert!(first.allowed);
+        assert_eq!(first.remaining, 1);
+
+        let second = limiter.check_user_daily_limit_at("user_1", "free", now).unwrap();
+        assert!(second.allowed);
+        assert_eq!(second.remaining, 0);
+
+        let third = limiter.check_user_daily_limit_at("user_1", "free", now).unwrap();
+        assert!(!third.allowed);
+        assert_eq!(third.remaining, 0);
+        assert_eq!(third.reset_at, 1641081600000);
+    }
+
+    #[test]
+    fn test_user_limits_are_per_user_and_reset_daily() {
+        let limiter = limiter(&[("free", 1)]);
+        let now = 1640995200000;
+
+        assert!(limiter.check_user_daily_limit_at("user_1", "free", now).unwrap().allowed);
+        assert!(!limiter.check_user_daily_limit_at("user_1", "free", now).unwrap().allowed);
+
+        // Other users have their own counters
+        assert!(limiter.check_user_daily_limit_at("user_2", "free", now).unwrap().allowed);
+
+        // Counter resets on the next UTC day
+        let tomorrow = next_utc_day_start_ms(now);
+        assert!(limiter.check_user_daily_limit_at("user_1", "free", tomorrow).unwrap().allowed);
+    }
+
+    #[test]
+    fn test_refund_returns_one_request_in_the_current_window() {
+        let store = InMemoryRateLimitStore::new();
+        let now = 1640995200000;
+
+        assert!(store.hit_daily("user:1", 1, now).allowed);
+        assert!(!store.hit_daily("user:1", 1, now).allowed);
+        store.refund_daily("user:1", now);
+        assert!(store.hit_daily("user:1", 1, now).allowed);
+
+        // A refund after the window reset leaves the new window alone
+        let tomorrow = next_utc_day_start_ms(now);
+        store.refund_daily("user:1", tomorrow);
+        assert!(!store.hit_daily("user:1", 1, now).allowed);
+        store.refund_daily("user:unknown", now);
+    }
+
+    #[test]
+    fn test_expired_daily_windows_are_pruned() {
+        let store = InMemoryRateLimitStore::new();
+        let now = 1640995200000;
+        for user in 0..PRUNE_THRESHOLD {
+            store.hit_daily(&format!("user:{}", user), 1, now);
+        }
+        assert_eq!(store.windows.lock().unwrap().len(), PRUNE_THRESHOLD);
+
+        // Current windows are kept; yesterday's are dropped once the map is full
+        store.hit_daily("user:0", 1, now);
+        assert_eq!(store.windows.lock().unwrap().len(), PRUNE_THRESHOLD);
+        store.hit_daily("user:new", 1, next_utc_day_start_ms(now));
+        assert_eq!(store.windows.lock().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_unlimited_tier() {
+        let limiter = limiter(&[("free", 2)]);
+        assert!(limiter.check_user_daily_limit_at("user_1", "premium", 0).is_none());
+    }
+
+    #[test]
+    fn test_ip_limit_uses_a_sliding_window() {
+        let limiter = IpRateLimiter::new(Arc::new(InMemoryRateLimitStore::new()), 2);
+        let now = 1640995200000;
+
+        assert!(limiter.check_ip_at("203.0.113.7", now).unwrap().allowed);
+        assert!(limiter.check_ip_at("203.0.113.7", now + 30_000).unwrap().allowed);
+        let blocked = limiter.check_ip_at("203.0.113.7", now + 45_000).unwrap();
+        assert!(!blocked.allowed);
+        assert_eq!(blocked.reset_at, now + 60_000);
+
+        // Other IPs are counted separately
+        assert!(limiter.check_ip_at("198.51.100.1", now + 45_000).unwrap().allowed);
+
+        // The first request has left the window, the second has not
+        assert!(limiter.check_ip_at("203.0.113.7", now + 60_000).unwrap().allowed);
+        assert!(!limiter.check_ip_at("203.0.113.7", now + 61_000).unwrap().allowed);
+    }
+
+    #[test]
+    fn test_ip_limit_disabled_at_zero() {
+        let limiter = IpRateLimiter::new(Arc::new(InMemoryRateLimitStore::new()), 0);
+        assert!(limiter.check_ip_at("203.0.113.7", 0).is_none());
+    }
+}
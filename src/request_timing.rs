@@ -0,0 +1,183 @@
+//! Request Timing Module
+//!
+//! Breaks an invoke request's latency down by phase (auth, request
+//! preparation, attachment processing, web search, provider dispatch) to
+//! show where the time goes.
+//!
+//! Each timed phase runs inside a `phase` span named after it, nested
+//! under the request's HTTP span, and the span records its
+//! `duration_ms` when the phase ends. With `REQUEST_TIMING` enabled the
+//! per-phase totals are also logged once per request as a structured
+//! summary, when the request is finished (for streams and callbacks,
+//! after the response has been sent), and returned in a `Server-Timing`
+//! response header.
+
+use serde_json::{Map, Value};
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tracing::Instrument;
+
+/// Time spent in each phase of one request
+#[derive(Clone, Default)]
+pub struct RequestTimings {
+    /// Phases in the order first recorded, with their total time
+    phases: Arc<Mutex<Vec<(&'static str, Duration)>>>,
+}
+
+impl RequestTimings {
+    /// Create an empty breakdown
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Run `future` as `phase` inside its own span and record how long it took
+    pub async fn time<F: Future>(&self, phase: &'static str, future: F) -> F::Output {
+        let span = tracing::info_span!("phase", name = phase, duration_ms = tracing::field::Empty);
+        let started = Instant::now();
+        let output = future.instrument(span.clone()).await;
+        let elapsed = started.elapsed();
+        span.record("duration_ms", millis(elapsed));
+        self.record(phase, elapsed);
+        output
+    }
+
+    /// Add `elapsed` to `phase`; repeated phases are summed
+    pub fn record(&self, phase: &'static str, elapsed: Duration) {
+        let mut phases = self.phases.lock().unwrap();
+        match phases.iter_mut().find(|(name, _)| *name == phase) {
+            Some((_, total)) => *total += elapsed,
+            None => phases.push((phase, elapsed)),
+        }
+    }
+
+    /// Recorded phases in the order first seen
+    pub fn phases(&self) -> Vec<(&'static str, Duration)> {
+        self.phases.lock().unwrap().clone()
+    }
+
+    /// Phase durations in milliseconds, as a JSON object
+    pub fn summary(&self) -> Value {
+        let phases: Map<String, Value> = self
+            .phases()
+            .into_iter()
+            .map(|(phase, elapsed)| (phase.to_string(), Value::from(millis(elapsed))))
+            .collect();
+        Value::Object(phases)
+    }
+
+    /// Log the breakdown for `request_id` as one structured event
+    pub fn log_summary(&self, request_id: &str) {
+        let total: Duration = self.phases().iter().map(|(_, elapsed)| *elapsed).sum();
+        tracing::info!(
+            request_id,
+            phases = %self.summary(),
+            total_ms = millis(total),
+            "request timing"
+        );
+    }
+
+    /// Guard that logs the breakdown for `request_id` when dropped
+    ///
+    /// Move it into whatever finishes the request last, such as a stream
+    /// or a background task, so the summary covers the whole request.
+    pub fn log_on_drop(&self, request_id: &str) -> TimingSummary {
+        TimingSummary {
+            timings: self.clone(),
+            request_id: request_id.to_string(),
+        }
+    }
+
+    /// `Server-Timing` header value, e.g. `auth;dur=0.120, provider;dur=35.002`
+    pub fn server_timing(&self) -> String {
+        self.phases()
+            .into_iter()
+            .map(|(phase, elapsed)| format!("{};dur={:.3}", phase, millis(elapsed)))
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
+
+/// Logs a request's timing breakdown when dropped
+///
+/// See `RequestTimings::log_on_drop`.
+pub struct TimingSummary {
+    timings: RequestTimings,
+    request_id: String,
+}
+
+impl Drop for TimingSummary {
+    fn drop(&mut self) {
+        self.timings.log_summary(&self.request_id);
+    }
+}
+
+/// Duration in fractional milliseconds
+fn millis(elapsed: Duration) -> f64 {
+    elapsed.as_secs_f64() * 1000.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_phases_are_recorded_in_order_and_summed() {
+        let timings = RequestTimings::new();
+        let value = timings
+            .time("search", async {
+                tokio::time::sleep(Duration::from_millis(5)).await;
+                7
+            })
+            .await;
+        assert_eq!(value, 7);
+        timings.record("auth", Duration::from_millis(2));
+        timings.record("search", Duration::from_millis(10));
+
+        let phases = timings.phases();
+        assert_eq!(phases.iter().map(|(phase, _)| *phase).collect::<Vec<_>>(), ["search", "auth"]);
+        assert!(phases[0].1 >= Duration::from_millis(15));
+        assert_eq!(timings.summary()["auth"], 2.0);
+    }
+
+    #[test]
+    fn test_summary_is_logged_when_the_guard_is_dropped() {
+        #[derive(Clone, Default)]
+        struct Captured(Arc<Mutex<Vec<u8>>>);
+        impl std::io::Write for Captured {
+            fn write(&mut self, bytes: &[u8]) -> std::io::Result<usize> {
+                self.0.lock().unwrap().extend_from_slice(bytes);
+                Ok(bytes.len())
+            }
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+        let captured = Captured::default();
+        let writer = captured.clone();
+        let subscriber = tracing_subscriber::fmt().with_writer(move || writer.clone()).finish();
+
+        tracing::subscriber::with_default(subscriber, || {
+            let timings = RequestTimings::new();
+            let summary = timings.log_on_drop("req-1");
+            timings.record("files", Duration::from_millis(3));
+            assert!(captured.0.lock().unwrap().is_empty());
+
+            // Phases recorded before the guard goes are included
+            drop(summary);
+        });
+        let output = String::from_utf8(captured.0.lock().unwrap().clone()).unwrap();
+        assert!(output.contains("request timing"), "{}", output);
+        assert!(output.contains("req-1") && output.contains("files"), "{}", output);
+    }
+
+    #[test]
+    fn test_server_timing_header() {
+        let timings = RequestTimings::new();
+        assert_eq!(timings.server_timing(), "");
+
+        timings.record("auth", Duration::from_micros(120));
+        timings.record("provider", Duration::from_millis(35));
+        assert_eq!(timings.server_timing(), "auth;dur=0.120, provider;dur=35.000");
+    }
+}
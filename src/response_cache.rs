@@ -0,0 +1,161 @@
+//! Response Cache Module
+//!
+//! Deterministic requests (temperature 0) for the same provider, model,
+//! messages and options produce the same completion, so with
+//! `CACHE_DETERMINISTIC_RESPONSES` enabled their provider responses are
+//! cached for `RESPONSE_CACHE_TTL_SECS` and reused instead of paying for
+//! another provider call. At most `RESPONSE_CACHE_MAX_ENTRIES` responses
+//! are kept; the least recently used is evicted first.
+//!
+//! Unlike request deduplication this is not scoped to a caller: any
+//! identical deterministic request is served from the cache.
+
+use lru::LruCache;
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::num::NonZeroUsize;
+use std::sync::{Arc, Mutex};
+
+use crate::clock::{system_clock, SharedClock};
+use crate::types::{ChatMessage, InvokeOptions};
+
+/// Whether a request with these effective options is deterministic
+pub fn is_deterministic(options: &InvokeOptions) -> bool {
+    options.temperature == Some(0.0)
+}
+
+/// Cache key for a provider request
+///
+/// SHA-256 over the provider, model, messages and effective options, so
+/// the key does not retain prompt contents. The provider is part of the
+/// key because providers may serve different models under the same name.
+pub fn response_cache_key(provider: &str, model: &str, messages: &[ChatMessage], options: &InvokeOptions) -> String {
+    let material = json!({
+        "provider": provider,
+        "model": model,
+        "messages": messages,
+        "options": options,
+    });
+    format!("{:x}", Sha256::digest(material.to_string().as_bytes()))
+}
+
+/// Bounded LRU cache of provider responses with a TTL
+#[derive(Clone)]
+pub struct ResponseCache {
+    /// How long a cached response is served, in milliseconds
+    ttl_ms: u64,
+    /// Cached responses by key with the time they were stored (ms)
+    entries: Arc<Mutex<LruCache<String, (Value, u64)>>>,
+    /// Time source for expiry
+    clock: SharedClock,
+}
+
+impl ResponseCache {
+    /// Create an empty cache with the given TTL in seconds, holding at
+    /// most `max_entries` responses (at least one)
+    pub fn new(ttl_secs: u64, max_entries: usize) -> Self {
+        let capacity = NonZeroUsize::new(max_entries.max(1)).unwrap_or(NonZeroUsize::MIN);
+        Self {
+            ttl_ms: ttl_secs.saturating_mul(1000),
+            entries: Arc::new(Mutex::new(LruCache::new(capacity))),
+            clock: system_clock(),
+        }
+    }
+
+    /// Use the given clock for expiry instead of the system clock
+    pub fn with_clock(mut self, clock: SharedClock) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Cached response for `key`, if still fresh; an expired entry is
+    /// removed
+    pub fn get(&self, key: &str) -> Option<Value> {
+        let now = self.clock.now_ms();
+        let mut entries = self.entries.lock().unwrap();
+        let (value, stored_at) = entries.get(key)?;
+        if now.saturating_sub(*stored_at) < self.ttl_ms {
+            return Some(value.clone());
+        }
+        entries.pop(key);
+        None
+    }
+
+    /// Store a response, evicting the least recently used one when full
+    pub fn insert(&self, key: String, value: Value) {
+        let now = self.clock.now_ms();
+        self.entries.lock().unwrap().put(key, (value, now));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::MockClock;
+    use crate::types::MessageRole;
+    use std::time::Duration;
+
+    fn messages(content: &str) -> Vec<ChatMessage> {
+        vec![ChatMessage {
+            role: MessageRole::User,
+            content: content.to_string(),
+        }]
+    }
+
+    fn options(temperature: Option<f32>) -> InvokeOptions {
+        InvokeOptions {
+            temperature,
+            max_tokens: Some(100),
+        }
+    }
+
+    #[test]
+    fn test_only_zero_temperature_is_deterministic() {
+        assert!(is_deterministic(&options(Some(0.0))));
+        assert!(!is_deterministic(&options(Some(0.7))));
+        assert!(!is_deterministic(&options(None)));
+    }
+
+    #[test]
+    fn test_cache_key_covers_provider_model_messages_and_options() {
+        let key = response_cache_key("openai", "gpt-4o-mini", &messages("Hi"), &options(Some(0.0)));
+        assert_eq!(key, response_cache_key("openai", "gpt-4o-mini", &messages("Hi"), &options(Some(0.0))));
+        assert_eq!(key.len(), 64);
+        assert!(!key.contains("Hi"));
+
+        assert_ne!(key, response_cache_key("openrouter", "gpt-4o-mini", &messages("Hi"), &options(Some(0.0))));
+        assert_ne!(key, response_cache_key("openai", "gpt-4o", &messages("Hi"), &options(Some(0.0))));
+        assert_ne!(key, response_cache_key("openai", "gpt-4o-mini", &messages("Hello"), &options(Some(0.0))));
+        let mut longer = options(Some(0.0));
+        longer.max_tokens = Some(200);
+        assert_ne!(key, response_cache_key("openai", "gpt-4o-mini", &messages("Hi"), &longer));
+    }
+
+    #[test]
+    fn test_entries_expire_after_ttl() {
+        let clock = Arc::new(MockClock::new(1_000_000));
+        let cache = ResponseCache::new(60, 10).with_clock(clock.clone());
+        cache.insert("key".to_string(), json!({"message": "cached"}));
+
+        clock.advance(Duration::from_secs(59));
+        assert_eq!(cache.get("key"), Some(json!({"message": "cached"})));
+
+        // Expired entries are dropped when they are looked up
+        clock.advance(Duration::from_secs(1));
+        assert_eq!(cache.get("key"), None);
+        assert!(cache.entries.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_least_recently_used_entry_is_evicted_when_full() {
+        let cache = ResponseCache::new(60, 2);
+        cache.insert("first".to_string(), json!(1));
+        cache.insert("second".to_string(), json!(2));
+        assert_eq!(cache.get("first"), Some(json!(1)));
+
+        cache.insert("third".to_string(), json!(3));
+        assert_eq!(cache.get("second"), None);
+        assert_eq!(cache.get("first"), Some(json!(1)));
+        assert_eq!(cache.get("third"), Some(json!(3)));
+    }
+}
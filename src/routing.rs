@@ -1,26 +1,161 @@
 use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
 
-use crate::types::{Provider, RouteTarget};
+use crate::types::{InvokeOptions, Provider, RouteTarget};
 
+/// Targets for each route in the order they are tried; key = `${op}.${tier}`
 #[allow(dead_code)]
-pub type RoutingMap = HashMap<String, RouteTarget>; // key = `${op}.${tier}`
+pub type RoutingMap = HashMap<String, Vec<RouteTarget>>;
+
+/// Routing map shared by request handlers and replaceable at runtime
+/// 
+/// Readers take a snapshot, so a request keeps the routes it started with
+/// while a reload swaps in a new map.
+#[derive(Clone, Default)]
+pub struct SharedRouting(Arc<RwLock<Arc<RoutingMap>>>);
+
+impl SharedRouting {
+    pub fn new(routes: RoutingMap) -> Self {
+        Self(Arc::new(RwLock::new(Arc::new(routes))))
+    }
+    
+    /// Current routes
+    pub fn load(&self) -> Arc<RoutingMap> {
+        self.0.read().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+    
+    /// Replace every route at once, returning how many are now configured
+    pub fn replace(&self, routes: RoutingMap) -> usize {
+        let count = routes.len();
+        *self.0.write().unwrap_or_else(|e| e.into_inner()) = Arc::new(routes);
+        count
+    }
+}
+
+/// Default cap on the number of route entries parsed from `ROUTES`
+pub const DEFAULT_MAX_ROUTES: usize = 256;
+
+/// Default model name prefixes treated as accepting image input
+pub const DEFAULT_MULTIMODAL_MODELS: &str =
+    "gpt-4o,gpt-4-turbo,gpt-4.1,claude-3,grok-2-vision,pixtral,llama-3.2-11b-vision,llama-3.2-90b-vision";
+
+/// Default model name prefixes treated as reasoning models
+pub const DEFAULT_REASONING_MODELS: &str = "o1,o3,o4-mini,deepseek-reasoner,deepseek-r1";
+
+/// Result of parsing a routing configuration with diagnostics
+#[derive(Debug, Clone)]
+pub struct RoutingBuild {
+    /// Parsed routes keyed by `op.tier`
+    pub routes: RoutingMap,
+    /// Human-readable warnings raised while parsing
+    pub warnings: Vec<String>,
+}
+
+impl RoutingBuild {
+    /// Check that at least one route was parsed
+    /// 
+    /// An empty map means every invoke request fails, usually because
+    /// every `ROUTES` entry was malformed.
+    /// 
+    /// # Returns
+    /// A warning when no routes were parsed and `strict` is off
+    /// 
+    /// # Errors
+    /// When no routes were parsed and `strict` is on
+    pub fn check_nonempty(&self, strict: bool) -> Result<Option<String>, String> {
+        if !self.routes.is_empty() {
+            return Ok(None);
+        }
+        let message = "ROUTES produced no valid routes; every invoke request will fail".to_string();
+        if strict {
+            Err(format!("{} (STRICT_ROUTES is enabled)", message))
+        } else {
+            Ok(Some(message))
+        }
+    }
+}
 
 #[allow(dead_code)]
 pub fn build_routing(routes_raw: &str) -> RoutingMap {
+    let build = build_routing_checked(routes_raw, DEFAULT_MAX_ROUTES);
+    for warning in &build.warnings {
+        tracing::warn!("{}", warning);
+    }
+    build.routes
+}
+
+/// Parse a routing configuration, stopping after `max_routes` valid entries
+/// 
+/// Bare-provider routes are skipped, as no default models are known; see
+/// `build_routing_with_defaults`.
+#[allow(dead_code)]
+pub fn build_routing_checked(routes_raw: &str, max_routes: usize) -> RoutingBuild {
+    build_routing_with_defaults(routes_raw, max_routes, &HashMap::new())
+}
+
+/// Parse a routing configuration, stopping after `max_routes` valid entries
+/// 
+/// Entries beyond the cap are ignored and reported as a warning so a
+/// pathological `ROUTES` value cannot bloat memory.
+/// 
+/// Entries sharing a provider can be grouped: `group:openai => chat.fast=gpt-4o-mini,
+/// fim.fast=gpt-4o-mini` expands to `chat.fast=openai:gpt-4o-mini,fim.fast=openai:gpt-4o-mini`.
+/// A group applies to the following entries that give no provider, until the
+/// next group starts; entries with an explicit `provider:model` are unaffected.
+/// 
+/// Outside a group, an entry may name only a provider (`chat.fast=openai`)
+/// and use that provider's model from `default_models`
+/// (`PROVIDER_DEFAULT_MODELS`, keyed by provider id). Such a target is
+/// skipped with a warning when the provider has no default model.
+/// 
+/// An entry may list fallback targets separated by `|`
+/// (`chat.smart=anthropic:claude-3-5-sonnet|openai:gpt-4o`), tried in that
+/// order. Empty and invalid targets are skipped, and route options apply to
+/// every target. An entry without a valid target is ignored.
+pub fn build_routing_with_defaults(
+    routes_raw: &str,
+    max_routes: usize,
+    default_models: &HashMap<String, String>,
+) -> RoutingBuild {
     let mut map = HashMap::new();
+    let mut warnings = Vec::new();
+    let mut parsed = 0;
+    let mut group_provider = None;
     
     for pair in routes_raw.split(',') {
-        let trimmed = pair.trim();
+        let mut trimmed = pair.trim();
+        
+        // A group header starts a group and may carry its first entry
+        if let Some(group) = trimmed.strip_prefix("group:") {
+            match group.split_once("=>") {
+                Some((provider, first)) => {
+                    group_provider = Some(normalize_provider(provider.trim()));
+                    trimmed = first.trim();
+                }
+                None => {
+                    warnings.push(format!("Route group '{}' is missing '=>'; ignoring it", group.trim()));
+                    group_provider = None;
+                    continue;
+                }
+            }
+        }
+        
         if trimmed.is_empty() || !trimmed.contains('=') {
             continue;
         }
         
-        let parts: Vec<&str> = trimmed.split('=').map(|s| s.trim()).collect();
-        if parts.len() != 2 {
+        // Route options follow the target: `op.tier=provider:model;key=value;...`
+        let (lhs, rhs) = match trimmed.split_once('=') {
+            Some((lhs, rhs)) => (lhs.trim(), rhs.trim()),
+            None => continue,
+        };
+        let mut segments = rhs.split(';');
+        let target = segments.next().unwrap_or_default().trim();
+        if target.contains('=') {
             continue;
         }
         
-        let lhs_parts: Vec<&str> = parts[0].split('.').map(|s| s.trim()).collect();
+        let lhs_parts: Vec<&str> = lhs.split('.').map(|s| s.trim()).collect();
         if lhs_parts.len() != 2 {
             continue;
         }
@@ -28,49 +163,279 @@ pub fn build_routing(routes_raw: &str) -> RoutingMap {
         let op = lhs_parts[0];
         let tier = lhs_parts[1];
         
-        let (provider, model) = if parts[1].contains(':') {
-            let rhs_parts: Vec<&str> = parts[1].split(':').collect();
-            if rhs_parts.len() == 2 {
-                (normalize_provider(rhs_parts[0].trim()), rhs_parts[1].trim().to_string())
-            } else {
-                continue; // Skip invalid formats
-            }
-        } else {
-            continue; // Skip entries without colon
-        };
+        let targets: Vec<(Provider, String)> = target
+            .split('|')
+            .map(str::trim)
+            .filter(|target| !target.is_empty())
+            .filter_map(|target| {
+                parse_target(target, group_provider.as_ref(), default_models).or_else(|| {
+                    // A lone provider without a default model is worth a warning
+                    let provider = parse_provider(target).filter(|_| group_provider.is_none())?;
+                    warnings.push(format!(
+                        "Route {}.{} names no model and PROVIDER_DEFAULT_MODELS has none for {}; ignoring it",
+                        op,
+                        tier,
+                        provider.as_str()
+                    ));
+                    None
+                })
+            })
+            .collect();
+        if targets.is_empty() {
+            continue;
+        }
+        
+        if parsed >= max_routes {
+            warnings.push(format!(
+                "Route limit of {} reached; ignoring remaining route entries",
+                max_routes
+            ));
+            break;
+        }
+        parsed += 1;
         
         let key = format!("{}.{}", op, tier);
-        map.insert(key, RouteTarget { provider, model });
+        let mut defaults = InvokeOptions::default();
+        for option in segments.map(str::trim).filter(|option| !option.is_empty()) {
+            if let Err(message) = apply_route_option(&mut defaults, option) {
+                warnings.push(format!("Route {}: {}", key, message));
+            }
+        }
+        let targets = targets
+            .into_iter()
+            .map(|(provider, model)| RouteTarget {
+                provider,
+                model,
+                defaults: defaults.clone(),
+            })
+            .collect();
+        map.insert(key, targets);
     }
     
-    map
+    RoutingBuild { routes: map, warnings }
 }
 
+/// Provider and model named by one route target
+/// 
+/// Accepts `provider:model`, a bare model inside a group, or a bare
+/// provider with a default model. `None` for anything else.
+fn parse_target(
+    target: &str,
+    group_provider: Option<&Provider>,
+    default_models: &HashMap<String, String>,
+) -> Option<(Provider, String)> {
+    if target.contains(':') {
+        let (provider, model) = target.split_once(':')?;
+        if model.contains(':') {
+            return None;
+        }
+        return Some((normalize_provider(provider.trim()), model.trim().to_string()));
+    }
+    if let Some(provider) = group_provider {
+        return Some((provider.clone(), target.to_string())); // Bare model inside a group
+    }
+    let provider = parse_provider(target)?;
+    let model = default_model(default_models, &provider)?;
+    Some((provider, model))
+}
+
+/// Apply one `key=value` route option to the route's default options
+/// 
+/// Supports `temperature` (0.0-2.0) and `max_tokens` (at least 1).
+fn apply_route_option(defaults: &mut InvokeOptions, option: &str) -> Result<(), String> {
+    let (key, value) = option
+        .split_once('=')
+        .map(|(key, value)| (key.trim(), value.trim()))
+        .ok_or_else(|| format!("ignoring option '{}' without a value", option))?;
+    
+    match key {
+        "temperature" => match value.parse::<f32>() {
+            Ok(temperature) if (0.0..=2.0).contains(&temperature) => defaults.temperature = Some(temperature),
+            _ => return Err(format!("ignoring invalid temperature '{}'", value)),
+        },
+        "max_tokens" => match value.parse::<u32>() {
+            Ok(max_tokens) if max_tokens >= 1 => defaults.max_tokens = Some(max_tokens),
+            _ => return Err(format!("ignoring invalid max_tokens '{}'", value)),
+        },
+        _ => return Err(format!("ignoring unknown option '{}'", key)),
+    }
+    Ok(())
+}
+
+/// Targets configured for `op.tier`, in the order they are tried
 #[allow(dead_code)]
-pub fn resolve_route<'a>(map: &'a RoutingMap, op: &str, tier: &str) -> Option<&'a RouteTarget> {
+pub fn resolve_route<'a>(map: &'a RoutingMap, op: &str, tier: &str) -> Option<&'a [RouteTarget]> {
     let key = format!("{}.{}", op, tier);
-    map.get(&key)
+    map.get(&key).map(Vec::as_slice)
+}
+
+/// Whether `route` targets a model listed in `BLOCKED_MODELS`
+/// 
+/// Entries are `provider:model`, compared case-insensitively; provider
+/// aliases such as `cf` match as well. Entries without a provider are
+/// ignored.
+pub fn is_blocked_model(route: &RouteTarget, blocked_models: &[String]) -> bool {
+    blocked_models.iter().any(|entry| {
+        entry.split_once(':').is_some_and(|(provider, model)| {
+            parse_provider(provider).as_ref() == Some(&route.provider)
+                && model.trim().eq_ignore_ascii_case(&route.model)
+        })
+    })
+}
+
+/// Whether `model` accepts image input
+/// 
+/// Matches case-insensitively against the configured model name prefixes.
+pub fn is_multimodal_model(model: &str, multimodal_models: &[String]) -> bool {
+    matches_model_prefix(model, multimodal_models)
+}
+
+/// Whether `model` is a reasoning model (o1-style)
+/// 
+/// Reasoning models take `max_completion_tokens` instead of `max_tokens`
+/// and may return their reasoning separately from the answer. Matches
+/// case-insensitively against the configured model name prefixes.
+pub fn is_reasoning_model(model: &str, reasoning_models: &[String]) -> bool {
+    matches_model_prefix(model, reasoning_models)
+}
+
+/// Whether `model` starts with any of `prefixes`, ignoring case
+fn matches_model_prefix(model: &str, prefixes: &[String]) -> bool {
+    let model = model.to_lowercase();
+    prefixes
+        .iter()
+        .any(|prefix| model.starts_with(&prefix.to_lowercase()))
+}
+
+/// Resolve the targets for a request carrying image attachments
+/// 
+/// Text-only models are dropped from the `op.tier` targets. When none of
+/// them accepts images, the `op.vision_tier` targets are used instead.
+/// 
+/// # Returns
+/// - `Ok(None)` when no `op.tier` route is configured
+/// - `Ok(Some(targets))` with targets to try, in order
+/// - `Err(message)` when every model is text-only and no fallback route exists
+pub fn resolve_multimodal_route<'a>(
+    map: &'a RoutingMap,
+    op: &str,
+    tier: &str,
+    vision_tier: &str,
+    multimodal_models: &[String],
+) -> Result<Option<Vec<&'a RouteTarget>>, String> {
+    let targets = match resolve_route(map, op, tier) {
+        Some(targets) => targets,
+        None => return Ok(None),
+    };
+    let multimodal: Vec<&RouteTarget> = targets
+        .iter()
+        .filter(|target| is_multimodal_model(&target.model, multimodal_models))
+        .collect();
+    if !multimodal.is_empty() {
+        return Ok(Some(multimodal));
+    }
+    
+    let models = targets.iter().map(|target| target.model.as_str()).collect::<Vec<_>>().join(", ");
+    match resolve_route(map, op, vision_tier) {
+        Some(fallback) => {
+            tracing::debug!(
+                "Routing image request from {}.{} ({}) to {}.{}",
+                op, tier, models, op, vision_tier
+            );
+            Ok(Some(fallback.iter().collect()))
+        }
+        None => Err(format!(
+            "Model {} does not accept images and no {}.{} route is configured",
+            models, op, vision_tier
+        )),
+    }
 }
 
 #[allow(dead_code)]
 fn normalize_provider(provider_str: &str) -> Provider {
-    match provider_str.to_lowercase().as_str() {
-        "cf" | "cloudflare" => Provider::Cloudflare,
-        "mistral" => Provider::Mistral,
-        "openai" => Provider::OpenAI,
-        "xai" => Provider::Xai,
-        "groq" => Provider::Groq,
-        "openrouter" => Provider::OpenRouter,
-        "meta" => Provider::Meta,
-        "anthropic" => Provider::Anthropic,
-        _ => Provider::OpenAI,
+    parse_provider(provider_str).unwrap_or(Provider::OpenAI)
+}
+
+/// Provider named by `name` (case-insensitive, `cloudflare` for `cf`)
+fn parse_provider(name: &str) -> Option<Provider> {
+    match name.trim().to_lowercase().as_str() {
+        "cf" | "cloudflare" => Some(Provider::Cloudflare),
+        "mistral" => Some(Provider::Mistral),
+        "openai" => Some(Provider::OpenAI),
+        "xai" => Some(Provider::Xai),
+        "groq" => Some(Provider::Groq),
+        "openrouter" => Some(Provider::OpenRouter),
+        "meta" => Some(Provider::Meta),
+        "anthropic" => Some(Provider::Anthropic),
+        _ => None,
     }
 }
 
+/// Default model configured for `provider`, whichever alias names it
+fn default_model(default_models: &HashMap<String, String>, provider: &Provider) -> Option<String> {
+    default_models
+        .iter()
+        .find(|(name, _)| parse_provider(name).as_ref() == Some(provider))
+        .map(|(_, model)| model.clone())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::config::{parse_csv, parse_named_values};
+    use crate::types::Operation;
 
+    #[test]
+    fn test_check_nonempty() {
+        let build = build_routing_checked("chat.fast=openai:gpt-4o-mini", DEFAULT_MAX_ROUTES);
+        assert_eq!(build.check_nonempty(true), Ok(None));
+        
+        let build = build_routing_checked("chat=openai,not a route", DEFAULT_MAX_ROUTES);
+        assert!(build.routes.is_empty());
+        let warning = build.check_nonempty(false).unwrap().unwrap();
+        assert!(warning.contains("no valid routes"));
+        let error = build.check_nonempty(true).unwrap_err();
+        assert!(error.contains("STRICT_ROUTES"));
+    }
+    
+    #[test]
+    fn test_image_route_resolves_by_default_tier() {
+        let routing = build_routing("chat.fast=openai:gpt-4o-mini,image.default=openai:dall-e-3");
+        let op = Operation::ImageGen;
+        
+        let route = &resolve_route(&routing, op.as_str(), op.default_tier().as_str()).unwrap()[0];
+        assert!(matches!(route.provider, Provider::OpenAI));
+        assert_eq!(route.model, "dall-e-3");
+        assert!(resolve_route(&routing, op.as_str(), "fast").is_none());
+    }
+    
+    #[test]
+    fn test_bare_provider_route_uses_default_model() {
+        let default_models = parse_named_values(Some("openai:gpt-4o-mini, cloudflare:@cf/meta/llama-3.1-8b-instruct"));
+        let build = build_routing_with_defaults(
+            "chat.fast=openai;temperature=0.2,chat.cheap=cf,chat.smart=anthropic",
+            DEFAULT_MAX_ROUTES,
+            &default_models,
+        );
+        
+        let fast = &build.routes["chat.fast"][0];
+        assert!(matches!(fast.provider, Provider::OpenAI));
+        assert_eq!(fast.model, "gpt-4o-mini");
+        assert_eq!(fast.defaults.temperature, Some(0.2));
+        assert_eq!(build.routes["chat.cheap"][0].model, "@cf/meta/llama-3.1-8b-instruct");
+        
+        // No default for anthropic: skipped with a warning
+        assert!(!build.routes.contains_key("chat.smart"));
+        assert_eq!(build.warnings.len(), 1);
+        assert!(build.warnings[0].contains("chat.smart"), "{}", build.warnings[0]);
+        assert!(build.warnings[0].contains("anthropic"), "{}", build.warnings[0]);
+        
+        // Inside a group a bare name is still a model
+        let build = build_routing_with_defaults("group:groq => chat.fast=openai", DEFAULT_MAX_ROUTES, &default_models);
+        assert!(matches!(build.routes["chat.fast"][0].provider, Provider::Groq));
+        assert_eq!(build.routes["chat.fast"][0].model, "openai");
+    }
+    
     #[test]
     fn test_build_routing() {
         let routes_raw = "chat.fast=openai:gpt-4o-mini,chat.smart=anthropic:claude-3-5-sonnet-20241022";
@@ -78,15 +443,95 @@ mod tests {
         
         assert_eq!(routing.len(), 2);
         
-        let fast_route = routing.get("chat.fast").unwrap();
+        let fast_route = &routing["chat.fast"][0];
         assert!(matches!(fast_route.provider, Provider::OpenAI));
         assert_eq!(fast_route.model, "gpt-4o-mini");
         
-        let smart_route = routing.get("chat.smart").unwrap();
+        let smart_route = &routing["chat.smart"][0];
         assert!(matches!(smart_route.provider, Provider::Anthropic));
         assert_eq!(smart_route.model, "claude-3-5-sonnet-20241022");
     }
 
+    #[test]
+    fn test_build_routing_fallback_targets() {
+        let routing = build_routing(
+            "chat.smart=anthropic:claude-3-5-sonnet|openai:gpt-4o;temperature=0.3,chat.fast=groq:llama-3.1-8b-instant",
+        );
+        
+        let smart = resolve_route(&routing, "chat", "smart").unwrap();
+        assert_eq!(smart.len(), 2);
+        assert!(matches!(smart[0].provider, Provider::Anthropic));
+        assert_eq!(smart[0].model, "claude-3-5-sonnet");
+        assert!(matches!(smart[1].provider, Provider::OpenAI));
+        assert_eq!(smart[1].model, "gpt-4o");
+        // Route options apply to every target
+        assert!(smart.iter().all(|target| target.defaults.temperature == Some(0.3)));
+        
+        // A single target is a one-element list
+        assert_eq!(resolve_route(&routing, "chat", "fast").unwrap().len(), 1);
+    }
+    
+    #[test]
+    fn test_build_routing_skips_empty_and_invalid_fallbacks() {
+        let routing = build_routing("chat.smart= | openai:gpt-4o || no-colon | a:b:c |groq:llama-3.1-8b-instant|");
+        let smart = &routing["chat.smart"];
+        assert_eq!(smart.iter().map(|target| target.model.as_str()).collect::<Vec<_>>(), ["gpt-4o", "llama-3.1-8b-instant"]);
+        
+        // Groups and provider defaults work per target
+        let default_models = parse_named_values(Some("openai:gpt-4o-mini"));
+        let build = build_routing_with_defaults(
+            "group:mistral => code.fast=codestral|openai:gpt-4o",
+            DEFAULT_MAX_ROUTES,
+            &default_models,
+        );
+        let fast = &build.routes["code.fast"];
+        assert_eq!(fast.len(), 2);
+        assert!(matches!(fast[0].provider, Provider::Mistral));
+        assert!(matches!(fast[1].provider, Provider::OpenAI));
+        assert!(build.warnings.is_empty());
+        
+        let build = build_routing_with_defaults("chat.fast=anthropic|openai", DEFAULT_MAX_ROUTES, &default_models);
+        assert_eq!(build.routes["chat.fast"].len(), 1);
+        assert_eq!(build.routes["chat.fast"][0].model, "gpt-4o-mini");
+        assert_eq!(build.warnings.len(), 1);
+        
+        // Nothing valid at all drops the entry
+        assert!(build_routing("chat.fast=|nonsense|").is_empty());
+    }
+    
+    #[test]
+    fn test_build_routing_route_defaults() {
+        let routes_raw = "chat.fast=openai:gpt-4o-mini;temperature=0.7;max_tokens=512,fim.fast=mistral:codestral";
+        let build = build_routing_checked(routes_raw, DEFAULT_MAX_ROUTES);
+        assert!(build.warnings.is_empty());
+        
+        let fast_route = &build.routes["chat.fast"][0];
+        assert_eq!(fast_route.model, "gpt-4o-mini");
+        assert_eq!(fast_route.defaults.temperature, Some(0.7));
+        assert_eq!(fast_route.defaults.max_tokens, Some(512));
+        
+        // Plain provider:model routes have no defaults
+        let fim_route = &build.routes["fim.fast"][0];
+        assert_eq!(fim_route.model, "codestral");
+        assert_eq!(fim_route.defaults, InvokeOptions::default());
+    }
+    
+    #[test]
+    fn test_build_routing_invalid_route_options() {
+        let routes_raw = "chat.fast=openai:gpt-4o-mini; temperature = 0 ;top_p=0.9;max_tokens=0;temperature=hot";
+        let build = build_routing_checked(routes_raw, DEFAULT_MAX_ROUTES);
+        
+        // The route is kept with the valid options; the rest are reported
+        let route = &build.routes["chat.fast"][0];
+        assert_eq!(route.defaults.temperature, Some(0.0));
+        assert_eq!(route.defaults.max_tokens, None);
+        assert_eq!(build.warnings.len(), 3);
+        assert!(build.warnings[0].contains("unknown option 'top_p'"));
+        
+        // Options cannot stand in for the target
+        assert!(build_routing("chat.fast=temperature=0.7").is_empty());
+    }
+    
     #[test]
     fn test_resolve_route() {
         let routes_raw = "chat.fast=openai:gpt-4o-mini";
@@ -111,7 +556,7 @@ mod tests {
         let routing = build_routing(routes_raw);
         
         assert_eq!(routing.len(), 1);
-        let route = routing.get("fim.fast").unwrap();
+        let route = &routing["fim.fast"][0];
         assert!(matches!(route.provider, Provider::Mistral));
         assert_eq!(route.model, "codestral");
     }
@@ -148,6 +593,50 @@ mod tests {
         assert_eq!(routing.len(), 0);
     }
     
+    #[test]
+    fn test_build_routing_groups() {
+        let grouped = build_routing(
+            "group:openai => chat.fast=gpt-4o-mini, fim.fast=gpt-4o-mini;temperature=0.2, \
+             group:anthropic => chat.smart=claude-3-5-sonnet, code.smart=claude-3-opus",
+        );
+        let expanded = build_routing(
+            "chat.fast=openai:gpt-4o-mini,fim.fast=openai:gpt-4o-mini;temperature=0.2,\
+             chat.smart=anthropic:claude-3-5-sonnet,code.smart=anthropic:claude-3-opus",
+        );
+        
+        assert_eq!(grouped.len(), 4);
+        for (key, route) in &expanded {
+            let (group_route, route) = (&grouped[key][0], &route[0]);
+            assert_eq!(group_route.provider, route.provider, "{}", key);
+            assert_eq!(group_route.model, route.model, "{}", key);
+            assert_eq!(group_route.defaults.temperature, route.defaults.temperature, "{}", key);
+        }
+    }
+    
+    #[test]
+    fn test_build_routing_groups_mixed_with_plain_entries() {
+        let routes_raw = "chat.fast=groq:llama-3.1-8b-instant,\
+            group:cf => chat.smart=@cf/meta/llama-3-8b, code.fast=mistral:codestral-latest, fim.fast=@cf/qwen/coder";
+        let routing = build_routing(routes_raw);
+        
+        assert_eq!(routing.len(), 4);
+        assert!(matches!(routing["chat.fast"][0].provider, Provider::Groq));
+        assert!(matches!(routing["chat.smart"][0].provider, Provider::Cloudflare));
+        assert_eq!(routing["chat.smart"][0].model, "@cf/meta/llama-3-8b");
+        // An explicit provider inside a group wins and does not end the group
+        assert!(matches!(routing["code.fast"][0].provider, Provider::Mistral));
+        assert!(matches!(routing["fim.fast"][0].provider, Provider::Cloudflare));
+        
+        // Bare models outside any group are still rejected
+        assert!(build_routing("chat.fast=gpt-4o-mini").is_empty());
+        
+        // A malformed header is reported and does not leak its provider
+        let build = build_routing_checked("group:openai chat.fast=gpt-4o-mini, fim.fast=gpt-4o", DEFAULT_MAX_ROUTES);
+        assert!(build.routes.is_empty());
+        assert_eq!(build.warnings.len(), 1);
+        assert!(build.warnings[0].contains("missing '=>'"));
+    }
+    
     #[test]
     fn test_build_routing_whitespace_handling() {
         let routes_raw = " chat.fast = openai : gpt-4o-mini , chat.smart = anthropic : claude-3-5-sonnet ";
@@ -155,11 +644,11 @@ mod tests {
         
         assert_eq!(routing.len(), 2);
         
-        let fast_route = routing.get("chat.fast").unwrap();
+        let fast_route = &routing["chat.fast"][0];
         assert!(matches!(fast_route.provider, Provider::OpenAI));
         assert_eq!(fast_route.model, "gpt-4o-mini");
         
-        let smart_route = routing.get("chat.smart").unwrap();
+        let smart_route = &routing["chat.smart"][0];
         assert!(matches!(smart_route.provider, Provider::Anthropic));
         assert_eq!(smart_route.model, "claude-3-5-sonnet");
     }
@@ -171,7 +660,7 @@ mod tests {
         
         assert_eq!(routing.len(), 1);
         // Should use the last occurrence
-        let route = routing.get("chat.fast").unwrap();
+        let route = &routing["chat.fast"][0];
         assert!(matches!(route.provider, Provider::Anthropic));
         assert_eq!(route.model, "claude-3-5-sonnet");
     }
@@ -206,15 +695,15 @@ mod tests {
         // Valid resolutions
         let chat_fast = resolve_route(&routing, "chat", "fast");
         assert!(chat_fast.is_some());
-        assert!(matches!(chat_fast.unwrap().provider, Provider::OpenAI));
+        assert!(matches!(chat_fast.unwrap()[0].provider, Provider::OpenAI));
         
         let chat_smart = resolve_route(&routing, "chat", "smart");
         assert!(chat_smart.is_some());
-        assert!(matches!(chat_smart.unwrap().provider, Provider::Anthropic));
+        assert!(matches!(chat_smart.unwrap()[0].provider, Provider::Anthropic));
         
         let fim_fast = resolve_route(&routing, "fim", "fast");
         assert!(fim_fast.is_some());
-        assert!(matches!(fim_fast.unwrap().provider, Provider::Mistral));
+        assert!(matches!(fim_fast.unwrap()[0].provider, Provider::Mistral));
         
         // Invalid resolutions
         assert!(resolve_route(&routing, "chat", "nonexistent").is_none());
@@ -229,11 +718,11 @@ mod tests {
         let routes_raw = "test.route=xai:grok-beta,another.route=groq:llama-3.1-70b";
         let routing = build_routing(routes_raw);
         
-        let xai_route = routing.get("test.route").unwrap();
+        let xai_route = &routing["test.route"][0];
         assert!(matches!(xai_route.provider, Provider::Xai));
         assert_eq!(xai_route.model, "grok-beta");
         
-        let groq_route = routing.get("another.route").unwrap();
+        let groq_route = &routing["another.route"][0];
         assert!(matches!(groq_route.provider, Provider::Groq));
         assert_eq!(groq_route.model, "llama-3.1-70b");
     }
@@ -245,13 +734,77 @@ mod tests {
         
         assert_eq!(routing.len(), 2);
         
-        let fast_route = routing.get("chat.fast").unwrap();
+        let fast_route = &routing["chat.fast"][0];
         assert_eq!(fast_route.model, "gpt-4-0125-preview");
         
-        let smart_route = routing.get("chat.smart").unwrap();
+        let smart_route = &routing["chat.smart"][0];
         assert_eq!(smart_route.model, "claude-3-5-sonnet-20241022");
     }
     
+    #[test]
+    fn test_build_routing_checked_caps_routes() {
+        let routes_raw = (0..10)
+            .map(|i| format!("chat.tier{}=openai:gpt-4o-mini", i))
+            .collect::<Vec<_>>()
+            .join(",");
+        
+        let build = build_routing_checked(&routes_raw, 4);
+        assert_eq!(build.routes.len(), 4);
+        assert!(build.routes.contains_key("chat.tier0"));
+        assert!(build.routes.contains_key("chat.tier3"));
+        assert!(!build.routes.contains_key("chat.tier4"));
+        assert_eq!(build.warnings.len(), 1);
+        assert!(build.warnings[0].contains("Route limit of 4"));
+        
+        // Within the cap there are no warnings
+        let build = build_routing_checked(&routes_raw, 10);
+        assert_eq!(build.routes.len(), 10);
+        assert!(build.warnings.is_empty());
+    }
+    
+    #[test]
+    fn test_is_blocked_model() {
+        let blocked = parse_csv(Some("openai:gpt-4o, cf:@cf/meta/llama-3.1-70b-instruct, gpt-4-turbo"));
+        let routing = build_routing(
+            "chat.smart=openai:GPT-4o,chat.fast=openai:gpt-4o-mini,chat.big=cloudflare:@cf/meta/llama-3.1-70b-instruct,chat.turbo=openai:gpt-4-turbo,chat.other=openrouter:gpt-4o",
+        );
+        
+        assert!(is_blocked_model(&routing["chat.smart"][0], &blocked));
+        assert!(is_blocked_model(&routing["chat.big"][0], &blocked));
+        assert!(!is_blocked_model(&routing["chat.fast"][0], &blocked));
+        // Entries must name the provider
+        assert!(!is_blocked_model(&routing["chat.turbo"][0], &blocked));
+        assert!(!is_blocked_model(&routing["chat.other"][0], &blocked));
+        assert!(!is_blocked_model(&routing["chat.smart"][0], &[]));
+    }
+    
+    #[test]
+    fn test_resolve_multimodal_route_falls_back_to_vision() {
+        let multimodal = parse_csv(Some(DEFAULT_MULTIMODAL_MODELS));
+        let routing = build_routing("chat.fast=groq:llama-3.1-8b-instant,chat.smart=openai:gpt-4o,chat.vision=openai:gpt-4o-mini");
+        
+        // Text-only model switches to the vision route
+        let route = resolve_multimodal_route(&routing, "chat", "fast", "vision", &multimodal).unwrap().unwrap();
+        assert_eq!(route[0].model, "gpt-4o-mini");
+        
+        // Multimodal model is kept
+        let route = resolve_multimodal_route(&routing, "chat", "smart", "vision", &multimodal).unwrap().unwrap();
+        assert_eq!(route[0].model, "gpt-4o");
+        
+        // Unknown tier is left to the caller
+        assert_eq!(resolve_multimodal_route(&routing, "chat", "missing", "vision", &multimodal), Ok(None));
+        
+        // Text-only fallbacks are dropped from a mixed list
+        let routing = build_routing("chat.smart=groq:llama-3.1-8b-instant|openai:gpt-4o|mistral:mistral-large");
+        let route = resolve_multimodal_route(&routing, "chat", "smart", "vision", &multimodal).unwrap().unwrap();
+        assert_eq!(route.iter().map(|target| target.model.as_str()).collect::<Vec<_>>(), ["gpt-4o"]);
+        
+        // No fallback configured
+        let routing = build_routing("chat.fast=groq:llama-3.1-8b-instant");
+        let err = resolve_multimodal_route(&routing, "chat", "fast", "vision", &multimodal).unwrap_err();
+        assert!(err.contains("chat.vision"));
+    }
+    
     #[test]
     fn test_build_routing_edge_cases() {
         // Test with trailing comma
@@ -1,17 +1,42 @@
 use anyhow::{anyhow, Result};
+use lru::LruCache;
 use regex::Regex;
 use reqwest::Client;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
-use std::sync::{Arc, Mutex};
+use std::num::NonZeroUsize;
+use std::sync::{Arc, LazyLock, Mutex};
 use std::time::{Duration, Instant};
+use tokio::sync::{Semaphore, SemaphorePermit};
 use tokio::time::timeout;
 
 use crate::config::Config;
 use crate::types::{SearchResult, SearchResponse};
 
-// Simple in-memory cache for search results
-type SearchCache = Arc<Mutex<HashMap<String, (SearchResponse, Instant)>>>;
+use crate::clock::{system_clock, SharedClock};
+use crate::http_client::{build_client, send_with_retry};
+use crate::secret::Secret;
+
+// In-memory LRU cache for search results, stamped with cache time in ms
+type SearchCache = Arc<Mutex<LruCache<String, (SearchResponse, u64)>>>;
+
+/// Empty cache holding at most `max_entries` results (at least one)
+fn new_cache(max_entries: usize) -> SearchCache {
+    let capacity = NonZeroUsize::new(max_entries.max(1)).unwrap_or(NonZeroUsize::MIN);
+    Arc::new(Mutex::new(LruCache::new(capacity)))
+}
+
+/// Drop the entries whose cache time fails `keep`
+fn retain_cached(cache: &mut LruCache<String, (SearchResponse, u64)>, keep: impl Fn(u64) -> bool) {
+    let stale: Vec<String> = cache
+        .iter()
+        .filter(|(_, (_, cached_at))| !keep(*cached_at))
+        .map(|(key, _)| key.clone())
+        .collect();
+    for key in stale {
+        cache.pop(&key);
+    }
+}
 
 #[derive(Debug, Serialize, Deserialize)]
 struct TavilyRequest {
@@ -35,6 +60,9 @@ struct TavilyResult {
     title: String,
     url: String,
     content: String,
+    // Only present when the request set `include_raw_content`
+    #[serde(default)]
+    raw_content: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -73,29 +101,184 @@ struct SearxngResult {
     content: Option<String>,
 }
 
+/// Cut `text` down to at most `max_chars` characters
+fn truncate_chars(mut text: String, max_chars: usize) -> String {
+    if let Some((index, _)) = text.char_indices().nth(max_chars) {
+        text.truncate(index);
+    }
+    text
+}
+
+/// Strip tags from an HTML fragment and decode the common entities
+fn html_to_text(fragment: &str) -> String {
+    static TAGS: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"<[^>]*>").unwrap());
+    let text = TAGS
+        .replace_all(fragment, "")
+        .replace("&quot;", "\"")
+        .replace("&#x27;", "'")
+        .replace("&#39;", "'")
+        .replace("&nbsp;", " ")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&amp;", "&");
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Target of a DuckDuckGo result link, unwrapping its `/l/?uddg=` redirect
+fn duckduckgo_result_url(href: &str) -> String {
+    let href = href.replace("&amp;", "&");
+    if let Some((_, rest)) = href.split_once("uddg=") {
+        let encoded = rest.split('&').next().unwrap_or_default();
+        if let Ok(url) = urlencoding::decode(encoded) {
+            return url.into_owned();
+        }
+    }
+    match href.strip_prefix("//") {
+        Some(rest) => format!("https://{}", rest),
+        None => href,
+    }
+}
+
+/// Parse result titles, links and snippets from a DuckDuckGo HTML page
+///
+/// Ads (links through `duckduckgo.com/y.js`) are skipped. DuckDuckGo has
+/// no relevance scores, so `score` is always `None`.
+pub fn parse_duckduckgo_html(html: &str) -> Vec<SearchResult> {
+    static LINKS: LazyLock<Regex> = LazyLock::new(|| {
+        Regex::new(r#"(?s)<a[^>]*class="result__a"[^>]*href="([^"]*)"[^>]*>(.*?)</a>"#).unwrap()
+    });
+    static SNIPPETS: LazyLock<Regex> =
+        LazyLock::new(|| Regex::new(r#"(?s)class="result__snippet"[^>]*>(.*?)</a>"#).unwrap());
+
+    let matches: Vec<_> = LINKS.captures_iter(html).collect();
+    matches
+        .iter()
+        .enumerate()
+        .filter_map(|(index, link)| {
+            let href = &link[1];
+            if href.contains("duckduckgo.com/y.js") {
+                return None;
+            }
+            // The snippet belongs to this result if it precedes the next link
+            let end = link.get(0).map_or(0, |m| m.end());
+            let next = matches.get(index + 1).and_then(|m| m.get(0)).map_or(html.len(), |m| m.start());
+            let snippet = SNIPPETS
+                .captures(&html[end..next])
+                .map(|snippet| html_to_text(&snippet[1]))
+                .unwrap_or_default();
+            Some(SearchResult {
+                title: html_to_text(&link[2]),
+                url: duckduckgo_result_url(href),
+                snippet,
+                score: None,
+                raw_content: None,
+            })
+        })
+        .take(5)
+        .collect()
+}
+
+/// Neutralize SearXNG query syntax in a user-derived query
+///
+/// SearXNG treats words starting with `!` (engine and category bangs, `!!`
+/// external bangs that redirect elsewhere) and `:` (language) as operators.
+/// Unless a word is listed in `allowed` (case-insensitive), its operator
+/// prefix is stripped so it is searched as a plain word; bare operators are
+/// dropped.
+pub fn sanitize_searxng_query(query: &str, allowed: &[String]) -> String {
+    query
+        .split_whitespace()
+        .filter_map(|word| {
+            if !word.starts_with(['!', ':']) || allowed.iter().any(|op| op.eq_ignore_ascii_case(word)) {
+                return Some(word);
+            }
+            Some(word.trim_start_matches(['!', ':'])).filter(|word| !word.is_empty())
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Context added in place of search results when every provider failed
+/// and `SEARCH_ALL_FAILED_BEHAVIOR` is `degrade`
+pub const SEARCH_UNAVAILABLE_NOTE: &str = "Live web search was requested but is currently unavailable. \
+Answer from your existing knowledge and tell the user that up-to-date information could not be checked.";
+
+/// Format search results as context for the provider
+pub fn search_context(response: &SearchResponse) -> String {
+    let mut context = format!("Web search results for \"{}\":", response.query);
+    for (index, result) in response.results.iter().enumerate() {
+        context.push_str(&format!(
+            "\n\n{}. {} ({})\n{}",
+            index + 1,
+            result.title,
+            result.url,
+            result.snippet
+        ));
+    }
+    context
+}
+
+/// Normalize a query for matching repeated turns
+///
+/// Lowercases, collapses whitespace and drops trailing punctuation, so
+/// "What's the weather in Paris?" and "what's the weather in paris"
+/// match.
+pub fn normalize_query(query: &str) -> String {
+    query
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .trim_end_matches(|c: char| c.is_ascii_punctuation())
+        .to_lowercase()
+}
+
+/// Whole milliseconds since `started`
+fn elapsed_ms(started: Instant) -> u64 {
+    u64::try_from(started.elapsed().as_millis()).unwrap_or(u64::MAX)
+}
+
 #[derive(Clone)]
 #[allow(dead_code)]
 pub struct SearchService {
     config: Config,
     client: Client,
     cache: SearchCache,
+    /// Recent results per conversation, keyed by session and normalized query
+    session_cache: SearchCache,
+    // Caps outbound provider calls in flight, shared by all clones of the service
+    concurrency: Arc<Semaphore>,
+    clock: SharedClock,
 }
 
 #[allow(dead_code)]
 impl SearchService {
     pub fn new(config: Config) -> Self {
-        let client = Client::builder()
-            .timeout(Duration::from_millis(3500))
-            .build()
-            .expect("Failed to create HTTP client");
+        let client = build_client(&config, Duration::from_millis(3500));
+        let concurrency = Arc::new(Semaphore::new(config.search.max_concurrency.max(1)));
+        let max_entries = config.search.cache_max_entries;
 
         Self {
             config,
             client,
-            cache: Arc::new(Mutex::new(HashMap::new())),
+            cache: new_cache(max_entries),
+            session_cache: new_cache(max_entries),
+            concurrency,
+            clock: system_clock(),
         }
     }
 
+    /// Use the given clock for cache expiry instead of the system clock
+    pub fn with_clock(mut self, clock: SharedClock) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Whether an entry cached at `cached_at` (ms) is still fresh
+    fn is_fresh(&self, cached_at: u64) -> bool {
+        let ttl_ms = self.config.search.cache_duration.saturating_mul(1000);
+        self.clock.now_ms().saturating_sub(cached_at) < ttl_ms
+    }
+
     /// Detect if a query needs internet search
     pub fn needs_internet_search(&self, query: &str) -> bool {
         if !self.config.search.enabled {
@@ -123,7 +306,16 @@ impl SearchService {
     }
 
     /// Perform web search using available providers
+    ///
+    /// Providers are tried in order (Tavily, Brave, DuckDuckGo, SearXNG)
+    /// until one returns results.
+    ///
+    /// # Errors
+    /// When at least one provider was tried and every one failed. Such
+    /// failures are not cached.
     pub async fn perform_web_search(&self, query: &str) -> Result<SearchResponse> {
+        let started = Instant::now();
+
         // If search is not enabled, return disabled response
         if !self.config.search.enabled {
             return Ok(SearchResponse {
@@ -136,25 +328,33 @@ impl SearchService {
 
         // Check cache first
         let cache_key = format!("search:{}", query);
-        if let Ok(cache) = self.cache.lock() {
+        if let Ok(mut cache) = self.cache.lock() {
             if let Some((cached_response, cached_at)) = cache.get(&cache_key) {
-                if cached_at.elapsed() < Duration::from_secs(self.config.search.cache_duration) {
-                    return Ok(cached_response.clone());
+                if self.is_fresh(*cached_at) {
+                    // Report this lookup's time, not the original search's
+                    return Ok(SearchResponse {
+                        took_ms: elapsed_ms(started),
+                        ..cached_response.clone()
+                    });
                 }
             }
         }
 
         let mut results = Vec::new();
         let mut provider = "none";
+        let mut attempted = 0;
+        let mut failed = 0;
 
         // Try Tavily first
         if results.is_empty() && !self.config.search.tavily.api_key.is_empty() {
+            attempted += 1;
             match self.search_tavily(query).await {
                 Ok(tavily_results) if !tavily_results.is_empty() => {
                     results = tavily_results;
                     provider = "tavily";
                 }
                 Err(_) => {
+                    failed += 1;
                     tracing::warn!("Tavily provider failed, trying next...");
                 }
                 _ => {}
@@ -163,65 +363,132 @@ impl SearchService {
 
         // Try Brave if Tavily didn't work
         if results.is_empty() && !self.config.search.brave.api_key.is_empty() {
+            attempted += 1;
             match self.search_brave(query).await {
                 Ok(brave_results) if !brave_results.is_empty() => {
                     results = brave_results;
                     provider = "brave";
                 }
                 Err(_) => {
+                    failed += 1;
                     tracing::warn!("Brave provider failed, trying next...");
                 }
                 _ => {}
             }
         }
 
+        // Try DuckDuckGo when no API provider returned results
+        if results.is_empty() && self.config.search.duckduckgo.enabled {
+            attempted += 1;
+            match self.search_duckduckgo(query).await {
+                Ok(duckduckgo_results) if !duckduckgo_results.is_empty() => {
+                    results = duckduckgo_results;
+                    provider = "duckduckgo";
+                }
+                Err(_) => {
+                    failed += 1;
+                    tracing::warn!("DuckDuckGo provider failed, trying next...");
+                }
+                _ => {}
+            }
+        }
+
         // Fall back to SearXNG only if API providers failed
         if results.is_empty() && self.config.search.searxng.enabled {
+            attempted += 1;
             match self.search_searxng(query).await {
                 Ok(searxng_results) if !searxng_results.is_empty() => {
                     results = searxng_results;
                     provider = "searxng";
                 }
                 Err(_) => {
+                    failed += 1;
                     tracing::warn!("SearXNG provider failed");
                 }
                 _ => {}
             }
         }
 
+        if attempted > 0 && failed == attempted {
+            return Err(anyhow!("All {} search providers failed", attempted));
+        }
+
         let response = SearchResponse {
             query: query.to_string(),
             results,
             provider: provider.to_string(),
-            took_ms: 0, // TODO: Measure actual time
+            took_ms: elapsed_ms(started),
         };
 
-        // Cache the response
+        // Cache the response, evicting the least recently used entry when full
         if let Ok(mut cache) = self.cache.lock() {
-            cache.insert(cache_key, (response.clone(), Instant::now()));
+            cache.put(cache_key, (response.clone(), self.clock.now_ms()));
+        }
+
+        Ok(response)
+    }
+
+    /// Search within a conversation, reusing a recent turn's results
+    ///
+    /// Within `SEARCH_SESSION_WINDOW_SECS`, a query that normalizes to one
+    /// already searched in the same session is answered from that search,
+    /// even when the global cache has expired or the wording differs in case,
+    /// spacing or trailing punctuation.
+    pub async fn perform_session_search(&self, session_id: &str, query: &str) -> Result<SearchResponse> {
+        let window_ms = self.config.search.session_window_secs.saturating_mul(1000);
+        if window_ms == 0 || !self.config.search.enabled {
+            return self.perform_web_search(query).await;
         }
 
+        let started = Instant::now();
+        let key = format!("{}\n{}", session_id, normalize_query(query));
+        let now = self.clock.now_ms();
+        if let Ok(mut cache) = self.session_cache.lock() {
+            retain_cached(&mut cache, |cached_at| now.saturating_sub(cached_at) < window_ms);
+            if let Some((response, _)) = cache.get(&key) {
+                return Ok(SearchResponse {
+                    took_ms: elapsed_ms(started),
+                    ..response.clone()
+                });
+            }
+        }
+
+        let response = self.perform_web_search(query).await?;
+        if let Ok(mut cache) = self.session_cache.lock() {
+            cache.put(key, (response.clone(), now));
+        }
         Ok(response)
     }
 
+    /// Wait for a slot under the global outbound search concurrency limit
+    async fn acquire_search_slot(&self) -> SemaphorePermit<'_> {
+        self.concurrency
+            .acquire()
+            .await
+            .expect("search concurrency semaphore is never closed")
+    }
+
     async fn search_tavily(&self, query: &str) -> Result<Vec<SearchResult>> {
+        let _slot = self.acquire_search_slot().await;
         let request = TavilyRequest {
-            api_key: self.config.search.tavily.api_key.clone(),
+            api_key: self.config.search.tavily.api_key.expose().to_string(),
             query: query.to_string(),
             search_depth: "basic".to_string(),
             include_answer: true,
-            include_raw_content: false,
+            include_raw_content: self.config.search.include_raw_content,
             max_results: 5,
         };
 
         let response = timeout(
             Duration::from_millis(3500),
-            self.client
-                .post(format!("{}/search", self.config.search.tavily.base_url))
-                .header("Content-Type", "application/json")
-                .header("api-key", &self.config.search.tavily.api_key)
-                .json(&request)
-                .send(),
+            send_with_retry(
+                self.client
+                    .post(format!("{}/search", self.config.search.tavily.base_url))
+                    .header("Content-Type", "application/json")
+                    .header("api-key", self.config.search.tavily.api_key.expose())
+                    .json(&request),
+                &self.config.retryable_status_codes,
+            ),
         )
         .await
         .map_err(|_| anyhow!("Tavily request timeout"))?
@@ -236,6 +503,7 @@ impl SearchService {
             .await
             .map_err(|e| anyhow!("Failed to parse Tavily response: {}", e))?;
 
+        let max_raw_chars = self.config.search.raw_content_max_chars;
         Ok(tavily_response
             .results
             .into_iter()
@@ -244,11 +512,16 @@ impl SearchService {
                 url: result.url,
                 snippet: result.content,
                 score: None,
+                raw_content: result
+                    .raw_content
+                    .filter(|_| self.config.search.include_raw_content)
+                    .map(|content| truncate_chars(content, max_raw_chars)),
             })
             .collect())
     }
 
     async fn search_brave(&self, query: &str) -> Result<Vec<SearchResult>> {
+        let _slot = self.acquire_search_slot().await;
         let params = BraveRequest {
             q: query.to_string(),
             count: 5,
@@ -257,11 +530,13 @@ impl SearchService {
 
         let response = timeout(
             Duration::from_millis(3500),
-            self.client
-                .get(format!("{}/v1/web/search", self.config.search.brave.base_url))
-                .header("X-Subscription-Token", &self.config.search.brave.api_key)
-                .query(&params)
-                .send(),
+            send_with_retry(
+                self.client
+                    .get(format!("{}/v1/web/search", self.config.search.brave.base_url))
+                    .header("X-Subscription-Token", self.config.search.brave.api_key.expose())
+                    .query(&params),
+                &self.config.retryable_status_codes,
+            ),
         )
         .await
         .map_err(|_| anyhow!("Brave request timeout"))?
@@ -286,23 +561,64 @@ impl SearchService {
                 url: result.url,
                 snippet: result.description,
                 score: None,
+                raw_content: None,
             })
             .collect())
     }
 
+    async fn search_duckduckgo(&self, query: &str) -> Result<Vec<SearchResult>> {
+        let _slot = self.acquire_search_slot().await;
+
+        let response = timeout(
+            Duration::from_millis(3500),
+            send_with_retry(
+                self.client
+                    .get(format!("{}/html/", self.config.search.duckduckgo.base_url))
+                    .query(&[("q", query)]),
+                &self.config.retryable_status_codes,
+            ),
+        )
+        .await
+        .map_err(|_| anyhow!("DuckDuckGo request timeout"))?
+        .map_err(|e| anyhow!("DuckDuckGo request failed: {}", e))?;
+
+        if !response.status().is_success() {
+            return Err(anyhow!("DuckDuckGo error: {}", response.status()));
+        }
+
+        let html = response
+            .text()
+            .await
+            .map_err(|e| anyhow!("Failed to read DuckDuckGo response: {}", e))?;
+
+        Ok(parse_duckduckgo_html(&html))
+    }
+
     async fn search_searxng(&self, query: &str) -> Result<Vec<SearchResult>> {
+        let _slot = self.acquire_search_slot().await;
+        let searxng = &self.config.search.searxng;
+        let query = sanitize_searxng_query(query, &searxng.allowed_operators);
         let mut params = HashMap::new();
-        params.insert("q", query);
+        params.insert("q", query.as_str());
         params.insert("format", "json");
         params.insert("safesearch", "1");
         params.insert("pageno", "1");
 
+        let mut request = self
+            .client
+            .get(format!("{}/search", searxng.base_url))
+            .query(&params);
+
+        // Forward credentials for instances behind a proxy or basic auth
+        if let (Some(header), Some(value)) = (&searxng.auth_header, &searxng.auth_value) {
+            request = request.header(header.as_str(), value.expose());
+        } else if let Some(username) = &searxng.username {
+            request = request.basic_auth(username, searxng.password.as_ref().map(Secret::expose));
+        }
+
         let response = timeout(
             Duration::from_millis(5000), // Slightly longer timeout for SearXNG
-            self.client
-                .get(format!("{}/search", self.config.search.searxng.base_url))
-                .query(&params)
-                .send(),
+            send_with_retry(request, &self.config.retryable_status_codes),
         )
         .await
         .map_err(|_| anyhow!("SearXNG request timeout"))?
@@ -325,15 +641,32 @@ impl SearchService {
                 url: result.url,
                 snippet: result.content.unwrap_or_else(|| "No content available".to_string()),
                 score: None,
+                raw_content: None,
             })
             .collect())
     }
 
+    /// Remove every cached entry, returning how many were removed
+    ///
+    /// Unlike `cleanup_cache`, fresh entries are dropped as well.
+    pub fn clear_cache(&self) -> usize {
+        [&self.cache, &self.session_cache]
+            .into_iter()
+            .map(|cache| match cache.lock() {
+                Ok(mut cache) => {
+                    let cleared = cache.len();
+                    cache.clear();
+                    cleared
+                }
+                Err(_) => 0,
+            })
+            .sum()
+    }
+
     /// Clear expired entries from cache
     pub fn cleanup_cache(&self) {
         if let Ok(mut cache) = self.cache.lock() {
-            let cache_duration = Duration::from_secs(self.config.search.cache_duration);
-            cache.retain(|_, (_, cached_at)| cached_at.elapsed() < cache_duration);
+            retain_cached(&mut cache, |cached_at| self.is_fresh(cached_at));
         }
     }
 }
@@ -341,24 +674,43 @@ impl SearchService {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::config::{Config, SearchConfig, TavilyConfig, BraveConfig, SearxngConfig};
+    use crate::config::{Config, SearchConfig, SearchDisabledBehavior, SearchFailedBehavior, TavilyConfig, BraveConfig, DuckDuckGoConfig, SearxngConfig};
+    use crate::test_support::{closed_addr, spawn_mock};
+    use std::sync::atomic::{AtomicUsize, Ordering};
     
     fn create_test_config(enabled: bool) -> Config {
         let mut config = Config::from_env();
         config.search = SearchConfig {
             enabled,
+            disabled_behavior: SearchDisabledBehavior::Silent,
+            all_failed_behavior: SearchFailedBehavior::Proceed,
+            enabled_operations: vec!["chat".to_string()],
             cache_duration: 300, // 5 minutes
+            cache_max_entries: 1000,
+            session_window_secs: 120,
+            max_concurrency: 8,
+            include_raw_content: false,
+            raw_content_max_chars: 4000,
             tavily: TavilyConfig {
-                api_key: "test_tavily_key".to_string(),
+                api_key: Secret::from("test_tavily_key"),
                 base_url: "https://api.tavily.com".to_string(),
             },
             brave: BraveConfig {
-                api_key: "test_brave_key".to_string(),
+                api_key: Secret::from("test_brave_key"),
                 base_url: "https://api.search.brave.com".to_string(),
             },
+            duckduckgo: DuckDuckGoConfig {
+                enabled: false,
+                base_url: "https://html.duckduckgo.com".to_string(),
+            },
             searxng: SearxngConfig {
                 base_url: "http://localhost:8090".to_string(),
                 enabled: true,
+                auth_header: None,
+                auth_value: None,
+                username: None,
+                password: None,
+                allowed_operators: Vec::new(),
             },
         };
         config
@@ -369,9 +721,9 @@ mod tests {
         let config = create_test_config(true);
         let service = SearchService::new(config.clone());
         
-        assert_eq!(service.config.search.enabled, true);
+        assert!(service.config.search.enabled);
         assert_eq!(service.config.search.cache_duration, 300);
-        assert_eq!(service.config.search.tavily.api_key, "test_tavily_key");
+        assert_eq!(service.config.search.tavily.api_key.expose(), "test_tavily_key");
     }
     
     #[test]
@@ -474,6 +826,394 @@ mod tests {
         service.cleanup_cache();
     }
     
+    #[test]
+    fn test_cleanup_cache_expires_with_mock_clock() {
+        use crate::clock::{Clock, MockClock};
+        
+        let clock = Arc::new(MockClock::new(1_640_995_200_000));
+        let service = SearchService::new(create_test_config(true)).with_clock(clock.clone());
+        let response = SearchResponse {
+            query: "cached".to_string(),
+            results: Vec::new(),
+            provider: "tavily".to_string(),
+            took_ms: 1,
+        };
+        service
+            .cache
+            .lock()
+            .unwrap()
+            .put("search:cached".to_string(), (response, clock.now_ms()));
+        
+        clock.advance(Duration::from_secs(299));
+        service.cleanup_cache();
+        assert_eq!(service.cache.lock().unwrap().len(), 1);
+        
+        clock.advance(Duration::from_secs(1));
+        service.cleanup_cache();
+        assert!(service.cache.lock().unwrap().is_empty());
+    }
+    
+    #[test]
+    fn test_cache_evicts_least_recently_used_entries() {
+        let mut config = create_test_config(true);
+        config.search.cache_max_entries = 3;
+        let service = SearchService::new(config);
+        let response = |query: &str| SearchResponse {
+            query: query.to_string(),
+            results: Vec::new(),
+            provider: "tavily".to_string(),
+            took_ms: 1,
+        };
+        
+        let mut cache = service.cache.lock().unwrap();
+        for query in ["a", "b", "c"] {
+            cache.put(format!("search:{}", query), (response(query), 0));
+        }
+        // Touch "a" so "b" becomes the oldest
+        assert!(cache.get("search:a").is_some());
+        for query in ["d", "e"] {
+            cache.put(format!("search:{}", query), (response(query), 0));
+        }
+        
+        assert_eq!(cache.len(), 3);
+        assert!(!cache.contains("search:b"));
+        assert!(!cache.contains("search:c"));
+        for query in ["a", "d", "e"] {
+            assert!(cache.contains(&format!("search:{}", query)), "{} evicted", query);
+        }
+    }
+    
+    /// Start a fake SearXNG instance that records the headers of each request
+    async fn spawn_searxng() -> (String, Arc<Mutex<Vec<axum::http::HeaderMap>>>) {
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let recorder = seen.clone();
+        let app = axum::Router::new().route(
+            "/search",
+            axum::routing::get(move |headers: axum::http::HeaderMap| {
+                let recorder = recorder.clone();
+                async move {
+                    recorder.lock().unwrap().push(headers);
+                    axum::Json(serde_json::json!([
+                        {"title": "Result", "url": "https://example.com", "content": "Snippet"}
+                    ]))
+                }
+            }),
+        );
+        
+        let addr = spawn_mock(app).await;
+        (format!("http://{}", addr), seen)
+    }
+    
+    /// SearXNG stub that responds slowly and records the peak number of
+    /// requests it was serving at once
+    async fn spawn_slow_searxng(delay: Duration) -> (String, Arc<AtomicUsize>, Arc<AtomicUsize>) {
+        let in_flight = Arc::new(AtomicUsize::new(0));
+        let peak = Arc::new(AtomicUsize::new(0));
+        let (in_flight_handler, peak_handler) = (in_flight.clone(), peak.clone());
+        let app = axum::Router::new().route(
+            "/search",
+            axum::routing::get(move || {
+                let (in_flight, peak) = (in_flight_handler.clone(), peak_handler.clone());
+                async move {
+                    let current = in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+                    peak.fetch_max(current, Ordering::SeqCst);
+                    tokio::time::sleep(delay).await;
+                    in_flight.fetch_sub(1, Ordering::SeqCst);
+                    axum::Json(serde_json::json!([
+                        {"title": "Result", "url": "https://example.com", "content": "Snippet"}
+                    ]))
+                }
+            }),
+        );
+        
+        let addr = spawn_mock(app).await;
+        (format!("http://{}", addr), in_flight, peak)
+    }
+    
+    /// Start a fake Tavily API that records request bodies and returns
+    /// one result with raw page content
+    async fn spawn_tavily() -> (String, Arc<Mutex<Vec<serde_json::Value>>>) {
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let recorder = seen.clone();
+        let app = axum::Router::new().route(
+            "/search",
+            axum::routing::post(move |axum::Json(body): axum::Json<serde_json::Value>| {
+                let recorder = recorder.clone();
+                async move {
+                    recorder.lock().unwrap().push(body);
+                    axum::Json(serde_json::json!({
+                        "query": "rust",
+                        "answer": "Rust is a language.",
+                        "results": [{
+                            "title": "Rust",
+                            "url": "https://www.rust-lang.org",
+                            "content": "A language empowering everyone.",
+                            "raw_content": "Rust: A language empowering everyone to build reliable software.",
+                            "score": 0.98
+                        }]
+                    }))
+                }
+            }),
+        );
+        
+        let addr = spawn_mock(app).await;
+        (format!("http://{}", addr), seen)
+    }
+    
+    const DUCKDUCKGO_PAGE: &str = r#"
+        <div class="result results_links results_links_deep result--ad">
+          <a rel="nofollow" class="result__a" href="https://duckduckgo.com/y.js?ad_provider=x&amp;u3=ad">Sponsored</a>
+          <a class="result__snippet" href="https://duckduckgo.com/y.js?ad_provider=x">Buy now</a>
+        </div>
+        <div class="result results_links results_links_deep web-result">
+          <h2 class="result__title">
+            <a rel="nofollow" class="result__a" href="//duckduckgo.com/l/?uddg=https%3A%2F%2Fwww.rust%2Dlang.org%2F&amp;rut=abc">Rust Programming <b>Language</b></a>
+          </h2>
+          <a class="result__snippet" href="//duckduckgo.com/l/?uddg=x">A language empowering everyone to build reliable &amp; efficient software.</a>
+        </div>
+        <div class="result results_links results_links_deep web-result">
+          <a rel="nofollow" class="result__a" href="https://doc.rust-lang.org/book/">The Rust Programming Language - Book</a>
+        </div>
+    "#;
+    
+    #[test]
+    fn test_parse_duckduckgo_html() {
+        let results = parse_duckduckgo_html(DUCKDUCKGO_PAGE);
+        assert_eq!(results.len(), 2);
+        
+        assert_eq!(results[0].title, "Rust Programming Language");
+        assert_eq!(results[0].url, "https://www.rust-lang.org/");
+        assert_eq!(results[0].snippet, "A language empowering everyone to build reliable & efficient software.");
+        assert!(results[0].score.is_none());
+        
+        // A result without a snippet doesn't borrow the next one's
+        assert_eq!(results[1].url, "https://doc.rust-lang.org/book/");
+        assert_eq!(results[1].snippet, "");
+        
+        assert!(parse_duckduckgo_html("<html>No results.</html>").is_empty());
+    }
+    
+    #[tokio::test]
+    async fn test_duckduckgo_runs_before_searxng() {
+        let app = axum::Router::new().route(
+            "/html/",
+            axum::routing::get(|axum::extract::Query(params): axum::extract::Query<HashMap<String, String>>| async move {
+                assert_eq!(params["q"], "rust");
+                axum::response::Html(DUCKDUCKGO_PAGE)
+            }),
+        );
+        let addr = spawn_mock(app).await;
+        let (searxng_url, searxng_seen) = spawn_searxng().await;
+        
+        let mut config = create_test_config(true);
+        config.search.tavily.api_key = Secret::default();
+        config.search.brave.api_key = Secret::default();
+        config.search.duckduckgo.enabled = true;
+        config.search.duckduckgo.base_url = format!("http://{}", addr);
+        config.search.searxng.base_url = searxng_url;
+        
+        let response = SearchService::new(config).perform_web_search("rust").await.unwrap();
+        assert_eq!(response.provider, "duckduckgo");
+        assert_eq!(response.results.len(), 2);
+        assert!(searxng_seen.lock().unwrap().is_empty());
+    }
+    
+    #[tokio::test]
+    async fn test_tavily_raw_content_follows_config() {
+        let (base_url, seen) = spawn_tavily().await;
+        
+        // Disabled by default: flag off and no raw content in results
+        let mut config = create_test_config(true);
+        config.search.tavily.base_url = base_url.clone();
+        let results = SearchService::new(config).search_tavily("rust").await.unwrap();
+        assert_eq!(results[0].snippet, "A language empowering everyone.");
+        assert!(results[0].raw_content.is_none());
+        
+        // Enabled: flag sent and raw content capped to the configured length
+        let mut config = create_test_config(true);
+        config.search.tavily.base_url = base_url;
+        config.search.include_raw_content = true;
+        config.search.raw_content_max_chars = 4;
+        let results = SearchService::new(config).search_tavily("rust").await.unwrap();
+        assert_eq!(results[0].raw_content.as_deref(), Some("Rust"));
+        
+        let seen = seen.lock().unwrap();
+        assert_eq!(seen[0]["include_raw_content"], false);
+        assert_eq!(seen[1]["include_raw_content"], true);
+    }
+    
+    #[test]
+    fn test_truncate_chars_respects_char_boundaries() {
+        assert_eq!(truncate_chars("héllo".to_string(), 2), "hé");
+        assert_eq!(truncate_chars("short".to_string(), 100), "short");
+        assert_eq!(truncate_chars("abc".to_string(), 0), "");
+    }
+    
+    #[tokio::test]
+    async fn test_outbound_search_concurrency_is_capped() {
+        let (base_url, in_flight, peak) = spawn_slow_searxng(Duration::from_millis(50)).await;
+        let mut config = create_test_config(true);
+        config.search.tavily.api_key = Secret::default();
+        config.search.brave.api_key = Secret::default();
+        config.search.searxng.base_url = base_url;
+        config.search.max_concurrency = 2;
+        let service = SearchService::new(config);
+        
+        // Distinct queries from independent callers so none are served from cache
+        let searches = (0..12).map(|i| {
+            let service = service.clone();
+            tokio::spawn(async move { service.perform_web_search(&format!("query {}", i)).await })
+        });
+        for result in futures::future::join_all(searches).await {
+            assert_eq!(result.unwrap().unwrap().provider, "searxng");
+        }
+        
+        assert_eq!(peak.load(Ordering::SeqCst), 2);
+        assert_eq!(in_flight.load(Ordering::SeqCst), 0);
+    }
+    
+    #[tokio::test]
+    async fn test_searxng_forwards_configured_auth_header() {
+        let (base_url, seen) = spawn_searxng().await;
+        
+        let mut config = create_test_config(true);
+        config.search.searxng.base_url = base_url.clone();
+        config.search.searxng.auth_header = Some("X-Api-Token".to_string());
+        config.search.searxng.auth_value = Some(Secret::from("secret-token"));
+        let results = SearchService::new(config).search_searxng("rust").await.unwrap();
+        assert_eq!(results.len(), 1);
+        
+        // Basic auth is used when no custom header is configured
+        let mut config = create_test_config(true);
+        config.search.searxng.base_url = base_url.clone();
+        config.search.searxng.username = Some("user".to_string());
+        config.search.searxng.password = Some(Secret::from("pass"));
+        SearchService::new(config).search_searxng("rust").await.unwrap();
+        
+        // No credentials are sent when unset
+        let mut config = create_test_config(true);
+        config.search.searxng.base_url = base_url;
+        SearchService::new(config).search_searxng("rust").await.unwrap();
+        
+        let seen = seen.lock().unwrap();
+        assert_eq!(seen[0]["x-api-token"], "secret-token");
+        assert_eq!(seen[1]["authorization"], "Basic dXNlcjpwYXNz");
+        assert!(!seen[2].contains_key("x-api-token"));
+        assert!(!seen[2].contains_key("authorization"));
+    }
+    
+    #[test]
+    fn test_sanitize_searxng_query_neutralizes_operators() {
+        let none: Vec<String> = Vec::new();
+        assert_eq!(sanitize_searxng_query("!!g rust tutorials", &none), "g rust tutorials");
+        assert_eq!(sanitize_searxng_query("!wp :fr rust ownership", &none), "wp fr rust ownership");
+        assert_eq!(sanitize_searxng_query("what is !! and ! in rust", &none), "what is and in rust");
+        assert_eq!(sanitize_searxng_query(":!:en  news", &none), "en news");
+        
+        // Operators elsewhere in a word are plain text to SearXNG
+        assert_eq!(sanitize_searxng_query("std::mem::take in macro_rules! bodies", &none), "std::mem::take in macro_rules! bodies");
+        assert_eq!(sanitize_searxng_query("plain query", &none), "plain query");
+    }
+    
+    #[test]
+    fn test_sanitize_searxng_query_keeps_allowed_operators() {
+        let allowed = vec!["!wp".to_string(), ":en".to_string()];
+        assert_eq!(sanitize_searxng_query("!WP rust :en", &allowed), "!WP rust :en");
+        assert_eq!(sanitize_searxng_query("!!wp rust :de", &allowed), "wp rust de");
+    }
+    
+    #[tokio::test]
+    async fn test_all_providers_failing_is_an_error_and_not_cached() {
+        let closed = closed_addr();
+        let mut config = create_test_config(true);
+        config.search.tavily.api_key = Secret::default();
+        config.search.brave.api_key = Secret::default();
+        config.search.searxng.base_url = format!("http://{}", closed);
+        let service = SearchService::new(config.clone());
+        
+        let error = service.perform_web_search("rust").await.unwrap_err();
+        assert_eq!(error.to_string(), "All 1 search providers failed");
+        assert_eq!(service.clear_cache(), 0);
+        
+        // With no provider configured there is nothing to fail
+        config.search.searxng.enabled = false;
+        let response = SearchService::new(config).perform_web_search("rust").await.unwrap();
+        assert_eq!(response.provider, "none");
+    }
+    
+    #[test]
+    fn test_normalize_query() {
+        assert_eq!(normalize_query("  What's the   weather in Paris?! "), "what's the weather in paris");
+        assert_eq!(normalize_query("rust 1.80"), "rust 1.80");
+        assert_eq!(normalize_query("???"), "");
+    }
+    
+    #[tokio::test]
+    async fn test_session_search_reuses_results_within_window() {
+        let (base_url, seen) = spawn_searxng().await;
+        let clock = Arc::new(crate::clock::MockClock::new(1_000_000));
+        let mut config = create_test_config(true);
+        config.search.tavily.api_key = Secret::default();
+        config.search.brave.api_key = Secret::default();
+        config.search.searxng.base_url = base_url;
+        // Without the global cache every non-session search goes upstream
+        config.search.cache_duration = 0;
+        let service = SearchService::new(config).with_clock(clock.clone());
+        
+        // Two turns of one conversation asking the same thing
+        let first = service.perform_session_search("chat-1", "Weather in Paris today?").await.unwrap();
+        let second = service.perform_session_search("chat-1", "weather in paris  today").await.unwrap();
+        assert_eq!(seen.lock().unwrap().len(), 1);
+        assert_eq!(second.results.len(), first.results.len());
+        
+        // Other conversations and expired windows search again
+        service.perform_session_search("chat-2", "Weather in Paris today?").await.unwrap();
+        assert_eq!(seen.lock().unwrap().len(), 2);
+        clock.advance(Duration::from_secs(120));
+        service.perform_session_search("chat-1", "Weather in Paris today?").await.unwrap();
+        assert_eq!(seen.lock().unwrap().len(), 3);
+    }
+    
+    #[tokio::test]
+    async fn test_took_ms_measures_provider_time() {
+        let (base_url, _, _) = spawn_slow_searxng(Duration::from_millis(30)).await;
+        let mut config = create_test_config(true);
+        config.search.tavily.api_key = Secret::default();
+        config.search.brave.api_key = Secret::default();
+        config.search.searxng.base_url = base_url;
+        let service = SearchService::new(config);
+        
+        let response = service.perform_web_search("rust").await.unwrap();
+        assert_eq!(response.provider, "searxng");
+        assert!(response.took_ms >= 30, "{}", response.took_ms);
+        
+        // A cache hit reports its own (near zero) lookup time
+        let cached = service.perform_web_search("rust").await.unwrap();
+        assert_eq!(cached.results.len(), response.results.len());
+        assert!(cached.took_ms < 30, "{}", cached.took_ms);
+    }
+    
+    #[tokio::test]
+    async fn test_clear_cache_forces_next_query_to_miss() {
+        let (base_url, seen) = spawn_searxng().await;
+        let mut config = create_test_config(true);
+        config.search.tavily.api_key = Secret::default();
+        config.search.brave.api_key = Secret::default();
+        config.search.searxng.base_url = base_url;
+        let service = SearchService::new(config);
+        
+        service.perform_web_search("rust").await.unwrap();
+        service.perform_web_search("rust").await.unwrap();
+        assert_eq!(seen.lock().unwrap().len(), 1); // second query served from cache
+        
+        assert_eq!(service.clear_cache(), 1);
+        assert_eq!(service.clear_cache(), 0);
+        
+        let response = service.perform_web_search("rust").await.unwrap();
+        assert_eq!(response.provider, "searxng");
+        assert_eq!(seen.lock().unwrap().len(), 2);
+    }
+    
     #[test]
     fn test_search_result_serialization() {
         let result = SearchResult {
@@ -481,6 +1221,7 @@ mod tests {
             url: "https://example.com".to_string(),
             snippet: "Test snippet here".to_string(),
             score: Some(0.85),
+            raw_content: None,
         };
         
         let json = serde_json::to_string(&result).unwrap();
@@ -502,12 +1243,14 @@ mod tests {
                     url: "https://example1.com".to_string(),
                     snippet: "Snippet 1".to_string(),
                     score: Some(0.9),
+                    raw_content: None,
                 },
                 SearchResult {
                     title: "Result 2".to_string(),
                     url: "https://example2.com".to_string(),
                     snippet: "Snippet 2".to_string(),
                     score: Some(0.8),
+                    raw_content: None,
                 },
             ],
             provider: "tavily".to_string(),
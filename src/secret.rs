@@ -0,0 +1,107 @@
+//! Secret Value Module
+//!
+//! `Secret` wraps sensitive configuration values (API keys, the JWT
+//! signing secret) so they cannot leak through logs or serialized config:
+//! - `Debug` and serialization print `[REDACTED]` instead of the value
+//! - `Display` is deliberately not implemented
+//! - The backing memory is zeroized when the value is dropped
+//!
+//! Code that genuinely needs the value (request headers, token signing)
+//! reads it through `expose()`.
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
+use zeroize::Zeroize;
+
+/// Placeholder printed in place of secret values
+const REDACTED: &str = "[REDACTED]";
+
+/// Sensitive string that never reveals itself when formatted
+#[derive(Clone, Default)]
+pub struct Secret(String);
+
+impl Secret {
+    /// Access the underlying value
+    ///
+    /// Only call this where the raw value is required, such as when
+    /// building an outbound request or signing a token.
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+
+    /// Whether the secret is empty (i.e. not configured)
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl From<String> for Secret {
+    fn from(value: String) -> Self {
+        Self(value)
+    }
+}
+
+impl From<&str> for Secret {
+    fn from(value: &str) -> Self {
+        Self(value.to_string())
+    }
+}
+
+impl fmt::Debug for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Secret({})", REDACTED)
+    }
+}
+
+impl Drop for Secret {
+    fn drop(&mut self) {
+        self.0.zeroize();
+    }
+}
+
+impl Serialize for Secret {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(REDACTED)
+    }
+}
+
+impl<'de> Deserialize<'de> for Secret {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer).map(Self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_debug_does_not_reveal_value() {
+        let secret = Secret::from("sk-very-secret");
+
+        let debug = format!("{:?}", secret);
+        assert!(!debug.contains("sk-very-secret"));
+        assert_eq!(debug, "Secret([REDACTED])");
+
+        // Nested in other Debug output as well
+        let nested = format!("{:?}", Some(secret.clone()));
+        assert!(!nested.contains("sk-very-secret"));
+    }
+
+    #[test]
+    fn test_expose_returns_value() {
+        let secret = Secret::from("sk-very-secret".to_string());
+        assert_eq!(secret.expose(), "sk-very-secret");
+        assert!(!secret.is_empty());
+        assert!(Secret::default().is_empty());
+    }
+
+    #[test]
+    fn test_serialization_is_redacted() {
+        let secret = Secret::from("sk-very-secret");
+        assert_eq!(serde_json::to_string(&secret).unwrap(), "\"[REDACTED]\"");
+
+        let parsed: Secret = serde_json::from_str("\"sk-from-json\"").unwrap();
+        assert_eq!(parsed.expose(), "sk-from-json");
+    }
+}
@@ -0,0 +1,256 @@
+//! Self Check Module
+//!
+//! Preflight validation of a configuration against the environment it is
+//! about to run in, used by `rust-ai check`.
+//!
+//! The report covers which environment variables are set, which AI
+//! providers are usable, whether the bind address is free and whether
+//! Convex and the enabled search providers are reachable. It reuses the
+//! startup checks (`check_jwt_config`, `provider_warnings`, route parsing)
+//! so the preflight and the server agree on what is wrong.
+
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::net::TcpListener;
+use std::time::Duration;
+
+use crate::config::{non_empty_env, Config};
+use crate::http_client::build_client;
+use crate::routing::build_routing_with_defaults;
+
+/// Environment variables reported by the self check
+///
+/// Only whether each is set is reported, never the value.
+const CHECKED_ENV_VARS: &[&str] = &[
+    "BIND_ADDRESS",
+    "ACTION_TOKEN_SECRET",
+    "AUTH_REQUIRED",
+    "ROUTES",
+    "CF_ACCOUNT_ID",
+    "CF_API_TOKEN",
+    "MISTRAL_API_KEY",
+    "OPENAI_API_KEY",
+    "XAI_API_KEY",
+    "GROQ_API_KEY",
+    "OPENROUTER_API_KEY",
+    "META_API_KEY",
+    "ANTHROPIC_API_KEY",
+    "CONVEX_URL",
+    "TAVILY_API_KEY",
+    "BRAVE_SEARCH_API_KEY",
+    "SEARXNG_BASE_URL",
+];
+
+/// Timeout for each reachability probe
+const PROBE_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// Usability of one AI provider
+#[derive(Debug, Clone, Serialize)]
+pub struct ProviderCheck {
+    /// Provider name
+    pub name: String,
+    /// Whether the provider has the credentials and endpoint it needs
+    pub usable: bool,
+    /// Why the provider is not usable
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub issue: Option<String>,
+}
+
+/// Reachability of an external service
+#[derive(Debug, Clone, Serialize)]
+pub struct ServiceCheck {
+    /// Service name
+    pub name: String,
+    /// Whether the service is enabled in the configuration
+    pub enabled: bool,
+    /// Whether the service answered; `None` when it was not probed
+    pub reachable: Option<bool>,
+}
+
+/// Result of `Config::self_check`
+#[derive(Debug, Clone, Serialize)]
+pub struct SelfCheckReport {
+    /// Whether each checked environment variable (or its `_FILE` variant) is set
+    pub env_vars: BTreeMap<String, bool>,
+    /// Usability of every AI provider
+    pub providers: Vec<ProviderCheck>,
+    /// Whether the bind address could be bound
+    pub bind_address_available: bool,
+    /// Reachability of Convex and the search providers
+    pub services: Vec<ServiceCheck>,
+    /// Problems that prevent the server from starting or serving traffic
+    pub errors: Vec<String>,
+    /// Likely misconfigurations that do not block startup
+    pub warnings: Vec<String>,
+}
+
+impl SelfCheckReport {
+    /// Whether the server can start and serve traffic
+    ///
+    /// Requires no errors, a free bind address, at least one usable
+    /// provider and every enabled service to be reachable.
+    pub fn ok(&self) -> bool {
+        self.errors.is_empty()
+            && self.bind_address_available
+            && self.providers.iter().any(|provider| provider.usable)
+            && self.services.iter().all(|service| service.reachable != Some(false))
+    }
+}
+
+impl Config {
+    /// Validate this configuration against the running environment
+    ///
+    /// Probes the network for service reachability, so it can take up to a
+    /// few seconds per enabled service.
+    pub async fn self_check(&self) -> SelfCheckReport {
+        let env_vars = CHECKED_ENV_VARS
+            .iter()
+            .map(|name| {
+                let set = non_empty_env(name).is_some() || non_empty_env(&format!("{}_FILE", name)).is_some();
+                (name.to_string(), set)
+            })
+            .collect();
+
+        let providers = self
+            .provider_credentials()
+            .into_iter()
+            .map(|(name, key_var, url_var, key, base_url, _)| {
+                let issue = if key.expose().trim().is_empty() {
+                    Some(format!("{} is not set", key_var))
+                } else if base_url.trim().is_empty() {
+                    Some(format!("{} is not set", url_var))
+                } else {
+                    None
+                };
+                ProviderCheck {
+                    name: name.to_string(),
+                    usable: issue.is_none(),
+                    issue,
+                }
+            })
+            .collect();
+
+        let mut errors = Vec::new();
+        let mut warnings = Vec::new();
+        match self.check_jwt_config() {
+            Ok(warning) => warnings.extend(warning),
+            Err(error) => errors.push(error),
+        }
+        warnings.extend(self.provider_warnings());
+        warnings.extend(build_routing_with_defaults(&self.routes_raw, self.max_routes, &self.provider_default_models).warnings);
+
+        SelfCheckReport {
+            env_vars,
+            providers,
+            bind_address_available: TcpListener::bind(&self.bind_address).is_ok(),
+            services: self.check_services().await,
+            errors,
+            warnings,
+        }
+    }
+
+    /// Probe Convex and the configured search providers
+    async fn check_services(&self) -> Vec<ServiceCheck> {
+        let search = &self.search;
+        let targets = [
+            ("convex", self.convex.enabled && !self.convex.url.trim().is_empty(), &self.convex.url),
+            ("tavily", search.enabled && !search.tavily.api_key.expose().is_empty(), &search.tavily.base_url),
+            ("brave", search.enabled && !search.brave.api_key.expose().is_empty(), &search.brave.base_url),
+            ("duckduckgo", search.enabled && search.duckduckgo.enabled, &search.duckduckgo.base_url),
+            ("searxng", search.enabled && search.searxng.enabled, &search.searxng.base_url),
+        ];
+
+        let client = build_client(self, PROBE_TIMEOUT);
+        let mut services = Vec::new();
+        for (name, enabled, url) in targets {
+            // Any HTTP response counts; auth and routing errors still mean
+            // the service is up
+            let reachable = if enabled {
+                Some(client.get(url.as_str()).send().await.is_ok())
+            } else {
+                None
+            };
+            services.push(ServiceCheck {
+                name: name.to_string(),
+                enabled,
+                reachable,
+            });
+        }
+        services
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::secret::Secret;
+    use crate::test_support::closed_addr;
+
+    /// Config with nothing external configured
+    fn minimal_config() -> Config {
+        let mut config = Config::from_env();
+        config.cloudflare.api_token = Secret::default();
+        config.mistral.api_key = Secret::default();
+        config.openai.api_key = Secret::default();
+        config.xai.api_key = Secret::default();
+        config.groq.api_key = Secret::default();
+        config.openrouter.api_key = Secret::default();
+        config.meta.api_key = Secret::default();
+        config.anthropic.api_key = Secret::default();
+        config.convex.enabled = false;
+        config.search.enabled = false;
+        config.bind_address = "127.0.0.1:0".to_string();
+        config.action_token_secret = Some(Secret::from("secret"));
+        config
+    }
+
+    #[tokio::test]
+    async fn test_report_flags_missing_provider_keys() {
+        let report = minimal_config().self_check().await;
+
+        assert_eq!(report.providers.len(), 8);
+        assert!(report.providers.iter().all(|provider| !provider.usable));
+        let openai = report.providers.iter().find(|provider| provider.name == "OpenAI").unwrap();
+        assert_eq!(openai.issue.as_deref(), Some("OPENAI_API_KEY is not set"));
+        assert!(!report.ok());
+
+        assert!(report.bind_address_available);
+        assert!(report.errors.is_empty());
+        assert!(report.services.iter().all(|service| service.reachable.is_none()));
+        assert!(report.env_vars.contains_key("OPENAI_API_KEY"));
+    }
+
+    #[tokio::test]
+    async fn test_report_with_a_usable_provider_is_ok() {
+        let mut config = minimal_config();
+        config.openai.api_key = Secret::from("sk-proj-abc");
+        let report = config.self_check().await;
+
+        let usable: Vec<_> = report.providers.iter().filter(|p| p.usable).map(|p| p.name.as_str()).collect();
+        assert_eq!(usable, ["OpenAI"]);
+        assert!(report.ok());
+
+        // Secrets never appear in the report
+        assert!(!serde_json::to_string(&report).unwrap().contains("sk-proj-abc"));
+    }
+
+    #[tokio::test]
+    async fn test_report_flags_busy_bind_address_and_unreachable_services() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let taken = listener.local_addr().unwrap().to_string();
+        // Nothing listens on a port we just released
+        let closed = closed_addr();
+
+        let mut config = minimal_config();
+        config.openai.api_key = Secret::from("sk-proj-abc");
+        config.bind_address = taken;
+        config.convex.enabled = true;
+        config.convex.url = format!("http://{}", closed);
+        let report = config.self_check().await;
+
+        assert!(!report.bind_address_available);
+        let convex = report.services.iter().find(|service| service.name == "convex").unwrap();
+        assert_eq!(convex.reachable, Some(false));
+        assert!(!report.ok());
+    }
+}
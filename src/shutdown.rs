@@ -0,0 +1,143 @@
+//! Shutdown Module
+//!
+//! Broadcasts the start of graceful shutdown to long-lived responses.
+//!
+//! Axum's graceful shutdown waits for open connections to finish, which an
+//! SSE stream never does on its own. Streams wrapped with `until_shutdown`
+//! send a final `event: shutdown` and end once shutdown begins, so clients
+//! can reconnect to another instance instead of seeing a dropped connection.
+
+use axum::response::sse::Event;
+use futures::{Stream, StreamExt};
+use std::convert::Infallible;
+use std::sync::Arc;
+use tokio::sync::watch;
+
+/// SSE event name sent to clients when the server shuts down
+pub const SHUTDOWN_EVENT: &str = "shutdown";
+
+/// Cloneable handle to the server-wide shutdown state
+#[derive(Clone)]
+pub struct ShutdownSignal {
+    sender: Arc<watch::Sender<bool>>,
+}
+
+impl Default for ShutdownSignal {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ShutdownSignal {
+    /// Create a signal that has not been triggered
+    pub fn new() -> Self {
+        let (sender, _) = watch::channel(false);
+        Self {
+            sender: Arc::new(sender),
+        }
+    }
+
+    /// Notify all subscribers that shutdown has begun
+    pub fn trigger(&self) {
+        self.sender.send_replace(true);
+    }
+
+    /// Whether shutdown has begun
+    #[cfg(test)]
+    pub fn is_triggered(&self) -> bool {
+        *self.sender.borrow()
+    }
+
+    /// Receiver that observes the shutdown state
+    pub fn subscribe(&self) -> watch::Receiver<bool> {
+        self.sender.subscribe()
+    }
+}
+
+/// Terminal event sent to SSE clients on shutdown
+fn shutdown_event() -> Event {
+    Event::default().event(SHUTDOWN_EVENT).data("server shutting down")
+}
+
+/// End an SSE stream with a `shutdown` event once shutdown begins
+///
+/// Events are passed through unchanged until then. A stream that finishes
+/// on its own ends without the shutdown event.
+pub fn until_shutdown<S>(stream: S, shutdown: &ShutdownSignal) -> impl Stream<Item = Result<Event, Infallible>>
+where
+    S: Stream<Item = Result<Event, Infallible>> + Send + 'static,
+{
+    let receiver = shutdown.subscribe();
+    futures::stream::unfold(Some((stream.boxed(), receiver)), |state| async move {
+        let (mut stream, mut receiver) = state?;
+        let next = tokio::select! {
+            biased;
+            // A dropped sender means the server is going away as well
+            _ = receiver.wait_for(|&triggered| triggered) => None,
+            item = stream.next() => Some(item),
+        };
+        match next {
+            None => Some((Ok(shutdown_event()), None)),
+            Some(item) => item.map(|item| (item, Some((stream, receiver)))),
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::response::sse::Sse;
+    use axum::response::IntoResponse;
+    use std::time::Duration;
+
+    /// Collect the SSE body produced for `stream`
+    async fn sse_body(stream: impl Stream<Item = Result<Event, Infallible>> + Send + 'static) -> String {
+        let response = Sse::new(stream).into_response();
+        let bytes = tokio::time::timeout(
+            Duration::from_secs(5),
+            axum::body::to_bytes(response.into_body(), usize::MAX),
+        )
+        .await
+        .expect("stream did not end")
+        .unwrap();
+        String::from_utf8(bytes.to_vec()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_stream_ends_with_shutdown_event() {
+        let shutdown = ShutdownSignal::new();
+        // One event, then a stream that would stay open forever
+        let events = futures::stream::once(async { Ok(Event::default().data("hello")) })
+            .chain(futures::stream::pending());
+        let stream = until_shutdown(events, &shutdown);
+
+        let trigger = shutdown.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            trigger.trigger();
+        });
+
+        let body = sse_body(stream).await;
+        assert!(shutdown.is_triggered());
+        assert!(body.starts_with("data: hello\n\n"), "{}", body);
+        assert!(body.ends_with("event: shutdown\ndata: server shutting down\n\n"), "{}", body);
+    }
+
+    #[tokio::test]
+    async fn test_streams_opened_after_shutdown_end_immediately() {
+        let shutdown = ShutdownSignal::new();
+        shutdown.trigger();
+
+        let body = sse_body(until_shutdown(futures::stream::pending(), &shutdown)).await;
+        assert_eq!(body, "event: shutdown\ndata: server shutting down\n\n");
+    }
+
+    #[tokio::test]
+    async fn test_finished_stream_has_no_shutdown_event() {
+        let shutdown = ShutdownSignal::new();
+        let events = futures::stream::iter([Ok(Event::default().data("done"))]);
+
+        let body = sse_body(until_shutdown(events, &shutdown)).await;
+        assert_eq!(body, "data: done\n\n");
+    }
+}
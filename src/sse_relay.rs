@@ -0,0 +1,50 @@
+//! SSE Relay Module
+//!
+//! Keep-alive handling for the server-sent event streams we send clients.
+//!
+//! Comment lines in a provider's stream (`: ping`, `:keep-alive`) are never
+//! passed on; only the deltas parsed from its `data:` lines are. Instead
+//! we send our own `: keep-alive` comment whenever nothing has been sent
+//! for `SSE_KEEPALIVE_INTERVAL_SECS`, so clients and proxies do not time
+//! out while a slow upstream is still thinking.
+
+use axum::response::sse::{Event, KeepAlive, Sse};
+use futures::Stream;
+use std::convert::Infallible;
+use std::time::Duration;
+
+/// Text of every keep-alive comment we send
+pub const KEEPALIVE_COMMENT: &str = "keep-alive";
+
+/// SSE response that sends our own keep-alive comment when the stream is
+/// quiet for `interval` (zero disables it)
+pub fn sse_with_keepalive<S>(stream: S, interval: Duration) -> Sse<S>
+where
+    S: Stream<Item = Result<Event, Infallible>> + Send + 'static,
+{
+    let sse = Sse::new(stream);
+    if interval.is_zero() {
+        return sse;
+    }
+    sse.keep_alive(KeepAlive::new().interval(interval).text(KEEPALIVE_COMMENT))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::response::IntoResponse;
+
+    #[tokio::test]
+    async fn test_quiet_upstream_gets_our_keepalive() {
+        let slow = futures::stream::once(async {
+            tokio::time::sleep(Duration::from_millis(250)).await;
+            Ok(Event::default().data("late"))
+        });
+        let response = sse_with_keepalive(slow, Duration::from_millis(50)).into_response();
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let body = String::from_utf8(bytes.to_vec()).unwrap();
+
+        assert!(body.starts_with(": keep-alive\n\n"), "{}", body);
+        assert!(body.ends_with("data: late\n\n"), "{}", body);
+    }
+}
@@ -0,0 +1,130 @@
+//! Stream Deadline Module
+//!
+//! Caps the total lifetime of a streaming (SSE) response.
+//!
+//! A stuck provider stream would otherwise hold the connection open
+//! indefinitely. Streams wrapped with `with_max_duration` end with a
+//! terminal `error` event once `SSE_MAX_DURATION_SECS` has passed, and the
+//! provider timeout is logged.
+
+use axum::response::sse::Event;
+use futures::{Stream, StreamExt};
+use serde_json::json;
+use std::convert::Infallible;
+use std::time::Duration;
+use tokio::time::Instant;
+
+/// SSE event name for a stream ended by the server
+pub const ERROR_EVENT: &str = "error";
+
+/// Terminal event sent when a stream exceeds its maximum duration
+fn timeout_event(max_duration: Duration) -> Event {
+    Event::default().event(ERROR_EVENT).data(
+        json!({
+            "error": "provider timeout",
+            "message": format!("Stream exceeded the maximum duration of {}s", max_duration.as_secs()),
+        })
+        .to_string(),
+    )
+}
+
+/// End an SSE stream with an `error` event after `max_duration`
+///
+/// Events are passed through unchanged until the deadline. A zero
+/// `max_duration` leaves the stream unbounded.
+///
+/// # Arguments
+/// * `stream` - Events relayed from the provider
+/// * `max_duration` - Longest the stream may stay open
+/// * `provider` - Provider id, for the timeout log
+pub fn with_max_duration<S>(
+    stream: S,
+    max_duration: Duration,
+    provider: &str,
+) -> impl Stream<Item = Result<Event, Infallible>>
+where
+    S: Stream<Item = Result<Event, Infallible>> + Send + 'static,
+{
+    let deadline = (!max_duration.is_zero()).then(|| Instant::now() + max_duration);
+    let provider = provider.to_string();
+    futures::stream::unfold(Some((stream.boxed(), provider)), move |state| async move {
+        let (mut stream, provider) = state?;
+        let next = match deadline {
+            Some(deadline) => tokio::select! {
+                biased;
+                _ = tokio::time::sleep_until(deadline) => None,
+                item = stream.next() => Some(item),
+            },
+            None => Some(stream.next().await),
+        };
+        match next {
+            None => {
+                tracing::warn!(
+                    "Provider {} stream timed out after {}s, closing",
+                    provider,
+                    max_duration.as_secs_f64()
+                );
+                Some((Ok(timeout_event(max_duration)), None))
+            }
+            Some(item) => item.map(|item| (item, Some((stream, provider)))),
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::response::sse::Sse;
+    use axum::response::IntoResponse;
+
+    /// Provider that sends one chunk every `interval`, forever
+    fn slow_streamer(interval: Duration) -> impl Stream<Item = Result<Event, Infallible>> + Send + 'static {
+        futures::stream::unfold(0u32, move |chunk| async move {
+            tokio::time::sleep(interval).await;
+            Some((Ok(Event::default().data(format!("chunk {}", chunk))), chunk + 1))
+        })
+    }
+
+    /// Collect the SSE body produced for `stream`
+    async fn sse_body(stream: impl Stream<Item = Result<Event, Infallible>> + Send + 'static) -> String {
+        let response = Sse::new(stream).into_response();
+        let bytes = tokio::time::timeout(
+            Duration::from_secs(5),
+            axum::body::to_bytes(response.into_body(), usize::MAX),
+        )
+        .await
+        .expect("stream did not end")
+        .unwrap();
+        String::from_utf8(bytes.to_vec()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_stream_terminates_at_max_duration() {
+        let started = Instant::now();
+        let stream = with_max_duration(slow_streamer(Duration::from_millis(40)), Duration::from_millis(300), "openai");
+
+        let body = sse_body(stream).await;
+        let elapsed = started.elapsed();
+        assert!(elapsed >= Duration::from_millis(300), "{:?}", elapsed);
+        assert!(elapsed < Duration::from_secs(2), "{:?}", elapsed);
+
+        assert!(body.starts_with("data: chunk 0\n\n"), "{}", body);
+        let last = body.trim_end().rsplit("\n\n").next().unwrap();
+        assert!(last.starts_with("event: error\ndata: "), "{}", last);
+        assert!(last.contains("provider timeout"), "{}", last);
+    }
+
+    #[tokio::test]
+    async fn test_stream_finishing_in_time_is_untouched() {
+        let events = futures::stream::iter([Ok(Event::default().data("done"))]);
+        let body = sse_body(with_max_duration(events, Duration::from_secs(60), "openai")).await;
+        assert_eq!(body, "data: done\n\n");
+    }
+
+    #[tokio::test]
+    async fn test_zero_duration_is_unbounded() {
+        let events = slow_streamer(Duration::from_millis(5)).take(3);
+        let body = sse_body(with_max_duration(events, Duration::ZERO, "openai")).await;
+        assert_eq!(body, "data: chunk 0\n\ndata: chunk 1\n\ndata: chunk 2\n\n");
+    }
+}
@@ -0,0 +1,22 @@
+//! Test Support Module
+//!
+//! Helpers shared by the unit tests of several modules. Only compiled
+//! for tests.
+
+use axum::Router;
+use std::net::SocketAddr;
+
+/// Serve `app` on a free local port and return its address
+///
+/// The server runs on the calling test's runtime and stops with it.
+pub async fn spawn_mock(app: Router) -> SocketAddr {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+    addr
+}
+
+/// Local address that nothing listens on, for connection failures
+pub fn closed_addr() -> SocketAddr {
+    std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap()
+}
@@ -33,7 +33,10 @@ pub struct ChatMessage {
 /// - System: Instructions or context for the AI
 /// - User: Messages from the human user
 /// - Assistant: Responses from the AI
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+/// 
+/// Always serialized lowercase. Deserialization is lenient about casing
+/// and accepts common aliases (see `MessageRole::parse`).
+#[derive(Debug, Clone, Serialize, PartialEq)]
 #[serde(rename_all = "lowercase")]
 pub enum MessageRole {
     /// System messages (prompts, instructions)
@@ -44,11 +47,33 @@ pub enum MessageRole {
     Assistant,
 }
 
+impl MessageRole {
+    /// Parse a role in any casing, mapping `human` to user and `ai`/`bot`
+    /// to assistant
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "system" => Some(Self::System),
+            "user" | "human" => Some(Self::User),
+            "assistant" | "ai" | "bot" => Some(Self::Assistant),
+            _ => None,
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for MessageRole {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let value = String::deserialize(deserializer)?;
+        MessageRole::parse(&value).ok_or_else(|| {
+            serde::de::Error::unknown_variant(&value, &["system", "user", "assistant"])
+        })
+    }
+}
+
 /// AI model generation options with validation
 /// 
 /// Controls the behavior of AI model inference with validated ranges
 /// to ensure reasonable and safe parameter values.
-#[derive(Debug, Clone, Serialize, Deserialize, Validate)]
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, Validate)]
 pub struct InvokeOptions {
     /// Temperature for response randomness (0.0 = deterministic, 2.0 = very random)
     #[validate(range(min = 0.0, max = 2.0))]
@@ -58,6 +83,16 @@ pub struct InvokeOptions {
     pub max_tokens: Option<u32>,
 }
 
+impl InvokeOptions {
+    /// Fill any option left unset from `defaults` (e.g. a route's defaults)
+    pub fn with_defaults(&self, defaults: &InvokeOptions) -> InvokeOptions {
+        InvokeOptions {
+            temperature: self.temperature.or(defaults.temperature),
+            max_tokens: self.max_tokens.or(defaults.max_tokens),
+        }
+    }
+}
+
 /// File attachment metadata for multimodal requests
 /// 
 /// Represents uploaded files that can be processed alongside text input.
@@ -74,17 +109,30 @@ pub struct Attachment {
     pub size: Option<u64>,
 }
 
+/// Current invoke request schema version
+pub const CURRENT_API_VERSION: u32 = 2;
+
 /// Main API invocation request structure
 /// 
 /// This is the primary request format for all AI operations.
 /// Contains all necessary information for routing, processing, and
 /// generating AI responses.
+/// 
+/// Request bodies are parsed according to `api_version`:
+/// - `2` (current, default): `op` plus an `input` object
+/// - `1` (legacy): `operation` plus top-level `messages`; other unknown
+///   top-level fields are carried into `input`
+/// 
+/// Both shapes normalize to this structure.
 #[derive(Debug, Clone, Serialize, Deserialize, Validate)]
+#[serde(try_from = "RawInvokeRequest")]
 pub struct InvokeRequest {
+    /// Schema version the request body was written against
+    pub api_version: u32,
     /// Type of operation to perform (chat, code completion, etc.)
     pub op: Operation,
-    /// User subscription tier for rate limiting and features
-    pub tier: Option<String>,
+    /// Routing tier selecting the `op.tier` route (defaults to fast)
+    pub tier: Option<Tier>,
     /// Input data specific to the operation type
     pub input: HashMap<String, serde_json::Value>,
     /// AI model generation options (temperature, max_tokens, etc.)
@@ -95,6 +143,149 @@ pub struct InvokeRequest {
     pub enable_search: Option<bool>,
     /// File attachments for multimodal processing
     pub attachments: Option<Vec<Attachment>>,
+    /// Language the assistant must reply in (ISO 639-1 code, e.g. "fr")
+    pub language: Option<String>,
+    /// URL the result is POSTed to instead of being returned in the response
+    pub callback_url: Option<String>,
+    /// Whether the result is sent as a server-sent event stream
+    pub stream: Option<bool>,
+    /// Whether the first two route targets are raced, keeping the first
+    /// reply (both providers are billed)
+    pub race: Option<bool>,
+    /// Client-chosen conversation id; turns of one conversation share
+    /// recent search results
+    pub session_id: Option<String>,
+}
+
+/// Wire format accepted for any supported invoke request version
+#[derive(Deserialize)]
+struct RawInvokeRequest {
+    #[serde(default = "current_api_version")]
+    api_version: u32,
+    op: Option<Operation>,
+    operation: Option<Operation>,
+    tier: Option<Tier>,
+    input: Option<HashMap<String, serde_json::Value>>,
+    messages: Option<serde_json::Value>,
+    options: Option<InvokeOptions>,
+    token: Option<String>,
+    enable_search: Option<bool>,
+    attachments: Option<Vec<Attachment>>,
+    language: Option<String>,
+    callback_url: Option<String>,
+    stream: Option<bool>,
+    race: Option<bool>,
+    session_id: Option<String>,
+    /// Remaining top-level fields (legacy input values)
+    #[serde(flatten)]
+    extra: HashMap<String, serde_json::Value>,
+}
+
+fn current_api_version() -> u32 {
+    CURRENT_API_VERSION
+}
+
+impl TryFrom<RawInvokeRequest> for InvokeRequest {
+    type Error = String;
+
+    fn try_from(raw: RawInvokeRequest) -> Result<Self, Self::Error> {
+        let (op, input) = match raw.api_version {
+            1 => {
+                let op = raw.operation.ok_or("missing field `operation`")?;
+                let mut input = raw.extra;
+                if let Some(messages) = raw.messages {
+                    input.insert("messages".to_string(), messages);
+                }
+                (op, input)
+            }
+            2 => (
+                raw.op.ok_or("missing field `op`")?,
+                raw.input.ok_or("missing field `input`")?,
+            ),
+            version => return Err(format!("unsupported api_version {}", version)),
+        };
+
+        Ok(InvokeRequest {
+            api_version: raw.api_version,
+            op,
+            tier: raw.tier,
+            input,
+            options: raw.options,
+            token: raw.token,
+            enable_search: raw.enable_search,
+            attachments: raw.attachments,
+            language: raw.language,
+            callback_url: raw.callback_url,
+            stream: raw.stream,
+            race: raw.race,
+            session_id: raw.session_id,
+        })
+    }
+}
+
+impl InvokeRequest {
+    /// Chat messages from `input.messages`, empty when absent or malformed
+    pub fn messages(&self) -> Vec<ChatMessage> {
+        self.parse_messages().unwrap_or_default()
+    }
+    
+    /// Chat messages from `input.messages`
+    /// 
+    /// # Errors
+    /// A client-facing message when the messages are missing, empty or not
+    /// a list of `{ "role", "content" }` objects
+    pub fn parse_messages(&self) -> Result<Vec<ChatMessage>, String> {
+        let messages = self
+            .input
+            .get("messages")
+            .ok_or_else(|| "input.messages is required".to_string())?;
+        let messages: Vec<ChatMessage> = serde_json::from_value(messages.clone())
+            .map_err(|_| "input.messages must be a list of { role, content } messages".to_string())?;
+        if messages.is_empty() {
+            return Err("input.messages must not be empty".to_string());
+        }
+        Ok(messages)
+    }
+    
+    /// Image generation input from `input.prompt`, `input.size`,
+    /// `input.quality` and `input.response_format`
+    /// 
+    /// # Errors
+    /// A client-facing message when the prompt is missing or empty, or an
+    /// option has the wrong type or an unknown response format
+    pub fn parse_image_request(&self) -> Result<ImageRequest, String> {
+        let prompt = match self.input.get("prompt") {
+            Some(serde_json::Value::String(prompt)) if !prompt.trim().is_empty() => prompt.clone(),
+            Some(serde_json::Value::String(_)) => return Err("input.prompt must not be empty".to_string()),
+            Some(_) => return Err("input.prompt must be a string".to_string()),
+            None => return Err("input.prompt is required".to_string()),
+        };
+        let option = |name: &str| match self.input.get(name) {
+            None | Some(serde_json::Value::Null) => Ok(None),
+            Some(serde_json::Value::String(value)) => Ok(Some(value.clone())),
+            Some(_) => Err(format!("input.{} must be a string", name)),
+        };
+        let response_format = option("response_format")?;
+        if let Some(format) = &response_format {
+            if format != "url" && format != "b64_json" {
+                return Err("input.response_format must be \"url\" or \"b64_json\"".to_string());
+            }
+        }
+        Ok(ImageRequest {
+            prompt,
+            size: option("size")?,
+            quality: option("quality")?,
+            response_format,
+        })
+    }
+    
+    /// Whether any attachment is an image
+    pub fn has_image_attachments(&self) -> bool {
+        self.attachments
+            .iter()
+            .flatten()
+            .any(|attachment| attachment.content_type.starts_with("image/"))
+    }
 }
 
 /// Operation types supported by the AI system
@@ -102,6 +293,7 @@ pub struct InvokeRequest {
 /// Defines the different types of AI operations that can be performed:
 /// - Chat: Conversational interactions
 /// - FIM: Fill-in-middle code completion
+/// - Image: Image generation from a text prompt
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 #[serde(rename_all = "lowercase")]
 pub enum Operation {
@@ -109,6 +301,110 @@ pub enum Operation {
     Chat,
     /// Fill-in-middle code completion
     Fim,
+    /// Image generation
+    #[serde(rename = "image")]
+    ImageGen,
+}
+
+impl Operation {
+    /// Operation identifier as used in routing keys
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Operation::Chat => "chat",
+            Operation::Fim => "fim",
+            Operation::ImageGen => "image",
+        }
+    }
+
+    /// Tier routed to when the request names none
+    /// 
+    /// Image generation has no fast/smart split, so its routes use
+    /// `image.default`.
+    pub fn default_tier(&self) -> Tier {
+        match self {
+            Operation::ImageGen => Tier::Other("default".to_string()),
+            Operation::Chat | Operation::Fim => Tier::default(),
+        }
+    }
+}
+
+/// Input of an image generation request (`op: "image"`)
+/// 
+/// Unset options are left to the provider's defaults.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ImageRequest {
+    /// Description of the image to generate
+    pub prompt: String,
+    /// Image dimensions, e.g. "1024x1024"
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub size: Option<String>,
+    /// Rendering quality, e.g. "standard" or "hd"
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub quality: Option<String>,
+    /// "url" for hosted images or "b64_json" for inline base64
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub response_format: Option<String>,
+}
+
+/// Routing tier of an invoke request
+/// 
+/// `fast` and `smart` are the standard tiers. Any other well-formed name
+/// is kept as `Other`, so deployments can route custom tiers (`vision`,
+/// `cheap`, ...) through `ROUTES`. Standard tiers are matched in any
+/// casing; custom names are kept as given.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub enum Tier {
+    /// Low-latency models
+    #[default]
+    Fast,
+    /// Higher-quality models
+    Smart,
+    /// Deployment-specific tier
+    Other(String),
+}
+
+impl Tier {
+    /// Parse a tier name
+    /// 
+    /// # Errors
+    /// A client-facing message when the name is empty or contains
+    /// anything but letters, digits, `-` and `_` (such a tier could never
+    /// match a route key)
+    pub fn parse(value: &str) -> Result<Self, String> {
+        let value = value.trim();
+        match value.to_lowercase().as_str() {
+            "fast" => Ok(Self::Fast),
+            "smart" => Ok(Self::Smart),
+            _ if value.is_empty() => Err("tier must not be empty".to_string()),
+            _ if !value.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') => Err(format!(
+                "invalid tier '{}': tier names may only contain letters, digits, '-' and '_'",
+                value
+            )),
+            _ => Ok(Self::Other(value.to_string())),
+        }
+    }
+
+    /// Tier name as used in routing keys
+    pub fn as_str(&self) -> &str {
+        match self {
+            Tier::Fast => "fast",
+            Tier::Smart => "smart",
+            Tier::Other(name) => name,
+        }
+    }
+}
+
+impl Serialize for Tier {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for Tier {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let value = String::deserialize(deserializer)?;
+        Tier::parse(&value).map_err(serde::de::Error::custom)
+    }
 }
 
 /// AI provider enumeration
@@ -138,16 +434,35 @@ pub enum Provider {
     Anthropic,
 }
 
+impl Provider {
+    /// Provider identifier as used in routing and limit configuration
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Provider::Cloudflare => "cf",
+            Provider::Mistral => "mistral",
+            Provider::OpenAI => "openai",
+            Provider::Xai => "xai",
+            Provider::Groq => "groq",
+            Provider::OpenRouter => "openrouter",
+            Provider::Meta => "meta",
+            Provider::Anthropic => "anthropic",
+        }
+    }
+}
+
 /// Route target for provider routing
 /// 
 /// Specifies which provider and model to use for a request.
 /// Used by the routing system to direct requests appropriately.
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct RouteTarget {
     /// AI provider to use
     pub provider: Provider,
     /// Specific model name at the provider
     pub model: String,
+    /// Options applied when the client does not set them
+    #[serde(default)]
+    pub defaults: InvokeOptions,
 }
 
 /// Standardized API response wrapper
@@ -164,6 +479,9 @@ pub struct ApiResponse<T> {
     pub message: Option<String>,
     /// Error message (present on error)
     pub error: Option<String>,
+    /// Id of the server log entry for an internal error
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub correlation_id: Option<String>,
 }
 
 impl<T> ApiResponse<T> {
@@ -180,6 +498,7 @@ impl<T> ApiResponse<T> {
             data: Some(data),
             message: None,
             error: None,
+            correlation_id: None,
         }
     }
 
@@ -197,8 +516,15 @@ impl<T> ApiResponse<T> {
             data: None,
             message: None,
             error: Some(message),
+            correlation_id: None,
         }
     }
+
+    /// Attach the correlation id logged with an internal error
+    pub fn with_correlation_id(mut self, correlation_id: String) -> Self {
+        self.correlation_id = Some(correlation_id);
+        self
+    }
 }
 
 /// Authenticated user information
@@ -215,8 +541,13 @@ pub struct AuthUser {
     pub is_anonymous: bool,
     /// Account creation timestamp
     pub created_at: chrono::DateTime<chrono::Utc>,
+    /// Subscription tier of the account (`GUEST_TIER` for anonymous users)
+    pub subscription_tier: String,
 }
 
+/// Subscription tier reported for guest and anonymous users
+pub const GUEST_TIER: &str = "guest";
+
 /// Web search result item
 /// 
 /// Represents a single search result from web search providers.
@@ -231,6 +562,9 @@ pub struct SearchResult {
     pub snippet: String,
     /// Relevance score (provider-dependent, optional)
     pub score: Option<f32>,
+    /// Full page content, when the provider returns it and it is enabled
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub raw_content: Option<String>,
 }
 
 /// Web search response container
@@ -252,7 +586,6 @@ pub struct SearchResponse {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use serde_json;
     use chrono::Utc;
 
     #[test]
@@ -282,10 +615,58 @@ mod tests {
         assert_eq!(serde_json::to_string(&MessageRole::Assistant).unwrap(), "\"assistant\"");
     }
 
+    #[test]
+    fn test_message_role_deserialization_is_lenient() {
+        let role = |value: &str| serde_json::from_value::<MessageRole>(serde_json::json!(value));
+        for value in ["user", "User", "USER", " human ", "Human"] {
+            assert_eq!(role(value).unwrap(), MessageRole::User, "{}", value);
+        }
+        for value in ["assistant", "Assistant", "AI", "ai", "bot", "Bot"] {
+            assert_eq!(role(value).unwrap(), MessageRole::Assistant, "{}", value);
+        }
+        assert_eq!(role("SYSTEM").unwrap(), MessageRole::System);
+        
+        let error = role("narrator").unwrap_err().to_string();
+        assert!(error.contains("unknown variant `narrator`"), "{}", error);
+        
+        // Aliases are normalized on the way out
+        let message: ChatMessage = serde_json::from_str(r#"{"role":"Human","content":"Hi"}"#).unwrap();
+        assert_eq!(serde_json::to_value(&message).unwrap()["role"], "user");
+    }
+
     #[test]
     fn test_operation_serialization() {
         assert_eq!(serde_json::to_string(&Operation::Chat).unwrap(), "\"chat\"");
         assert_eq!(serde_json::to_string(&Operation::Fim).unwrap(), "\"fim\"");
+        assert_eq!(serde_json::to_string(&Operation::ImageGen).unwrap(), "\"image\"");
+        assert_eq!(Operation::ImageGen.as_str(), "image");
+        assert_eq!(Operation::ImageGen.default_tier().as_str(), "default");
+        assert_eq!(Operation::Chat.default_tier(), Tier::Fast);
+    }
+
+    #[test]
+    fn test_parse_image_request() {
+        let request = |input: serde_json::Value| -> InvokeRequest {
+            serde_json::from_value(serde_json::json!({ "op": "image", "input": input })).unwrap()
+        };
+        
+        let image = request(serde_json::json!({
+            "prompt": "A lighthouse at dusk",
+            "size": "1024x1024",
+            "quality": "hd"
+        }))
+        .parse_image_request()
+        .unwrap();
+        assert_eq!(image.prompt, "A lighthouse at dusk");
+        assert_eq!(image.size.as_deref(), Some("1024x1024"));
+        assert_eq!(image.quality.as_deref(), Some("hd"));
+        assert_eq!(image.response_format, None);
+        
+        let error = |input| request(input).parse_image_request().unwrap_err();
+        assert_eq!(error(serde_json::json!({})), "input.prompt is required");
+        assert_eq!(error(serde_json::json!({ "prompt": "  " })), "input.prompt must not be empty");
+        assert_eq!(error(serde_json::json!({ "prompt": "x", "size": 1024 })), "input.size must be a string");
+        assert!(error(serde_json::json!({ "prompt": "x", "response_format": "png" })).contains("response_format"));
     }
 
     #[test]
@@ -293,17 +674,41 @@ mod tests {
         let route = RouteTarget {
             provider: Provider::OpenAI,
             model: "gpt-4o-mini".to_string(),
+            defaults: InvokeOptions::default(),
         };
         
         assert_eq!(route.provider, Provider::OpenAI);
         assert_eq!(route.model, "gpt-4o-mini");
     }
 
+    #[test]
+    fn test_invoke_options_with_defaults() {
+        let defaults = InvokeOptions {
+            temperature: Some(0.2),
+            max_tokens: Some(512),
+        };
+        
+        // Client values win; unset ones come from the defaults
+        let client = InvokeOptions {
+            temperature: Some(0.9),
+            max_tokens: None,
+        };
+        assert_eq!(
+            client.with_defaults(&defaults),
+            InvokeOptions {
+                temperature: Some(0.9),
+                max_tokens: Some(512),
+            }
+        );
+        assert_eq!(InvokeOptions::default().with_defaults(&defaults), defaults);
+    }
+
     #[test]
     fn test_route_target_serialization() {
         let route = RouteTarget {
             provider: Provider::Anthropic,
             model: "claude-3-5-sonnet".to_string(),
+            defaults: InvokeOptions::default(),
         };
         
         let json = serde_json::to_string(&route).unwrap();
@@ -380,17 +785,23 @@ mod tests {
         ]));
         
         let request = InvokeRequest {
+            api_version: CURRENT_API_VERSION,
             op: Operation::Chat,
-            tier: Some("fast".to_string()),
+            tier: Some(Tier::Fast),
             input,
             options: None,
             token: None,
             enable_search: None,
             attachments: None,
+            language: None,
+            callback_url: None,
+            stream: None,
+            race: None,
+            session_id: None,
         };
         
         assert_eq!(request.op, Operation::Chat);
-        assert_eq!(request.tier, Some("fast".to_string()));
+        assert_eq!(request.tier, Some(Tier::Fast));
         assert!(request.input.contains_key("messages"));
         assert!(request.attachments.is_none());
         assert!(request.options.is_none());
@@ -411,8 +822,9 @@ mod tests {
         ]));
         
         let request = InvokeRequest {
+            api_version: CURRENT_API_VERSION,
             op: Operation::Fim,
-            tier: Some("smart".to_string()),
+            tier: Some(Tier::Smart),
             input,
             options: Some(InvokeOptions {
                 temperature: Some(0.7),
@@ -428,10 +840,15 @@ mod tests {
                     size: None,
                 }
             ]),
+            language: Some("de".to_string()),
+            callback_url: None,
+            stream: None,
+            race: None,
+            session_id: None,
         };
         
         assert_eq!(request.op, Operation::Fim);
-        assert_eq!(request.tier, Some("smart".to_string()));
+        assert_eq!(request.tier, Some(Tier::Smart));
         assert!(request.input.contains_key("messages"));
         assert!(request.attachments.is_some());
         assert_eq!(request.attachments.as_ref().unwrap().len(), 1);
@@ -450,8 +867,9 @@ mod tests {
         ]));
         
         let request = InvokeRequest {
+            api_version: CURRENT_API_VERSION,
             op: Operation::Chat,
-            tier: Some("fast".to_string()),
+            tier: Some(Tier::Fast),
             input,
             options: Some(InvokeOptions {
                 temperature: Some(0.8),
@@ -460,6 +878,11 @@ mod tests {
             token: None,
             enable_search: None,
             attachments: None,
+            language: None,
+            callback_url: None,
+            stream: None,
+            race: None,
+            session_id: None,
         };
         
         let json = serde_json::to_string(&request).unwrap();
@@ -472,6 +895,89 @@ mod tests {
         assert_eq!(deserialized.options.as_ref().unwrap().max_tokens, Some(500));
     }
 
+    #[test]
+    fn test_tier_serde_and_validation() {
+        for (raw, tier) in [
+            ("fast", Tier::Fast),
+            ("Smart", Tier::Smart),
+            (" FAST ", Tier::Fast),
+            ("vision", Tier::Other("vision".to_string())),
+            ("cheap_v2", Tier::Other("cheap_v2".to_string())),
+        ] {
+            assert_eq!(serde_json::from_value::<Tier>(serde_json::json!(raw)).unwrap(), tier);
+        }
+        assert_eq!(serde_json::to_value(Tier::Smart).unwrap(), "smart");
+        assert_eq!(serde_json::to_value(Tier::Other("vision".to_string())).unwrap(), "vision");
+        assert_eq!(Tier::default().as_str(), "fast");
+        
+        // Names that could never match a route key are rejected
+        for raw in ["", "  ", "chat.fast", "fast tier", "smart=openai"] {
+            assert!(Tier::parse(raw).is_err(), "{:?}", raw);
+        }
+        let error = serde_json::from_value::<InvokeRequest>(serde_json::json!({
+            "op": "chat",
+            "tier": "sm.art",
+            "input": {"messages": [{"role": "user", "content": "Hi"}]}
+        }))
+        .unwrap_err();
+        assert!(error.to_string().contains("invalid tier"), "{}", error);
+    }
+    
+    #[test]
+    fn test_invoke_request_versions_normalize() {
+        let v2: InvokeRequest = serde_json::from_value(serde_json::json!({
+            "op": "chat",
+            "tier": "fast",
+            "input": {"messages": [{"role": "user", "content": "Hi"}]},
+            "options": {"temperature": 0.5}
+        })).unwrap();
+        
+        let v1: InvokeRequest = serde_json::from_value(serde_json::json!({
+            "api_version": 1,
+            "operation": "chat",
+            "tier": "fast",
+            "messages": [{"role": "user", "content": "Hi"}],
+            "options": {"temperature": 0.5}
+        })).unwrap();
+        
+        assert_eq!(v2.api_version, CURRENT_API_VERSION);
+        assert_eq!(v1.api_version, 1);
+        for request in [&v1, &v2] {
+            assert_eq!(request.op, Operation::Chat);
+            assert_eq!(request.tier, Some(Tier::Fast));
+            assert_eq!(request.input["messages"][0]["content"], "Hi");
+            assert_eq!(request.options.as_ref().unwrap().temperature, Some(0.5));
+        }
+        assert_eq!(v1.input, v2.input);
+        
+        // Legacy FIM fields are carried into input
+        let fim: InvokeRequest = serde_json::from_value(serde_json::json!({
+            "api_version": 1,
+            "operation": "fim",
+            "prompt": "def add(a, b):",
+            "suffix": "return c"
+        })).unwrap();
+        assert_eq!(fim.op, Operation::Fim);
+        assert_eq!(fim.input["prompt"], "def add(a, b):");
+    }
+    
+    #[test]
+    fn test_invoke_request_version_mismatch_rejected() {
+        // Legacy shape without declaring version 1
+        let err = serde_json::from_value::<InvokeRequest>(serde_json::json!({
+            "operation": "chat",
+            "messages": []
+        })).unwrap_err();
+        assert!(err.to_string().contains("missing field `op`"));
+        
+        let err = serde_json::from_value::<InvokeRequest>(serde_json::json!({
+            "api_version": 3,
+            "op": "chat",
+            "input": {}
+        })).unwrap_err();
+        assert!(err.to_string().contains("unsupported api_version 3"));
+    }
+
     #[test]
     fn test_api_response_success() {
         let response = ApiResponse::success(serde_json::json!({"result": "success"}));
@@ -531,6 +1037,7 @@ mod tests {
             email: Some("test@example.com".to_string()),
             is_anonymous: false,
             created_at: Utc::now(),
+            subscription_tier: "free".to_string(),
         };
         
         assert_eq!(user.id, "user_123");
@@ -545,6 +1052,7 @@ mod tests {
             email: None,
             is_anonymous: true,
             created_at: Utc::now(),
+            subscription_tier: GUEST_TIER.to_string(),
         };
         
         assert_eq!(user.id, "anon_456");
@@ -559,14 +1067,17 @@ mod tests {
             email: Some("user@test.com".to_string()),
             is_anonymous: false,
             created_at: chrono::DateTime::parse_from_rfc3339("2024-01-01T00:00:00Z").unwrap().with_timezone(&Utc),
+            subscription_tier: "premium".to_string(),
         };
         
         let json = serde_json::to_string(&user).unwrap();
+        assert!(json.contains(r#""subscription_tier":"premium""#));
         let deserialized: AuthUser = serde_json::from_str(&json).unwrap();
         
         assert_eq!(deserialized.id, user.id);
         assert_eq!(deserialized.email, user.email);
         assert_eq!(deserialized.is_anonymous, user.is_anonymous);
+        assert_eq!(deserialized.subscription_tier, "premium");
     }
 
     #[test]
@@ -576,6 +1087,7 @@ mod tests {
             url: "https://example.com/article".to_string(),
             snippet: "This is a test article snippet with relevant information.".to_string(),
             score: Some(0.95),
+            raw_content: None,
         };
         
         assert_eq!(result.title, "Test Article");
@@ -591,6 +1103,7 @@ mod tests {
             url: "https://example.com/another".to_string(),
             snippet: "Another snippet".to_string(),
             score: None,
+            raw_content: None,
         };
         
         assert!(result.score.is_none());
@@ -603,6 +1116,7 @@ mod tests {
             url: "https://test.com/serialize".to_string(),
             snippet: "Testing serialization functionality".to_string(),
             score: Some(0.88),
+            raw_content: None,
         };
         
         let json = serde_json::to_string(&result).unwrap();
@@ -624,12 +1138,14 @@ mod tests {
                     url: "https://rust-lang.org".to_string(),
                     snippet: "A systems programming language focused on safety, speed, and concurrency.".to_string(),
                     score: Some(0.98),
+                    raw_content: None,
                 },
                 SearchResult {
                     title: "Learn Rust".to_string(),
                     url: "https://doc.rust-lang.org/book/".to_string(),
                     snippet: "The Rust Programming Language book teaches you Rust.".to_string(),
                     score: Some(0.92),
+                    raw_content: None,
                 }
             ],
             provider: "tavily".to_string(),
@@ -664,6 +1180,7 @@ mod tests {
                     url: "https://test.example.com".to_string(),
                     snippet: "Test snippet content".to_string(),
                     score: Some(0.85),
+                    raw_content: None,
                 }
             ],
             provider: "searxng".to_string(),
@@ -693,4 +1210,4 @@ mod tests {
         let provider = serde_json::from_str::<Provider>(provider_json).unwrap();
         assert_eq!(provider, Provider::OpenAI);
     }
-}
\ No newline at end of file
+}